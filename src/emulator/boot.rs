@@ -0,0 +1,72 @@
+use std::fmt;
+use crate::emulator::constants::{DRAM_BASE, PAGE_SIZE};
+use crate::emulator::dtb::{DtbError, DtbNode};
+use crate::emulator::elf::ElfError;
+
+// Same layout as QEMU's virt machine, the firmware at the start of RAM jumps to the kernel 2 MiB above it
+pub const FIRMWARE_BASE: u64 = DRAM_BASE;
+pub const KERNEL_BASE: u64 = DRAM_BASE + 0x20_0000;
+
+// The initrd goes far enough above the kernel that decompressing it does not clobber the initrd
+const INITRD_MAX_OFFSET: u64 = 128 * 1024 * 1024;
+
+// Images for a kernel boot, paths are on the host. Without firmware the built-in SBI is used
+#[derive(Debug, Clone)]
+pub struct LinuxBoot {
+    pub firmware: Option<String>,
+    pub kernel: String,
+    pub initrd: Option<String>,
+    pub cmdline: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum BootError {
+    Io(String, std::io::Error),
+    Elf(ElfError),
+    Dtb(DtbError),
+    DoesNotFit { path: String, addr: u64, size: u64 },
+}
+
+impl fmt::Display for BootError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BootError::Io(path, e) => write!(f, "failed to read {}: {}", path, e),
+            BootError::Elf(e) => write!(f, "{}", e),
+            BootError::Dtb(e) => write!(f, "{}", e),
+            BootError::DoesNotFit { path, addr, size } => write!(f, "{} ({:#x} bytes) does not fit into guest memory at {:#x}", path, size, addr),
+        }
+    }
+}
+
+impl From<ElfError> for BootError {
+    fn from(e: ElfError) -> Self {
+        BootError::Elf(e)
+    }
+}
+
+impl From<DtbError> for BootError {
+    fn from(e: DtbError) -> Self {
+        BootError::Dtb(e)
+    }
+}
+
+// Halfway into RAM on small machines, 128 MiB above the kernel otherwise
+pub fn initrd_address(kernel_base: u64, memory_size: u64) -> u64 {
+    let offset = (memory_size / 2).min(INITRD_MAX_OFFSET);
+
+    (kernel_base + offset + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
+
+// Tells the kernel about its command line and where the initrd was placed
+pub fn set_chosen(tree: &mut DtbNode, cmdline: Option<&str>, initrd: Option<(u64, u64)>) {
+    let chosen = tree.child_mut("chosen");
+
+    if let Some(cmdline) = cmdline {
+        chosen.set_string("bootargs", cmdline);
+    }
+
+    if let Some((start, end)) = initrd {
+        chosen.set_u64("linux,initrd-start", start);
+        chosen.set_u64("linux,initrd-end", end);
+    }
+}
@@ -0,0 +1,245 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::emulator::state::memory::Watchpoint;
+use crate::emulator::state::rv64_cpu_context::{x_register_from_name, Register, RV64CPUContext, X_ABI_NAMES};
+
+// Where a breakpoint stops the machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Pc(u64),
+    // Watched physical range and the virtual address it was translated from, if it was set on one
+    Watch { watchpoint: Watchpoint, vaddr: Option<u64> },
+    // Register of a hart and the value it held when it was last checked
+    Register { hart_id: usize, register: Register, value: u64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Register(usize),
+    Hits,
+}
+
+// Values are compared unsigned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+
+    fn holds(self, lhs: u64, rhs: u64) -> bool {
+        match self {
+            Comparison::Eq => lhs == rhs,
+            Comparison::Ne => lhs != rhs,
+            Comparison::Lt => lhs < rhs,
+            Comparison::Le => lhs <= rhs,
+            Comparison::Gt => lhs > rhs,
+            Comparison::Ge => lhs >= rhs,
+        }
+    }
+}
+
+// "a0 == 0x10" or "hits >= 3", the hit count includes the current hit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub operand: Operand,
+    pub comparison: Comparison,
+    pub value: u64,
+}
+
+impl Condition {
+    pub fn holds(&self, cpu: &RV64CPUContext, hits: u64) -> bool {
+        let lhs = match self.operand {
+            Operand::Register(register) => cpu.x[register],
+            Operand::Hits => hits,
+        };
+
+        self.comparison.holds(lhs, self.value)
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens: Vec<&str> = s.split_whitespace().collect();
+
+        let [operand, comparison, value] = tokens[..] else {
+            return Err(format!("expected '<register|hits> <op> <value>', got '{}'", s));
+        };
+
+        let operand = match operand {
+            "hits" => Operand::Hits,
+            _ => Operand::Register(x_register_from_name(operand).ok_or_else(|| format!("unknown register '{}'", operand))?),
+        };
+
+        let comparison = match comparison {
+            "==" => Comparison::Eq,
+            "!=" => Comparison::Ne,
+            "<" => Comparison::Lt,
+            "<=" => Comparison::Le,
+            ">" => Comparison::Gt,
+            ">=" => Comparison::Ge,
+            _ => return Err(format!("unknown comparison '{}'", comparison)),
+        };
+
+        let value = parse_value(value).ok_or_else(|| format!("invalid value '{}'", value))?;
+
+        Ok(Condition { operand, comparison, value })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.operand {
+            Operand::Register(register) => write!(f, "{} {} {:#x}", X_ABI_NAMES[register], self.comparison.symbol(), self.value),
+            Operand::Hits => write!(f, "hits {} {}", self.comparison.symbol(), self.value),
+        }
+    }
+}
+
+// Hexadecimal with a 0x prefix, otherwise decimal, negative numbers are two's complement
+pub fn parse_value(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None if s.starts_with('-') => s.parse::<i64>().ok().map(|value| value as u64),
+        None => s.parse::<u64>().ok(),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub location: Location,
+    pub condition: Option<Condition>,
+    pub enabled: bool,
+    pub hits: u64,
+}
+
+// Breakpoints and watchpoints of the debugger, numbered like gdb does starting at 1
+#[derive(Default, Clone)]
+pub struct Breakpoints {
+    entries: Vec<Breakpoint>,
+    last_id: usize,
+}
+
+impl Breakpoints {
+    pub fn insert(&mut self, location: Location, condition: Option<Condition>) -> usize {
+        self.last_id += 1;
+        self.entries.push(Breakpoint { id: self.last_id, location, condition, enabled: true, hits: 0 });
+
+        self.last_id
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Breakpoint> {
+        let index = self.entries.iter().position(|breakpoint| breakpoint.id == id)?;
+
+        Some(self.entries.remove(index))
+    }
+
+    // Removes every pc breakpoint at addr, returns whether there was one
+    pub fn remove_pc(&mut self, addr: u64) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|breakpoint| breakpoint.location != Location::Pc(addr));
+
+        self.entries.len() != len
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Breakpoint> {
+        self.entries.iter().find(|breakpoint| breakpoint.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Breakpoint> {
+        self.entries.iter_mut().find(|breakpoint| breakpoint.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.entries.iter()
+    }
+
+    // Whether an enabled breakpoint is watching this physical range
+    pub fn is_watched(&self, watchpoint: &Watchpoint) -> bool {
+        self.entries.iter().any(|breakpoint| breakpoint.enabled && matches!(breakpoint.location, Location::Watch { watchpoint: other, .. } if other == *watchpoint))
+    }
+
+    // Counts the hit on every enabled breakpoint matching the location and returns the first one whose
+    // condition holds on the hart that reached it
+    pub fn hit(&mut self, matches: impl Fn(&Location) -> bool, cpu: &RV64CPUContext) -> Option<usize> {
+        let mut stop = None;
+
+        for breakpoint in self.entries.iter_mut().filter(|breakpoint| breakpoint.enabled && matches(&breakpoint.location)) {
+            breakpoint.hits += 1;
+
+            let holds = breakpoint.condition.is_none_or(|condition| condition.holds(cpu, breakpoint.hits));
+            if holds {
+                stop.get_or_insert(breakpoint.id);
+            }
+        }
+
+        stop
+    }
+
+    // Compares the registers watched on the hart with their last values, counts a hit on every enabled
+    // watch whose register changed and returns the first one whose condition holds with the old value
+    pub fn register_changed(&mut self, hart_id: usize, cpu: &RV64CPUContext) -> Option<(usize, u64)> {
+        let mut stop = None;
+
+        for breakpoint in self.entries.iter_mut() {
+            let Location::Register { hart_id: watched, register, value } = &mut breakpoint.location else {
+                continue;
+            };
+
+            if *watched != hart_id {
+                continue;
+            }
+
+            let current = cpu.read_debug_register(*register).unwrap_or(*value);
+
+            if current == *value {
+                continue;
+            }
+
+            let old = std::mem::replace(value, current);
+
+            if !breakpoint.enabled {
+                continue;
+            }
+
+            breakpoint.hits += 1;
+
+            let holds = breakpoint.condition.is_none_or(|condition| condition.holds(cpu, breakpoint.hits));
+            if holds {
+                stop.get_or_insert((breakpoint.id, old));
+            }
+        }
+
+        stop
+    }
+
+    // Takes the current values of the registers watched on the hart without reporting changes, for when the
+    // debugger changed them
+    pub fn refresh_registers(&mut self, hart_id: usize, cpu: &RV64CPUContext) {
+        for breakpoint in self.entries.iter_mut() {
+            if let Location::Register { hart_id: watched, register, value } = &mut breakpoint.location {
+                if *watched == hart_id {
+                    *value = cpu.read_debug_register(*register).unwrap_or(*value);
+                }
+            }
+        }
+    }
+}
@@ -6,4 +6,11 @@ pub const GPR_COUNT: usize = 32;
 pub const SATP_MODE_BARE: u64 = 0;
 pub const SATP_MODE_SV32: u64 = 1;
 pub const SATP_MODE_SV39: u64 = 8;
-pub const SATP_MODE_SV48: u64 = 9;
\ No newline at end of file
+pub const SATP_MODE_SV48: u64 = 9;
+pub const SATP_MODE_SV57: u64 = 10;
+
+// Start of guest RAM in the physical address space, everything below is reserved for devices
+pub const DRAM_BASE: u64 = 0x8000_0000;
+
+pub const PAGE_SHIFT: u64 = 12;
+pub const PAGE_SIZE: u64 = 1 << PAGE_SHIFT;
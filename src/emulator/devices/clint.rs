@@ -0,0 +1,280 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use crate::emulator::devices::{read_pieces, write_pieces, RV64Device};
+use crate::emulator::state::interrupts::InterruptLines;
+use crate::emulator::state::memory::{Device, MemoryType};
+use crate::emulator::state::rv64_cpu_context::MIPFlags;
+
+pub const CLINT_BASE: usize = 0x0200_0000;
+pub const CLINT_SIZE: usize = 0x1_0000;
+
+// mtime ticks per second as advertised to the guest
+pub const TIMEBASE_FREQUENCY: u64 = 10_000_000;
+
+// Register layout of the SiFive CLINT
+const MSIP_OFFSET: usize = 0x0000;
+const MTIMECMP_OFFSET: usize = 0x4000;
+const MTIME_OFFSET: usize = 0xBFF8;
+
+// mtimecmp registers run into mtime after this many harts
+pub const CLINT_MAX_HARTS: u64 = ((MTIME_OFFSET - MTIMECMP_OFFSET) / 8) as u64;
+
+enum ClintRegister {
+    Msip(usize),
+    MTimeCmp(usize),
+    MTime,
+}
+
+// Register values of the CLINT, taken and put back by reverse execution
+#[derive(Debug, Clone)]
+pub struct ClintSnapshot {
+    mtime: u64,
+    mtimecmp: Vec<u64>,
+    msip: Vec<bool>,
+}
+
+// Timer and software interrupt state, shared between the MMIO device, the platform advancing the clock
+// and the harts reading the time CSR
+pub struct ClintState {
+    mtime: Arc<AtomicU64>,
+    msip: Vec<AtomicBool>,
+    mtimecmp: Vec<AtomicU64>,
+    timer_line: AtomicU64, //Pending bit driven by the comparators, MTIP unless M-mode is emulated
+    lines: Vec<Arc<InterruptLines>>,
+}
+
+impl ClintState {
+    pub fn new(lines: Vec<Arc<InterruptLines>>) -> Arc<Self> {
+        Arc::new(Self {
+            mtime: Arc::new(AtomicU64::new(0)),
+            msip: lines.iter().map(|_| AtomicBool::new(false)).collect(),
+            // No timer interrupt until the guest programs a deadline
+            mtimecmp: lines.iter().map(|_| AtomicU64::new(u64::MAX)).collect(),
+            timer_line: AtomicU64::new(MIPFlags::MTIP.bits()),
+            lines,
+        })
+    }
+
+    // Counter backing the time CSR of every hart
+    pub fn get_mtime(&self) -> Arc<AtomicU64> {
+        self.mtime.clone()
+    }
+
+    pub fn read_mtime(&self) -> u64 {
+        self.mtime.load(Ordering::SeqCst)
+    }
+
+    pub fn write_mtime(&self, value: u64) {
+        self.mtime.store(value, Ordering::SeqCst);
+        self.update_timers();
+    }
+
+    pub fn read_mtimecmp(&self, hart_id: usize) -> u64 {
+        self.mtimecmp[hart_id].load(Ordering::SeqCst)
+    }
+
+    pub fn write_mtimecmp(&self, hart_id: usize, value: u64) {
+        self.mtimecmp[hart_id].store(value, Ordering::SeqCst);
+        self.update_timer(hart_id);
+    }
+
+    pub fn read_msip(&self, hart_id: usize) -> bool {
+        self.msip[hart_id].load(Ordering::SeqCst)
+    }
+
+    pub fn write_msip(&self, hart_id: usize, pending: bool) {
+        self.msip[hart_id].store(pending, Ordering::SeqCst);
+        self.lines[hart_id].set(MIPFlags::MSIP, pending);
+    }
+
+    pub fn snapshot(&self) -> ClintSnapshot {
+        ClintSnapshot {
+            mtime: self.read_mtime(),
+            mtimecmp: (0..self.lines.len()).map(|hart_id| self.read_mtimecmp(hart_id)).collect(),
+            msip: (0..self.lines.len()).map(|hart_id| self.read_msip(hart_id)).collect(),
+        }
+    }
+
+    // Puts the registers back and drives the interrupt lines to match them
+    pub fn restore(&self, snapshot: &ClintSnapshot) {
+        for hart_id in 0..self.lines.len() {
+            self.mtimecmp[hart_id].store(snapshot.mtimecmp[hart_id], Ordering::SeqCst);
+            self.write_msip(hart_id, snapshot.msip[hart_id]);
+        }
+
+        self.write_mtime(snapshot.mtime);
+    }
+
+    // Advances mtime by one tick
+    pub fn tick(&self) {
+        self.mtime.fetch_add(1, Ordering::SeqCst);
+        self.update_timers();
+    }
+
+    // Jumps mtime forward to the closest timer deadline, used while every hart is waiting for an interrupt
+    pub fn skip_to_next_deadline(&self) {
+        let mtime = self.read_mtime();
+
+        let deadline = self.mtimecmp.iter()
+            .map(|mtimecmp| mtimecmp.load(Ordering::SeqCst))
+            .filter(|&mtimecmp| mtimecmp > mtime && mtimecmp != u64::MAX)
+            .min();
+
+        match deadline {
+            Some(deadline) => self.write_mtime(deadline),
+            None => self.tick(),
+        }
+    }
+
+    // Lets the comparators drive STIP directly, for when there is no M-mode firmware to forward the interrupt
+    pub fn route_timer_interrupt(&self, line: MIPFlags) {
+        let old = MIPFlags::from_bits_retain(self.timer_line.swap(line.bits(), Ordering::SeqCst));
+
+        for lines in self.lines.iter() {
+            lines.lower(old);
+        }
+
+        self.update_timers();
+    }
+
+    fn update_timer(&self, hart_id: usize) {
+        let expired = self.read_mtime() >= self.read_mtimecmp(hart_id);
+        let line = MIPFlags::from_bits_retain(self.timer_line.load(Ordering::SeqCst));
+
+        self.lines[hart_id].set(line, expired);
+    }
+
+    fn update_timers(&self) {
+        for hart_id in 0..self.lines.len() {
+            self.update_timer(hart_id);
+        }
+    }
+
+    fn decode(&self, addr: usize) -> Option<(ClintRegister, usize)> {
+        let harts = self.lines.len();
+
+        if (MTIME_OFFSET..MTIME_OFFSET + 8).contains(&addr) {
+            return Some((ClintRegister::MTime, addr - MTIME_OFFSET));
+        }
+
+        if (MTIMECMP_OFFSET..MTIMECMP_OFFSET + 8 * harts).contains(&addr) {
+            let offset = addr - MTIMECMP_OFFSET;
+            return Some((ClintRegister::MTimeCmp(offset / 8), offset % 8));
+        }
+
+        if (MSIP_OFFSET..MSIP_OFFSET + 4 * harts).contains(&addr) {
+            let offset = addr - MSIP_OFFSET;
+            return Some((ClintRegister::Msip(offset / 4), offset % 4));
+        }
+
+        None
+    }
+
+    // Registers can be accessed with any width, narrower accesses see a slice of the register
+    fn read_register(&self, addr: usize, size: usize) -> u64 {
+        let (register, offset) = match self.decode(addr) {
+            Some(decoded) => decoded,
+            None => return 0,
+        };
+
+        let value = match register {
+            ClintRegister::Msip(hart_id) => self.read_msip(hart_id) as u64,
+            ClintRegister::MTimeCmp(hart_id) => self.read_mtimecmp(hart_id),
+            ClintRegister::MTime => self.read_mtime(),
+        };
+
+        (value >> (offset * 8)) & size_mask(size)
+    }
+
+    fn write_register(&self, addr: usize, size: usize, value: u64) {
+        let (register, offset) = match self.decode(addr) {
+            Some(decoded) => decoded,
+            None => return,
+        };
+
+        let shift = offset * 8;
+        let mask = size_mask(size) << shift;
+        let merge = |old: u64| (old & !mask) | ((value << shift) & mask);
+
+        match register {
+            ClintRegister::Msip(hart_id) => self.write_msip(hart_id, merge(self.read_msip(hart_id) as u64) & 1 != 0),
+            ClintRegister::MTimeCmp(hart_id) => self.write_mtimecmp(hart_id, merge(self.read_mtimecmp(hart_id))),
+            ClintRegister::MTime => self.write_mtime(merge(self.read_mtime())),
+        }
+    }
+}
+
+fn size_mask(size: usize) -> u64 {
+    if size >= 8 { u64::MAX } else { (1 << (size * 8)) - 1 }
+}
+
+// Core-local interruptor providing msip, mtimecmp and mtime to the harts
+pub struct Clint {
+    state: Arc<ClintState>,
+}
+
+impl Clint {
+    pub fn new(state: Arc<ClintState>) -> Clint {
+        Self { state }
+    }
+}
+
+impl RV64Device for Clint {
+    fn init(&self) {
+
+    }
+
+    fn destroy(&self) {
+
+    }
+}
+
+impl Device for Clint {
+    fn read_byte(&self, addr: usize) -> u8 {
+        self.state.read_register(addr, 1) as u8
+    }
+
+    fn write_byte(&mut self, addr: usize, value: u8) {
+        self.state.write_register(addr, 1, value as u64);
+    }
+
+    fn read_half_word(&self, addr: usize) -> u16 {
+        self.state.read_register(addr, 2) as u16
+    }
+
+    fn write_half_word(&mut self, addr: usize, value: u16) {
+        self.state.write_register(addr, 2, value as u64);
+    }
+
+    fn read_word(&self, addr: usize) -> u32 {
+        self.state.read_register(addr, 4) as u32
+    }
+
+    fn write_word(&mut self, addr: usize, value: u32) {
+        self.state.write_register(addr, 4, value as u64);
+    }
+
+    fn read_double_word(&self, addr: usize) -> u64 {
+        self.state.read_register(addr, 8)
+    }
+
+    fn write_double_word(&mut self, addr: usize, value: u64) {
+        self.state.write_register(addr, 8, value);
+    }
+
+    fn write(&mut self, addr: usize, len: usize, value: &[u8]) {
+        write_pieces(addr, &value[..len], 4, |addr, size, value| self.state.write_register(addr, size, value));
+    }
+
+    fn read(&self, addr: usize, len: usize, buf: &mut [u8]) {
+        read_pieces(addr, &mut buf[..len], 4, |addr, size| self.state.read_register(addr, size));
+    }
+
+    fn size(&self) -> u64 {
+        CLINT_SIZE as u64
+    }
+
+    fn memory_type(&self) -> MemoryType {
+        MemoryType::IO
+    }
+}
@@ -2,6 +2,9 @@ use std::sync::Arc;
 use crate::emulator::state::memory::Device;
 
 pub mod simple_fb;
+pub mod clint;
+pub mod plic;
+pub mod uart;
 
 trait RV64Device {
     fn init(&self);
@@ -12,4 +15,30 @@ trait RV64Device {
 trait FramebufferDevice {
     fn read(&mut self, addr: usize, len: usize, buf: &mut [u8]);
     fn write(&mut self, addr: usize, len: usize, value: &[u8]);
-}
\ No newline at end of file
+}
+
+// Splits a bulk access, e.g. from the debugger, into naturally aligned pieces no wider than width bytes, so every
+// register is accessed once with the width the device expects. The callbacks take the address and size of a piece
+pub(crate) fn read_pieces(addr: usize, buf: &mut [u8], width: usize, mut read: impl FnMut(usize, usize) -> u64) {
+    let mut offset = 0;
+
+    while offset < buf.len() {
+        let size = (width - (addr + offset) % width).min(buf.len() - offset);
+
+        buf[offset..offset + size].copy_from_slice(&read(addr + offset, size).to_le_bytes()[..size]);
+        offset += size;
+    }
+}
+
+pub(crate) fn write_pieces(addr: usize, value: &[u8], width: usize, mut write: impl FnMut(usize, usize, u64)) {
+    let mut offset = 0;
+
+    while offset < value.len() {
+        let size = (width - (addr + offset) % width).min(value.len() - offset);
+        let mut bytes = [0; 8];
+
+        bytes[..size].copy_from_slice(&value[offset..offset + size]);
+        write(addr + offset, size, u64::from_le_bytes(bytes));
+        offset += size;
+    }
+}
@@ -0,0 +1,369 @@
+use std::sync::{Arc, Mutex};
+use crate::emulator::devices::{read_pieces, write_pieces, RV64Device};
+use crate::emulator::state::interrupts::InterruptLines;
+use crate::emulator::state::memory::{Device, MemoryType};
+use crate::emulator::state::rv64_cpu_context::MIPFlags;
+
+pub const PLIC_BASE: usize = 0x0C00_0000;
+pub const PLIC_SIZE: usize = 0x60_0000;
+
+// Interrupt source ids 1 to PLIC_SOURCES - 1 are usable, 0 means "no interrupt"
+pub const PLIC_SOURCES: usize = 96;
+
+const PRIORITY_MASK: u32 = 0b111;
+
+// Register layout of the SiFive PLIC
+const PRIORITY_OFFSET: usize = 0x0000;
+const PENDING_OFFSET: usize = 0x1000;
+const ENABLE_OFFSET: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+const CONTEXT_OFFSET: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+
+const SOURCE_WORDS: usize = PLIC_SOURCES.div_ceil(32);
+
+// Each hart has a machine mode context followed by a supervisor mode context
+const CONTEXTS_PER_HART: usize = 2;
+
+#[derive(Clone)]
+struct PlicRegisters {
+    priority: Vec<u32>,
+    level: Vec<bool>, //Current state of the device lines
+    pending: Vec<bool>,
+    claimed: Vec<bool>, //Claimed by a context and not completed yet
+    enable: Vec<[u32; SOURCE_WORDS]>,
+    threshold: Vec<u32>,
+}
+
+impl PlicRegisters {
+    fn is_enabled(&self, context: usize, source: usize) -> bool {
+        (self.enable[context][source / 32] >> (source % 32)) & 1 != 0
+    }
+
+    // Highest priority pending source above the context's threshold, ties go to the lowest id
+    fn best_source(&self, context: usize) -> Option<usize> {
+        let mut best: Option<usize> = None;
+
+        for source in 1..PLIC_SOURCES {
+            if !self.pending[source] || !self.is_enabled(context, source) || self.priority[source] <= self.threshold[context] {
+                continue;
+            }
+
+            if best.is_none_or(|best| self.priority[source] > self.priority[best]) {
+                best = Some(source);
+            }
+        }
+
+        best
+    }
+}
+
+// Register values of the PLIC, taken and put back by reverse execution
+#[derive(Clone)]
+pub struct PlicSnapshot {
+    registers: PlicRegisters,
+}
+
+// Interrupt controller state, shared between the MMIO device and the devices raising interrupts
+pub struct PlicState {
+    registers: Mutex<PlicRegisters>,
+    lines: Vec<Arc<InterruptLines>>,
+}
+
+impl PlicState {
+    pub fn new(lines: Vec<Arc<InterruptLines>>) -> Arc<Self> {
+        let contexts = lines.len() * CONTEXTS_PER_HART;
+
+        Arc::new(Self {
+            registers: Mutex::new(PlicRegisters {
+                priority: vec![0; PLIC_SOURCES],
+                level: vec![false; PLIC_SOURCES],
+                pending: vec![false; PLIC_SOURCES],
+                claimed: vec![false; PLIC_SOURCES],
+                enable: vec![[0; SOURCE_WORDS]; contexts],
+                threshold: vec![0; contexts],
+            }),
+            lines,
+        })
+    }
+
+    // Level triggered gateway, a source is only pending again after its previous interrupt was completed
+    pub fn set_irq(&self, source: usize, level: bool) {
+        if source == 0 || source >= PLIC_SOURCES {
+            return;
+        }
+
+        let mut registers = self.registers.lock().unwrap();
+
+        registers.level[source] = level;
+
+        if level && !registers.claimed[source] {
+            registers.pending[source] = true;
+        } else if !level {
+            registers.pending[source] = false;
+        }
+
+        self.update(&registers);
+    }
+
+    pub fn claim(&self, context: usize) -> u32 {
+        let mut registers = self.registers.lock().unwrap();
+
+        let source = match registers.best_source(context) {
+            Some(source) => source,
+            None => return 0,
+        };
+
+        registers.pending[source] = false;
+        registers.claimed[source] = true;
+
+        self.update(&registers);
+
+        source as u32
+    }
+
+    pub fn complete(&self, context: usize, source: u32) {
+        let source = source as usize;
+
+        if source == 0 || source >= PLIC_SOURCES {
+            return;
+        }
+
+        let mut registers = self.registers.lock().unwrap();
+
+        // Completions for sources the context has not enabled are ignored
+        if !registers.is_enabled(context, source) || !registers.claimed[source] {
+            return;
+        }
+
+        registers.claimed[source] = false;
+
+        if registers.level[source] {
+            registers.pending[source] = true;
+        }
+
+        self.update(&registers);
+    }
+
+    pub fn snapshot(&self) -> PlicSnapshot {
+        PlicSnapshot { registers: self.registers.lock().unwrap().clone() }
+    }
+
+    // Puts the registers back and drives MEIP and SEIP to match them
+    pub fn restore(&self, snapshot: &PlicSnapshot) {
+        let mut registers = self.registers.lock().unwrap();
+        *registers = snapshot.registers.clone();
+
+        self.update(&registers);
+    }
+
+    // Drives MEIP and SEIP of every hart from the contexts' best pending source
+    fn update(&self, registers: &PlicRegisters) {
+        for (hart_id, lines) in self.lines.iter().enumerate() {
+            let context = hart_id * CONTEXTS_PER_HART;
+
+            lines.set(MIPFlags::MEIP, registers.best_source(context).is_some());
+            lines.set(MIPFlags::SEIP, registers.best_source(context + 1).is_some());
+        }
+    }
+
+    fn contexts(&self) -> usize {
+        self.lines.len() * CONTEXTS_PER_HART
+    }
+
+    // Reading the claim register claims the interrupt, unless it is only read to merge a partial write
+    fn read_register(&self, addr: usize, claim: bool) -> u32 {
+        if addr >= CONTEXT_OFFSET {
+            let context = (addr - CONTEXT_OFFSET) / CONTEXT_STRIDE;
+
+            if context >= self.contexts() {
+                return 0;
+            }
+
+            return match (addr - CONTEXT_OFFSET) % CONTEXT_STRIDE {
+                0 => self.registers.lock().unwrap().threshold[context],
+                4 if claim => self.claim(context),
+                _ => 0,
+            };
+        }
+
+        let registers = self.registers.lock().unwrap();
+
+        if addr >= ENABLE_OFFSET {
+            let context = (addr - ENABLE_OFFSET) / ENABLE_STRIDE;
+            let word = ((addr - ENABLE_OFFSET) % ENABLE_STRIDE) / 4;
+
+            if context >= self.contexts() || word >= SOURCE_WORDS {
+                return 0;
+            }
+
+            return registers.enable[context][word];
+        }
+
+        if addr >= PENDING_OFFSET {
+            let word = (addr - PENDING_OFFSET) / 4;
+
+            if word >= SOURCE_WORDS {
+                return 0;
+            }
+
+            return (0..32).filter(|bit| registers.pending.get(word * 32 + bit) == Some(&true))
+                .fold(0, |pending, bit| pending | (1 << bit));
+        }
+
+        registers.priority.get((addr - PRIORITY_OFFSET) / 4).copied().unwrap_or(0)
+    }
+
+    fn write_register(&self, addr: usize, value: u32) {
+        if addr >= CONTEXT_OFFSET {
+            let context = (addr - CONTEXT_OFFSET) / CONTEXT_STRIDE;
+
+            if context >= self.contexts() {
+                return;
+            }
+
+            match (addr - CONTEXT_OFFSET) % CONTEXT_STRIDE {
+                0 => {
+                    let mut registers = self.registers.lock().unwrap();
+                    registers.threshold[context] = value & PRIORITY_MASK;
+                    self.update(&registers);
+                }
+                4 => self.complete(context, value),
+                _ => {}
+            }
+
+            return;
+        }
+
+        let mut registers = self.registers.lock().unwrap();
+
+        if addr >= ENABLE_OFFSET {
+            let context = (addr - ENABLE_OFFSET) / ENABLE_STRIDE;
+            let word = ((addr - ENABLE_OFFSET) % ENABLE_STRIDE) / 4;
+
+            if context >= self.contexts() || word >= SOURCE_WORDS {
+                return;
+            }
+
+            // Source 0 does not exist
+            registers.enable[context][word] = if word == 0 { value & !1 } else { value };
+        } else if addr >= PENDING_OFFSET {
+            // Pending bits are read-only
+            return;
+        } else {
+            let source = (addr - PRIORITY_OFFSET) / 4;
+
+            if source == 0 || source >= PLIC_SOURCES {
+                return;
+            }
+
+            registers.priority[source] = value & PRIORITY_MASK;
+        }
+
+        self.update(&registers);
+    }
+}
+
+// Handle for a device to drive one PLIC interrupt source
+#[derive(Clone)]
+pub struct IrqLine {
+    plic: Arc<PlicState>,
+    source: usize,
+}
+
+impl IrqLine {
+    pub fn new(plic: Arc<PlicState>, source: usize) -> IrqLine {
+        Self { plic, source }
+    }
+
+    pub fn set(&self, level: bool) {
+        self.plic.set_irq(self.source, level);
+    }
+
+    pub fn source(&self) -> usize {
+        self.source
+    }
+}
+
+// Platform-level interrupt controller routing device interrupts to MEIP/SEIP of the harts
+pub struct Plic {
+    state: Arc<PlicState>,
+}
+
+impl Plic {
+    pub fn new(state: Arc<PlicState>) -> Plic {
+        Self { state }
+    }
+}
+
+impl RV64Device for Plic {
+    fn init(&self) {
+
+    }
+
+    fn destroy(&self) {
+
+    }
+}
+
+// All PLIC registers are 32 bits wide, narrower accesses see a slice of the register
+impl Device for Plic {
+    fn read_byte(&self, addr: usize) -> u8 {
+        (self.state.read_register(addr & !0b11, true) >> ((addr & 0b11) * 8)) as u8
+    }
+
+    fn write_byte(&mut self, addr: usize, value: u8) {
+        let shift = (addr & 0b11) * 8;
+        let old = self.state.read_register(addr & !0b11, false);
+        self.state.write_register(addr & !0b11, (old & !(0xFF << shift)) | ((value as u32) << shift));
+    }
+
+    fn read_half_word(&self, addr: usize) -> u16 {
+        (self.state.read_register(addr & !0b11, true) >> ((addr & 0b10) * 8)) as u16
+    }
+
+    fn write_half_word(&mut self, addr: usize, value: u16) {
+        let shift = (addr & 0b10) * 8;
+        let old = self.state.read_register(addr & !0b11, false);
+        self.state.write_register(addr & !0b11, (old & !(0xFFFF << shift)) | ((value as u32) << shift));
+    }
+
+    fn read_word(&self, addr: usize) -> u32 {
+        self.state.read_register(addr, true)
+    }
+
+    fn write_word(&mut self, addr: usize, value: u32) {
+        self.state.write_register(addr, value);
+    }
+
+    fn read_double_word(&self, addr: usize) -> u64 {
+        self.read_word(addr) as u64 | (self.read_word(addr + 4) as u64) << 32
+    }
+
+    fn write_double_word(&mut self, addr: usize, value: u64) {
+        self.write_word(addr, value as u32);
+        self.write_word(addr + 4, (value >> 32) as u32);
+    }
+
+    fn write(&mut self, addr: usize, len: usize, value: &[u8]) {
+        write_pieces(addr, &value[..len], 4, |addr, size, value| {
+            let shift = (addr & 0b11) * 8;
+            let mask = (u32::MAX >> (32 - size * 8)) << shift;
+            let old = self.state.read_register(addr & !0b11, false);
+
+            self.state.write_register(addr & !0b11, (old & !mask) | ((value as u32) << shift));
+        });
+    }
+
+    fn read(&self, addr: usize, len: usize, buf: &mut [u8]) {
+        read_pieces(addr, &mut buf[..len], 4, |addr, _size| (self.state.read_register(addr & !0b11, true) >> ((addr & 0b11) * 8)) as u64);
+    }
+
+    fn size(&self) -> u64 {
+        PLIC_SIZE as u64
+    }
+
+    fn memory_type(&self) -> MemoryType {
+        MemoryType::IO
+    }
+}
@@ -0,0 +1,467 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsFd, AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use bitflags::bitflags;
+use crate::emulator::devices::plic::IrqLine;
+use crate::emulator::devices::{read_pieces, write_pieces, RV64Device};
+use crate::emulator::state::memory::{Device, MemoryType};
+
+pub const UART_BASE: usize = 0x1000_0000;
+pub const UART_SIZE: usize = 0x100;
+pub const UART_IRQ: usize = 10;
+pub const UART_CLOCK_FREQUENCY: u64 = 3_686_400;
+
+// Register offsets, several registers share an offset depending on the access direction and LCR.DLAB
+const RBR_THR_DLL: usize = 0;
+const IER_DLM: usize = 1;
+const IIR_FCR: usize = 2;
+const LCR: usize = 3;
+const MCR: usize = 4;
+const LSR: usize = 5;
+const MSR: usize = 6;
+const SCR: usize = 7;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct IerFlags: u8 {
+        const RDI = 1 << 0;    // Received data available
+        const THRI = 1 << 1;   // Transmitter holding register empty
+        const RLSI = 1 << 2;   // Receiver line status
+        const MSI = 1 << 3;    // Modem status
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct LsrFlags: u8 {
+        const DR = 1 << 0;     // Data ready
+        const OE = 1 << 1;     // Overrun error
+        const THRE = 1 << 5;   // Transmitter holding register empty
+        const TEMT = 1 << 6;   // Transmitter empty
+    }
+}
+
+// Interrupt identification values, bit 0 clear means an interrupt is pending
+const IIR_NO_INTERRUPT: u8 = 0x01;
+const IIR_THRI: u8 = 0x02;
+const IIR_RDI: u8 = 0x04;
+const IIR_FIFO_ENABLED: u8 = 0xC0;
+
+const LCR_DLAB: u8 = 1 << 7;
+
+const FCR_ENABLE_FIFO: u8 = 1 << 0;
+const FCR_CLEAR_RX: u8 = 1 << 1;
+
+// How long the console thread waits for host input before checking whether it may still read it
+const CONSOLE_POLL_INTERVAL_MS: i32 = 20;
+
+// Whether fd has input or hit end of file within timeout_ms, without consuming anything
+fn wait_readable(fd: RawFd, timeout_ms: i32) -> bool {
+    let mut poll_fd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+
+    // Safe, poll only writes revents of the one entry it is given
+    unsafe { libc::poll(&mut poll_fd, 1, timeout_ms) > 0 }
+}
+
+// Where the guest's console goes
+#[derive(Debug, Clone)]
+pub enum UartBackend {
+    Stdio,
+    File(PathBuf), //Output only, for headless runs
+    UnixSocket(PathBuf), //Listens on the path, output is buffered until a client connects
+}
+
+impl FromStr for UartBackend {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once(':') {
+            None if value == "stdio" => Ok(UartBackend::Stdio),
+            Some(("file", path)) => Ok(UartBackend::File(PathBuf::from(path))),
+            Some(("unix", path)) => Ok(UartBackend::UnixSocket(PathBuf::from(path))),
+            _ => Err(format!("Invalid UART backend '{}', expected stdio, file:<path> or unix:<path>", value)),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct UartRegisters {
+    ier: IerFlags,
+    lcr: u8,
+    mcr: u8,
+    scr: u8,
+    fcr: u8,
+    divisor: u16,
+    rx: VecDeque<u8>,
+    thr_interrupt_pending: bool,
+}
+
+impl UartRegisters {
+    fn lsr(&self) -> LsrFlags {
+        // Transmission is instantaneous, the holding register is always empty
+        let mut lsr = LsrFlags::THRE | LsrFlags::TEMT;
+
+        if !self.rx.is_empty() {
+            lsr |= LsrFlags::DR;
+        }
+
+        lsr
+    }
+
+    fn iir(&self) -> u8 {
+        let fifo = if self.fcr & FCR_ENABLE_FIFO != 0 { IIR_FIFO_ENABLED } else { 0 };
+
+        if self.ier.contains(IerFlags::RDI) && !self.rx.is_empty() {
+            fifo | IIR_RDI
+        } else if self.ier.contains(IerFlags::THRI) && self.thr_interrupt_pending {
+            fifo | IIR_THRI
+        } else {
+            fifo | IIR_NO_INTERRUPT
+        }
+    }
+}
+
+// Register values and receive FIFO of the UART, taken and put back by reverse execution
+#[derive(Clone)]
+pub struct UartSnapshot {
+    registers: UartRegisters,
+}
+
+enum UartOutput {
+    Stdout,
+    File(File),
+    Socket { stream: Option<UnixStream>, buffer: Vec<u8> },
+}
+
+impl UartOutput {
+    fn write_byte(&mut self, byte: u8) {
+        // A console that went away must not take the guest down with it
+        let _ = match self {
+            UartOutput::Stdout => io::stdout().write_all(&[byte]).and_then(|_| io::stdout().flush()),
+            UartOutput::File(file) => file.write_all(&[byte]),
+            UartOutput::Socket { stream: Some(stream), .. } => stream.write_all(&[byte]),
+            UartOutput::Socket { stream: None, buffer } => {
+                buffer.push(byte);
+                Ok(())
+            }
+        };
+    }
+}
+
+// Register state of the UART, shared with the thread feeding host input into the receive FIFO
+pub struct UartState {
+    registers: Mutex<UartRegisters>,
+    output: Mutex<UartOutput>,
+    irq: IrqLine,
+    // The debugger prompt reads the same terminal, the console only takes host input while this is set
+    console_input: Mutex<bool>,
+    console_input_changed: Condvar,
+}
+
+impl UartState {
+    pub fn new(irq: IrqLine, backend: &UartBackend) -> io::Result<Arc<Self>> {
+        let output = match backend {
+            UartBackend::Stdio => UartOutput::Stdout,
+            UartBackend::File(path) => UartOutput::File(File::create(path)?),
+            UartBackend::UnixSocket(_) => UartOutput::Socket { stream: None, buffer: Vec::new() },
+        };
+
+        let state = Arc::new(Self {
+            registers: Mutex::new(UartRegisters {
+                ier: IerFlags::empty(),
+                lcr: 0,
+                mcr: 0,
+                scr: 0,
+                fcr: 0,
+                divisor: 0,
+                rx: VecDeque::new(),
+                thr_interrupt_pending: false,
+            }),
+            output: Mutex::new(output),
+            irq,
+            console_input: Mutex::new(false),
+            console_input_changed: Condvar::new(),
+        });
+
+        match backend {
+            UartBackend::Stdio => {
+                // A duplicate of fd 0, Stdin would buffer input the prompt then never sees
+                let stdin = File::from(io::stdin().as_fd().try_clone_to_owned()?);
+                state.attach_console(stdin);
+            }
+            UartBackend::UnixSocket(path) => {
+                let _ = std::fs::remove_file(path);
+                let listener = UnixListener::bind(path)?;
+                let uart = state.clone();
+                thread::spawn(move || uart.serve(listener));
+            }
+            UartBackend::File(_) => {}
+        }
+
+        Ok(state)
+    }
+
+    // Feeds what is read from input to the guest while console input is enabled
+    pub fn attach_console(self: &Arc<Self>, input: File) {
+        let uart = self.clone();
+        thread::spawn(move || uart.receive_console(input));
+    }
+
+    // Hands host console input to the guest, or leaves it to whoever else reads the terminal
+    pub fn set_console_input(&self, enabled: bool) {
+        *self.console_input.lock().unwrap() = enabled;
+        self.console_input_changed.notify_all();
+    }
+
+    // Queues host input for the guest
+    pub fn receive(&self, bytes: &[u8]) {
+        let mut registers = self.registers.lock().unwrap();
+        registers.rx.extend(bytes);
+        self.update_irq(&registers);
+    }
+
+    // Console access for firmware calls, bypassing the register interface
+    pub fn transmit(&self, bytes: &[u8]) {
+        let mut output = self.output.lock().unwrap();
+
+        for byte in bytes {
+            output.write_byte(*byte);
+        }
+    }
+
+    pub fn take_input(&self, max: usize) -> Vec<u8> {
+        let mut registers = self.registers.lock().unwrap();
+        let len = max.min(registers.rx.len());
+        let input = registers.rx.drain(..len).collect();
+
+        self.update_irq(&registers);
+
+        input
+    }
+
+    pub fn snapshot(&self) -> UartSnapshot {
+        UartSnapshot { registers: self.registers.lock().unwrap().clone() }
+    }
+
+    // Puts the registers back and drives the interrupt line to match them
+    pub fn restore(&self, snapshot: &UartSnapshot) {
+        let mut registers = self.registers.lock().unwrap();
+        *registers = snapshot.registers.clone();
+
+        self.update_irq(&registers);
+    }
+
+    fn receive_from(&self, mut input: impl Read) {
+        let mut buf = [0u8; 64];
+
+        loop {
+            match input.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(len) => self.receive(&buf[..len]),
+            }
+        }
+    }
+
+    // Input is only read when poll says it is there, so nothing is taken from the terminal while the
+    // console is disabled, not even a read that was already waiting
+    fn receive_console(&self, mut input: File) {
+        let mut buf = [0u8; 64];
+
+        loop {
+            drop(self.console_input_changed.wait_while(self.console_input.lock().unwrap(), |enabled| !*enabled).unwrap());
+
+            if !wait_readable(input.as_raw_fd(), CONSOLE_POLL_INTERVAL_MS) {
+                continue;
+            }
+
+            // Holding the lock, set_console_input(false) returns only once this read is done
+            let enabled = self.console_input.lock().unwrap();
+
+            if !*enabled {
+                continue;
+            }
+
+            match input.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(len) => self.receive(&buf[..len]),
+            }
+        }
+    }
+
+    // Accepts one client at a time, flushing whatever the guest printed while nobody was listening
+    fn serve(&self, listener: UnixListener) {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+
+            let reader = match stream.try_clone() {
+                Ok(reader) => reader,
+                Err(_) => continue,
+            };
+
+            if let UartOutput::Socket { stream: connected, buffer } = &mut *self.output.lock().unwrap() {
+                let _ = stream.write_all(buffer);
+                buffer.clear();
+                *connected = Some(stream);
+            }
+
+            self.receive_from(reader);
+
+            if let UartOutput::Socket { stream: connected, .. } = &mut *self.output.lock().unwrap() {
+                *connected = None;
+            }
+        }
+    }
+
+    fn update_irq(&self, registers: &UartRegisters) {
+        self.irq.set(registers.iir() & IIR_NO_INTERRUPT == 0);
+    }
+
+    fn read_register(&self, offset: usize) -> u8 {
+        let mut registers = self.registers.lock().unwrap();
+        let dlab = registers.lcr & LCR_DLAB != 0;
+
+        let value = match offset {
+            RBR_THR_DLL if dlab => registers.divisor as u8,
+            RBR_THR_DLL => registers.rx.pop_front().unwrap_or(0),
+            IER_DLM if dlab => (registers.divisor >> 8) as u8,
+            IER_DLM => registers.ier.bits(),
+            IIR_FCR => {
+                let iir = registers.iir();
+
+                // Reading the IIR acknowledges a transmitter empty interrupt
+                if iir & 0x0F == IIR_THRI {
+                    registers.thr_interrupt_pending = false;
+                }
+
+                iir
+            }
+            LCR => registers.lcr,
+            MCR => registers.mcr,
+            LSR => registers.lsr().bits(),
+            MSR => 0xB0, //DCD, DSR and CTS asserted
+            SCR => registers.scr,
+            _ => 0,
+        };
+
+        self.update_irq(&registers);
+
+        value
+    }
+
+    fn write_register(&self, offset: usize, value: u8) {
+        let mut registers = self.registers.lock().unwrap();
+        let dlab = registers.lcr & LCR_DLAB != 0;
+
+        match offset {
+            RBR_THR_DLL if dlab => registers.divisor = (registers.divisor & 0xFF00) | value as u16,
+            RBR_THR_DLL => {
+                self.output.lock().unwrap().write_byte(value);
+                registers.thr_interrupt_pending = true;
+            }
+            IER_DLM if dlab => registers.divisor = (registers.divisor & 0x00FF) | ((value as u16) << 8),
+            IER_DLM => {
+                let ier = IerFlags::from_bits_truncate(value);
+
+                // Enabling the transmitter interrupt fires right away since the holding register is empty
+                if ier.contains(IerFlags::THRI) && !registers.ier.contains(IerFlags::THRI) {
+                    registers.thr_interrupt_pending = true;
+                }
+
+                registers.ier = ier;
+            }
+            IIR_FCR => {
+                if value & FCR_CLEAR_RX != 0 {
+                    registers.rx.clear();
+                }
+
+                registers.fcr = value;
+            }
+            LCR => registers.lcr = value,
+            MCR => registers.mcr = value,
+            SCR => registers.scr = value,
+            _ => {}
+        }
+
+        self.update_irq(&registers);
+    }
+}
+
+// NS16550A compatible UART with byte wide registers
+pub struct Uart {
+    state: Arc<UartState>,
+}
+
+impl Uart {
+    pub fn new(state: Arc<UartState>) -> Uart {
+        Self { state }
+    }
+}
+
+impl RV64Device for Uart {
+    fn init(&self) {
+
+    }
+
+    fn destroy(&self) {
+
+    }
+}
+
+impl Device for Uart {
+    fn read_byte(&self, addr: usize) -> u8 {
+        self.state.read_register(addr)
+    }
+
+    fn write_byte(&mut self, addr: usize, value: u8) {
+        self.state.write_register(addr, value);
+    }
+
+    fn read_half_word(&self, addr: usize) -> u16 {
+        self.read_byte(addr) as u16
+    }
+
+    fn write_half_word(&mut self, addr: usize, value: u16) {
+        self.write_byte(addr, value as u8);
+    }
+
+    fn read_word(&self, addr: usize) -> u32 {
+        self.read_byte(addr) as u32
+    }
+
+    fn write_word(&mut self, addr: usize, value: u32) {
+        self.write_byte(addr, value as u8);
+    }
+
+    fn read_double_word(&self, addr: usize) -> u64 {
+        self.read_byte(addr) as u64
+    }
+
+    fn write_double_word(&mut self, addr: usize, value: u64) {
+        self.write_byte(addr, value as u8);
+    }
+
+    fn write(&mut self, addr: usize, len: usize, value: &[u8]) {
+        write_pieces(addr, &value[..len], 1, |addr, _size, value| self.state.write_register(addr, value as u8));
+    }
+
+    fn read(&self, addr: usize, len: usize, buf: &mut [u8]) {
+        read_pieces(addr, &mut buf[..len], 1, |addr, _size| self.state.read_register(addr) as u64);
+    }
+
+    fn size(&self) -> u64 {
+        UART_SIZE as u64
+    }
+
+    fn memory_type(&self) -> MemoryType {
+        MemoryType::IO
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use device_tree::DeviceTree;
+
+const FDT_MAGIC: u32 = 0xD00D_FEED;
+const FDT_VERSION: u32 = 17;
+const FDT_LAST_COMPATIBLE_VERSION: u32 = 16;
+
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_END: u32 = 9;
+
+const FDT_HEADER_SIZE: usize = 40;
+
+#[derive(Debug)]
+pub enum DtbError {
+    Parse(String),
+    TooLarge { size: usize },
+}
+
+impl std::fmt::Display for DtbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DtbError::Parse(e) => write!(f, "invalid device tree blob: {}", e),
+            DtbError::TooLarge { size } => write!(f, "device tree blob of {:#x} bytes does not fit into guest memory", size),
+        }
+    }
+}
+
+// Device tree node, properties keep their raw big endian encoding
+#[derive(Debug, Clone, Default)]
+pub struct DtbNode {
+    pub name: String,
+    pub properties: Vec<(String, Vec<u8>)>,
+    pub children: Vec<DtbNode>,
+}
+
+impl DtbNode {
+    pub fn new(name: &str) -> DtbNode {
+        Self { name: name.to_string(), properties: Vec::new(), children: Vec::new() }
+    }
+
+    // Replaces an existing property of the same name, so the tree never holds duplicates
+    pub fn set_property(&mut self, name: &str, value: Vec<u8>) {
+        match self.properties.iter_mut().find(|(property, _)| property == name) {
+            Some((_, old)) => *old = value,
+            None => self.properties.push((name.to_string(), value)),
+        }
+    }
+
+    pub fn set_empty(&mut self, name: &str) {
+        self.set_property(name, Vec::new());
+    }
+
+    pub fn set_u32(&mut self, name: &str, value: u32) {
+        self.set_cells(name, &[value]);
+    }
+
+    pub fn set_u64(&mut self, name: &str, value: u64) {
+        self.set_cells(name, &[(value >> 32) as u32, value as u32]);
+    }
+
+    pub fn set_cells(&mut self, name: &str, cells: &[u32]) {
+        self.set_property(name, cells.iter().flat_map(|cell| cell.to_be_bytes()).collect());
+    }
+
+    pub fn set_string(&mut self, name: &str, value: &str) {
+        self.set_strings(name, &[value]);
+    }
+
+    pub fn set_strings(&mut self, name: &str, values: &[&str]) {
+        self.set_property(name, values.iter().flat_map(|value| value.bytes().chain([0])).collect());
+    }
+
+    pub fn get_property(&self, name: &str) -> Option<&[u8]> {
+        self.properties.iter().find(|(property, _)| property == name).map(|(_, value)| value.as_slice())
+    }
+
+    pub fn remove_property(&mut self, name: &str) {
+        self.properties.retain(|(property, _)| property != name);
+    }
+
+    pub fn add_child(&mut self, child: DtbNode) -> &mut DtbNode {
+        self.children.push(child);
+        self.children.last_mut().unwrap()
+    }
+
+    // Looks up a node by its absolute path like "/soc/serial@10000000"
+    pub fn find(&self, path: &str) -> Option<&DtbNode> {
+        path.split('/').filter(|name| !name.is_empty())
+            .try_fold(self, |node, name| node.children.iter().find(|child| child.name == name))
+    }
+
+    pub fn find_mut(&mut self, path: &str) -> Option<&mut DtbNode> {
+        path.split('/').filter(|name| !name.is_empty())
+            .try_fold(self, |node, name| node.children.iter_mut().find(|child| child.name == name))
+    }
+
+    // Returns the named child, creating it if the tree does not have one yet
+    pub fn child_mut(&mut self, name: &str) -> &mut DtbNode {
+        match self.children.iter().position(|child| child.name == name) {
+            Some(index) => &mut self.children[index],
+            None => self.add_child(DtbNode::new(name)),
+        }
+    }
+}
+
+impl From<device_tree::Node> for DtbNode {
+    fn from(node: device_tree::Node) -> Self {
+        Self {
+            name: node.name,
+            properties: node.props,
+            children: node.children.into_iter().map(DtbNode::from).collect(),
+        }
+    }
+}
+
+// Parses a user supplied blob so the platform can amend it before handing it to the guest
+pub fn parse_blob(blob: &[u8]) -> Result<DtbNode, DtbError> {
+    let tree = DeviceTree::load(blob).map_err(|e| DtbError::Parse(format!("{:?}", e)))?;
+
+    Ok(DtbNode::from(tree.root))
+}
+
+struct FdtWriter {
+    structure: Vec<u8>,
+    strings: Vec<u8>,
+    string_offsets: HashMap<String, u32>,
+}
+
+impl FdtWriter {
+    fn token(&mut self, token: u32) {
+        self.structure.extend_from_slice(&token.to_be_bytes());
+    }
+
+    fn align(&mut self) {
+        while !self.structure.len().is_multiple_of(4) {
+            self.structure.push(0);
+        }
+    }
+
+    // Property names are stored once in the strings block and referenced by offset
+    fn string_offset(&mut self, name: &str) -> u32 {
+        if let Some(offset) = self.string_offsets.get(name) {
+            return *offset;
+        }
+
+        let offset = self.strings.len() as u32;
+        self.strings.extend_from_slice(name.as_bytes());
+        self.strings.push(0);
+        self.string_offsets.insert(name.to_string(), offset);
+
+        offset
+    }
+
+    fn node(&mut self, node: &DtbNode) {
+        self.token(FDT_BEGIN_NODE);
+        self.structure.extend_from_slice(node.name.as_bytes());
+        self.structure.push(0);
+        self.align();
+
+        for (name, value) in node.properties.iter() {
+            let name_offset = self.string_offset(name);
+
+            self.token(FDT_PROP);
+            self.token(value.len() as u32);
+            self.token(name_offset);
+            self.structure.extend_from_slice(value);
+            self.align();
+        }
+
+        for child in node.children.iter() {
+            self.node(child);
+        }
+
+        self.token(FDT_END_NODE);
+    }
+}
+
+// Flattens the tree into a version 17 blob without memory reservations
+pub fn to_blob(root: &DtbNode, boot_cpuid: u32) -> Vec<u8> {
+    let mut writer = FdtWriter { structure: Vec::new(), strings: Vec::new(), string_offsets: HashMap::new() };
+
+    writer.node(root);
+    writer.token(FDT_END);
+
+    // Header, then the empty reservation map terminated by a zero entry
+    let reserve_offset = FDT_HEADER_SIZE;
+    let structure_offset = reserve_offset + 16;
+    let strings_offset = structure_offset + writer.structure.len();
+    let total_size = strings_offset + writer.strings.len();
+
+    let header = [
+        FDT_MAGIC,
+        total_size as u32,
+        structure_offset as u32,
+        strings_offset as u32,
+        reserve_offset as u32,
+        FDT_VERSION,
+        FDT_LAST_COMPATIBLE_VERSION,
+        boot_cpuid,
+        writer.strings.len() as u32,
+        writer.structure.len() as u32,
+    ];
+
+    let mut blob: Vec<u8> = header.iter().flat_map(|field| field.to_be_bytes()).collect();
+    blob.extend_from_slice(&[0; 16]);
+    blob.extend_from_slice(&writer.structure);
+    blob.extend_from_slice(&writer.strings);
+
+    blob
+}
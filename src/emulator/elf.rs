@@ -0,0 +1,336 @@
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use crate::emulator::state::memory::MemoryManagementUnit;
+
+pub const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+
+const ET_EXEC: u16 = 2;
+const ET_DYN: u16 = 3;
+const EM_RISCV: u16 = 243;
+
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+const SHN_UNDEF: u16 = 0;
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+const SHDR_SIZE: usize = 64;
+const SYM_SIZE: usize = 24;
+
+#[derive(Debug)]
+pub enum ElfError {
+    Io(std::io::Error),
+    Truncated,
+    BadMagic,
+    UnsupportedClass(u8),
+    UnsupportedEncoding(u8),
+    UnsupportedType(u16),
+    UnsupportedMachine(u16),
+    SegmentNotMapped { paddr: u64, size: u64 },
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ElfError::Io(e) => write!(f, "failed to read ELF file: {}", e),
+            ElfError::Truncated => write!(f, "ELF file is truncated"),
+            ElfError::BadMagic => write!(f, "not an ELF file"),
+            ElfError::UnsupportedClass(class) => write!(f, "unsupported ELF class {}, expected ELFCLASS64", class),
+            ElfError::UnsupportedEncoding(data) => write!(f, "unsupported ELF data encoding {}, expected little endian", data),
+            ElfError::UnsupportedType(kind) => write!(f, "unsupported ELF type {}, expected an executable", kind),
+            ElfError::UnsupportedMachine(machine) => write!(f, "unsupported ELF machine {}, expected EM_RISCV", machine),
+            ElfError::SegmentNotMapped { paddr, size } => write!(f, "segment at {:#x} with size {:#x} is outside of guest memory", paddr, size),
+        }
+    }
+}
+
+impl From<std::io::Error> for ElfError {
+    fn from(e: std::io::Error) -> Self {
+        ElfError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Object,
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u64,
+    pub size: u64,
+    pub kind: SymbolKind,
+}
+
+// Symbols sorted by address, used to turn addresses back into names
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new(mut symbols: Vec<Symbol>) -> SymbolTable {
+        symbols.sort_by_key(|symbol| symbol.address);
+        Self { symbols }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    // Closest symbol at or below addr and the offset into it, sized symbols must contain addr
+    pub fn lookup(&self, addr: u64) -> Option<(&Symbol, u64)> {
+        let index = self.symbols.partition_point(|symbol| symbol.address <= addr);
+        let symbol = self.symbols[..index].last()?;
+        let offset = addr - symbol.address;
+
+        if symbol.size != 0 && offset >= symbol.size {
+            return None;
+        }
+
+        Some((symbol, offset))
+    }
+
+    // Formats an address as name+offset for the debugger and tracer
+    pub fn describe(&self, addr: u64) -> Option<String> {
+        self.lookup(addr).map(|(symbol, offset)| match offset {
+            0 => symbol.name.clone(),
+            _ => format!("{}+{:#x}", symbol.name, offset),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ElfSegment {
+    pub vaddr: u64,
+    pub paddr: u64,
+    pub offset: u64,
+    pub file_size: u64,
+    pub memory_size: u64,
+    pub flags: u32,
+}
+
+impl ElfSegment {
+    pub fn is_executable(&self) -> bool {
+        self.flags & PF_X != 0
+    }
+}
+
+pub struct ElfImage {
+    pub entry: u64,
+    pub segments: Vec<ElfSegment>,
+    pub symbols: SymbolTable,
+    data: Vec<u8>,
+}
+
+fn read_bytes(data: &[u8], offset: u64, len: u64) -> Result<&[u8], ElfError> {
+    let start = usize::try_from(offset).map_err(|_| ElfError::Truncated)?;
+    let end = start.checked_add(usize::try_from(len).map_err(|_| ElfError::Truncated)?).ok_or(ElfError::Truncated)?;
+
+    data.get(start..end).ok_or(ElfError::Truncated)
+}
+
+// File offset of entry index of a header table, offsets come from the file and can point anywhere
+fn table_entry(offset: u64, index: u64, entry_size: usize) -> Result<u64, ElfError> {
+    index.checked_mul(entry_size as u64).and_then(|entry| offset.checked_add(entry)).ok_or(ElfError::Truncated)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+pub fn is_elf(data: &[u8]) -> bool {
+    data.starts_with(&ELF_MAGIC)
+}
+
+// Peeks at the magic so callers can fall back to loading a raw image
+pub fn is_elf_file(path: &Path) -> bool {
+    let mut magic = [0u8; 4];
+
+    match fs::File::open(path) {
+        Ok(mut file) => file.read_exact(&mut magic).is_ok() && is_elf(&magic),
+        Err(_) => false,
+    }
+}
+
+impl ElfImage {
+    pub fn from_file(path: &Path) -> Result<ElfImage, ElfError> {
+        Self::parse(fs::read(path)?)
+    }
+
+    pub fn parse(data: Vec<u8>) -> Result<ElfImage, ElfError> {
+        let header = read_bytes(&data, 0, EHDR_SIZE as u64)?;
+
+        if !is_elf(header) {
+            return Err(ElfError::BadMagic);
+        }
+
+        if header[4] != ELFCLASS64 {
+            return Err(ElfError::UnsupportedClass(header[4]));
+        }
+
+        if header[5] != ELFDATA2LSB {
+            return Err(ElfError::UnsupportedEncoding(header[5]));
+        }
+
+        let kind = read_u16(header, 16);
+        if kind != ET_EXEC && kind != ET_DYN {
+            return Err(ElfError::UnsupportedType(kind));
+        }
+
+        let machine = read_u16(header, 18);
+        if machine != EM_RISCV {
+            return Err(ElfError::UnsupportedMachine(machine));
+        }
+
+        let entry = read_u64(header, 24);
+        let phoff = read_u64(header, 32);
+        let shoff = read_u64(header, 40);
+        let phnum = read_u16(header, 56) as u64;
+        let shnum = read_u16(header, 60) as u64;
+
+        let mut segments = Vec::new();
+
+        for i in 0..phnum {
+            let phdr = read_bytes(&data, table_entry(phoff, i, PHDR_SIZE)?, PHDR_SIZE as u64)?;
+
+            if read_u32(phdr, 0) != PT_LOAD {
+                continue;
+            }
+
+            let segment = ElfSegment {
+                flags: read_u32(phdr, 4),
+                offset: read_u64(phdr, 8),
+                vaddr: read_u64(phdr, 16),
+                paddr: read_u64(phdr, 24),
+                file_size: read_u64(phdr, 32),
+                memory_size: read_u64(phdr, 40),
+            };
+
+            // Make sure the file contents of the segment are actually there and fit into it
+            read_bytes(&data, segment.offset, segment.file_size)?;
+
+            if segment.file_size > segment.memory_size {
+                return Err(ElfError::Truncated);
+            }
+
+            segments.push(segment);
+        }
+
+        let symbols = Self::parse_symbols(&data, shoff, shnum)?;
+
+        Ok(ElfImage { entry, segments, symbols, data })
+    }
+
+    fn parse_symbols(data: &[u8], shoff: u64, shnum: u64) -> Result<SymbolTable, ElfError> {
+        let mut symbols = Vec::new();
+
+        for i in 0..shnum {
+            let shdr = read_bytes(data, table_entry(shoff, i, SHDR_SIZE)?, SHDR_SIZE as u64)?;
+
+            if read_u32(shdr, 4) != SHT_SYMTAB {
+                continue;
+            }
+
+            let table = read_bytes(data, read_u64(shdr, 24), read_u64(shdr, 32))?;
+
+            // sh_link points at the string table holding the symbol names
+            let strtab_index = read_u32(shdr, 40) as u64;
+            let strtab_header = read_bytes(data, table_entry(shoff, strtab_index, SHDR_SIZE)?, SHDR_SIZE as u64)?;
+            let strtab = read_bytes(data, read_u64(strtab_header, 24), read_u64(strtab_header, 32))?;
+
+            for sym in table.chunks_exact(SYM_SIZE) {
+                let name_offset = read_u32(sym, 0) as usize;
+                let kind = sym[4] & 0xF;
+                let section = read_u16(sym, 6);
+
+                if name_offset == 0 || section == SHN_UNDEF || name_offset >= strtab.len() {
+                    continue;
+                }
+
+                let kind = match kind {
+                    STT_FUNC => SymbolKind::Function,
+                    STT_OBJECT => SymbolKind::Object,
+                    0 => SymbolKind::Other, //Assembler labels have no type
+                    _ => continue, //Section and file symbols
+                };
+
+                let name = &strtab[name_offset..];
+                let name = &name[..name.iter().position(|&c| c == 0).unwrap_or(name.len())];
+
+                symbols.push(Symbol {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    address: read_u64(sym, 8),
+                    size: read_u64(sym, 16),
+                    kind,
+                });
+            }
+        }
+
+        Ok(SymbolTable::new(symbols))
+    }
+
+    // Entry point as a physical address, for images linked at a different virtual address
+    pub fn physical_entry(&self) -> u64 {
+        self.segments.iter()
+            .find(|segment| self.entry >= segment.vaddr && self.entry - segment.vaddr < segment.memory_size)
+            .map(|segment| self.entry - segment.vaddr + segment.paddr)
+            .unwrap_or(self.entry)
+    }
+
+    // Contents of the segment stored in the file, without the zero filled tail
+    pub fn segment_data(&self, segment: &ElfSegment) -> Result<&[u8], ElfError> {
+        read_bytes(&self.data, segment.offset, segment.file_size.min(segment.memory_size))
+    }
+
+    // Copies every PT_LOAD segment to its physical address and zeroes the rest of it (.bss)
+    pub fn load(&self, mmu: &mut MemoryManagementUnit) -> Result<(), ElfError> {
+        for segment in self.segments.iter() {
+            if segment.memory_size == 0 {
+                continue;
+            }
+
+            if !mmu.is_mapped(segment.paddr as usize, segment.memory_size as usize) {
+                return Err(ElfError::SegmentNotMapped { paddr: segment.paddr, size: segment.memory_size });
+            }
+
+            let contents = self.segment_data(segment)?;
+            mmu.write(segment.paddr as usize, contents.len(), contents);
+
+            let bss_size = (segment.memory_size - contents.len() as u64) as usize;
+            if bss_size > 0 {
+                mmu.write(segment.paddr as usize + contents.len(), bss_size, &vec![0; bss_size]);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,729 @@
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::str::FromStr;
+use crate::emulator::history::ReplayStop;
+use crate::emulator::interpreter::RV64Platform;
+use crate::emulator::state::memory::{WatchKind, Watchpoint};
+use crate::emulator::state::rv64_cpu_context::{CSRAddress, PrivilegeMode, F_ABI_NAMES, X_ABI_NAMES};
+
+// gdb's register numbering for RISC-V, CSRs follow the FPRs at their address plus CSR_REGNUM
+const PC_REGNUM: usize = 32;
+const FPR_REGNUM: usize = 33;
+const CSR_REGNUM: usize = 65;
+const PRIV_REGNUM: usize = CSR_REGNUM + 4096;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+const PACKET_SIZE: usize = 0x4000;
+
+// Rounds the machine runs between checks for a Ctrl-C from gdb
+const INTERRUPT_POLL_ROUNDS: usize = 4096;
+
+// Where the stub listens for gdb
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GdbTarget {
+    Tcp(String),
+    UnixSocket(PathBuf),
+}
+
+impl FromStr for GdbTarget {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once(':') {
+            Some(("unix", path)) => Ok(GdbTarget::UnixSocket(PathBuf::from(path))),
+            Some(("tcp", addr)) => Ok(GdbTarget::Tcp(addr.to_string())),
+            Some((_, port)) if port.parse::<u16>().is_ok() => Ok(GdbTarget::Tcp(value.to_string())),
+            None if value.parse::<u16>().is_ok() => Ok(GdbTarget::Tcp(format!("127.0.0.1:{}", value))),
+            _ => Err(format!("Invalid gdb target '{}', expected <port>, <host>:<port> or unix:<path>", value)),
+        }
+    }
+}
+
+pub(crate) trait Connection: Read + Write {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        TcpStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+impl Connection for UnixStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        UnixStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+fn accept(target: &GdbTarget) -> io::Result<Box<dyn Connection>> {
+    match target {
+        GdbTarget::Tcp(addr) => {
+            let listener = TcpListener::bind(addr)?;
+            println!("Waiting for gdb on {}", listener.local_addr()?);
+
+            let (stream, _) = listener.accept()?;
+            stream.set_nodelay(true)?;
+
+            Ok(Box::new(stream))
+        }
+        GdbTarget::UnixSocket(path) => {
+            let _ = std::fs::remove_file(path);
+            let listener = UnixListener::bind(path)?;
+            println!("Waiting for gdb on {}", path.display());
+
+            let (stream, _) = listener.accept()?;
+
+            Ok(Box::new(stream))
+        }
+    }
+}
+
+// Waits for gdb and lets it control the machine. Once gdb detaches the guest keeps running until it
+// shuts down
+pub fn serve(platform: &mut RV64Platform, target: &GdbTarget) -> io::Result<()> {
+    let connection = accept(target)?;
+
+    if GdbSession::new(platform, connection).run()? == SessionEnd::Detached {
+        platform.set_console_input(true);
+
+        while !platform.is_halted() {
+            let _ = platform.step();
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SessionEnd {
+    Detached,
+    Killed,
+    Exited,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum StopReason {
+    Signal(u8),
+    Breakpoint,
+    Watchpoint(WatchKind, u64),
+    Exited(u8),
+    HistoryStart, //Reverse execution ran out of recorded history
+}
+
+enum Packet {
+    Command(Vec<u8>),
+    Interrupt,
+}
+
+enum Action {
+    Reply(String),
+    StartNoAck,
+    Resume { step: bool },
+    Reverse { step: bool },
+    Detach,
+    Kill,
+}
+
+// Thread ids are hart ids plus one, gdb reserves 0 for "any thread"
+fn thread_id(hart_id: usize) -> usize {
+    hart_id + 1
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    })
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+fn parse_hex(value: &str) -> Option<u64> {
+    u64::from_str_radix(value, 16).ok()
+}
+
+// Splits "addr,len" as used by the memory and breakpoint packets
+fn parse_range(args: &str) -> Option<(u64, u64)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((parse_hex(addr)?, parse_hex(len)?))
+}
+
+fn register_size(regnum: usize) -> usize {
+    match regnum {
+        //fflags, frm and fcsr are described as 32-bit registers in the fpu feature
+        n if n > CSR_REGNUM && n <= CSR_REGNUM + CSRAddress::FCSR as usize => 4,
+        _ => 8,
+    }
+}
+
+// Register description gdb asks for through qXfer, the CSR numbers match read_csr
+fn target_xml() -> String {
+    let mut xml = String::from("<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\"><target version=\"1.0\">");
+    xml.push_str("<architecture>riscv:rv64</architecture>");
+
+    xml.push_str("<feature name=\"org.gnu.gdb.riscv.cpu\">");
+    for (i, name) in X_ABI_NAMES.iter().enumerate() {
+        let _ = write!(xml, "<reg name=\"{}\" bitsize=\"64\" type=\"int\" regnum=\"{}\"/>", name, i);
+    }
+    let _ = write!(xml, "<reg name=\"pc\" bitsize=\"64\" type=\"code_ptr\" regnum=\"{}\"/>", PC_REGNUM);
+    xml.push_str("</feature>");
+
+    xml.push_str("<feature name=\"org.gnu.gdb.riscv.fpu\">");
+    for (i, name) in F_ABI_NAMES.iter().enumerate() {
+        let _ = write!(xml, "<reg name=\"{}\" bitsize=\"64\" type=\"ieee_double\" regnum=\"{}\"/>", name, FPR_REGNUM + i);
+    }
+    for csr in [CSRAddress::FFlags, CSRAddress::FRM, CSRAddress::FCSR] {
+        let _ = write!(xml, "<reg name=\"{}\" bitsize=\"32\" type=\"int\" regnum=\"{}\"/>", csr.name(), CSR_REGNUM + csr as usize);
+    }
+    xml.push_str("</feature>");
+
+    xml.push_str("<feature name=\"org.gnu.gdb.riscv.csr\">");
+    for csr in CSRAddress::ALL.iter().filter(|csr| register_size(CSR_REGNUM + **csr as usize) == 8) {
+        let _ = write!(xml, "<reg name=\"{}\" bitsize=\"64\" type=\"int\" regnum=\"{}\"/>", csr.name(), CSR_REGNUM + *csr as usize);
+    }
+    xml.push_str("</feature>");
+
+    xml.push_str("<feature name=\"org.gnu.gdb.riscv.virtual\">");
+    let _ = write!(xml, "<reg name=\"priv\" bitsize=\"64\" type=\"int\" regnum=\"{}\"/>", PRIV_REGNUM);
+    xml.push_str("</feature>");
+
+    xml.push_str("</target>");
+    xml
+}
+
+// One gdb connection, the machine only runs while gdb has resumed it
+pub(crate) struct GdbSession<'a> {
+    platform: &'a mut RV64Platform,
+    connection: Box<dyn Connection>,
+    input: Vec<u8>,
+    no_ack: bool,
+    hart: usize, //Thread selected with Hg, registers and memory are accessed through it
+    last_stop: StopReason,
+    watchpoints: Vec<(u64, Watchpoint)>, //Physical watchpoints and the virtual address gdb asked for
+}
+
+impl<'a> GdbSession<'a> {
+    pub(crate) fn new(platform: &'a mut RV64Platform, connection: Box<dyn Connection>) -> Self {
+        Self { platform, connection, input: Vec::new(), no_ack: false, hart: 0, last_stop: StopReason::Signal(SIGTRAP), watchpoints: Vec::new() }
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        if self.input.is_empty() {
+            let mut buf = [0; 4096];
+            let len = self.connection.read(&mut buf)?;
+
+            if len == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+
+            self.input.extend_from_slice(&buf[..len]);
+        }
+
+        Ok(self.input.remove(0))
+    }
+
+    fn receive(&mut self) -> io::Result<Packet> {
+        loop {
+            match self.read_byte()? {
+                0x03 => return Ok(Packet::Interrupt),
+                b'$' => {}
+                _ => continue, //Acks and line noise between packets
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+
+            let checksum = [self.read_byte()?, self.read_byte()?];
+            let valid = std::str::from_utf8(&checksum).ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+                .is_some_and(|checksum| checksum == data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)));
+
+            if !self.no_ack {
+                self.connection.write_all(if valid { b"+" } else { b"-" })?;
+            }
+
+            if !valid {
+                continue;
+            }
+
+            // '}' escapes the next byte, which is xored with 0x20
+            let mut command = Vec::with_capacity(data.len());
+            let mut bytes = data.into_iter();
+            while let Some(byte) = bytes.next() {
+                match byte {
+                    b'}' => command.push(bytes.next().unwrap_or(0) ^ 0x20),
+                    byte => command.push(byte),
+                }
+            }
+
+            return Ok(Packet::Command(command));
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let mut packet = vec![b'$'];
+        for byte in data.bytes() {
+            match byte {
+                b'$' | b'#' | b'}' | b'*' => packet.extend_from_slice(&[b'}', byte ^ 0x20]),
+                byte => packet.push(byte),
+            }
+        }
+
+        let checksum = packet[1..].iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        packet.extend_from_slice(format!("#{:02x}", checksum).as_bytes());
+
+        loop {
+            self.connection.write_all(&packet)?;
+            self.connection.flush()?;
+
+            if self.no_ack {
+                return Ok(());
+            }
+
+            // Resend until gdb acknowledges the packet
+            loop {
+                match self.read_byte()? {
+                    b'+' => return Ok(()),
+                    b'-' => break,
+                    _ => continue,
+                }
+            }
+        }
+    }
+
+    pub(crate) fn run(&mut self) -> io::Result<SessionEnd> {
+        loop {
+            let command = match self.receive() {
+                Ok(Packet::Command(command)) => command,
+                Ok(Packet::Interrupt) => continue, //The machine is already stopped
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(SessionEnd::Detached),
+                Err(e) => return Err(e),
+            };
+
+            match self.handle_command(&String::from_utf8_lossy(&command)) {
+                Action::Reply(reply) => self.send(&reply)?,
+                Action::StartNoAck => {
+                    // The OK itself still gets acknowledged
+                    self.send("OK")?;
+                    self.no_ack = true;
+                }
+                Action::Resume { step } => {
+                    self.platform.set_console_input(true);
+                    let stop = self.resume(step);
+                    self.platform.set_console_input(false);
+
+                    self.last_stop = stop?;
+                    let reply = self.stop_reply();
+                    self.send(&reply)?;
+
+                    if let StopReason::Exited(_) = self.last_stop {
+                        return Ok(SessionEnd::Exited);
+                    }
+                }
+                Action::Reverse { step } => {
+                    self.last_stop = self.reverse(step);
+                    let reply = self.stop_reply();
+                    self.send(&reply)?;
+                }
+                Action::Detach => {
+                    self.send("OK")?;
+                    return Ok(SessionEnd::Detached);
+                }
+                Action::Kill => return Ok(SessionEnd::Killed),
+            }
+        }
+    }
+
+    fn stop_reply(&self) -> String {
+        let thread = thread_id(self.hart);
+
+        match self.last_stop {
+            StopReason::Signal(signal) => format!("T{:02x}thread:{:x};", signal, thread),
+            StopReason::Breakpoint => format!("T{:02x}thread:{:x};swbreak:;", SIGTRAP, thread),
+            StopReason::Watchpoint(kind, addr) => {
+                let name = match kind {
+                    WatchKind::Write => "watch",
+                    WatchKind::Read => "rwatch",
+                    WatchKind::Access => "awatch",
+                };
+
+                format!("T{:02x}thread:{:x};{}:{:x};", SIGTRAP, thread, name, addr)
+            }
+            StopReason::Exited(code) => format!("W{:02x}", code),
+            StopReason::HistoryStart => format!("T{:02x}thread:{:x};replaylog:begin;", SIGTRAP, thread),
+        }
+    }
+
+    // Runs the machine until something gdb cares about happens. Traps are part of normal guest
+    // execution and don't stop it
+    fn resume(&mut self, step: bool) -> io::Result<StopReason> {
+        let mut rounds: usize = 0;
+
+        loop {
+            let _ = self.platform.step();
+
+            if let Some(reset) = self.platform.get_reset() {
+                return Ok(StopReason::Exited(reset.reason as u8));
+            }
+
+            if let Some((hart_id, hit)) = self.platform.take_watch_hit() {
+                let vaddr = self.watchpoints.iter()
+                    .find(|(_, watchpoint)| *watchpoint == hit.watchpoint)
+                    .map_or(hit.addr, |(vaddr, watchpoint)| vaddr + hit.addr.saturating_sub(watchpoint.addr));
+
+                self.hart = hart_id;
+                return Ok(StopReason::Watchpoint(hit.watchpoint.kind, vaddr));
+            }
+
+            if step {
+                return Ok(StopReason::Signal(SIGTRAP));
+            }
+
+            if let Some((hart_id, _)) = self.platform.hart_at_breakpoint() {
+                self.hart = hart_id;
+                return Ok(StopReason::Breakpoint);
+            }
+
+            rounds += 1;
+            if rounds.is_multiple_of(INTERRUPT_POLL_ROUNDS) && self.poll_interrupt()? {
+                return Ok(StopReason::Signal(SIGINT));
+            }
+        }
+    }
+
+    // bs and bc, the machine goes back through the history the platform records. Breakpoints stop where
+    // they are, watchpoints right before the access that hit them
+    fn reverse(&mut self, step: bool) -> StopReason {
+        if step {
+            return match self.platform.reverse_step(1) {
+                true => StopReason::Signal(SIGTRAP),
+                false => StopReason::HistoryStart,
+            };
+        }
+
+        let watchpoints = &self.watchpoints;
+
+        let stop = self.platform.reverse_continue(|platform, _| {
+            if let Some((hart_id, hit)) = platform.take_watch_hit() {
+                let vaddr = watchpoints.iter()
+                    .find(|(_, watchpoint)| *watchpoint == hit.watchpoint)
+                    .map_or(hit.addr, |(vaddr, watchpoint)| vaddr + hit.addr.saturating_sub(watchpoint.addr));
+
+                return Some(ReplayStop::Before((hart_id, StopReason::Watchpoint(hit.watchpoint.kind, vaddr))));
+            }
+
+            platform.hart_at_breakpoint().map(|(hart_id, _)| ReplayStop::After((hart_id, StopReason::Breakpoint)))
+        });
+
+        match stop {
+            Some((hart_id, reason)) => {
+                self.hart = hart_id;
+                reason
+            }
+            None => StopReason::HistoryStart,
+        }
+    }
+
+    // Checks for the Ctrl-C byte without blocking the running machine
+    fn poll_interrupt(&mut self) -> io::Result<bool> {
+        let mut buf = [0; 64];
+
+        self.connection.set_nonblocking(true)?;
+        let result = self.connection.read(&mut buf);
+        self.connection.set_nonblocking(false)?;
+
+        match result {
+            Ok(0) => Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(len) => {
+                self.input.extend(buf[..len].iter().filter(|&&byte| byte != 0x03));
+                Ok(buf[..len].contains(&0x03))
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn handle_command(&mut self, command: &str) -> Action {
+        let reply = match command.split_at(command.len().min(1)) {
+            ("?", _) => self.stop_reply(),
+            ("q", query) => self.handle_query(query),
+            ("Q", "StartNoAckMode") => return Action::StartNoAck,
+            ("H", args) => self.select_thread(args),
+            ("T", thread) => match parse_hex(thread) {
+                Some(thread) if thread >= 1 && thread as usize <= self.platform.get_hart_count() => "OK".to_string(),
+                _ => "E01".to_string(),
+            },
+            ("g", _) => self.read_registers(),
+            ("G", values) => self.write_registers(values),
+            ("p", regnum) => parse_hex(regnum).and_then(|regnum| self.read_register(regnum as usize))
+                .unwrap_or_else(|| "E01".to_string()),
+            ("P", args) => self.write_register(args),
+            ("m", args) => self.read_memory(args),
+            ("M", args) => self.write_memory(args),
+            ("Z", args) => self.set_breakpoint(args, true),
+            ("z", args) => self.set_breakpoint(args, false),
+            ("c", addr) | ("s", addr) => {
+                if let Some(addr) = parse_hex(addr) {
+                    self.platform.get_cpu_context_mut(self.hart).pc = addr;
+                }
+
+                return Action::Resume { step: command.starts_with('s') };
+            }
+            ("C", _) => return Action::Resume { step: false },
+            ("b", "s") => return Action::Reverse { step: true },
+            ("b", "c") => return Action::Reverse { step: false },
+            ("S", _) => return Action::Resume { step: true },
+            ("v", args) => return self.handle_v_command(args),
+            ("D", _) => return Action::Detach,
+            ("k", _) => return Action::Kill,
+            _ => String::new(),
+        };
+
+        Action::Reply(reply)
+    }
+
+    fn handle_query(&mut self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            // gdb only offers reverse execution when the history is being recorded
+            let reverse = if self.platform.is_recording() { ";ReverseStep+;ReverseContinue+" } else { "" };
+
+            return format!("PacketSize={:x};qXfer:features:read+;swbreak+;hwbreak+;vContSupported+;QStartNoAckMode+{}", PACKET_SIZE, reverse);
+        }
+
+        if let Some(args) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let xml = target_xml();
+
+            return match parse_range(args) {
+                Some((offset, len)) => {
+                    let start = (offset as usize).min(xml.len());
+                    let end = start.saturating_add(len as usize).min(xml.len());
+                    let prefix = if end == xml.len() { "l" } else { "m" };
+
+                    format!("{}{}", prefix, &xml[start..end])
+                }
+                None => "E01".to_string(),
+            };
+        }
+
+        if let Some(thread) = query.strip_prefix("ThreadExtraInfo,") {
+            let hart_id = parse_hex(thread).unwrap_or(1).max(1) as usize - 1;
+            let state = if !self.platform.is_running(hart_id) { "stopped" } else { "running" };
+
+            return to_hex(format!("hart {} {}", hart_id, state).as_bytes());
+        }
+
+        match query {
+            "fThreadInfo" => {
+                let threads: Vec<String> = (0..self.platform.get_hart_count()).map(|hart_id| format!("{:x}", thread_id(hart_id))).collect();
+                format!("m{}", threads.join(","))
+            }
+            "sThreadInfo" => "l".to_string(),
+            "C" => format!("QC{:x}", thread_id(self.hart)),
+            "Attached" => "1".to_string(),
+            "Symbol::" => "OK".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    // Hg picks the thread for register and memory access, Hc is accepted but every hart runs anyway
+    fn select_thread(&mut self, args: &str) -> String {
+        let (op, thread) = args.split_at(args.len().min(1));
+
+        if op == "g" {
+            match parse_hex(thread) {
+                Some(thread) if thread >= 1 && thread as usize <= self.platform.get_hart_count() => self.hart = thread as usize - 1,
+                Some(0) | None => {} //0 and -1 mean any thread
+                Some(_) => return "E01".to_string(),
+            }
+        }
+
+        "OK".to_string()
+    }
+
+    fn handle_v_command(&mut self, args: &str) -> Action {
+        if args == "Cont?" {
+            return Action::Reply("vCont;c;C;s;S".to_string());
+        }
+
+        let Some(actions) = args.strip_prefix("Cont;") else {
+            return Action::Reply(String::new());
+        };
+
+        // Harts can't be resumed individually, a step of any thread steps the whole machine by one round
+        for action in actions.split(';') {
+            let (action, thread) = action.split_once(':').map_or((action, None), |(action, thread)| (action, Some(thread)));
+
+            if action.starts_with('s') || action.starts_with('S') {
+                if let Some(thread) = thread.and_then(parse_hex).filter(|&thread| thread >= 1 && thread as usize <= self.platform.get_hart_count()) {
+                    self.hart = thread as usize - 1;
+                }
+
+                return Action::Resume { step: true };
+            }
+        }
+
+        Action::Resume { step: false }
+    }
+
+    fn read_registers(&self) -> String {
+        let cpu = self.platform.get_cpu_context(self.hart);
+        let values = cpu.x.iter().chain(std::iter::once(&cpu.pc));
+
+        values.map(|value| to_hex(&value.to_le_bytes())).collect()
+    }
+
+    fn write_registers(&mut self, values: &str) -> String {
+        let Some(bytes) = from_hex(values).filter(|bytes| bytes.len() >= 33 * 8) else {
+            return "E01".to_string();
+        };
+
+        let cpu = self.platform.get_cpu_context_mut(self.hart);
+        let mut values = bytes.chunks_exact(8).map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()));
+
+        for register in 0..32 {
+            cpu.set_register(register, values.next().unwrap());
+        }
+        cpu.pc = values.next().unwrap();
+
+        "OK".to_string()
+    }
+
+    fn read_register(&self, regnum: usize) -> Option<String> {
+        let cpu = self.platform.get_cpu_context(self.hart);
+
+        let value = match regnum {
+            0..=31 => cpu.x[regnum],
+            PC_REGNUM => cpu.pc,
+            n if (FPR_REGNUM..CSR_REGNUM).contains(&n) => cpu.f[n - FPR_REGNUM],
+            PRIV_REGNUM => cpu.csrs.get_current_privilege() as u64,
+            n if (CSR_REGNUM..PRIV_REGNUM).contains(&n) => cpu.csrs.read_csr((n - CSR_REGNUM) as u16, true).ok()?,
+            _ => return None,
+        };
+
+        Some(to_hex(&value.to_le_bytes()[..register_size(regnum)]))
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let parsed = args.split_once('=').and_then(|(regnum, value)| {
+            let mut bytes = from_hex(value)?;
+            bytes.resize(8, 0);
+
+            Some((parse_hex(regnum)? as usize, u64::from_le_bytes(bytes[..8].try_into().unwrap())))
+        });
+
+        let Some((regnum, value)) = parsed else {
+            return "E01".to_string();
+        };
+
+        let cpu = self.platform.get_cpu_context_mut(self.hart);
+
+        let privilege = match value {
+            0 => Some(PrivilegeMode::User),
+            1 => Some(PrivilegeMode::Supervisor),
+            3 => Some(PrivilegeMode::Machine),
+            _ => None,
+        };
+
+        match regnum {
+            0..=31 => cpu.set_register(regnum, value),
+            PC_REGNUM => cpu.pc = value,
+            n if (FPR_REGNUM..CSR_REGNUM).contains(&n) => cpu.set_register_float(n - FPR_REGNUM, value),
+            PRIV_REGNUM if privilege.is_some() => cpu.csrs.change_privilege(privilege.unwrap()),
+            n if (CSR_REGNUM..PRIV_REGNUM).contains(&n) => {
+                if cpu.csrs.write_csr((n - CSR_REGNUM) as u16, value, true).is_err() {
+                    return "E01".to_string();
+                }
+            }
+            _ => return "E01".to_string(),
+        }
+
+        "OK".to_string()
+    }
+
+    fn read_memory(&self, args: &str) -> String {
+        let Some((addr, len)) = parse_range(args) else {
+            return "E01".to_string();
+        };
+
+        let mut buf = vec![0; (len as usize).min(PACKET_SIZE / 2)];
+
+        match self.platform.get_cpu_context(self.hart).debug_read(addr, &mut buf) {
+            Ok(()) => to_hex(&buf),
+            Err(_) => "E14".to_string(),
+        }
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let parsed = args.split_once(':').and_then(|(range, data)| Some((parse_range(range)?, from_hex(data)?)));
+
+        let Some(((addr, len), data)) = parsed.filter(|((_, len), data)| *len as usize == data.len()) else {
+            return "E01".to_string();
+        };
+
+        match self.platform.get_cpu_context(self.hart).debug_write(addr, &data[..len as usize]) {
+            Ok(()) => "OK".to_string(),
+            Err(_) => "E14".to_string(),
+        }
+    }
+
+    // Software and hardware breakpoints are the same thing here, watchpoints are translated to the
+    // physical address the selected hart currently maps them to
+    fn set_breakpoint(&mut self, args: &str, insert: bool) -> String {
+        let mut fields = args.split(',');
+        let kind = fields.next();
+        let range = fields.next().and_then(parse_hex).zip(fields.next().and_then(parse_hex));
+
+        let (kind, (addr, len)) = match (kind, range) {
+            (Some(kind), Some(range)) => (kind, range),
+            _ => return "E01".to_string(),
+        };
+
+        let watch_kind = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.platform.add_breakpoint(addr);
+                } else {
+                    self.platform.remove_breakpoint(addr);
+                }
+
+                return "OK".to_string();
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return String::new(),
+        };
+
+        let Ok(paddr) = self.platform.get_cpu_context(self.hart).debug_translate(addr) else {
+            return "E14".to_string();
+        };
+
+        let watchpoint = Watchpoint { addr: paddr, len, kind: watch_kind };
+
+        if insert {
+            self.platform.add_watchpoint(watchpoint);
+            self.watchpoints.push((addr, watchpoint));
+        } else {
+            self.platform.remove_watchpoint(&watchpoint);
+            self.watchpoints.retain(|(_, other)| *other != watchpoint);
+        }
+
+        "OK".to_string()
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::{HashMap, VecDeque};
+use crate::emulator::devices::clint::ClintSnapshot;
+use crate::emulator::devices::plic::PlicSnapshot;
+use crate::emulator::devices::uart::UartSnapshot;
+use crate::emulator::interpreter::Interpreter;
+use crate::emulator::sbi::Sbi;
+
+// Rounds between two snapshots, going back replays at most this many rounds from the closest one
+pub const SNAPSHOT_INTERVAL: u64 = 10_000;
+
+// Snapshots kept before the oldest ones are dropped, bounds how far back the machine can go
+pub const SNAPSHOT_LIMIT: usize = 1_000;
+
+// Machine state at the start of a round. Memory isn't copied, it is brought back by undoing the MMU's
+// journal until it holds journal_len entries again
+pub(crate) struct Snapshot {
+    pub step: u64,
+    pub journal_len: usize,
+    pub harts: Vec<Interpreter>,
+    pub sbi: Option<Sbi>,
+    pub clint: ClintSnapshot,
+    pub plic: PlicSnapshot,
+    pub uart: Option<UartSnapshot>,
+    pub reservations: HashMap<u64, u64>,
+}
+
+// Where a stop found while replaying puts the machine, watchpoints stop right before the access
+// that hit them, breakpoints after the round that reached them
+pub(crate) enum ReplayStop<T> {
+    Before(T),
+    After(T),
+}
+
+// Execution recorded since the debugger started recording, counted in rounds of RV64Platform::step
+pub(crate) struct History {
+    snapshots: VecDeque<Snapshot>,
+    step: u64,
+    interval: u64,
+    limit: usize,
+}
+
+impl History {
+    pub fn new(first: Snapshot, interval: u64, limit: usize) -> Self {
+        let step = first.step;
+
+        Self { snapshots: VecDeque::from([first]), step, interval: interval.max(1), limit: limit.max(1) }
+    }
+
+    pub fn step(&self) -> u64 {
+        self.step
+    }
+
+    pub fn oldest_step(&self) -> u64 {
+        self.snapshots[0].step
+    }
+
+    // Latest snapshot taken before step
+    pub fn snapshot_before(&self, step: u64) -> Option<u64> {
+        self.snapshots.iter().rev().map(|snapshot| snapshot.step).find(|snapshot| *snapshot < step)
+    }
+
+    // Counts a round, returns whether a snapshot is due after it
+    pub fn advance(&mut self) -> bool {
+        self.step += 1;
+
+        self.step.is_multiple_of(self.interval)
+    }
+
+    // Drops the oldest snapshot once there are too many, returns how many journal entries only it needed
+    pub fn push(&mut self, snapshot: Snapshot) -> usize {
+        self.snapshots.push_back(snapshot);
+
+        if self.snapshots.len() <= self.limit {
+            return 0;
+        }
+
+        self.snapshots.pop_front();
+        let forgotten = self.snapshots[0].journal_len;
+
+        for snapshot in self.snapshots.iter_mut() {
+            snapshot.journal_len -= forgotten;
+        }
+
+        forgotten
+    }
+
+    // Latest snapshot taken at or before step, or the oldest one. The ones after it are dropped, replaying
+    // takes them again
+    pub fn rewind(&mut self, step: u64) -> &Snapshot {
+        let index = self.snapshots.iter().rposition(|snapshot| snapshot.step <= step).unwrap_or(0);
+
+        self.snapshots.truncate(index + 1);
+        self.step = self.snapshots[index].step;
+
+        &self.snapshots[index]
+    }
+}
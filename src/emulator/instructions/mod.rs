@@ -1,3 +1,4 @@
+use crate::emulator::instructions::rv64::disasm::Disassembly;
 use crate::emulator::state::rv64_cpu_context::{Exception, RV64CPUContext};
 
 pub mod rv64;
@@ -8,6 +9,11 @@ pub trait ParsableInstructionGroup {
     fn parse(instr: u32) -> InstructionFn;
 }
 
+// Renders an instruction of the group as assembly, None for encodings the group doesn't implement
+pub trait DisassemblableInstructionGroup {
+    fn disassemble(instr: u32, pc: u64) -> Option<Disassembly>;
+}
+
 trait Instruction {
     fn execute(&self, cpu: &mut RV64CPUContext);
 }
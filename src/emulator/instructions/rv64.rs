@@ -1,10 +1,13 @@
-use crate::emulator::instructions::{InstructionFn, ParsableInstructionGroup};
+use crate::emulator::instructions::{DisassemblableInstructionGroup, InstructionFn, ParsableInstructionGroup};
 use crate::emulator::instructions::rv64::amo::{AtomicOpcodeGroup, ATOMIC_OPCODE};
 use crate::emulator::instructions::rv64::int_op::{IntOp32OpcodeGroup, IntOpOpcodeGroup, OP_OPCODE, OP_32_OPCODE};
 use crate::emulator::instructions::rv64::int_op_imm::{IntOpImmOpcodeGroup, LuiOpcodeGroup, LUI_OPCODE, AUIPC_OPCODE, OP_IMM_OPCODE, OP_IMM_32_OPCODE, AuipcOpcodeGroup, IntOpImm32OpcodeGroup};
 use crate::emulator::instructions::rv64::jump_branch::{JalOpcodeGroup, JalrOpcodeGroup, JAL_OPCODE, JALR_OPCODE, BRANCH_OPCODE, BranchOpcodeGroup};
 use crate::emulator::instructions::rv64::load_store::{LoadOpcodeGroup, StoreOpcodeGroup, LOAD_OPCODE, STORE_OPCODE};
 use crate::emulator::instructions::rv64::system::{SystemOpcodeGroup, SYSTEM_OPCODE};
+use crate::emulator::instructions::rv64::misc_mem::{MiscMemOpcodeGroup, MISC_MEM_OPCODE};
+use crate::emulator::instructions::rv64::fp::{FloatingPointOpcodeGroup, FusedMultiplyAddOpcodeGroup, LoadFloatingPointOpcodeGroup, StoreFloatingPointOpcodeGroup, FMADD_OPCODE, FMSUB_OPCODE, FNMADD_OPCODE, FNMSUB_OPCODE, LOAD_FP_OPCODE, OP_FP_OPCODE, STORE_FP_OPCODE};
+use crate::emulator::instructions::rv64::disasm::Disassembly;
 use crate::emulator::state::rv64_cpu_context::Exception;
 
 pub mod int_op;
@@ -14,6 +17,9 @@ pub mod load_store;
 pub mod system;
 pub mod amo;
 pub mod fp;
+pub mod compressed;
+pub mod misc_mem;
+pub mod disasm;
 
 type InstructionResult = Result<(), Exception>;
 
@@ -37,10 +43,42 @@ impl RV64InstructionParser {
             LOAD_OPCODE => LoadOpcodeGroup::parse(instr),
             STORE_OPCODE => StoreOpcodeGroup::parse(instr),
             SYSTEM_OPCODE => SystemOpcodeGroup::parse(instr),
+            MISC_MEM_OPCODE => MiscMemOpcodeGroup::parse(instr),
             ATOMIC_OPCODE => AtomicOpcodeGroup::parse(instr),
+            LOAD_FP_OPCODE => LoadFloatingPointOpcodeGroup::parse(instr),
+            STORE_FP_OPCODE => StoreFloatingPointOpcodeGroup::parse(instr),
+            OP_FP_OPCODE => FloatingPointOpcodeGroup::parse(instr),
+            FMADD_OPCODE | FMSUB_OPCODE | FNMSUB_OPCODE | FNMADD_OPCODE => FusedMultiplyAddOpcodeGroup::parse(instr),
             _ => { |_,_| { Err(Exception::IllegalInstruction) } }
         }
     }
+
+    // Same dispatch as parse, for the disassembler
+    pub fn disassemble(instr: u32, pc: u64) -> Option<Disassembly> {
+        let opcode: u8 = (instr & 0x7F) as u8;
+
+        match opcode {
+            OP_OPCODE => IntOpOpcodeGroup::disassemble(instr, pc),
+            OP_32_OPCODE => IntOp32OpcodeGroup::disassemble(instr, pc),
+            OP_IMM_OPCODE => IntOpImmOpcodeGroup::disassemble(instr, pc),
+            OP_IMM_32_OPCODE => IntOpImm32OpcodeGroup::disassemble(instr, pc),
+            LUI_OPCODE => LuiOpcodeGroup::disassemble(instr, pc),
+            AUIPC_OPCODE => AuipcOpcodeGroup::disassemble(instr, pc),
+            JAL_OPCODE => JalOpcodeGroup::disassemble(instr, pc),
+            JALR_OPCODE => JalrOpcodeGroup::disassemble(instr, pc),
+            BRANCH_OPCODE => BranchOpcodeGroup::disassemble(instr, pc),
+            LOAD_OPCODE => LoadOpcodeGroup::disassemble(instr, pc),
+            STORE_OPCODE => StoreOpcodeGroup::disassemble(instr, pc),
+            SYSTEM_OPCODE => SystemOpcodeGroup::disassemble(instr, pc),
+            MISC_MEM_OPCODE => MiscMemOpcodeGroup::disassemble(instr, pc),
+            ATOMIC_OPCODE => AtomicOpcodeGroup::disassemble(instr, pc),
+            LOAD_FP_OPCODE => LoadFloatingPointOpcodeGroup::disassemble(instr, pc),
+            STORE_FP_OPCODE => StoreFloatingPointOpcodeGroup::disassemble(instr, pc),
+            OP_FP_OPCODE => FloatingPointOpcodeGroup::disassemble(instr, pc),
+            FMADD_OPCODE | FMSUB_OPCODE | FNMSUB_OPCODE | FNMADD_OPCODE => FusedMultiplyAddOpcodeGroup::disassemble(instr, pc),
+            _ => None,
+        }
+    }
 }
 
 #[macro_export] macro_rules! wrap_r_type {
@@ -57,6 +95,21 @@ impl RV64InstructionParser {
     }
 }
 
+#[macro_export] macro_rules! wrap_r4_type {
+    ($exec_fn:ident) => {
+        {
+            fn wrapper(cpu_context: &mut RV64CPUContext, instr: u32) -> Result<(), Exception> {
+                let rd = ((instr >> 7) & 0x1F) as u8;
+                let rs1 = ((instr >> 15) & 0x1F) as u8;
+                let rs2 = ((instr >> 20) & 0x1F) as u8;
+                let rs3 = ((instr >> 27) & 0x1F) as u8;
+                $exec_fn(cpu_context, instr, rd, rs1, rs2, rs3)
+            }
+            wrapper
+        }
+    }
+}
+
 #[macro_export] macro_rules! wrap_j_type {
     ($exec_fn:ident) => {
         {
@@ -130,8 +183,8 @@ impl RV64InstructionParser {
 
                 let mut imm = imm1_4 | imm5_10 | imm11 | imm12;
 
-                if(((instr >> 31) & 1) > 1) {
-                    imm |= !0xFFFFF;
+                if(((instr >> 31) & 1) > 0) {
+                    imm |= !0x1FFF_u64;
                 }
 
                 $exec_fn(cpu_context, instr, rs1, rs2, imm)
@@ -165,7 +218,7 @@ impl RV64InstructionParser {
     ($exec_fn:ident) => {
         {
             fn wrapper(cpu_context: &mut RV64CPUContext, instr: u32) -> Result<(), Exception> {
-                let imm = (((instr >> 12) & 0xFFFFF) as u64) << 12; //Bits 1 to 4
+                let imm = (instr & 0xFFFFF000) as i32 as i64 as u64; //Bits 12 to 31, sign extended
                 let rs1 = ((instr >> 7) & 0x1F) as u8;
 
                 $exec_fn(cpu_context, instr, rs1, imm)
@@ -179,10 +232,14 @@ impl RV64InstructionParser {
     ($exec_fn:ident) => {
         {
             fn wrapper(cpu_context: &mut RV64CPUContext, instr: u32) -> Result<(), Exception> {
-                let imm = (((instr >> 25) & 0x3F) as u64) << 5 | (((instr >> 7) & 0x1F) as u64);
+                let mut imm = (((instr >> 25) & 0x7F) as u64) << 5 | (((instr >> 7) & 0x1F) as u64);
                 let rs1 = ((instr >> 15) & 0x1F) as u8;
                 let rs2 = ((instr >> 20) & 0x1F) as u8;
 
+                if((instr & (1<<31)) > 0) {
+                    imm |= !0xFFF_u64;
+                }
+
                 $exec_fn(cpu_context, instr, rs1, rs2, imm)
             }
             wrapper
@@ -1,8 +1,10 @@
 use std::cmp::{max, min};
-use crate::emulator::instructions::{InstructionFn, ParsableInstructionGroup};
+use crate::emulator::instructions::{DisassemblableInstructionGroup, InstructionFn, ParsableInstructionGroup};
+use crate::emulator::instructions::rv64::disasm::{self, Disassembly};
 use crate::emulator::state::rv64_cpu_context::{Exception, PrivilegeMode, RV64CPUContext};
 use crate::{wrap_r_type};
 use crate::emulator::instructions::rv64::InstructionResult;
+use crate::emulator::state::translation::AccessType;
 
 pub const ATOMIC_OPCODE: u8 = 0b010_1111;
 
@@ -19,12 +21,15 @@ fn exec_lr_w(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2:
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 4, AccessType::Load)?;
+
     let value = {
         let memory = cpu_context.memory.write().unwrap();
-        let value = memory.read_word(addr as usize) as i32;
+        let value = memory.read_word(paddr) as i32;
+        cpu_context.commit.load(addr);
 
         // Store reservation in global state
-        memory.set_reservation(cpu_context.hart_id, addr);
+        memory.set_reservation(cpu_context.hart_id, paddr as u64);
 
         value
     };
@@ -41,17 +46,24 @@ fn exec_sc_w(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2:
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 4, AccessType::Store)?;
+
     let value: u64 = {
         let mut memory = cpu_context.memory.write().unwrap();
 
-        if !memory.check_reservation(cpu_context.hart_id, addr) {
+        let value = if !memory.check_reservation(cpu_context.hart_id, paddr as u64) {
             1_u64
         } else {
-            memory.write_word(addr as usize, src as u32);
-            memory.clear_reservations_for_addr(addr);
+            //The store breaks the reservations other harts hold on this address
+            memory.write_word(paddr, src as u32);
+            cpu_context.commit.store(addr, 4, src as u32 as u64);
 
             0_u64
-        }
+        };
+
+        memory.clear_reservation(cpu_context.hart_id);
+
+        value
     };
 
     cpu_context.set_register(rd as usize, value);
@@ -66,10 +78,14 @@ fn exec_amoswap_w(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8,
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 4, AccessType::Store)?;
+
     let old_value = {
         let mut memory = cpu_context.memory.write().unwrap();
-        let old_value = memory.read_word(addr as usize) as i32;
-        memory.write_word(addr as usize, src as u32);
+        let old_value = memory.read_word(paddr) as i32;
+        cpu_context.commit.load(addr);
+        memory.write_word(paddr, src as u32);
+        cpu_context.commit.store(addr, 4, src as u32 as u64);
 
         old_value
     };
@@ -87,12 +103,16 @@ fn exec_amoadd_w(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8,
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 4, AccessType::Store)?;
+
     let old_value = {
         let mut memory = cpu_context.memory.write().unwrap();
-        let old_value = memory.read_word(addr as usize) as i32;
+        let old_value = memory.read_word(paddr) as i32;
+        cpu_context.commit.load(addr);
 
         let value = old_value.wrapping_add(src);
-        memory.write_word(addr as usize, value as u32);
+        memory.write_word(paddr, value as u32);
+        cpu_context.commit.store(addr, 4, value as u32 as u64);
 
         old_value
     };
@@ -110,12 +130,16 @@ fn exec_amoxor_w(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8,
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 4, AccessType::Store)?;
+
     let old_value = {
         let mut memory = cpu_context.memory.write().unwrap();
-        let old_value = memory.read_word(addr as usize) as i32;
+        let old_value = memory.read_word(paddr) as i32;
+        cpu_context.commit.load(addr);
 
         let value = old_value ^ src;
-        memory.write_word(addr as usize, value as u32);
+        memory.write_word(paddr, value as u32);
+        cpu_context.commit.store(addr, 4, value as u32 as u64);
 
         old_value
     };
@@ -133,12 +157,16 @@ fn exec_amoor_w(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, r
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 4, AccessType::Store)?;
+
     let old_value = {
         let mut memory = cpu_context.memory.write().unwrap();
-        let old_value = memory.read_word(addr as usize) as i32;
+        let old_value = memory.read_word(paddr) as i32;
+        cpu_context.commit.load(addr);
 
         let value = old_value | src;
-        memory.write_word(addr as usize, value as u32);
+        memory.write_word(paddr, value as u32);
+        cpu_context.commit.store(addr, 4, value as u32 as u64);
 
         old_value
     };
@@ -156,12 +184,16 @@ fn exec_amoand_w(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8,
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 4, AccessType::Store)?;
+
     let old_value = {
         let mut memory = cpu_context.memory.write().unwrap();
-        let old_value = memory.read_word(addr as usize) as i32;
+        let old_value = memory.read_word(paddr) as i32;
+        cpu_context.commit.load(addr);
 
         let value = old_value & src;
-        memory.write_word(addr as usize, value as u32);
+        memory.write_word(paddr, value as u32);
+        cpu_context.commit.store(addr, 4, value as u32 as u64);
 
         old_value
     };
@@ -179,12 +211,16 @@ fn exec_amomin_w(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8,
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 4, AccessType::Store)?;
+
     let old_value = {
         let mut memory = cpu_context.memory.write().unwrap();
-        let old_value = memory.read_word(addr as usize) as i32;
+        let old_value = memory.read_word(paddr) as i32;
+        cpu_context.commit.load(addr);
 
         let value = min(old_value, src);
-        memory.write_word(addr as usize, value as u32);
+        memory.write_word(paddr, value as u32);
+        cpu_context.commit.store(addr, 4, value as u32 as u64);
 
         old_value
     };
@@ -202,12 +238,16 @@ fn exec_amomax_w(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8,
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 4, AccessType::Store)?;
+
     let old_value = {
         let mut memory = cpu_context.memory.write().unwrap();
-        let old_value = memory.read_word(addr as usize) as i32;
+        let old_value = memory.read_word(paddr) as i32;
+        cpu_context.commit.load(addr);
 
         let value = max(old_value, src);
-        memory.write_word(addr as usize, value as u32);
+        memory.write_word(paddr, value as u32);
+        cpu_context.commit.store(addr, 4, value as u32 as u64);
 
         old_value
     };
@@ -225,12 +265,16 @@ fn exec_amominu_w(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8,
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 4, AccessType::Store)?;
+
     let old_value = {
         let mut memory = cpu_context.memory.write().unwrap();
-        let old_value = memory.read_word(addr as usize);
+        let old_value = memory.read_word(paddr);
+        cpu_context.commit.load(addr);
 
         let value = min(old_value, src);
-        memory.write_word(addr as usize, value);
+        memory.write_word(paddr, value);
+        cpu_context.commit.store(addr, 4, value as u64);
 
         old_value
     };
@@ -248,12 +292,16 @@ fn exec_amomaxu_w(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8,
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 4, AccessType::Store)?;
+
     let old_value = {
         let mut memory = cpu_context.memory.write().unwrap();
-        let old_value = memory.read_word(addr as usize);
+        let old_value = memory.read_word(paddr);
+        cpu_context.commit.load(addr);
 
         let value = max(old_value, src);
-        memory.write_word(addr as usize, value);
+        memory.write_word(paddr, value);
+        cpu_context.commit.store(addr, 4, value as u64);
 
         old_value
     };
@@ -270,12 +318,15 @@ fn exec_lr_d(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2:
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 8, AccessType::Load)?;
+
     let value = {
         let memory = cpu_context.memory.write().unwrap();
-        let value = memory.read_double_word(addr as usize);
+        let value = memory.read_double_word(paddr);
+        cpu_context.commit.load(addr);
 
         // Store reservation in global state
-        memory.set_reservation(cpu_context.hart_id, addr);
+        memory.set_reservation(cpu_context.hart_id, paddr as u64);
 
         value
     };
@@ -292,17 +343,24 @@ fn exec_sc_d(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2:
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 8, AccessType::Store)?;
+
     let value: u64 = {
         let mut memory = cpu_context.memory.write().unwrap();
 
-        if !memory.check_reservation(cpu_context.hart_id, addr) {
+        let value = if !memory.check_reservation(cpu_context.hart_id, paddr as u64) {
             1_u64
         } else {
-            memory.write_double_word(addr as usize, src);
-            memory.clear_reservations_for_addr(addr);
+            //The store breaks the reservations other harts hold on this address
+            memory.write_double_word(paddr, src);
+            cpu_context.commit.store(addr, 8, src);
 
             0_u64
-        }
+        };
+
+        memory.clear_reservation(cpu_context.hart_id);
+
+        value
     };
 
     cpu_context.set_register(rd as usize, value);
@@ -317,10 +375,14 @@ fn exec_amoswap_d(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8,
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 8, AccessType::Store)?;
+
     let old_value = {
         let mut memory = cpu_context.memory.write().unwrap();
-        let old_value = memory.read_double_word(addr as usize);
-        memory.write_double_word(addr as usize, src);
+        let old_value = memory.read_double_word(paddr);
+        cpu_context.commit.load(addr);
+        memory.write_double_word(paddr, src);
+        cpu_context.commit.store(addr, 8, src);
 
         old_value
     };
@@ -338,12 +400,16 @@ fn exec_amoadd_d(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8,
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 8, AccessType::Store)?;
+
     let old_value = {
         let mut memory = cpu_context.memory.write().unwrap();
-        let old_value = memory.read_double_word(addr as usize);
+        let old_value = memory.read_double_word(paddr);
+        cpu_context.commit.load(addr);
 
         let value = old_value.wrapping_add(src);
-        memory.write_double_word(addr as usize, value);
+        memory.write_double_word(paddr, value);
+        cpu_context.commit.store(addr, 8, value);
 
         old_value
     };
@@ -361,12 +427,16 @@ fn exec_amoxor_d(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8,
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 8, AccessType::Store)?;
+
     let old_value = {
         let mut memory = cpu_context.memory.write().unwrap();
-        let old_value = memory.read_double_word(addr as usize);
+        let old_value = memory.read_double_word(paddr);
+        cpu_context.commit.load(addr);
 
         let value = old_value ^ src;
-        memory.write_double_word(addr as usize, value);
+        memory.write_double_word(paddr, value);
+        cpu_context.commit.store(addr, 8, value);
 
         old_value
     };
@@ -384,12 +454,16 @@ fn exec_amoor_d(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, r
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 8, AccessType::Store)?;
+
     let old_value = {
         let mut memory = cpu_context.memory.write().unwrap();
-        let old_value = memory.read_double_word(addr as usize);
+        let old_value = memory.read_double_word(paddr);
+        cpu_context.commit.load(addr);
 
         let value = old_value | src;
-        memory.write_double_word(addr as usize, value);
+        memory.write_double_word(paddr, value);
+        cpu_context.commit.store(addr, 8, value);
 
         old_value
     };
@@ -407,12 +481,16 @@ fn exec_amoand_d(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8,
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 8, AccessType::Store)?;
+
     let old_value = {
         let mut memory = cpu_context.memory.write().unwrap();
-        let old_value = memory.read_double_word(addr as usize);
+        let old_value = memory.read_double_word(paddr);
+        cpu_context.commit.load(addr);
 
         let value = old_value & src;
-        memory.write_double_word(addr as usize, value);
+        memory.write_double_word(paddr, value);
+        cpu_context.commit.store(addr, 8, value);
 
         old_value
     };
@@ -430,12 +508,16 @@ fn exec_amomin_d(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8,
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 8, AccessType::Store)?;
+
     let old_value = {
         let mut memory = cpu_context.memory.write().unwrap();
-        let old_value = memory.read_double_word(addr as usize) as i64;
+        let old_value = memory.read_double_word(paddr) as i64;
+        cpu_context.commit.load(addr);
 
         let value = min(old_value, src);
-        memory.write_double_word(addr as usize, value as u64);
+        memory.write_double_word(paddr, value as u64);
+        cpu_context.commit.store(addr, 8, value as u64);
 
         old_value
     };
@@ -453,12 +535,16 @@ fn exec_amomax_d(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8,
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 8, AccessType::Store)?;
+
     let old_value = {
         let mut memory = cpu_context.memory.write().unwrap();
-        let old_value = memory.read_double_word(addr as usize) as i64;
+        let old_value = memory.read_double_word(paddr) as i64;
+        cpu_context.commit.load(addr);
 
         let value = max(old_value, src);
-        memory.write_double_word(addr as usize, value as u64);
+        memory.write_double_word(paddr, value as u64);
+        cpu_context.commit.store(addr, 8, value as u64);
 
         old_value
     };
@@ -476,12 +562,16 @@ fn exec_amominu_d(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8,
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 8, AccessType::Store)?;
+
     let old_value = {
         let mut memory = cpu_context.memory.write().unwrap();
-        let old_value = memory.read_double_word(addr as usize);
+        let old_value = memory.read_double_word(paddr);
+        cpu_context.commit.load(addr);
 
         let value = min(old_value, src);
-        memory.write_double_word(addr as usize, value);
+        memory.write_double_word(paddr, value);
+        cpu_context.commit.store(addr, 8, value);
 
         old_value
     };
@@ -499,12 +589,16 @@ fn exec_amomaxu_d(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8,
         return Err(Exception::LoadAddressMisaligned);
     }
 
+    let paddr = cpu_context.translate_access(addr, 8, AccessType::Store)?;
+
     let old_value = {
         let mut memory = cpu_context.memory.write().unwrap();
-        let old_value = memory.read_double_word(addr as usize);
+        let old_value = memory.read_double_word(paddr);
+        cpu_context.commit.load(addr);
 
         let value = max(old_value, src);
-        memory.write_double_word(addr as usize, value);
+        memory.write_double_word(paddr, value);
+        cpu_context.commit.store(addr, 8, value);
 
         old_value
     };
@@ -545,4 +639,45 @@ impl ParsableInstructionGroup for AtomicOpcodeGroup {
             _ => |_,_| { Err(Exception::IllegalInstruction) },
         }
     }
-}
\ No newline at end of file
+}
+impl DisassemblableInstructionGroup for AtomicOpcodeGroup {
+    fn disassemble(instr: u32, _pc: u64) -> Option<Disassembly> {
+        let (rd, rs1, rs2) = (disasm::rd(instr), disasm::rs1(instr), disasm::rs2(instr));
+
+        let width = match disasm::funct3(instr) {
+            0x2 => "w",
+            0x3 => "d",
+            _ => return None
+        };
+
+        let operation = match (instr >> 27) & 0x1F {
+            0x2 => "lr",
+            0x3 => "sc",
+            0x01 => "amoswap",
+            0x00 => "amoadd",
+            0x04 => "amoxor",
+            0x08 => "amoor",
+            0x0C => "amoand",
+            0x10 => "amomin",
+            0x14 => "amomax",
+            0x18 => "amominu",
+            0x1c => "amomaxu",
+            _ => return None
+        };
+
+        let ordering = match (instr >> 25) & 0x3 {
+            0b11 => ".aqrl",
+            0b10 => ".aq",
+            0b01 => ".rl",
+            _ => "",
+        };
+
+        let mnemonic = format!("{}.{}{}", operation, width, ordering);
+        let address = format!("({})", disasm::x(rs1));
+
+        Some(match operation {
+            "lr" => Disassembly::new(&mnemonic, vec![disasm::x(rd), address]),
+            _ => Disassembly::new(&mnemonic, vec![disasm::x(rd), disasm::x(rs2), address]),
+        })
+    }
+}
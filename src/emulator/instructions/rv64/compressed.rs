@@ -0,0 +1,229 @@
+use crate::emulator::instructions::rv64::fp::{LOAD_FP_OPCODE, STORE_FP_OPCODE};
+use crate::emulator::instructions::rv64::int_op::{OP_OPCODE, OP_32_OPCODE};
+use crate::emulator::instructions::rv64::int_op_imm::{LUI_OPCODE, OP_IMM_OPCODE, OP_IMM_32_OPCODE};
+use crate::emulator::instructions::rv64::jump_branch::{BRANCH_OPCODE, JAL_OPCODE, JALR_OPCODE};
+use crate::emulator::instructions::rv64::load_store::{LOAD_OPCODE, STORE_OPCODE};
+
+// RVC expansion: every 16-bit parcel is rewritten into the 32-bit instruction it stands for,
+// so the base opcode groups execute it unchanged.
+
+const EBREAK: u32 = 0x0010_0073;
+
+const REG_RA: u32 = 1;
+const REG_SP: u32 = 2;
+
+// Parcels with the two low bits not set to 11 are compressed instructions
+#[inline(always)]
+pub fn is_compressed(instr: u32) -> bool {
+    instr & 0b11 != 0b11
+}
+
+#[inline(always)]
+fn bits(parcel: u32, high: u32, low: u32) -> u32 {
+    (parcel >> low) & ((1 << (high - low + 1)) - 1)
+}
+
+// Moves bit `from` of the parcel to bit `to` of the immediate
+#[inline(always)]
+fn bit(parcel: u32, from: u32, to: u32) -> u32 {
+    ((parcel >> from) & 1) << to
+}
+
+#[inline(always)]
+fn sign_extend(value: u32, width: u32) -> i32 {
+    ((value << (32 - width)) as i32) >> (32 - width)
+}
+
+// Registers x8-x15 as encoded by the 3-bit rd'/rs1'/rs2' fields
+#[inline(always)]
+fn compressed_register(field: u32) -> u32 {
+    field + 8
+}
+
+fn r_type(opcode: u8, rd: u32, funct3: u32, rs1: u32, rs2: u32, funct7: u32) -> u32 {
+    (funct7 << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode as u32
+}
+
+fn i_type(opcode: u8, rd: u32, funct3: u32, rs1: u32, imm: i32) -> u32 {
+    ((imm as u32 & 0xFFF) << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode as u32
+}
+
+fn s_type(opcode: u8, funct3: u32, rs1: u32, rs2: u32, imm: i32) -> u32 {
+    let imm = imm as u32;
+    (bits(imm, 11, 5) << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | (bits(imm, 4, 0) << 7) | opcode as u32
+}
+
+fn b_type(funct3: u32, rs1: u32, rs2: u32, imm: i32) -> u32 {
+    let imm = imm as u32;
+    bit(imm, 12, 31) | (bits(imm, 10, 5) << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12)
+        | (bits(imm, 4, 1) << 8) | bit(imm, 11, 7) | BRANCH_OPCODE as u32
+}
+
+fn j_type(rd: u32, imm: i32) -> u32 {
+    let imm = imm as u32;
+    bit(imm, 20, 31) | (bits(imm, 10, 1) << 21) | bit(imm, 11, 20) | (bits(imm, 19, 12) << 12)
+        | (rd << 7) | JAL_OPCODE as u32
+}
+
+fn u_type(opcode: u8, rd: u32, imm: i32) -> u32 {
+    (imm as u32 & 0xFFFF_F000) | (rd << 7) | opcode as u32
+}
+
+// Expands a 16-bit parcel into its 32-bit equivalent, None for reserved and illegal encodings
+pub fn expand(parcel: u16) -> Option<u32> {
+    let parcel = parcel as u32;
+    let funct3 = bits(parcel, 15, 13);
+
+    match parcel & 0b11 {
+        0b00 => expand_quadrant0(parcel, funct3),
+        0b01 => expand_quadrant1(parcel, funct3),
+        0b10 => expand_quadrant2(parcel, funct3),
+        _ => None,
+    }
+}
+
+fn expand_quadrant0(parcel: u32, funct3: u32) -> Option<u32> {
+    let rd = compressed_register(bits(parcel, 4, 2));
+    let rs1 = compressed_register(bits(parcel, 9, 7));
+
+    // Offsets of the word and double word loads/stores
+    let word_offset = (bits(parcel, 12, 10) << 3) | bit(parcel, 6, 2) | bit(parcel, 5, 6);
+    let double_offset = (bits(parcel, 12, 10) << 3) | (bits(parcel, 6, 5) << 6);
+
+    match funct3 {
+        0b000 => {
+            // C.ADDI4SPN, the all-zero parcel is defined illegal
+            let imm = (bits(parcel, 12, 11) << 4) | (bits(parcel, 10, 7) << 6) | bit(parcel, 6, 2) | bit(parcel, 5, 3);
+
+            if imm == 0 {
+                return None;
+            }
+
+            Some(i_type(OP_IMM_OPCODE, rd, 0b000, REG_SP, imm as i32))
+        }
+        0b001 => Some(i_type(LOAD_FP_OPCODE, rd, 0b011, rs1, double_offset as i32)),  // C.FLD
+        0b010 => Some(i_type(LOAD_OPCODE, rd, 0b010, rs1, word_offset as i32)),       // C.LW
+        0b011 => Some(i_type(LOAD_OPCODE, rd, 0b011, rs1, double_offset as i32)),     // C.LD
+        0b101 => Some(s_type(STORE_FP_OPCODE, 0b011, rs1, rd, double_offset as i32)), // C.FSD
+        0b110 => Some(s_type(STORE_OPCODE, 0b010, rs1, rd, word_offset as i32)),      // C.SW
+        0b111 => Some(s_type(STORE_OPCODE, 0b011, rs1, rd, double_offset as i32)),    // C.SD
+        _ => None,
+    }
+}
+
+fn expand_quadrant1(parcel: u32, funct3: u32) -> Option<u32> {
+    let rd = bits(parcel, 11, 7);
+    let imm = sign_extend(bit(parcel, 12, 5) | bits(parcel, 6, 2), 6);
+
+    match funct3 {
+        0b000 => Some(i_type(OP_IMM_OPCODE, rd, 0b000, rd, imm)), // C.ADDI, C.NOP
+        0b001 => {
+            // C.ADDIW, rd = 0 is reserved
+            if rd == 0 {
+                return None;
+            }
+
+            Some(i_type(OP_IMM_32_OPCODE, rd, 0b000, rd, imm))
+        }
+        0b010 => Some(i_type(OP_IMM_OPCODE, rd, 0b000, 0, imm)),  // C.LI
+        0b011 if rd == REG_SP => {
+            // C.ADDI16SP
+            let imm = bit(parcel, 12, 9) | bit(parcel, 6, 4) | bit(parcel, 5, 6) | (bits(parcel, 4, 3) << 7) | bit(parcel, 2, 5);
+
+            if imm == 0 {
+                return None;
+            }
+
+            Some(i_type(OP_IMM_OPCODE, REG_SP, 0b000, REG_SP, sign_extend(imm, 10)))
+        }
+        0b011 => {
+            // C.LUI
+            if imm == 0 {
+                return None;
+            }
+
+            Some(u_type(LUI_OPCODE, rd, imm << 12))
+        }
+        0b100 => expand_arithmetic(parcel),
+        0b101 => {
+            // C.J
+            let offset = bit(parcel, 12, 11) | bit(parcel, 11, 4) | (bits(parcel, 10, 9) << 8) | bit(parcel, 8, 10)
+                | bit(parcel, 7, 6) | bit(parcel, 6, 7) | (bits(parcel, 5, 3) << 1) | bit(parcel, 2, 5);
+
+            Some(j_type(0, sign_extend(offset, 12)))
+        }
+        _ => {
+            // C.BEQZ and C.BNEZ
+            let rs1 = compressed_register(bits(parcel, 9, 7));
+            let offset = bit(parcel, 12, 8) | (bits(parcel, 11, 10) << 3) | (bits(parcel, 6, 5) << 6)
+                | (bits(parcel, 4, 3) << 1) | bit(parcel, 2, 5);
+
+            Some(b_type(if funct3 == 0b110 { 0b000 } else { 0b001 }, rs1, 0, sign_extend(offset, 9)))
+        }
+    }
+}
+
+// C.SRLI, C.SRAI, C.ANDI and the register-register ALU group
+fn expand_arithmetic(parcel: u32) -> Option<u32> {
+    let rd = compressed_register(bits(parcel, 9, 7));
+    let rs2 = compressed_register(bits(parcel, 4, 2));
+    let shamt = bit(parcel, 12, 5) | bits(parcel, 6, 2);
+
+    match bits(parcel, 11, 10) {
+        0b00 => Some(i_type(OP_IMM_OPCODE, rd, 0b101, rd, shamt as i32)),            // C.SRLI
+        0b01 => Some(i_type(OP_IMM_OPCODE, rd, 0b101, rd, (0x400 | shamt) as i32)),  // C.SRAI
+        0b10 => Some(i_type(OP_IMM_OPCODE, rd, 0b111, rd, sign_extend(shamt, 6))),   // C.ANDI
+        _ => match (bit(parcel, 12, 0), bits(parcel, 6, 5)) {
+            (0, 0b00) => Some(r_type(OP_OPCODE, rd, 0b000, rd, rs2, 0x20)),    // C.SUB
+            (0, 0b01) => Some(r_type(OP_OPCODE, rd, 0b100, rd, rs2, 0x00)),    // C.XOR
+            (0, 0b10) => Some(r_type(OP_OPCODE, rd, 0b110, rd, rs2, 0x00)),    // C.OR
+            (0, 0b11) => Some(r_type(OP_OPCODE, rd, 0b111, rd, rs2, 0x00)),    // C.AND
+            (1, 0b00) => Some(r_type(OP_32_OPCODE, rd, 0b000, rd, rs2, 0x20)), // C.SUBW
+            (1, 0b01) => Some(r_type(OP_32_OPCODE, rd, 0b000, rd, rs2, 0x00)), // C.ADDW
+            _ => None,
+        },
+    }
+}
+
+fn expand_quadrant2(parcel: u32, funct3: u32) -> Option<u32> {
+    let rd = bits(parcel, 11, 7);
+    let rs2 = bits(parcel, 6, 2);
+
+    // Stack pointer relative offsets
+    let load_word_offset = bit(parcel, 12, 5) | (bits(parcel, 6, 4) << 2) | (bits(parcel, 3, 2) << 6);
+    let load_double_offset = bit(parcel, 12, 5) | (bits(parcel, 6, 5) << 3) | (bits(parcel, 4, 2) << 6);
+    let store_word_offset = (bits(parcel, 12, 9) << 2) | (bits(parcel, 8, 7) << 6);
+    let store_double_offset = (bits(parcel, 12, 10) << 3) | (bits(parcel, 9, 7) << 6);
+
+    match funct3 {
+        0b000 => {
+            // C.SLLI
+            let shamt = bit(parcel, 12, 5) | rs2;
+            Some(i_type(OP_IMM_OPCODE, rd, 0b001, rd, shamt as i32))
+        }
+        0b001 => Some(i_type(LOAD_FP_OPCODE, rd, 0b011, REG_SP, load_double_offset as i32)), // C.FLDSP
+        0b010 | 0b011 => {
+            // C.LWSP and C.LDSP, rd = 0 is reserved
+            if rd == 0 {
+                return None;
+            }
+
+            if funct3 == 0b010 {
+                Some(i_type(LOAD_OPCODE, rd, 0b010, REG_SP, load_word_offset as i32))
+            } else {
+                Some(i_type(LOAD_OPCODE, rd, 0b011, REG_SP, load_double_offset as i32))
+            }
+        }
+        0b100 => match (bit(parcel, 12, 0), rd, rs2) {
+            (0, 0, 0) => None,                                                    // C.JR with rs1 = 0 is reserved
+            (0, _, 0) => Some(i_type(JALR_OPCODE, 0, 0b000, rd, 0)),              // C.JR
+            (0, _, _) => Some(r_type(OP_OPCODE, rd, 0b000, 0, rs2, 0x00)),        // C.MV
+            (_, 0, 0) => Some(EBREAK),                                            // C.EBREAK
+            (_, _, 0) => Some(i_type(JALR_OPCODE, REG_RA, 0b000, rd, 0)),         // C.JALR
+            (_, _, _) => Some(r_type(OP_OPCODE, rd, 0b000, rd, rs2, 0x00)),       // C.ADD
+        },
+        0b101 => Some(s_type(STORE_FP_OPCODE, 0b011, REG_SP, rs2, store_double_offset as i32)), // C.FSDSP
+        0b110 => Some(s_type(STORE_OPCODE, 0b010, REG_SP, rs2, store_word_offset as i32)),      // C.SWSP
+        _ => Some(s_type(STORE_OPCODE, 0b011, REG_SP, rs2, store_double_offset as i32)),        // C.SDSP
+    }
+}
@@ -0,0 +1,160 @@
+use std::fmt;
+use crate::emulator::elf::SymbolTable;
+use crate::emulator::instructions::rv64::RV64InstructionParser;
+use crate::emulator::instructions::rv64::compressed::{expand, is_compressed};
+use crate::emulator::state::rv64_cpu_context::{CSRAddress, F_ABI_NAMES, X_ABI_NAMES};
+
+// Rendered instruction, pc relative jumps and branches also carry their absolute target
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disassembly {
+    pub mnemonic: String,
+    pub operands: Vec<String>,
+    pub target: Option<u64>,
+}
+
+impl Disassembly {
+    pub fn new(mnemonic: &str, operands: Vec<String>) -> Disassembly {
+        Self { mnemonic: mnemonic.to_string(), operands, target: None }
+    }
+
+    pub fn with_target(mut self, target: u64) -> Disassembly {
+        self.target = Some(target);
+        self
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.operands.is_empty() {
+            return write!(f, "{}", self.mnemonic);
+        }
+
+        write!(f, "{:<7} {}", self.mnemonic, self.operands.join(", "))
+    }
+}
+
+pub fn x(register: u32) -> String {
+    X_ABI_NAMES[register as usize].to_string()
+}
+
+pub fn f(register: u32) -> String {
+    F_ABI_NAMES[register as usize].to_string()
+}
+
+pub fn csr(address: u32) -> String {
+    CSRAddress::ALL.iter()
+        .find(|csr| **csr as u32 == address)
+        .map_or_else(|| format!("{:#x}", address), |csr| csr.name().to_string())
+}
+
+// Load and store operands like "8(sp)"
+pub fn offset(imm: i64, base: u32) -> String {
+    format!("{}({})", imm, x(base))
+}
+
+pub fn rd(instr: u32) -> u32 {
+    (instr >> 7) & 0x1F
+}
+
+pub fn rs1(instr: u32) -> u32 {
+    (instr >> 15) & 0x1F
+}
+
+pub fn rs2(instr: u32) -> u32 {
+    (instr >> 20) & 0x1F
+}
+
+pub fn rs3(instr: u32) -> u32 {
+    (instr >> 27) & 0x1F
+}
+
+pub fn funct3(instr: u32) -> u32 {
+    (instr >> 12) & 0x07
+}
+
+pub fn funct7(instr: u32) -> u32 {
+    (instr >> 25) & 0x7F
+}
+
+pub fn imm_i(instr: u32) -> i64 {
+    (instr as i32 >> 20) as i64
+}
+
+pub fn imm_s(instr: u32) -> i64 {
+    (((instr & 0xFE00_0000) as i32 >> 20) | ((instr >> 7) & 0x1F) as i32) as i64
+}
+
+pub fn imm_b(instr: u32) -> i64 {
+    let imm = ((instr >> 8) & 0xF) << 1 | ((instr >> 25) & 0x3F) << 5 | ((instr >> 7) & 1) << 11 | (instr >> 31) << 12;
+    ((imm << 19) as i32 >> 19) as i64
+}
+
+pub fn imm_j(instr: u32) -> i64 {
+    let imm = ((instr >> 21) & 0x3FF) << 1 | ((instr >> 20) & 1) << 11 | ((instr >> 12) & 0xFF) << 12 | (instr >> 31) << 20;
+    ((imm << 11) as i32 >> 11) as i64
+}
+
+// Upper 20 bits as written in lui/auipc
+pub fn imm_u(instr: u32) -> u32 {
+    instr >> 12
+}
+
+// Instruction parcel at the start of bytes and its length, None if bytes ends in the middle of it
+pub fn read_instruction(bytes: &[u8]) -> Option<(u32, usize)> {
+    let low = u16::from_le_bytes(bytes.get(0..2)?.try_into().unwrap()) as u32;
+
+    if is_compressed(low) {
+        return Some((low, 2));
+    }
+
+    let high = u16::from_le_bytes(bytes.get(2..4)?.try_into().unwrap()) as u32;
+
+    Some((low | high << 16, 4))
+}
+
+// Decodes an instruction as returned by fetch_instruction, compressed ones are shown as the
+// instruction they expand to
+pub fn disassemble(instr: u32, pc: u64) -> Disassembly {
+    if is_compressed(instr) {
+        return match instr {
+            0 => Disassembly::new("unimp", vec![]),
+            _ => expand(instr as u16)
+                .and_then(|expanded| RV64InstructionParser::disassemble(expanded, pc))
+                .unwrap_or_else(|| Disassembly::new(".2byte", vec![format!("{:#06x}", instr)])),
+        };
+    }
+
+    RV64InstructionParser::disassemble(instr, pc)
+        .unwrap_or_else(|| Disassembly::new(".4byte", vec![format!("{:#010x}", instr)]))
+}
+
+// Disassembly with the symbol of a jump or branch target appended
+pub fn format_instruction(instr: u32, pc: u64, symbols: &SymbolTable) -> String {
+    let disassembly = disassemble(instr, pc);
+
+    match disassembly.target.and_then(|target| symbols.describe(target)) {
+        Some(symbol) => format!("{} <{}>", disassembly, symbol),
+        None => disassembly.to_string(),
+    }
+}
+
+// objdump style listing of code loaded at base, with a label line wherever a symbol starts
+pub fn listing(bytes: &[u8], base: u64, symbols: &SymbolTable) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    while let Some((instr, length)) = read_instruction(&bytes[offset..]) {
+        let addr = base + offset as u64;
+
+        if let Some((symbol, 0)) = symbols.lookup(addr) {
+            lines.push(format!("{:016x} <{}>:", addr, symbol.name));
+        }
+
+        let raw = if length == 2 { format!("{:04x}", instr) } else { format!("{:08x}", instr) };
+        lines.push(format!("{:>16x}:  {:<8}  {}", addr, raw, format_instruction(instr, addr, symbols)));
+
+        offset += length;
+    }
+
+    lines
+}
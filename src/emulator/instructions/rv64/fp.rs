@@ -1,114 +1,112 @@
-use std::ops::{Div, Mul};
-use crate::emulator::instructions::{InstructionFn, ParsableInstructionGroup};
-use crate::emulator::state::rv64_cpu_context::{CSRAddress, Exception, FFlags, PrivilegeMode, RV64CPUContext};
-use crate::{wrap_b_type, wrap_b_type_u, wrap_i_type, wrap_i_type_sh, wrap_j_type, wrap_r_type, wrap_s_type};
+use crate::emulator::instructions::{DisassemblableInstructionGroup, InstructionFn, ParsableInstructionGroup};
+use crate::emulator::instructions::rv64::disasm::{self, Disassembly};
+use crate::emulator::state::rv64_cpu_context::{CSRAddress, Exception, FFlags, RV64CPUContext};
+use crate::{wrap_i_type, wrap_r4_type, wrap_r_type, wrap_s_type};
 use crate::emulator::instructions::rv64::InstructionResult;
+use crate::emulator::softfloat;
+use crate::emulator::softfloat::{FloatFormat, RoundingMode, DOUBLE, SINGLE};
 
-pub const LOAD_FP_OPCODE: u8 = 0b111_0011;
-pub const STORE_FP_OPCODE: u8 = 0b111_0011;
+pub const LOAD_FP_OPCODE: u8 = 0b000_0111;
+pub const STORE_FP_OPCODE: u8 = 0b010_0111;
 pub const OP_FP_OPCODE: u8 = 0b101_0011;
+pub const FMADD_OPCODE: u8 = 0b100_0011;
+pub const FMSUB_OPCODE: u8 = 0b100_0111;
+pub const FNMSUB_OPCODE: u8 = 0b100_1011;
+pub const FNMADD_OPCODE: u8 = 0b100_1111;
 
+// fmt field of OP-FP and the fused opcodes
+const FMT_S: u32 = 0b00;
+const FMT_D: u32 = 0b01;
+
+// Rounding mode value that selects the dynamic mode in frm
+const RM_DYNAMIC: u8 = 0b111;
 
 pub struct FloatingPointOpcodeGroup {}
 pub struct LoadFloatingPointOpcodeGroup {}
 pub struct StoreFloatingPointOpcodeGroup {}
+pub struct FusedMultiplyAddOpcodeGroup {}
 
+// Every floating point instruction raises Illegal Instruction while mstatus.FS is Off
+#[inline(always)]
+fn check_fp_enabled(cpu_context: &RV64CPUContext) -> InstructionResult {
+    if !cpu_context.csrs.is_fp_enabled() {
+        return Err(Exception::IllegalInstruction);
+    }
 
-type FloatingExecutionFn = fn(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8);
+    Ok(())
+}
 
+// Only single and double precision are implemented, H and Q are reserved
 #[inline(always)]
-fn apply_rounding_mode(value: f64, mode: u8) -> f64 {
-    match mode {
-        0 => value,
-        1 => value.trunc(),
-        3 => value.ceil(),
-        2 => value.floor(),
-        _ => value.round(),
+fn decode_fmt(instr: u32) -> Result<u32, Exception> {
+    match (instr >> 25) & 0x3 {
+        FMT_S => Ok(FMT_S),
+        FMT_D => Ok(FMT_D),
+        _ => Err(Exception::IllegalInstruction),
     }
 }
 
-fn update_fp_flags(cpu_context: &mut RV64CPUContext, op: &str, rs1_val: f64, rs2_val: f64, result: f64, is_f32: bool) -> Result<(), Exception> {
-    let mut flags: u64 = 0;
-
-    if is_f32 {
-        // Convert to f32 for proper flag detection at single precision
-        let rs1_f32 = rs1_val as f32;
-        let rs2_f32 = rs2_val as f32;
-        let result_f32 = result as f32;
+#[inline(always)]
+fn float_format(fmt: u32) -> FloatFormat {
+    if fmt == FMT_S { SINGLE } else { DOUBLE }
+}
 
-        // Check for Invalid Operation (NV)
-        if result_f32.is_nan() && !rs1_f32.is_nan() && !rs2_f32.is_nan() {
-            flags |= FFlags::NV.bits();
-        }
+// Resolves the rounding mode of an instruction, reading frm for the dynamic mode
+fn decode_rounding_mode(cpu_context: &mut RV64CPUContext, instr: u32) -> Result<RoundingMode, Exception> {
+    let mut rm = ((instr >> 12) & 0x7) as u8;
 
-        // Check for Divide by Zero (DZ)
-        if op == "div" && rs2_f32 == 0.0 {
-            flags |= FFlags::DZ.bits();
-        }
+    if rm == RM_DYNAMIC {
+        rm = cpu_context.csrs.read_csr(CSRAddress::FRM as u16, false)? as u8;
+    }
 
-        // Check for Overflow (OF)
-        if result_f32.is_infinite() && !rs1_f32.is_infinite() && !rs2_f32.is_infinite() {
-            flags |= FFlags::OF.bits();
-        }
+    // 5 and 6 are reserved, as is an invalid mode in frm
+    RoundingMode::from_bits(rm).ok_or(Exception::IllegalInstruction)
+}
 
-        // Check for Underflow (UF)
-        if let core::num::FpCategory::Subnormal = result_f32.classify() {
-            flags |= FFlags::UF.bits();
-        }
+// Reads an operand as a raw bit pattern of the given format, unboxed singles become the canonical NaN
+#[inline(always)]
+fn read_float(cpu_context: &RV64CPUContext, fmt: u32, register: u8) -> u64 {
+    if fmt == FMT_S {
+        cpu_context.get_register_single(register as usize) as u64
     } else {
-        // Double precision checks
-        // Check for Invalid Operation (NV)
-        if result.is_nan() && !rs1_val.is_nan() && !rs2_val.is_nan() {
-            flags |= FFlags::NV.bits();
-        }
-
-        // Check for Divide by Zero (DZ)
-        if op == "div" && rs2_val == 0.0 {
-            flags |= FFlags::DZ.bits();
-        }
-
-        // Check for Overflow (OF)
-        if result.is_infinite() && !rs1_val.is_infinite() && !rs2_val.is_infinite() {
-            flags |= FFlags::OF.bits();
-        }
-
-        // Check for Underflow (UF)
-        if let core::num::FpCategory::Subnormal = result.classify() {
-            flags |= FFlags::UF.bits();
-        }
+        cpu_context.f[register as usize]
     }
+}
 
-    // If any flags were set, write them to FFLAGS CSR (OR with existing flags)
-    if flags != 0 {
-        let current_flags = cpu_context.csrs.read_csr(CSRAddress::FFlags as u16, false)?;
-        cpu_context.csrs.write_csr(CSRAddress::FFlags as u16, current_flags | flags, false)?;
+// Writes a result, NaN-boxing singles
+#[inline(always)]
+fn write_float(cpu_context: &mut RV64CPUContext, fmt: u32, register: u8, bits: u64) {
+    if fmt == FMT_S {
+        cpu_context.set_register_single(register as usize, bits as u32);
+    } else {
+        cpu_context.set_register_float(register as usize, bits);
     }
+}
 
-    Ok(())
+// Accrues exception flags in fflags
+#[inline(always)]
+fn raise_flags(cpu_context: &mut RV64CPUContext, flags: FFlags) {
+    if !flags.is_empty() {
+        cpu_context.csrs.set_fcsr_bit(flags.bits());
+    }
 }
 
 fn exec_load_fp(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, imm: u64) -> InstructionResult {
-    let address = cpu_context.x[rs1 as usize].wrapping_add(imm);
+    check_fp_enabled(cpu_context)?;
 
-    if address >= cpu_context.memory.read().unwrap().size() {
-        return Err(Exception::LoadAccessFault);
-    }
+    let address = cpu_context.x[rs1 as usize].wrapping_add(imm);
 
     match (instr >> 12) & 0x7 {
         0x2 => {
-            let mut value: u64 = cpu_context.memory.read().unwrap().read_word(address as usize) as u64;
-
-            if (value & 0x80000000) > 0 {
-                value |= !0xFFFFFFFF_u64;
-            }
+            let value = cpu_context.load(address, 4)?;
 
-            cpu_context.set_register_float(rd as usize, value as f32 as f64);
+            write_float(cpu_context, FMT_S, rd, value);
             Ok(())
         }
         0x3 => {
-            let value: f64 = cpu_context.memory.read().unwrap().read_double_word(address as usize) as f64;
+            let value = cpu_context.load(address, 8)?;
 
-            cpu_context.set_register_float(rd as usize, value);
+            write_float(cpu_context, FMT_D, rd, value);
             Ok(())
         }
         _ => { Err(Exception::IllegalInstruction) }
@@ -116,20 +114,19 @@ fn exec_load_fp(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, i
 }
 
 fn exec_store_fp(cpu_context: &mut RV64CPUContext, instr: u32, rs1: u8, rs2: u8, imm: u64) -> InstructionResult {
-    let address = cpu_context.x[rs1 as usize].wrapping_add(imm);
+    check_fp_enabled(cpu_context)?;
 
-    if address + 7 >= cpu_context.memory.read().unwrap().size() {
-        return Err(Exception::StoreAccessFault);
-    }
+    let address = cpu_context.x[rs1 as usize].wrapping_add(imm);
 
     match (instr >> 12) & 0x7 {
         0x2 => {
-            cpu_context.memory.write().unwrap().write_word(address as usize, cpu_context.f[rs2 as usize] as u32);
+            // Transfers move the raw low bits and never check the NaN-boxing
+            cpu_context.store(address, 4, cpu_context.f[rs2 as usize] & 0xFFFF_FFFF)?;
 
             Ok(())
         }
         0x3 => {
-            cpu_context.memory.write().unwrap().write_double_word(address as usize, cpu_context.f[rs2 as usize] as u64);
+            cpu_context.store(address, 8, cpu_context.f[rs2 as usize])?;
 
             Ok(())
         }
@@ -137,217 +134,266 @@ fn exec_store_fp(cpu_context: &mut RV64CPUContext, instr: u32, rs1: u8, rs2: u8,
     }
 }
 
+// Shared body of FADD, FSUB, FMUL and FDIV
+fn exec_binary_op(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8, op: fn(FloatFormat, u64, u64, RoundingMode) -> (u64, FFlags)) -> InstructionResult {
+    check_fp_enabled(cpu_context)?;
+
+    let fmt = decode_fmt(instr)?;
+    let rm = decode_rounding_mode(cpu_context, instr)?;
+
+    let a = read_float(cpu_context, fmt, rs1);
+    let b = read_float(cpu_context, fmt, rs2);
+    let (result, flags) = op(float_format(fmt), a, b, rm);
+
+    write_float(cpu_context, fmt, rd, result);
+    raise_flags(cpu_context, flags);
+
+    Ok(())
+}
+
 fn exec_fadd(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> InstructionResult {
-    let mut rm = ((instr >> 12) & 0x7) as u8;
+    exec_binary_op(cpu_context, instr, rd, rs1, rs2, softfloat::add)
+}
 
-    if(rm == 16) {
-        //Dynamic rounding
-        rm = cpu_context.csrs.read_csr(CSRAddress::FRM as u16, false)? as u8;
-    }
+fn exec_fsub(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> InstructionResult {
+    exec_binary_op(cpu_context, instr, rd, rs1, rs2, softfloat::sub)
+}
 
-    match (instr >> 25) & 0x3 {
-        0x0 => {
-            cpu_context.set_register_float(rd as usize, apply_rounding_mode((cpu_context.f[rs1 as usize] as f32 + cpu_context.f[rs2 as usize] as f32) as f64, rm));
+fn exec_fmul(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> InstructionResult {
+    exec_binary_op(cpu_context, instr, rd, rs1, rs2, softfloat::mul)
+}
 
-            update_fp_flags(cpu_context, "add", cpu_context.f[rs1 as usize], cpu_context.f[rs2 as usize], cpu_context.f[rd as usize], true)?;
-        }
-        0x1 => {
-            cpu_context.set_register_float(rd as usize, apply_rounding_mode((cpu_context.f[rs1 as usize] as f32 + cpu_context.f[rs2 as usize] as f32) as f64, rm));
+fn exec_fdiv(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> InstructionResult {
+    exec_binary_op(cpu_context, instr, rd, rs1, rs2, softfloat::div)
+}
 
-            update_fp_flags(cpu_context, "add", cpu_context.f[rs1 as usize], cpu_context.f[rs2 as usize], cpu_context.f[rd as usize], false)?;
-        }
-        _ => { cpu_context.csrs.write_csr(CSRAddress::FFlags as u16, FFlags::NV.bits(), false)? }
+fn exec_fsqrt(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> InstructionResult {
+    check_fp_enabled(cpu_context)?;
+
+    // rs2 is part of the encoding and must be zero
+    if rs2 != 0 {
+        return Err(Exception::IllegalInstruction);
     }
 
+    let fmt = decode_fmt(instr)?;
+    let rm = decode_rounding_mode(cpu_context, instr)?;
+
+    let (result, flags) = softfloat::sqrt(float_format(fmt), read_float(cpu_context, fmt, rs1), rm);
+
+    write_float(cpu_context, fmt, rd, result);
+    raise_flags(cpu_context, flags);
+
     Ok(())
 }
 
-fn exec_fsub(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> InstructionResult {
-    let mut rm = ((instr >> 12) & 0x7) as u8;
+fn exec_fsgnj(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> InstructionResult {
+    check_fp_enabled(cpu_context)?;
 
-    if(rm == 16) {
-        //Dynamic rounding
-        rm = cpu_context.csrs.read_csr(CSRAddress::FRM as u16, false)? as u8;
-    }
+    let fmt = decode_fmt(instr)?;
+    let sign = float_format(fmt).sign_mask();
 
-    match (instr >> 25) & 0x3 {
-        0x0 => {
-            cpu_context.set_register_float(rd as usize, apply_rounding_mode((cpu_context.f[rs1 as usize] as f32 - cpu_context.f[rs2 as usize] as f32) as f64, rm));
+    let a = read_float(cpu_context, fmt, rs1);
+    let b = read_float(cpu_context, fmt, rs2);
 
-            update_fp_flags(cpu_context, "sub", cpu_context.f[rs1 as usize], cpu_context.f[rs2 as usize], cpu_context.f[rd as usize], true)?;
-        }
-        0x1 => {
-            cpu_context.set_register_float(rd as usize, apply_rounding_mode((cpu_context.f[rs1 as usize] as f32 - cpu_context.f[rs2 as usize] as f32) as f64, rm));
+    // Sign injection only touches the sign bit, the rest of rs1 is copied verbatim
+    let result = match (instr >> 12) & 0x7 {
+        0x0 => (a & !sign) | (b & sign),    // FSGNJ
+        0x1 => (a & !sign) | (!b & sign),   // FSGNJN
+        0x2 => a ^ (b & sign),              // FSGNJX
+        _ => return Err(Exception::IllegalInstruction),
+    };
 
-            update_fp_flags(cpu_context, "sub", cpu_context.f[rs1 as usize], cpu_context.f[rs2 as usize], cpu_context.f[rd as usize], false)?;
-        }
-        _ => { cpu_context.csrs.write_csr(CSRAddress::FFlags as u16, FFlags::NV.bits(), false)? }
-    }
+    write_float(cpu_context, fmt, rd, result);
 
     Ok(())
 }
 
-fn exec_fmul(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> Result<(), Exception> {
-    let mut rm = ((instr >> 12) & 0x7) as u8;
+fn exec_fminmax(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> InstructionResult {
+    check_fp_enabled(cpu_context)?;
 
-    if(rm == 16) {
-        //Dynamic rounding
-        rm = cpu_context.csrs.read_csr(CSRAddress::FRM as u16, false)? as u8;
-    }
+    let fmt = decode_fmt(instr)?;
 
-    match (instr >> 25) & 0x3 {
-        0x0 => {
-            cpu_context.set_register_float(rd as usize, apply_rounding_mode((cpu_context.f[rs1 as usize] as f32).mul(cpu_context.f[rs2 as usize] as f32) as f64, rm));
+    let op = match (instr >> 12) & 0x7 {
+        0x0 => softfloat::min,
+        0x1 => softfloat::max,
+        _ => return Err(Exception::IllegalInstruction),
+    };
 
-            update_fp_flags(cpu_context, "fmul", cpu_context.f[rs1 as usize], cpu_context.f[rs2 as usize], cpu_context.f[rd as usize], true)?;
-        }
-        0x1 => {
-            cpu_context.set_register_float(rd as usize, apply_rounding_mode(cpu_context.f[rs1 as usize].mul(cpu_context.f[rs2 as usize]), rm));
+    let a = read_float(cpu_context, fmt, rs1);
+    let b = read_float(cpu_context, fmt, rs2);
+    let (result, flags) = op(float_format(fmt), a, b);
 
-            update_fp_flags(cpu_context, "fmul", cpu_context.f[rs1 as usize], cpu_context.f[rs2 as usize], cpu_context.f[rd as usize], false)?;
-        }
-        _ => { cpu_context.csrs.write_csr(CSRAddress::FFlags as u16, FFlags::NV.bits(), false)? }
-    }
+    write_float(cpu_context, fmt, rd, result);
+    raise_flags(cpu_context, flags);
 
     Ok(())
 }
 
-fn exec_fdiv(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> Result<(), Exception> {
-    let mut rm = ((instr >> 12) & 0x7) as u8;
+// FCVT.S.D and FCVT.D.S
+fn exec_fcvt_fp_fp(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> InstructionResult {
+    check_fp_enabled(cpu_context)?;
 
-    if(rm == 16) {
-        //Dynamic rounding
-        rm = cpu_context.csrs.read_csr(CSRAddress::FRM as u16, false)? as u8;
+    let fmt = decode_fmt(instr)?;
+    let rm = decode_rounding_mode(cpu_context, instr)?;
+
+    // rs2 holds the source format and has to be the other one
+    let source = rs2 as u32;
+
+    if (fmt, source) != (FMT_S, FMT_D) && (fmt, source) != (FMT_D, FMT_S) {
+        return Err(Exception::IllegalInstruction);
     }
 
-    match (instr >> 25) & 0x3 {
-        0x0 => {
-            if(cpu_context.f[rs2 as usize] as f32 == 0f32) {
-                update_fp_flags(cpu_context, "div", cpu_context.f[rs1 as usize], cpu_context.f[rs2 as usize], cpu_context.f[rd as usize], true)?;
+    let a = read_float(cpu_context, source, rs1);
+    let (result, flags) = softfloat::convert(float_format(source), float_format(fmt), a, rm);
 
-                return Ok(());
-            }
+    write_float(cpu_context, fmt, rd, result);
+    raise_flags(cpu_context, flags);
 
-            cpu_context.set_register_float(rd as usize, apply_rounding_mode((cpu_context.f[rs1 as usize] as f32).div(cpu_context.f[rs2 as usize] as f32) as f64, rm));
+    Ok(())
+}
 
-            update_fp_flags(cpu_context, "div", cpu_context.f[rs1 as usize], cpu_context.f[rs2 as usize], cpu_context.f[rd as usize], true)?;
-        }
-        0x1 => {
-            if(cpu_context.f[rs2 as usize] == 0f64) {
-                update_fp_flags(cpu_context, "div", cpu_context.f[rs1 as usize], cpu_context.f[rs2 as usize], cpu_context.f[rd as usize], false)?;
+// FCVT.{W,WU,L,LU}.{S,D}, out of range values and NaN saturate and raise NV
+fn exec_fcvt_int_fp(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> InstructionResult {
+    check_fp_enabled(cpu_context)?;
 
-                return Ok(());
-            }
+    let fmt = decode_fmt(instr)?;
+    let rm = decode_rounding_mode(cpu_context, instr)?;
 
-            cpu_context.set_register_float(rd as usize, apply_rounding_mode(cpu_context.f[rs1 as usize].div(cpu_context.f[rs2 as usize]), rm));
-            update_fp_flags(cpu_context, "div", cpu_context.f[rs1 as usize], cpu_context.f[rs2 as usize], cpu_context.f[rd as usize], false)?;
-        }
-        _ => { cpu_context.csrs.write_csr(CSRAddress::FFlags as u16, FFlags::NV.bits(), false)? }
-    }
+    let (signed, width) = match rs2 {
+        0x0 => (true, 32),
+        0x1 => (false, 32),
+        0x2 => (true, 64),
+        0x3 => (false, 64),
+        _ => return Err(Exception::IllegalInstruction),
+    };
+
+    let a = read_float(cpu_context, fmt, rs1);
+    let (result, flags) = softfloat::to_int(float_format(fmt), a, rm, signed, width);
+
+    cpu_context.set_register(rd as usize, result);
+    raise_flags(cpu_context, flags);
 
     Ok(())
 }
 
-fn exec_fsqrt(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> Result<(), Exception> {
-    let mut rm = ((instr >> 12) & 0x7) as u8;
+// FCVT.{S,D}.{W,WU,L,LU}
+fn exec_fcvt_fp_int(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> InstructionResult {
+    check_fp_enabled(cpu_context)?;
 
-    if(rm == 16) {
-        //Dynamic rounding
-        rm = cpu_context.csrs.read_csr(CSRAddress::FRM as u16, false)? as u8;
-    }
+    let fmt = decode_fmt(instr)?;
+    let rm = decode_rounding_mode(cpu_context, instr)?;
 
-    match (instr >> 25) & 0x3 {
-        0x0 => {
-            cpu_context.set_register_float(rd as usize, apply_rounding_mode((cpu_context.f[rs1 as usize] as f32).sqrt() as f64, rm));
-            update_fp_flags(cpu_context, "sqrt", cpu_context.f[rs1 as usize], cpu_context.f[rs2 as usize], cpu_context.f[rd as usize], false)?;
-        }
-        0x1 => {
-            cpu_context.set_register_float(rd as usize, apply_rounding_mode(cpu_context.f[rs1 as usize].sqrt(), rm));
-            update_fp_flags(cpu_context, "sqrt", cpu_context.f[rs1 as usize], cpu_context.f[rs2 as usize], cpu_context.f[rd as usize], false)?;
-        }
-        _ => { cpu_context.csrs.write_csr(CSRAddress::FFlags as u16, FFlags::NV.bits(), false)? }
-    }
+    let (signed, width) = match rs2 {
+        0x0 => (true, 32),
+        0x1 => (false, 32),
+        0x2 => (true, 64),
+        0x3 => (false, 64),
+        _ => return Err(Exception::IllegalInstruction),
+    };
+
+    let (result, flags) = softfloat::from_int(float_format(fmt), cpu_context.x[rs1 as usize], signed, width, rm);
+
+    write_float(cpu_context, fmt, rd, result);
+    raise_flags(cpu_context, flags);
 
     Ok(())
 }
 
-fn exec_fminmax(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> Result<(), Exception> {
-    let mode = ((instr >> 12) & 0x7) as u8;
+// FEQ is a quiet comparison, FLT and FLE signal on any NaN operand
+fn exec_fcmp(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> InstructionResult {
+    check_fp_enabled(cpu_context)?;
 
-    match (instr >> 25) & 0x3 {
-        0x0 => {
-            cpu_context.set_register_float(rd as usize, match mode {
-                0x0 => (cpu_context.f[rs1 as usize] as f32).min(cpu_context.f[rs2 as usize] as f32) as f64,
-                0x1 => (cpu_context.f[rs1 as usize] as f32).max(cpu_context.f[rs2 as usize] as f32) as f64,
-                _ => { cpu_context.csrs.write_csr(CSRAddress::FFlags as u16, FFlags::NV.bits(), false)?;
-                    return Ok(());
-                }
-            });
-            update_fp_flags(cpu_context, "minmax", cpu_context.f[rs1 as usize], cpu_context.f[rs2 as usize], cpu_context.f[rd as usize], false)?;
-        }
-        0x1 => {
-            cpu_context.set_register_float(rd as usize, match mode {
-                0x0 => cpu_context.f[rs1 as usize].min(cpu_context.f[rs2 as usize]),
-                0x1 => cpu_context.f[rs1 as usize].max(cpu_context.f[rs2 as usize]),
-                _ => { cpu_context.csrs.write_csr(CSRAddress::FFlags as u16, FFlags::NV.bits(), false)?;
-                    return Ok(());
-                }
-            });
-            update_fp_flags(cpu_context, "minmax", cpu_context.f[rs1 as usize], cpu_context.f[rs2 as usize], cpu_context.f[rd as usize], false)?;
-        }
-        _ => {  }
-    }
+    let fmt = decode_fmt(instr)?;
+
+    let op = match (instr >> 12) & 0x7 {
+        0x2 => softfloat::eq,
+        0x1 => softfloat::lt,
+        0x0 => softfloat::le,
+        _ => return Err(Exception::IllegalInstruction),
+    };
+
+    let a = read_float(cpu_context, fmt, rs1);
+    let b = read_float(cpu_context, fmt, rs2);
+    let (result, flags) = op(float_format(fmt), a, b);
+
+    cpu_context.set_register(rd as usize, result as u64);
+    raise_flags(cpu_context, flags);
 
     Ok(())
 }
 
-fn exec_fmadd(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> Result<(), Exception> {
-    let mut rm = ((instr >> 12) & 0x7) as u8;
+// FMV.X.W, FMV.X.D and FCLASS share funct5
+fn exec_fmv_x_fclass(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> InstructionResult {
+    check_fp_enabled(cpu_context)?;
 
-    if(rm == 16) {
-        //Dynamic rounding
-        rm = cpu_context.csrs.read_csr(CSRAddress::FRM as u16, false)? as u8;
+    if rs2 != 0 {
+        return Err(Exception::IllegalInstruction);
     }
 
-    let rs3 = ((instr >> 27) & 0x1F) as u8;
+    let fmt = decode_fmt(instr)?;
 
-    match (instr >> 25) & 0x3 {
-        0x0 => {
-            cpu_context.set_register_float(rd as usize, apply_rounding_mode(((cpu_context.f[rs1 as usize] as f32) * (cpu_context.f[rs2 as usize] as f32) + (cpu_context.f[rs3 as usize] as f32)) as f64, rm));
-            update_fp_flags(cpu_context, "fmadd", cpu_context.f[rs1 as usize], cpu_context.f[rs2 as usize], cpu_context.f[rd as usize], false)?;
-        }
-        0x1 => {
-            cpu_context.set_register_float(rd as usize, apply_rounding_mode(cpu_context.f[rs1 as usize] * cpu_context.f[rs2 as usize] + cpu_context.f[rs3 as usize], rm));
-            update_fp_flags(cpu_context, "fmadd", cpu_context.f[rs1 as usize], cpu_context.f[rs2 as usize], cpu_context.f[rd as usize], false)?;
-        }
-        _ => { cpu_context.csrs.write_csr(CSRAddress::FFlags as u16, FFlags::NV.bits(), false)? }
-    }
+    let result = match ((instr >> 12) & 0x7, fmt) {
+        // FMV.X.W copies the raw low bits, whether or not they are NaN-boxed
+        (0x0, FMT_S) => cpu_context.f[rs1 as usize] as u32 as i32 as i64 as u64,
+        (0x0, _) => cpu_context.f[rs1 as usize],
+        (0x1, _) => softfloat::classify(float_format(fmt), read_float(cpu_context, fmt, rs1)),
+        _ => return Err(Exception::IllegalInstruction),
+    };
+
+    cpu_context.set_register(rd as usize, result);
 
     Ok(())
 }
 
-fn exec_fmsub(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> Result<(), Exception> {
-    let mut rm = ((instr >> 12) & 0x7) as u8;
+// FMV.W.X and FMV.D.X
+fn exec_fmv_fp_x(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> InstructionResult {
+    check_fp_enabled(cpu_context)?;
 
-    if(rm == 16) {
-        //Dynamic rounding
-        rm = cpu_context.csrs.read_csr(CSRAddress::FRM as u16, false)? as u8;
+    if rs2 != 0 || (instr >> 12) & 0x7 != 0 {
+        return Err(Exception::IllegalInstruction);
     }
 
-    let rs3 = ((instr >> 27) & 0x1F) as u8;
+    let fmt = decode_fmt(instr)?;
+    let source = cpu_context.x[rs1 as usize];
 
-    match (instr >> 25) & 0x3 {
-        0x0 => {
-            cpu_context.set_register_float(rd as usize, apply_rounding_mode(((cpu_context.f[rs1 as usize] as f32) * (cpu_context.f[rs2 as usize] as f32) - (cpu_context.f[rs3 as usize] as f32)) as f64, rm));
-            update_fp_flags(cpu_context, "fmadd", cpu_context.f[rs1 as usize], cpu_context.f[rs2 as usize], cpu_context.f[rd as usize], false)?;
-        }
-        0x1 => {
-            cpu_context.set_register_float(rd as usize, apply_rounding_mode(cpu_context.f[rs1 as usize] * cpu_context.f[rs2 as usize] - cpu_context.f[rs3 as usize], rm));
-            update_fp_flags(cpu_context, "fmadd", cpu_context.f[rs1 as usize], cpu_context.f[rs2 as usize], cpu_context.f[rd as usize], false)?;
-        }
-        _ => { cpu_context.csrs.write_csr(CSRAddress::FFlags as u16, FFlags::NV.bits(), false)? }
+    write_float(cpu_context, fmt, rd, source);
+
+    Ok(())
+}
+
+// Shared body of the four fused multiply-add opcodes, the product is never rounded on its own
+fn exec_fused(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8, rs3: u8) -> InstructionResult {
+    check_fp_enabled(cpu_context)?;
+
+    let fmt = decode_fmt(instr)?;
+    let rm = decode_rounding_mode(cpu_context, instr)?;
+    let format = float_format(fmt);
+
+    // (negate product, negate addend)
+    let (negate_product, negate_addend) = match (instr & 0x7F) as u8 {
+        FMADD_OPCODE => (false, false),
+        FMSUB_OPCODE => (false, true),
+        FNMSUB_OPCODE => (true, false),
+        _ => (true, true),
+    };
+
+    let mut a = read_float(cpu_context, fmt, rs1);
+    let b = read_float(cpu_context, fmt, rs2);
+    let mut c = read_float(cpu_context, fmt, rs3);
+
+    if negate_product {
+        a ^= format.sign_mask();
+    }
+
+    if negate_addend {
+        c ^= format.sign_mask();
     }
 
+    let (result, flags) = softfloat::fused_mul_add(format, a, b, c, rm);
+
+    write_float(cpu_context, fmt, rd, result);
+    raise_flags(cpu_context, flags);
+
     Ok(())
 }
 
@@ -355,36 +401,207 @@ impl ParsableInstructionGroup for FloatingPointOpcodeGroup {
     fn parse(instr: u32) -> InstructionFn {
         let funct5 = ((instr >> 27) & 0x1F) as u8;
 
-        match (funct5) {
-            0x0 => wrap_r_type!(exec_fadd),
-            0x1 => wrap_r_type!(exec_fsub),
-            0x2 => wrap_r_type!(exec_fmul),
-            0x3 => wrap_r_type!(exec_fdiv),
-            0x5 => wrap_r_type!(exec_fminmax),
-            0xB => wrap_r_type!(exec_fsqrt),
+        match funct5 {
+            0x00 => wrap_r_type!(exec_fadd),
+            0x01 => wrap_r_type!(exec_fsub),
+            0x02 => wrap_r_type!(exec_fmul),
+            0x03 => wrap_r_type!(exec_fdiv),
+            0x04 => wrap_r_type!(exec_fsgnj),
+            0x05 => wrap_r_type!(exec_fminmax),
+            0x08 => wrap_r_type!(exec_fcvt_fp_fp),
+            0x0B => wrap_r_type!(exec_fsqrt),
+            0x14 => wrap_r_type!(exec_fcmp),
+            0x18 => wrap_r_type!(exec_fcvt_int_fp),
+            0x1A => wrap_r_type!(exec_fcvt_fp_int),
+            0x1C => wrap_r_type!(exec_fmv_x_fclass),
+            0x1E => wrap_r_type!(exec_fmv_fp_x),
             _ => |_,_| { Err(Exception::IllegalInstruction) },
         }
     }
 }
 
-impl ParsableInstructionGroup for LoadFloatingPointOpcodeGroup {
-    fn parse(instr: u32) -> InstructionFn {
-        let funct5 = ((instr >> 27) & 0x1F) as u8;
-        let fmt = ((instr >> 25) & 0x3) as u8;
+impl ParsableInstructionGroup for FusedMultiplyAddOpcodeGroup {
+    fn parse(_instr: u32) -> InstructionFn {
+        wrap_r4_type!(exec_fused)
+    }
+}
 
-        match (fmt, funct5) {
-            (_, _) => wrap_i_type!(exec_load_fp),
-        }
+impl ParsableInstructionGroup for LoadFloatingPointOpcodeGroup {
+    fn parse(_instr: u32) -> InstructionFn {
+        wrap_i_type!(exec_load_fp)
     }
 }
 
 impl ParsableInstructionGroup for StoreFloatingPointOpcodeGroup {
-    fn parse(instr: u32) -> InstructionFn {
-        let funct5 = ((instr >> 27) & 0x1F) as u8;
-        let fmt = ((instr >> 25) & 0x3) as u8;
+    fn parse(_instr: u32) -> InstructionFn {
+        wrap_s_type!(exec_store_fp)
+    }
+}
 
-        match (fmt, funct5) {
-            (_, _) => wrap_s_type!(exec_store_fp),
-        }
+// Suffix of the instruction's fmt field, None for the unimplemented H and Q formats
+fn fmt_suffix(fmt: u32) -> Option<&'static str> {
+    match fmt {
+        FMT_S => Some("s"),
+        FMT_D => Some("d"),
+        _ => None,
+    }
+}
+
+// Operands followed by the static rounding mode, the dynamic one is left implicit like binutils does
+fn with_rounding_mode(instr: u32, mut operands: Vec<String>) -> Vec<String> {
+    let rm = ((instr >> 12) & 0x7) as u8;
+
+    if rm != RM_DYNAMIC {
+        operands.push(match rm {
+            0 => "rne".to_string(),
+            1 => "rtz".to_string(),
+            2 => "rdn".to_string(),
+            3 => "rup".to_string(),
+            4 => "rmm".to_string(),
+            _ => rm.to_string(),
+        });
+    }
+
+    operands
+}
+
+// Integer operand width of fcvt, selected by the rs2 field
+fn int_suffix(rs2: u32) -> Option<&'static str> {
+    match rs2 {
+        0x0 => Some("w"),
+        0x1 => Some("wu"),
+        0x2 => Some("l"),
+        0x3 => Some("lu"),
+        _ => None,
+    }
+}
+
+impl DisassemblableInstructionGroup for FloatingPointOpcodeGroup {
+    fn disassemble(instr: u32, _pc: u64) -> Option<Disassembly> {
+        let (rd, rs1, rs2) = (disasm::rd(instr), disasm::rs1(instr), disasm::rs2(instr));
+        let fmt = fmt_suffix((instr >> 25) & 0x3)?;
+        let funct3 = disasm::funct3(instr);
+
+        let binary = |operation: &str| Disassembly::new(
+            &format!("{}.{}", operation, fmt),
+            vec![disasm::f(rd), disasm::f(rs1), disasm::f(rs2)]
+        );
+
+        Some(match (instr >> 27) & 0x1F {
+            0x00..=0x03 => {
+                let operation = ["fadd", "fsub", "fmul", "fdiv"][((instr >> 27) & 0x3) as usize];
+
+                Disassembly::new(
+                    &format!("{}.{}", operation, fmt),
+                    with_rounding_mode(instr, vec![disasm::f(rd), disasm::f(rs1), disasm::f(rs2)])
+                )
+            },
+            0x0B if rs2 == 0 => Disassembly::new(&format!("fsqrt.{}", fmt), with_rounding_mode(instr, vec![disasm::f(rd), disasm::f(rs1)])),
+            0x04 => {
+                let (operation, pseudo) = match funct3 {
+                    0x0 => ("fsgnj", "fmv"),
+                    0x1 => ("fsgnjn", "fneg"),
+                    0x2 => ("fsgnjx", "fabs"),
+                    _ => return None
+                };
+
+                match rs1 == rs2 {
+                    true => Disassembly::new(&format!("{}.{}", pseudo, fmt), vec![disasm::f(rd), disasm::f(rs1)]),
+                    false => binary(operation),
+                }
+            },
+            0x05 => match funct3 {
+                0x0 => binary("fmin"),
+                0x1 => binary("fmax"),
+                _ => return None
+            },
+            // Widening to double is exact, so binutils never shows a rounding mode for it
+            0x08 => {
+                let source = fmt_suffix(rs2)?;
+                let operands = vec![disasm::f(rd), disasm::f(rs1)];
+
+                Disassembly::new(&format!("fcvt.{}.{}", fmt, source), if fmt == "d" { operands } else { with_rounding_mode(instr, operands) })
+            },
+            0x14 => {
+                let operation = match funct3 {
+                    0x2 => "feq",
+                    0x1 => "flt",
+                    0x0 => "fle",
+                    _ => return None
+                };
+
+                Disassembly::new(&format!("{}.{}", operation, fmt), vec![disasm::x(rd), disasm::f(rs1), disasm::f(rs2)])
+            },
+            0x18 => Disassembly::new(
+                &format!("fcvt.{}.{}", int_suffix(rs2)?, fmt),
+                with_rounding_mode(instr, vec![disasm::x(rd), disasm::f(rs1)])
+            ),
+            0x1A => {
+                let operands = vec![disasm::f(rd), disasm::x(rs1)];
+                let exact = fmt == "d" && rs2 < 0x2;
+
+                Disassembly::new(&format!("fcvt.{}.{}", fmt, int_suffix(rs2)?), if exact { operands } else { with_rounding_mode(instr, operands) })
+            },
+            0x1C if rs2 == 0 => match funct3 {
+                0x0 => Disassembly::new(&format!("fmv.x.{}", if fmt == "s" { "w" } else { fmt }), vec![disasm::x(rd), disasm::f(rs1)]),
+                0x1 => Disassembly::new(&format!("fclass.{}", fmt), vec![disasm::x(rd), disasm::f(rs1)]),
+                _ => return None
+            },
+            0x1E if rs2 == 0 && funct3 == 0 => Disassembly::new(
+                &format!("fmv.{}.x", if fmt == "s" { "w" } else { fmt }),
+                vec![disasm::f(rd), disasm::x(rs1)]
+            ),
+            _ => return None
+        })
     }
-}
\ No newline at end of file
+}
+
+impl DisassemblableInstructionGroup for FusedMultiplyAddOpcodeGroup {
+    fn disassemble(instr: u32, _pc: u64) -> Option<Disassembly> {
+        let fmt = fmt_suffix((instr >> 25) & 0x3)?;
+
+        let operation = match (instr & 0x7F) as u8 {
+            FMADD_OPCODE => "fmadd",
+            FMSUB_OPCODE => "fmsub",
+            FNMSUB_OPCODE => "fnmsub",
+            _ => "fnmadd",
+        };
+
+        Some(Disassembly::new(&format!("{}.{}", operation, fmt), with_rounding_mode(instr, vec![
+            disasm::f(disasm::rd(instr)),
+            disasm::f(disasm::rs1(instr)),
+            disasm::f(disasm::rs2(instr)),
+            disasm::f(disasm::rs3(instr)),
+        ])))
+    }
+}
+
+impl DisassemblableInstructionGroup for LoadFloatingPointOpcodeGroup {
+    fn disassemble(instr: u32, _pc: u64) -> Option<Disassembly> {
+        let mnemonic = match disasm::funct3(instr) {
+            0x2 => "flw",
+            0x3 => "fld",
+            _ => return None
+        };
+
+        Some(Disassembly::new(mnemonic, vec![
+            disasm::f(disasm::rd(instr)),
+            disasm::offset(disasm::imm_i(instr), disasm::rs1(instr)),
+        ]))
+    }
+}
+
+impl DisassemblableInstructionGroup for StoreFloatingPointOpcodeGroup {
+    fn disassemble(instr: u32, _pc: u64) -> Option<Disassembly> {
+        let mnemonic = match disasm::funct3(instr) {
+            0x2 => "fsw",
+            0x3 => "fsd",
+            _ => return None
+        };
+
+        Some(Disassembly::new(mnemonic, vec![
+            disasm::f(disasm::rs2(instr)),
+            disasm::offset(disasm::imm_s(instr), disasm::rs1(instr)),
+        ]))
+    }
+}
@@ -1,4 +1,5 @@
-use crate::emulator::instructions::{InstructionFn, ParsableInstructionGroup};
+use crate::emulator::instructions::{DisassemblableInstructionGroup, InstructionFn, ParsableInstructionGroup};
+use crate::emulator::instructions::rv64::disasm::{self, Disassembly};
 use crate::emulator::state::rv64_cpu_context::{Exception, RV64CPUContext};
 use crate::wrap_r_type;
 
@@ -12,13 +13,13 @@ type ExecutionFn = fn(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1:
 
 
 fn exec_add(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> Result<(), Exception> {
-    cpu_context.set_register(rd as usize, cpu_context.x[rs1 as usize] + cpu_context.x[rs2 as usize]);
+    cpu_context.set_register(rd as usize, cpu_context.x[rs1 as usize].wrapping_add(cpu_context.x[rs2 as usize]));
 
     Ok(())
 }
 
 fn exec_sub(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> Result<(), Exception> {
-    cpu_context.set_register(rd as usize, cpu_context.x[rs1 as usize] - cpu_context.x[rs2 as usize]);
+    cpu_context.set_register(rd as usize, cpu_context.x[rs1 as usize].wrapping_sub(cpu_context.x[rs2 as usize]));
 
     Ok(())
 }
@@ -42,19 +43,19 @@ fn exec_and(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2:
 }
 
 fn exec_sll(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> Result<(), Exception> {
-    cpu_context.set_register(rd as usize, cpu_context.x[rs1 as usize] << cpu_context.x[rs2 as usize]);
+    cpu_context.set_register(rd as usize, cpu_context.x[rs1 as usize] << (cpu_context.x[rs2 as usize] & 0x3F));
 
     Ok(())
 }
 
 fn exec_srl(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> Result<(), Exception> {
-    cpu_context.set_register(rd as usize, cpu_context.x[rs1 as usize] >> cpu_context.x[rs2 as usize]);
+    cpu_context.set_register(rd as usize, cpu_context.x[rs1 as usize] >> (cpu_context.x[rs2 as usize] & 0x3F));
 
     Ok(())
 }
 
 fn exec_sra(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> Result<(), Exception> {
-    cpu_context.set_register(rd as usize, (cpu_context.x[rs1 as usize] as i64 >> cpu_context.x[rs2 as usize]) as u64);
+    cpu_context.set_register(rd as usize, (cpu_context.x[rs1 as usize] as i64 >> (cpu_context.x[rs2 as usize] & 0x3F)) as u64);
 
     Ok(())
 }
@@ -72,31 +73,31 @@ fn exec_sltu(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2:
 }
 
 fn exec_addw(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> Result<(), Exception> {
-    cpu_context.set_register(rd as usize, (cpu_context.x[rs1 as usize] as u32 + cpu_context.x[rs2 as usize] as u32) as i32 as i64 as u64);
+    cpu_context.set_register(rd as usize, (cpu_context.x[rs1 as usize] as u32).wrapping_add(cpu_context.x[rs2 as usize] as u32) as i32 as i64 as u64);
 
     Ok(())
 }
 
 fn exec_subw(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> Result<(), Exception> {
-    cpu_context.set_register(rd as usize, (cpu_context.x[rs1 as usize] as u32 - cpu_context.x[rs2 as usize] as u32) as i32 as i64 as u64);
+    cpu_context.set_register(rd as usize, (cpu_context.x[rs1 as usize] as u32).wrapping_sub(cpu_context.x[rs2 as usize] as u32) as i32 as i64 as u64);
 
     Ok(())
 }
 
 fn exec_sllw(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> Result<(), Exception> {
-    cpu_context.set_register(rd as usize, ((cpu_context.x[rs1 as usize] as u32) << (cpu_context.x[rs2 as usize] as u32)) as i32 as i64 as u64);
+    cpu_context.set_register(rd as usize, ((cpu_context.x[rs1 as usize] as u32) << (cpu_context.x[rs2 as usize] as u32 & 0x1F)) as i32 as i64 as u64);
 
     Ok(())
 }
 
 fn exec_srlw(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> Result<(), Exception> {
-    cpu_context.set_register(rd as usize, ((cpu_context.x[rs1 as usize] as u32) >> (cpu_context.x[rs2 as usize] as u32)) as i32 as i64 as u64);
+    cpu_context.set_register(rd as usize, ((cpu_context.x[rs1 as usize] as u32) >> (cpu_context.x[rs2 as usize] as u32 & 0x1F)) as i32 as i64 as u64);
 
     Ok(())
 }
 
 fn exec_sraw(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> Result<(), Exception> {
-    cpu_context.set_register(rd as usize, ((cpu_context.x[rs1 as usize] as i32) >> (cpu_context.x[rs2 as usize] as u32)) as i64 as u64);
+    cpu_context.set_register(rd as usize, ((cpu_context.x[rs1 as usize] as i32) >> (cpu_context.x[rs2 as usize] as u32 & 0x1F)) as i64 as u64);
 
     Ok(())
 }
@@ -327,4 +328,65 @@ impl ParsableInstructionGroup for IntOp32OpcodeGroup {
             _ => |_,_| { Err(Exception::IllegalInstruction) }
         }
     }
-}
\ No newline at end of file
+}
+impl DisassemblableInstructionGroup for IntOpOpcodeGroup {
+    fn disassemble(instr: u32, _pc: u64) -> Option<Disassembly> {
+        let (rd, rs1, rs2) = (disasm::rd(instr), disasm::rs1(instr), disasm::rs2(instr));
+
+        let mnemonic = match (disasm::funct3(instr), disasm::funct7(instr)) {
+            (0x0, 0x0)  => "add",
+            (0x0, 0x20) => "sub",
+            (0x4, 0x0)  => "xor",
+            (0x6, 0x0)  => "or",
+            (0x7, 0x0)  => "and",
+            (0x1, 0x0)  => "sll",
+            (0x5, 0x0)  => "srl",
+            (0x5, 0x20) => "sra",
+            (0x2, 0x0)  => "slt",
+            (0x3, 0x0)  => "sltu",
+            (0x0, 0x01) => "mul",
+            (0x1, 0x01) => "mulh",
+            (0x2, 0x01) => "mulhsu",
+            (0x3, 0x01) => "mulhu",
+            (0x4, 0x01) => "div",
+            (0x5, 0x01) => "divu",
+            (0x6, 0x01) => "rem",
+            (0x7, 0x01) => "remu",
+            _ => return None
+        };
+
+        Some(match (mnemonic, rs1, rs2) {
+            ("add", 0, _) => Disassembly::new("mv", vec![disasm::x(rd), disasm::x(rs2)]), // c.mv expands to this
+            ("sub", 0, _) => Disassembly::new("neg", vec![disasm::x(rd), disasm::x(rs2)]),
+            ("sltu", 0, _) => Disassembly::new("snez", vec![disasm::x(rd), disasm::x(rs2)]),
+            ("slt", _, 0) => Disassembly::new("sltz", vec![disasm::x(rd), disasm::x(rs1)]),
+            ("slt", 0, _) => Disassembly::new("sgtz", vec![disasm::x(rd), disasm::x(rs2)]),
+            _ => Disassembly::new(mnemonic, vec![disasm::x(rd), disasm::x(rs1), disasm::x(rs2)]),
+        })
+    }
+}
+
+impl DisassemblableInstructionGroup for IntOp32OpcodeGroup {
+    fn disassemble(instr: u32, _pc: u64) -> Option<Disassembly> {
+        let (rd, rs1, rs2) = (disasm::rd(instr), disasm::rs1(instr), disasm::rs2(instr));
+
+        let mnemonic = match (disasm::funct3(instr), disasm::funct7(instr)) {
+            (0x0, 0x0)  => "addw",
+            (0x0, 0x20) => "subw",
+            (0x1, 0x0)  => "sllw",
+            (0x5, 0x0)  => "srlw",
+            (0x5, 0x20) => "sraw",
+            (0x0, 0x01) => "mulw",
+            (0x4, 0x01) => "divw",
+            (0x5, 0x01) => "divuw",
+            (0x6, 0x01) => "remw",
+            (0x7, 0x01) => "remuw",
+            _ => return None
+        };
+
+        Some(match (mnemonic, rs1) {
+            ("subw", 0) => Disassembly::new("negw", vec![disasm::x(rd), disasm::x(rs2)]),
+            _ => Disassembly::new(mnemonic, vec![disasm::x(rd), disasm::x(rs1), disasm::x(rs2)]),
+        })
+    }
+}
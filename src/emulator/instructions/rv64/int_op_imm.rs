@@ -1,4 +1,5 @@
-use crate::emulator::instructions::{InstructionFn, ParsableInstructionGroup};
+use crate::emulator::instructions::{DisassemblableInstructionGroup, InstructionFn, ParsableInstructionGroup};
+use crate::emulator::instructions::rv64::disasm::{self, Disassembly};
 use crate::emulator::state::rv64_cpu_context::{Exception, RV64CPUContext};
 use crate::{wrap_i_type, wrap_i_type_sh, wrap_u_type};
 use crate::emulator::instructions::rv64::InstructionResult;
@@ -54,7 +55,7 @@ fn exec_srli_srai(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8,
         return Err(Exception::IllegalInstruction);
     }
 
-    let is_arith = (imm >> 5) == 0x20;
+    let is_arith = (imm >> 10) & 1 == 1;
 
     if !is_arith {
         cpu_context.set_register(rd as usize, cpu_context.x[rs1 as usize] >> shift);
@@ -158,4 +159,65 @@ impl ParsableInstructionGroup for AuipcOpcodeGroup {
     fn parse(instr: u32) -> InstructionFn {
         wrap_u_type!(exec_auipc)
     }
-}
\ No newline at end of file
+}
+impl DisassemblableInstructionGroup for IntOpImmOpcodeGroup {
+    fn disassemble(instr: u32, _pc: u64) -> Option<Disassembly> {
+        let (rd, rs1, imm) = (disasm::rd(instr), disasm::rs1(instr), disasm::imm_i(instr));
+        let shamt = (instr >> 20) & 0x3F;
+        let funct6 = instr >> 26;
+
+        let (mnemonic, imm) = match (disasm::funct3(instr), funct6) {
+            (0x0, _) => ("addi", imm),
+            (0x4, _) => ("xori", imm),
+            (0x6, _) => ("ori", imm),
+            (0x7, _) => ("andi", imm),
+            (0x1, 0x0) => ("slli", shamt as i64),
+            (0x5, 0x0) => ("srli", shamt as i64),
+            (0x5, 0x10) => ("srai", shamt as i64),
+            (0x2, _) => ("slti", imm),
+            (0x3, _) => ("sltiu", imm),
+            _ => return None
+        };
+
+        Some(match (mnemonic, rd, rs1, imm) {
+            ("addi", 0, 0, 0) => Disassembly::new("nop", vec![]),
+            ("addi", _, 0, _) => Disassembly::new("li", vec![disasm::x(rd), imm.to_string()]),
+            ("addi", _, _, 0) => Disassembly::new("mv", vec![disasm::x(rd), disasm::x(rs1)]),
+            ("xori", _, _, -1) => Disassembly::new("not", vec![disasm::x(rd), disasm::x(rs1)]),
+            ("sltiu", _, _, 1) => Disassembly::new("seqz", vec![disasm::x(rd), disasm::x(rs1)]),
+            _ => Disassembly::new(mnemonic, vec![disasm::x(rd), disasm::x(rs1), imm.to_string()]),
+        })
+    }
+}
+
+impl DisassemblableInstructionGroup for IntOpImm32OpcodeGroup {
+    fn disassemble(instr: u32, _pc: u64) -> Option<Disassembly> {
+        let (rd, rs1, imm) = (disasm::rd(instr), disasm::rs1(instr), disasm::imm_i(instr));
+        let shamt = (instr >> 20) & 0x1F;
+
+        let (mnemonic, imm) = match (disasm::funct3(instr), disasm::funct7(instr)) {
+            (0x0, _) => ("addiw", imm),
+            (0x1, 0x0) => ("slliw", shamt as i64),
+            (0x5, 0x0) => ("srliw", shamt as i64),
+            (0x5, 0x20) => ("sraiw", shamt as i64),
+            _ => return None
+        };
+
+        Some(match (mnemonic, imm) {
+            ("addiw", 0) => Disassembly::new("sext.w", vec![disasm::x(rd), disasm::x(rs1)]),
+            _ => Disassembly::new(mnemonic, vec![disasm::x(rd), disasm::x(rs1), imm.to_string()]),
+        })
+    }
+}
+
+impl DisassemblableInstructionGroup for LuiOpcodeGroup {
+    fn disassemble(instr: u32, _pc: u64) -> Option<Disassembly> {
+        Some(Disassembly::new("lui", vec![disasm::x(disasm::rd(instr)), format!("{:#x}", disasm::imm_u(instr))]))
+    }
+}
+
+impl DisassemblableInstructionGroup for AuipcOpcodeGroup {
+    fn disassemble(instr: u32, _pc: u64) -> Option<Disassembly> {
+        Some(Disassembly::new("auipc", vec![disasm::x(disasm::rd(instr)), format!("{:#x}", disasm::imm_u(instr))]))
+    }
+}
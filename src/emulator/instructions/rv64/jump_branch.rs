@@ -1,4 +1,5 @@
-use crate::emulator::instructions::{InstructionFn, ParsableInstructionGroup};
+use crate::emulator::instructions::{DisassemblableInstructionGroup, InstructionFn, ParsableInstructionGroup};
+use crate::emulator::instructions::rv64::disasm::{self, Disassembly};
 use crate::emulator::state::rv64_cpu_context::{Exception, RV64CPUContext};
 use crate::{wrap_b_type, wrap_b_type_u, wrap_i_type, wrap_j_type};
 use crate::emulator::instructions::rv64::InstructionResult;
@@ -21,56 +22,56 @@ type JalrExecutionFn = fn(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8,
 
 fn exec_jal(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, imm: u64) -> InstructionResult {
     let old_pc = cpu_context.pc;
-    cpu_context.set_register(rd as usize, old_pc + 4);
-    cpu_context.pc = old_pc.wrapping_add(imm);
+    cpu_context.jump(old_pc.wrapping_add(imm))?;
+    cpu_context.set_register(rd as usize, old_pc.wrapping_add(cpu_context.instruction_length));
     Ok(())
 }
 
 fn exec_jalr(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, imm: u64) -> InstructionResult {
     let old_pc = cpu_context.pc;
-    cpu_context.pc = cpu_context.x[rs1 as usize].wrapping_add(imm) & !1;
-    cpu_context.set_register(rd as usize, old_pc + 4);
+    cpu_context.jump(cpu_context.x[rs1 as usize].wrapping_add(imm) & !1)?;
+    cpu_context.set_register(rd as usize, old_pc.wrapping_add(cpu_context.instruction_length));
     Ok(())
 }
 
 fn exec_beq(cpu_context: &mut RV64CPUContext, instr: u32, rs1: u8, rs2: u8, imm: u64) -> InstructionResult {
     if cpu_context.x[rs1 as usize] == cpu_context.x[rs2 as usize] {
-        cpu_context.pc = cpu_context.pc.wrapping_add(imm);
+        cpu_context.jump(cpu_context.pc.wrapping_add(imm))?;
     }
     Ok(())
 }
 
 fn exec_bne(cpu_context: &mut RV64CPUContext, instr: u32, rs1: u8, rs2: u8, imm: u64) -> InstructionResult {
     if cpu_context.x[rs1 as usize] != cpu_context.x[rs2 as usize] {
-        cpu_context.pc = cpu_context.pc.wrapping_add(imm);
+        cpu_context.jump(cpu_context.pc.wrapping_add(imm))?;
     }
     Ok(())
 }
 
 fn exec_blt(cpu_context: &mut RV64CPUContext, instr: u32, rs1: u8, rs2: u8, imm: u64) -> InstructionResult {
     if (cpu_context.x[rs1 as usize] as i64) < (cpu_context.x[rs2 as usize] as i64) {
-        cpu_context.pc = cpu_context.pc.wrapping_add(imm);
+        cpu_context.jump(cpu_context.pc.wrapping_add(imm))?;
     }
     Ok(())
 }
 
 fn exec_bltu(cpu_context: &mut RV64CPUContext, instr: u32, rs1: u8, rs2: u8, imm: u64) -> InstructionResult {
     if cpu_context.x[rs1 as usize] < cpu_context.x[rs2 as usize] {
-        cpu_context.pc = cpu_context.pc.wrapping_add(imm);
+        cpu_context.jump(cpu_context.pc.wrapping_add(imm))?;
     }
     Ok(())
 }
 
 fn exec_bge(cpu_context: &mut RV64CPUContext, instr: u32, rs1: u8, rs2: u8, imm: u64) -> InstructionResult {
     if (cpu_context.x[rs1 as usize] as i64) >= (cpu_context.x[rs2 as usize] as i64) {
-        cpu_context.pc = cpu_context.pc.wrapping_add(imm);
+        cpu_context.jump(cpu_context.pc.wrapping_add(imm))?;
     }
     Ok(())
 }
 
 fn exec_bgeu(cpu_context: &mut RV64CPUContext, instr: u32, rs1: u8, rs2: u8, imm: u64) -> InstructionResult {
     if cpu_context.x[rs1 as usize] >= cpu_context.x[rs2 as usize] {
-        cpu_context.pc = cpu_context.pc.wrapping_add(imm);
+        cpu_context.jump(cpu_context.pc.wrapping_add(imm))?;
     }
     Ok(())
 }
@@ -101,4 +102,58 @@ impl ParsableInstructionGroup for BranchOpcodeGroup {
             _ => |_,_| { Err(Exception::IllegalInstruction) },
         }
     }
-}
\ No newline at end of file
+}
+impl DisassemblableInstructionGroup for JalOpcodeGroup {
+    fn disassemble(instr: u32, pc: u64) -> Option<Disassembly> {
+        let rd = disasm::rd(instr);
+        let target = pc.wrapping_add(disasm::imm_j(instr) as u64);
+        let target_operand = format!("{:#x}", target);
+
+        Some(match rd {
+            0 => Disassembly::new("j", vec![target_operand]),
+            1 => Disassembly::new("jal", vec![target_operand]),
+            _ => Disassembly::new("jal", vec![disasm::x(rd), target_operand]),
+        }.with_target(target))
+    }
+}
+
+impl DisassemblableInstructionGroup for JalrOpcodeGroup {
+    fn disassemble(instr: u32, _pc: u64) -> Option<Disassembly> {
+        let (rd, rs1, imm) = (disasm::rd(instr), disasm::rs1(instr), disasm::imm_i(instr));
+
+        Some(match (rd, rs1, imm) {
+            (0, 1, 0) => Disassembly::new("ret", vec![]),
+            (0, _, 0) => Disassembly::new("jr", vec![disasm::x(rs1)]),
+            (1, _, 0) => Disassembly::new("jalr", vec![disasm::x(rs1)]),
+            _ => Disassembly::new("jalr", vec![disasm::x(rd), disasm::offset(imm, rs1)]),
+        })
+    }
+}
+
+impl DisassemblableInstructionGroup for BranchOpcodeGroup {
+    fn disassemble(instr: u32, pc: u64) -> Option<Disassembly> {
+        let (rs1, rs2) = (disasm::rs1(instr), disasm::rs2(instr));
+        let target = pc.wrapping_add(disasm::imm_b(instr) as u64);
+        let target_operand = format!("{:#x}", target);
+
+        let mnemonic = match disasm::funct3(instr) {
+            0x0 => "beq",
+            0x1 => "bne",
+            0x4 => "blt",
+            0x6 => "bltu",
+            0x5 => "bge",
+            0x7 => "bgeu",
+            _ => return None
+        };
+
+        Some(match (mnemonic, rs1, rs2) {
+            ("beq", _, 0) => Disassembly::new("beqz", vec![disasm::x(rs1), target_operand]),
+            ("bne", _, 0) => Disassembly::new("bnez", vec![disasm::x(rs1), target_operand]),
+            ("blt", _, 0) => Disassembly::new("bltz", vec![disasm::x(rs1), target_operand]),
+            ("bge", _, 0) => Disassembly::new("bgez", vec![disasm::x(rs1), target_operand]),
+            ("blt", 0, _) => Disassembly::new("bgtz", vec![disasm::x(rs2), target_operand]),
+            ("bge", 0, _) => Disassembly::new("blez", vec![disasm::x(rs2), target_operand]),
+            _ => Disassembly::new(mnemonic, vec![disasm::x(rs1), disasm::x(rs2), target_operand]),
+        }.with_target(target))
+    }
+}
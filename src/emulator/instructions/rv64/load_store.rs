@@ -1,4 +1,5 @@
-use crate::emulator::instructions::{InstructionFn, ParsableInstructionGroup};
+use crate::emulator::instructions::{DisassemblableInstructionGroup, InstructionFn, ParsableInstructionGroup};
+use crate::emulator::instructions::rv64::disasm::{self, Disassembly};
 use crate::emulator::state::rv64_cpu_context::{Exception, RV64CPUContext};
 use crate::{wrap_b_type, wrap_b_type_u, wrap_i_type, wrap_i_type_sh, wrap_j_type, wrap_s_type};
 use crate::emulator::instructions::rv64::InstructionResult;
@@ -24,11 +25,7 @@ fn exec_load_byte(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8,
 
     let address = cpu_context.x[rs1 as usize].wrapping_add(imm);
 
-    if address >= cpu_context.memory.read().unwrap().size() {
-        return Err(Exception::LoadAccessFault);
-    }
-
-    let mut value: u64 = cpu_context.memory.read().unwrap().read_byte(address as usize) as u64;
+    let mut value: u64 = cpu_context.load(address, 1)?;
 
     if (value & 0x80) > 0 {
         value |= !0xFF_u64;
@@ -45,11 +42,7 @@ fn exec_load_hword(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8
 
     let address = cpu_context.x[rs1 as usize].wrapping_add(imm);
 
-    if address + 1 >= cpu_context.memory.read().unwrap().size() {
-        return Err(Exception::LoadAccessFault);
-    }
-
-    let mut value: u64 = cpu_context.memory.read().unwrap().read_half_word(address as usize) as u64;
+    let mut value: u64 = cpu_context.load(address, 2)?;
 
     if (value & 0x8000) > 0 {
         value |= !0xFFFF_u64;
@@ -66,11 +59,7 @@ fn exec_load_word(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8,
 
     let address = cpu_context.x[rs1 as usize].wrapping_add(imm);
 
-    if address + 3 >= cpu_context.memory.read().unwrap().size() {
-        return Err(Exception::LoadAccessFault);
-    }
-
-    let mut value: u64 = cpu_context.memory.write().unwrap().read_word(address as usize) as u64;
+    let mut value: u64 = cpu_context.load(address, 4)?;
 
     if (value & 0x80000000) > 0 {
         value |= !0xFFFFFFFF_u64;
@@ -87,11 +76,7 @@ fn exec_load_dword(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8
 
     let address = cpu_context.x[rs1 as usize].wrapping_add(imm);
 
-    if address + 7 >= cpu_context.memory.read().unwrap().size() {
-        return Err(Exception::LoadAccessFault);
-    }
-
-    let value: u64 = cpu_context.memory.read().unwrap().read_double_word(address as usize);
+    let value: u64 = cpu_context.load(address, 8)?;
     cpu_context.set_register(rd as usize, value);
     Ok(())
 }
@@ -103,11 +88,7 @@ fn exec_load_byte_unsigned(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8,
 
     let address = cpu_context.x[rs1 as usize].wrapping_add(imm);
 
-    if address >= cpu_context.memory.read().unwrap().size() {
-        return Err(Exception::LoadAccessFault);
-    }
-
-    let value: u64 = cpu_context.memory.read().unwrap().read_byte(address as usize) as u64;
+    let value: u64 = cpu_context.load(address, 1)?;
     cpu_context.set_register(rd as usize, value);
     Ok(())
 }
@@ -119,11 +100,7 @@ fn exec_load_hword_unsigned(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8
 
     let address = cpu_context.x[rs1 as usize].wrapping_add(imm);
 
-    if address + 1 >= cpu_context.memory.read().unwrap().size() {
-        return Err(Exception::LoadAccessFault);
-    }
-
-    let value: u64 = cpu_context.memory.read().unwrap().read_half_word(address as usize) as u64;
+    let value: u64 = cpu_context.load(address, 2)?;
     cpu_context.set_register(rd as usize, value);
     Ok(())
 }
@@ -135,11 +112,7 @@ fn exec_load_word_unsigned(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8,
 
     let address = cpu_context.x[rs1 as usize].wrapping_add(imm);
 
-    if address + 3 >= cpu_context.memory.read().unwrap().size() {
-        return Err(Exception::LoadAccessFault);
-    }
-
-    let value: u64 = cpu_context.memory.read().unwrap().read_word(address as usize) as u64;
+    let value: u64 = cpu_context.load(address, 4)?;
     cpu_context.set_register(rd as usize, value);
     Ok(())
 }
@@ -147,44 +120,28 @@ fn exec_load_word_unsigned(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8,
 fn exec_store_byte(cpu_context: &mut RV64CPUContext, instr: u32, rs1: u8, rs2: u8, imm: u64) -> InstructionResult {
     let address = cpu_context.x[rs1 as usize].wrapping_add(imm);
 
-    if address >= cpu_context.memory.read().unwrap().size() {
-        return Err(Exception::StoreAccessFault);
-    }
-
-    cpu_context.memory.write().unwrap().write_byte(address as usize, cpu_context.x[rs2 as usize] as u8);
+    cpu_context.store(address, 1, cpu_context.x[rs2 as usize])?;
     Ok(())
 }
 
 fn exec_store_half_word(cpu_context: &mut RV64CPUContext, instr: u32, rs1: u8, rs2: u8, imm: u64) -> InstructionResult {
     let address = cpu_context.x[rs1 as usize].wrapping_add(imm);
 
-    if address + 1 >= cpu_context.memory.read().unwrap().size() {
-        return Err(Exception::StoreAccessFault);
-    }
-
-    cpu_context.memory.write().unwrap().write_half_word(address as usize, cpu_context.x[rs2 as usize] as u16);
+    cpu_context.store(address, 2, cpu_context.x[rs2 as usize])?;
     Ok(())
 }
 
 fn exec_store_word(cpu_context: &mut RV64CPUContext, instr: u32, rs1: u8, rs2: u8, imm: u64) -> InstructionResult {
     let address = cpu_context.x[rs1 as usize].wrapping_add(imm);
 
-    if address + 3 >= cpu_context.memory.read().unwrap().size() {
-        return Err(Exception::StoreAccessFault);
-    }
-
-    cpu_context.memory.write().unwrap().write_word(address as usize, cpu_context.x[rs2 as usize] as u32);
+    cpu_context.store(address, 4, cpu_context.x[rs2 as usize])?;
     Ok(())
 }
 
 fn exec_store_dword(cpu_context: &mut RV64CPUContext, instr: u32, rs1: u8, rs2: u8, imm: u64) -> InstructionResult {
     let address = cpu_context.x[rs1 as usize].wrapping_add(imm);
 
-    if address + 7 >= cpu_context.memory.read().unwrap().size() {
-        return Err(Exception::StoreAccessFault);
-    }
-
-    cpu_context.memory.write().unwrap().write_double_word(address as usize, cpu_context.x[rs2 as usize]);
+    cpu_context.store(address, 8, cpu_context.x[rs2 as usize])?;
     Ok(())
 }
 
@@ -217,4 +174,40 @@ impl ParsableInstructionGroup for StoreOpcodeGroup {
             _ => |_,_| { Err(Exception::IllegalInstruction) },
         }
     }
-}
\ No newline at end of file
+}
+impl DisassemblableInstructionGroup for LoadOpcodeGroup {
+    fn disassemble(instr: u32, _pc: u64) -> Option<Disassembly> {
+        let mnemonic = match disasm::funct3(instr) {
+            0x0 => "lb",
+            0x1 => "lh",
+            0x2 => "lw",
+            0x3 => "ld",
+            0x4 => "lbu",
+            0x5 => "lhu",
+            0x6 => "lwu",
+            _ => return None
+        };
+
+        Some(Disassembly::new(mnemonic, vec![
+            disasm::x(disasm::rd(instr)),
+            disasm::offset(disasm::imm_i(instr), disasm::rs1(instr)),
+        ]))
+    }
+}
+
+impl DisassemblableInstructionGroup for StoreOpcodeGroup {
+    fn disassemble(instr: u32, _pc: u64) -> Option<Disassembly> {
+        let mnemonic = match disasm::funct3(instr) {
+            0x0 => "sb",
+            0x1 => "sh",
+            0x2 => "sw",
+            0x3 => "sd",
+            _ => return None
+        };
+
+        Some(Disassembly::new(mnemonic, vec![
+            disasm::x(disasm::rs2(instr)),
+            disasm::offset(disasm::imm_s(instr), disasm::rs1(instr)),
+        ]))
+    }
+}
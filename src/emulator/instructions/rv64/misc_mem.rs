@@ -0,0 +1,55 @@
+use crate::emulator::instructions::{DisassemblableInstructionGroup, InstructionFn, ParsableInstructionGroup};
+use crate::emulator::instructions::rv64::disasm::Disassembly;
+use crate::emulator::state::rv64_cpu_context::{Exception, RV64CPUContext};
+use crate::wrap_i_type;
+use crate::emulator::instructions::rv64::InstructionResult;
+
+pub const MISC_MEM_OPCODE: u8 = 0b000_1111;
+
+pub struct MiscMemOpcodeGroup {}
+
+// Memory accesses of a single interpreted hart are already performed in program order
+fn exec_fence(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, imm: u64) -> InstructionResult {
+    Ok(())
+}
+
+// Instructions are fetched straight from memory, so there is no instruction cache to synchronize
+fn exec_fence_i(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, imm: u64) -> InstructionResult {
+    Ok(())
+}
+
+impl ParsableInstructionGroup for MiscMemOpcodeGroup {
+    fn parse(instr: u32) -> InstructionFn {
+        let funct3 = ((instr >> 12) & 0x07) as u8;
+
+        match funct3 {
+            0x0 => wrap_i_type!(exec_fence),
+            0x1 => wrap_i_type!(exec_fence_i),
+            _ => |_,_| { Err(Exception::IllegalInstruction) }
+        }
+    }
+}
+
+// Predecessor or successor set of a fence as written in assembly, e.g. "rw"
+fn fence_set(bits: u32) -> String {
+    "iorw".chars().enumerate()
+        .filter(|(index, _)| bits & (0b1000 >> index) != 0)
+        .map(|(_, access)| access)
+        .collect()
+}
+
+impl DisassemblableInstructionGroup for MiscMemOpcodeGroup {
+    fn disassemble(instr: u32, _pc: u64) -> Option<Disassembly> {
+        let fm = instr >> 28;
+        let pred = (instr >> 24) & 0xF;
+        let succ = (instr >> 20) & 0xF;
+
+        match ((instr >> 12) & 0x07, fm, pred, succ) {
+            (0x0, 0x8, 0b0011, 0b0011) => Some(Disassembly::new("fence.tso", vec![])),
+            (0x0, _, 0xF, 0xF) => Some(Disassembly::new("fence", vec![])),
+            (0x0, _, _, _) => Some(Disassembly::new("fence", vec![fence_set(pred), fence_set(succ)])),
+            (0x1, _, _, _) => Some(Disassembly::new("fence.i", vec![])),
+            _ => None
+        }
+    }
+}
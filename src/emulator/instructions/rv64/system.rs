@@ -1,6 +1,7 @@
-use crate::emulator::instructions::{InstructionFn, ParsableInstructionGroup};
-use crate::emulator::state::rv64_cpu_context::{Exception, PrivilegeMode, RV64CPUContext};
-use crate::{wrap_b_type, wrap_b_type_u, wrap_i_type, wrap_i_type_sh, wrap_j_type, wrap_s_type};
+use crate::emulator::instructions::{DisassemblableInstructionGroup, InstructionFn, ParsableInstructionGroup};
+use crate::emulator::instructions::rv64::disasm::{self, Disassembly};
+use crate::emulator::state::rv64_cpu_context::{CSRAddress, Exception, MStatusFlags, PrivilegeMode, RV64CPUContext};
+use crate::{wrap_b_type, wrap_b_type_u, wrap_i_type, wrap_i_type_sh, wrap_j_type, wrap_r_type, wrap_s_type};
 use crate::emulator::instructions::rv64::InstructionResult;
 
 pub const SYSTEM_OPCODE: u8 = 0b111_0011;
@@ -20,9 +21,77 @@ fn exec_ecall(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, imm
 }
 
 fn exec_ebreak(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, imm: u64) -> InstructionResult {
+    cpu_context.trap_value = cpu_context.pc;
     Err(Exception::Breakpoint)
 }
 
+fn exec_mret(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, imm: u64) -> InstructionResult {
+    if cpu_context.csrs.get_current_privilege() != PrivilegeMode::Machine {
+        return Err(Exception::IllegalInstruction);
+    }
+
+    let return_address = cpu_context.csrs.return_from_machine_trap();
+    cpu_context.jump(return_address)
+}
+
+fn exec_sret(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, imm: u64) -> InstructionResult {
+    match cpu_context.csrs.get_current_privilege() {
+        PrivilegeMode::User => return Err(Exception::IllegalInstruction),
+        PrivilegeMode::Supervisor => {
+            let mstatus = cpu_context.csrs.read_csr(CSRAddress::MStatus as u16, true)?;
+
+            if MStatusFlags::from_bits_retain(mstatus).contains(MStatusFlags::TSR) {
+                return Err(Exception::IllegalInstruction);
+            }
+        }
+        PrivilegeMode::Machine => {}
+    }
+
+    let return_address = cpu_context.csrs.return_from_supervisor_trap();
+    cpu_context.jump(return_address)
+}
+
+fn exec_wfi(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, imm: u64) -> InstructionResult {
+    match cpu_context.csrs.get_current_privilege() {
+        PrivilegeMode::User => return Err(Exception::IllegalInstruction),
+        PrivilegeMode::Supervisor => {
+            let mstatus = cpu_context.csrs.read_csr(CSRAddress::MStatus as u16, true)?;
+
+            if MStatusFlags::from_bits_retain(mstatus).contains(MStatusFlags::TW) {
+                return Err(Exception::IllegalInstruction);
+            }
+        }
+        PrivilegeMode::Machine => {}
+    }
+
+    // pc still advances, the hart resumes after the WFI once an interrupt is pending
+    cpu_context.waiting_for_interrupt = true;
+
+    Ok(())
+}
+
+fn exec_sfence_vma(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, rs2: u8) -> InstructionResult {
+    match cpu_context.csrs.get_current_privilege() {
+        PrivilegeMode::User => return Err(Exception::IllegalInstruction),
+        PrivilegeMode::Supervisor => {
+            let mstatus = cpu_context.csrs.read_csr(CSRAddress::MStatus as u16, true)?;
+
+            if MStatusFlags::from_bits_retain(mstatus).contains(MStatusFlags::TVM) {
+                return Err(Exception::IllegalInstruction);
+            }
+        }
+        PrivilegeMode::Machine => {}
+    }
+
+    // x0 as an operand means "every address" or "every address space"
+    let vaddr = if rs1 != 0 { Some(cpu_context.x[rs1 as usize]) } else { None };
+    let asid = if rs2 != 0 { Some(cpu_context.x[rs2 as usize] & 0xFFFF) } else { None };
+
+    cpu_context.flush_tlb(vaddr, asid);
+
+    Ok(())
+}
+
 fn exec_csrrw(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, imm: u64) -> InstructionResult {
     let old_value = cpu_context.csrs.read_csr(imm as u16, false)?;
 
@@ -30,6 +99,7 @@ fn exec_csrrw(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, imm
 
     let value = cpu_context.x[rs1 as usize];
     cpu_context.csrs.write_csr(imm as u16, value, false)?;
+    cpu_context.commit.csr(imm as u16);
 
     Ok(())
 }
@@ -43,6 +113,7 @@ fn exec_csrrs(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, imm
 
     let value = cpu_context.x[rs1 as usize];
     cpu_context.csrs.write_csr(imm as u16, old_value | value, false)?;
+    cpu_context.commit.csr(imm as u16);
 
     Ok(())
 }
@@ -56,6 +127,7 @@ fn exec_csrrc(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, imm
 
     let value = !cpu_context.x[rs1 as usize];
     cpu_context.csrs.write_csr(imm as u16, old_value & value, false)?;
+    cpu_context.commit.csr(imm as u16);
 
     Ok(())
 }
@@ -67,6 +139,7 @@ fn exec_csrrwi(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, im
 
     let value = rs1;
     cpu_context.csrs.write_csr(imm as u16, value as u64, false)?;
+    cpu_context.commit.csr(imm as u16);
 
     Ok(())
 }
@@ -80,6 +153,7 @@ fn exec_csrrsi(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, im
 
     let value = rs1 as u64;
     cpu_context.csrs.write_csr(imm as u16, old_value | value, false)?;
+    cpu_context.commit.csr(imm as u16);
 
     Ok(())
 }
@@ -93,6 +167,7 @@ fn exec_csrrci(cpu_context: &mut RV64CPUContext, instr: u32, rd: u8, rs1: u8, im
 
     let value = !(rs1 as u64);
     cpu_context.csrs.write_csr(imm as u16, old_value & value, false)?;
+    cpu_context.commit.csr(imm as u16);
 
     Ok(())
 }
@@ -103,11 +178,19 @@ impl ParsableInstructionGroup for SystemOpcodeGroup {
 
         match (funct3) {
             0x0  => {
+                let funct7 = ((instr >> 25) & 0x7F) as u8;
                 let funct12 = ((instr >> 20) & 0xFFF) as u16;
 
+                if funct7 == 0x09 {
+                    return wrap_r_type!(exec_sfence_vma);
+                }
+
                 match(funct12) {
                     0x0 => wrap_i_type_sh!(exec_ecall),
                     0x1 => wrap_i_type_sh!(exec_ebreak),
+                    0x102 => wrap_i_type_sh!(exec_sret),
+                    0x105 => wrap_i_type_sh!(exec_wfi),
+                    0x302 => wrap_i_type_sh!(exec_mret),
                     _ => |_,_| { Err(Exception::IllegalInstruction) },
                 }
             },
@@ -120,4 +203,56 @@ impl ParsableInstructionGroup for SystemOpcodeGroup {
             _ => |_,_| { Err(Exception::IllegalInstruction) },
         }
     }
-}
\ No newline at end of file
+}
+impl DisassemblableInstructionGroup for SystemOpcodeGroup {
+    fn disassemble(instr: u32, _pc: u64) -> Option<Disassembly> {
+        let (rd, rs1) = (disasm::rd(instr), disasm::rs1(instr));
+        let csr = disasm::csr(instr >> 20);
+
+        let mnemonic = match disasm::funct3(instr) {
+            0x0 => {
+                if disasm::funct7(instr) == 0x09 {
+                    let operands = match (rs1, disasm::rs2(instr)) {
+                        (0, 0) => vec![],
+                        (_, 0) => vec![disasm::x(rs1)],
+                        (_, rs2) => vec![disasm::x(rs1), disasm::x(rs2)],
+                    };
+
+                    return Some(Disassembly::new("sfence.vma", operands));
+                }
+
+                let mnemonic = match instr >> 20 {
+                    0x0 => "ecall",
+                    0x1 => "ebreak",
+                    0x102 => "sret",
+                    0x105 => "wfi",
+                    0x302 => "mret",
+                    _ => return None
+                };
+
+                return Some(Disassembly::new(mnemonic, vec![]));
+            },
+            0x1 => "csrrw",
+            0x2 => "csrrs",
+            0x3 => "csrrc",
+            0x5 => "csrrwi",
+            0x6 => "csrrsi",
+            0x7 => "csrrci",
+            _ => return None
+        };
+
+        // The immediate forms encode a 5 bit unsigned value in place of rs1
+        let source = if mnemonic.ends_with('i') { rs1.to_string() } else { disasm::x(rs1) };
+
+        Some(match (mnemonic, rd, rs1) {
+            ("csrrs", _, 0) => Disassembly::new("csrr", vec![disasm::x(rd), csr]),
+            ("csrrw", 0, _) => Disassembly::new("csrw", vec![csr, source]),
+            ("csrrs", 0, _) => Disassembly::new("csrs", vec![csr, source]),
+            ("csrrc", 0, _) => Disassembly::new("csrc", vec![csr, source]),
+            ("csrrwi", 0, _) => Disassembly::new("csrwi", vec![csr, source]),
+            ("csrrsi", 0, _) => Disassembly::new("csrsi", vec![csr, source]),
+            ("csrrci", 0, _) => Disassembly::new("csrci", vec![csr, source]),
+            _ => Disassembly::new(mnemonic, vec![disasm::x(rd), csr, source]),
+        })
+    }
+}
@@ -1,62 +1,1259 @@
-use std::collections::HashSet;
-use std::fs::File;
-use std::io::{BufReader, Read};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
 use std::path::Path;
 use std::sync::{Arc, RwLock};
 use rustyline::{DefaultEditor, Editor};
 use rustyline::history::DefaultHistory;
+use crate::emulator::breakpoints::{parse_value, Breakpoints, Condition, Location};
+use crate::emulator::constants::{DRAM_BASE, PAGE_SHIFT, PAGE_SIZE};
+use crate::emulator::devices::clint::{Clint, ClintState, CLINT_BASE, CLINT_SIZE, TIMEBASE_FREQUENCY};
+use crate::emulator::devices::plic::{IrqLine, Plic, PlicState, PLIC_BASE, PLIC_SIZE, PLIC_SOURCES};
+use crate::emulator::elf::{ElfError, ElfImage, SymbolTable};
+use crate::emulator::devices::uart::{Uart, UartBackend, UartState, UART_BASE, UART_CLOCK_FREQUENCY, UART_IRQ, UART_SIZE};
+use crate::emulator::boot::{initrd_address, set_chosen, BootError, LinuxBoot, FIRMWARE_BASE, KERNEL_BASE};
+use crate::emulator::dtb::{to_blob, DtbError, DtbNode};
+use crate::emulator::elf::is_elf_file;
+use crate::emulator::history::{History, ReplayStop, Snapshot, SNAPSHOT_INTERVAL, SNAPSHOT_LIMIT};
+use crate::emulator::sbi::{Sbi, SystemReset};
 use crate::emulator::instructions::rv64::RV64InstructionParser;
-use crate::emulator::state::memory::{Device, MemoryManagementUnit};
-use crate::emulator::state::rv64_cpu_context::{CSRAddress, Exception, MStatusFlags, PrivilegeMode, RV64CPUContext};
+use crate::emulator::instructions::rv64::disasm::{self, format_instruction, read_instruction};
+use crate::emulator::instructions::rv64::compressed::{expand, is_compressed};
+use crate::emulator::state::memory::{MemoryManagementUnit, WatchHit, WatchKind, Watchpoint};
+use crate::emulator::state::interrupts::InterruptLines;
+use crate::emulator::state::rv64_cpu_context::{CSRAddress, Exception, FFlags, MIPFlags, Register, RV64CPUContext, F_ABI_NAMES, NAN_BOX_MASK, X_ABI_NAMES};
 use crate::emulator::state::rv64_cpu_context::CSRAddress::MStatus;
+use crate::emulator::state::translation::AccessDirtyPolicy;
+use crate::emulator::trace::{TraceRange, Tracer};
 
+// Longest string x/s prints before giving up on finding the terminator
+const EXAMINE_STRING_LIMIT: usize = 256;
+
+// Why the debugger stopped running the machine
+#[derive(Debug, Clone, Copy)]
+enum Stop {
+    Exception(usize, Exception),
+    Reset(SystemReset),
+    Watchpoint(usize, usize, WatchHit),
+    Register { hart_id: usize, id: usize, old: u64, new: u64 },
+    Breakpoint(usize, usize),
+}
 
 pub struct RV64Platform {
     harts: Vec<Interpreter>,
     mmu: Arc<RwLock<MemoryManagementUnit>>,
+    memory_size: u64,
     editor: Editor<(), DefaultHistory>,
-    breakpoints: HashSet<u64>,
+    breakpoints: Breakpoints,
+    selected_hart: usize,
+    watch_hit: Option<(usize, WatchHit)>,
+    failed_expectations: usize,
+    history: Option<History>,
+    tracer: Option<Tracer>,
+    clint: Arc<ClintState>,
+    plic: Arc<PlicState>,
+    uart: Option<Arc<UartState>>,
+    symbols: SymbolTable,
+    sbi: Option<Sbi>,
+    device_tree: Option<Vec<u8>>,
+    stop_on_trap: bool,
 }
 
-impl RV64Platform {
-    pub fn new(threads: u64, memory_size: u64) -> RV64Platform {
-        let mut platform = RV64Platform {
-            harts: vec![],
-            mmu: Arc::new(RwLock::new(MemoryManagementUnit::new(memory_size as usize))),
-            breakpoints: HashSet::new(),
-            editor: DefaultEditor::new().unwrap()
+impl RV64Platform {
+    pub fn new(hart_count: u64, memory_size: u64) -> RV64Platform {
+        let mmu = Arc::new(RwLock::new(MemoryManagementUnit::new_at(DRAM_BASE as usize, memory_size as usize)));
+        let mut harts: Vec<Interpreter> = (0..hart_count).map(|_| Interpreter::new(DRAM_BASE, mmu.clone())).collect();
+
+        for (hart_id, hart) in harts.iter_mut().enumerate() {
+            hart.cpu_context.set_hart_id(hart_id as u64);
+        }
+
+        let lines: Vec<_> = harts.iter().map(|hart| hart.cpu_context.csrs.get_interrupt_lines()).collect();
+
+        let clint = ClintState::new(lines.clone());
+        mmu.write().unwrap().add_region(CLINT_BASE, CLINT_SIZE, Box::new(Clint::new(clint.clone())));
+
+        let plic = PlicState::new(lines);
+        mmu.write().unwrap().add_region(PLIC_BASE, PLIC_SIZE, Box::new(Plic::new(plic.clone())));
+
+        for hart in harts.iter_mut() {
+            hart.cpu_context.csrs.set_time_source(clint.get_mtime());
+        }
+
+        RV64Platform {
+            harts,
+            mmu,
+            memory_size,
+            breakpoints: Breakpoints::default(),
+            selected_hart: 0,
+            watch_hit: None,
+            failed_expectations: 0,
+            history: None,
+            tracer: None,
+            editor: DefaultEditor::new().unwrap(),
+            clint,
+            plic,
+            uart: None,
+            symbols: SymbolTable::default(),
+            sbi: None,
+            device_tree: None,
+            stop_on_trap: false,
+        }
+    }
+
+    // Selects Svade or Svadu behaviour for every hart of the machine
+    pub fn set_access_dirty_policy(&mut self, policy: AccessDirtyPolicy) {
+        for hart in self.harts.iter_mut() {
+            hart.cpu_context.csrs.set_access_dirty_policy(policy);
+        }
+    }
+
+    // Interrupt lines of every hart indexed by hart id, used to wire up interrupt controllers
+    pub fn get_interrupt_lines(&self) -> Vec<Arc<InterruptLines>> {
+        self.harts.iter().map(|hart| hart.cpu_context.csrs.get_interrupt_lines()).collect()
+    }
+
+    // Drives the timer, software or external interrupt line of a hart
+    pub fn set_interrupt_line(&self, hart_id: usize, lines: MIPFlags, level: bool) {
+        self.harts[hart_id].cpu_context.csrs.get_interrupt_lines().set(lines, level);
+    }
+
+    // Handle for a device to raise the numbered external interrupt source on the PLIC
+    pub fn get_irq_line(&self, source: usize) -> IrqLine {
+        IrqLine::new(self.plic.clone(), source)
+    }
+
+    pub fn set_irq(&self, source: usize, level: bool) {
+        self.plic.set_irq(source, level);
+    }
+
+    // Maps the NS16550A console at UART_BASE, its interrupt goes to the PLIC
+    pub fn attach_uart(&mut self, backend: &UartBackend) -> io::Result<()> {
+        let uart = UartState::new(self.get_irq_line(UART_IRQ), backend)?;
+        self.mmu.write().unwrap().add_region(UART_BASE, UART_SIZE, Box::new(Uart::new(uart.clone())));
+        self.uart = Some(uart);
+
+        Ok(())
+    }
+
+    // Host terminal input goes to the guest's console only while the machine runs, the debugger prompt
+    // reads it otherwise
+    pub fn set_console_input(&self, enabled: bool) {
+        if let Some(uart) = &self.uart {
+            uart.set_console_input(enabled);
+        }
+    }
+
+    pub fn load_disk_image(&mut self, disk_image: &str) {
+        let path = Path::new(disk_image);
+
+        if(path.is_file()) {
+            let mut file = File::open(path).unwrap();
+            let len = file.metadata().unwrap().len() as usize;
+            let mut buf = vec![0; len];
+
+            file.read(buf.as_mut_slice()).expect("Failed to read disk_image");
+
+            self.mmu.write().unwrap().write(DRAM_BASE as usize, len, buf.as_slice());
+        }
+    }
+
+    // Maps the PT_LOAD segments of an ELF executable and starts every hart at its entry point
+    pub fn load_elf(&mut self, path: &str) -> Result<(), ElfError> {
+        let image = ElfImage::from_file(Path::new(path))?;
+
+        image.load(&mut self.mmu.write().unwrap())?;
+
+        let entry = image.physical_entry();
+
+        for hart in self.harts.iter_mut() {
+            hart.cpu_context.pc = entry;
+        }
+
+        self.symbols = image.symbols;
+
+        Ok(())
+    }
+
+    pub fn get_symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    // Address followed by the symbol it belongs to, if the image had any
+    fn format_address(&self, addr: u64) -> String {
+        match self.symbols.describe(addr) {
+            Some(symbol) => format!("{:#x} <{}>", addr, symbol),
+            None => format!("{:#x}", addr),
+        }
+    }
+
+    // Accepts hexadecimal addresses as well as symbol names
+    fn parse_address(&self, arg: &str) -> Option<u64> {
+        match self.symbols.find(arg) {
+            Some(symbol) => Some(symbol.address),
+            None => u64::from_str_radix(arg.trim_start_matches("0x"), 16).ok(),
+        }
+    }
+
+    // Disassembles up to count instructions at vaddr as the hart sees it, stops at the first unmapped address
+    pub fn disassemble(&self, hart_id: usize, vaddr: u64, count: usize) -> Vec<String> {
+        self.disassemble_from(vaddr, count, |addr, buf| self.read_memory(hart_id, addr, false, buf))
+    }
+
+    fn disassemble_from(&self, vaddr: u64, count: usize, read: impl Fn(u64, &mut [u8]) -> Result<(), Exception>) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut addr = vaddr;
+
+        for _ in 0..count {
+            let mut bytes = [0u8; 4];
+
+            if read(addr, &mut bytes[..2]).is_err() {
+                break;
+            }
+
+            if !is_compressed(u16::from_le_bytes([bytes[0], bytes[1]]) as u32) && read(addr.wrapping_add(2), &mut bytes[2..]).is_err() {
+                break;
+            }
+
+            let (instr, length) = read_instruction(&bytes).unwrap();
+            let raw = if length == 2 { format!("{:04x}", instr) } else { format!("{:08x}", instr) };
+
+            lines.push(format!("{}:  {:<8}  {}", self.format_address(addr), raw, format_instruction(instr, addr, &self.symbols)));
+            addr = addr.wrapping_add(length as u64);
+        }
+
+        lines
+    }
+
+    // Guest memory as the debugger sees it, virtual addresses are translated through the hart's page tables
+    pub fn read_memory(&self, hart_id: usize, addr: u64, physical: bool, buf: &mut [u8]) -> Result<(), Exception> {
+        if !physical {
+            return self.harts[hart_id].cpu_context.debug_read(addr, buf);
+        }
+
+        let mmu = self.mmu.read().unwrap();
+
+        if !mmu.is_mapped(addr as usize, buf.len()) {
+            return Err(Exception::LoadAccessFault);
+        }
+
+        mmu.read(addr as usize, buf.len(), buf);
+        Ok(())
+    }
+
+    pub fn write_memory(&self, hart_id: usize, addr: u64, physical: bool, data: &[u8]) -> Result<(), Exception> {
+        if !physical {
+            return self.harts[hart_id].cpu_context.debug_write(addr, data);
+        }
+
+        let mut mmu = self.mmu.write().unwrap();
+
+        if !mmu.is_mapped(addr as usize, data.len()) {
+            return Err(Exception::StoreAccessFault);
+        }
+
+        mmu.write(addr as usize, data.len(), data);
+        Ok(())
+    }
+
+    // Formats memory like gdb's x/<count><format><size>: x, d and u print numbers of 1, 2, 4 or 8 bytes (b, h,
+    // w, g), s prints C strings and i instructions
+    pub fn examine(&self, hart_id: usize, spec: &str, addr: u64, physical: bool) -> Result<Vec<String>, String> {
+        let (count, format, size) = parse_examine_spec(spec).ok_or_else(|| format!("Invalid format '{}'", spec))?;
+        let read = |addr: u64, buf: &mut [u8]| self.read_memory(hart_id, addr, physical, buf);
+
+        let lines = match format {
+            'i' => self.disassemble_from(addr, count, read),
+            's' => {
+                let mut lines = Vec::new();
+                let mut addr = addr;
+
+                for _ in 0..count {
+                    let mut string = Vec::new();
+                    let mut byte = [0u8];
+
+                    while string.len() < EXAMINE_STRING_LIMIT && read(addr + string.len() as u64, &mut byte).is_ok() && byte[0] != 0 {
+                        string.push(byte[0]);
+                    }
+
+                    lines.push(format!("{}:  \"{}\"", self.format_address(addr), string.escape_ascii()));
+                    addr += string.len() as u64 + 1;
+                }
+
+                lines
+            }
+            _ => {
+                let mut bytes = vec![0u8; count * size];
+                read(addr, &mut bytes).map_err(|_| format!("Cannot access memory at {:#x}", addr))?;
+
+                let per_line = (16 / size).min(8);
+
+                bytes.chunks(per_line * size).enumerate().map(|(line, chunk)| {
+                    let values: Vec<String> = chunk.chunks(size).map(|value| {
+                        let mut raw = [0u8; 8];
+                        raw[..size].copy_from_slice(value);
+
+                        let value = u64::from_le_bytes(raw);
+                        let sign_extended = ((value << (64 - size * 8)) as i64) >> (64 - size * 8);
+
+                        match format {
+                            'd' => sign_extended.to_string(),
+                            'u' => value.to_string(),
+                            _ => format!("{:#0width$x}", value, width = size * 2 + 2),
+                        }
+                    }).collect();
+
+                    format!("{}:  {}", self.format_address(addr + (line * per_line * size) as u64), values.join("  "))
+                }).collect()
+            }
+        };
+
+        match lines.is_empty() {
+            true => Err(format!("Cannot access memory at {:#x}", addr)),
+            false => Ok(lines),
+        }
+    }
+
+    // Describes the machine to the guest the way QEMU's virt board does
+    pub fn generate_device_tree(&self) -> DtbNode {
+        let mut root = DtbNode::new("");
+        root.set_u32("#address-cells", 2);
+        root.set_u32("#size-cells", 2);
+        root.set_string("compatible", "rocket-v,virt");
+        root.set_string("model", "Rocket-V");
+
+        // Phandles 1..=harts are the interrupt controllers of the harts, the PLIC comes after them
+        let intc_phandle = |hart_id: usize| hart_id as u32 + 1;
+        let plic_phandle = self.harts.len() as u32 + 1;
+
+        let cpus = root.add_child(DtbNode::new("cpus"));
+        cpus.set_u32("#address-cells", 1);
+        cpus.set_u32("#size-cells", 0);
+        cpus.set_u32("timebase-frequency", TIMEBASE_FREQUENCY as u32);
+
+        for (hart_id, hart) in self.harts.iter().enumerate() {
+            let cpu = cpus.add_child(DtbNode::new(&format!("cpu@{:x}", hart_id)));
+            cpu.set_string("device_type", "cpu");
+            cpu.set_u32("reg", hart_id as u32);
+            cpu.set_string("status", "okay");
+            cpu.set_string("compatible", "riscv");
+            cpu.set_string("riscv,isa", &hart.cpu_context.csrs.get_isa_string());
+            cpu.set_string("mmu-type", "riscv,sv57");
+
+            let intc = cpu.add_child(DtbNode::new("interrupt-controller"));
+            intc.set_u32("#interrupt-cells", 1);
+            intc.set_empty("interrupt-controller");
+            intc.set_string("compatible", "riscv,cpu-intc");
+            intc.set_u32("phandle", intc_phandle(hart_id));
+        }
+
+        let memory = root.add_child(DtbNode::new(&format!("memory@{:x}", DRAM_BASE)));
+        memory.set_string("device_type", "memory");
+        memory.set_cells("reg", &reg_cells(DRAM_BASE, self.memory_size));
+
+        let soc = root.add_child(DtbNode::new("soc"));
+        soc.set_u32("#address-cells", 2);
+        soc.set_u32("#size-cells", 2);
+        soc.set_string("compatible", "simple-bus");
+        soc.set_empty("ranges");
+
+        // Software and timer interrupts of every hart, then machine and supervisor external interrupts
+        let clint_interrupts: Vec<u32> = (0..self.harts.len())
+            .flat_map(|hart_id| [intc_phandle(hart_id), 3, intc_phandle(hart_id), 7])
+            .collect();
+        let plic_interrupts: Vec<u32> = (0..self.harts.len())
+            .flat_map(|hart_id| [intc_phandle(hart_id), 11, intc_phandle(hart_id), 9])
+            .collect();
+
+        let clint = soc.add_child(DtbNode::new(&format!("clint@{:x}", CLINT_BASE)));
+        clint.set_strings("compatible", &["sifive,clint0", "riscv,clint0"]);
+        clint.set_cells("reg", &reg_cells(CLINT_BASE as u64, CLINT_SIZE as u64));
+        clint.set_cells("interrupts-extended", &clint_interrupts);
+
+        let plic = soc.add_child(DtbNode::new(&format!("plic@{:x}", PLIC_BASE)));
+        plic.set_strings("compatible", &["sifive,plic-1.0.0", "riscv,plic0"]);
+        plic.set_cells("reg", &reg_cells(PLIC_BASE as u64, PLIC_SIZE as u64));
+        plic.set_u32("#address-cells", 0);
+        plic.set_u32("#interrupt-cells", 1);
+        plic.set_empty("interrupt-controller");
+        plic.set_u32("riscv,ndev", PLIC_SOURCES as u32 - 1);
+        plic.set_cells("interrupts-extended", &plic_interrupts);
+        plic.set_u32("phandle", plic_phandle);
+
+        if self.uart.is_some() {
+            let serial = soc.add_child(DtbNode::new(&format!("serial@{:x}", UART_BASE)));
+            serial.set_string("compatible", "ns16550a");
+            serial.set_cells("reg", &reg_cells(UART_BASE as u64, UART_SIZE as u64));
+            serial.set_u32("clock-frequency", UART_CLOCK_FREQUENCY as u32);
+            serial.set_u32("interrupts", UART_IRQ as u32);
+            serial.set_u32("interrupt-parent", plic_phandle);
+        }
+
+        // Always there, booting a kernel adds the command line and initrd to it
+        let chosen = root.add_child(DtbNode::new("chosen"));
+
+        if self.uart.is_some() {
+            chosen.set_string("stdout-path", &format!("/soc/serial@{:x}", UART_BASE));
+        }
+
+        root
+    }
+
+    // Copies the blob to the top of RAM and points a1 of every hart at it, a0 holds the hart id
+    pub fn load_device_tree(&mut self, tree: &DtbNode) -> Result<u64, DtbError> {
+        let blob = to_blob(tree, 0);
+        let ram_end = DRAM_BASE + self.memory_size;
+
+        let addr = ram_end.checked_sub(blob.len() as u64)
+            .map(|addr| addr & !(PAGE_SIZE - 1))
+            .filter(|&addr| addr >= DRAM_BASE)
+            .ok_or(DtbError::TooLarge { size: blob.len() })?;
+
+        self.mmu.write().unwrap().write(addr as usize, blob.len(), &blob);
+
+        for (hart_id, hart) in self.harts.iter_mut().enumerate() {
+            hart.cpu_context.set_register(10, hart_id as u64);
+            hart.cpu_context.set_register(11, addr);
+        }
+
+        self.device_tree = Some(blob);
+
+        Ok(addr)
+    }
+
+    // The blob last handed to the guest
+    pub fn device_tree_blob(&self) -> Option<&[u8]> {
+        self.device_tree.as_deref()
+    }
+
+    // Emulates M-mode, hart 0 is put into S-mode and the others wait to be started through HSM
+    pub fn enable_builtin_sbi(&mut self) {
+        Sbi::prepare_hart(&mut self.harts[0].cpu_context);
+
+        self.clint.route_timer_interrupt(MIPFlags::STIP);
+        self.sbi = Some(Sbi::new(self.harts.len()));
+    }
+
+    // Copies a raw binary into guest memory, returns its size
+    fn load_binary(&mut self, path: &str, addr: u64) -> Result<u64, BootError> {
+        let data = std::fs::read(path).map_err(|e| BootError::Io(path.to_string(), e))?;
+        let size = data.len() as u64;
+
+        let mut mmu = self.mmu.write().unwrap();
+
+        if !mmu.is_mapped(addr as usize, data.len()) {
+            return Err(BootError::DoesNotFit { path: path.to_string(), addr, size });
+        }
+
+        mmu.write(addr as usize, data.len(), &data);
+
+        Ok(size)
+    }
+
+    // Resets the harts into the firmware the way QEMU's virt machine does, with the kernel, initrd
+    // and device tree already in memory
+    pub fn boot_linux(&mut self, boot: &LinuxBoot, mut tree: DtbNode) -> Result<(), BootError> {
+        match &boot.firmware {
+            Some(firmware) if is_elf_file(Path::new(firmware)) => {
+                self.load_elf(firmware)?;
+            }
+            Some(firmware) => {
+                self.load_binary(firmware, FIRMWARE_BASE)?;
+
+                for hart in self.harts.iter_mut() {
+                    hart.cpu_context.pc = FIRMWARE_BASE;
+                }
+            }
+            None => {
+                //Without firmware the kernel is entered directly in S-mode and SBI calls are served by the emulator
+                self.enable_builtin_sbi();
+                self.harts[0].cpu_context.pc = KERNEL_BASE;
+            }
+        }
+
+        let kernel_size = self.load_binary(&boot.kernel, KERNEL_BASE)?;
+
+        let initrd = match &boot.initrd {
+            Some(path) => {
+                let start = initrd_address(KERNEL_BASE, self.memory_size).max(KERNEL_BASE + kernel_size);
+                let size = self.load_binary(path, start)?;
+
+                Some((start, start + size))
+            }
+            None => None,
+        };
+
+        set_chosen(&mut tree, boot.cmdline.as_deref(), initrd);
+
+        let dtb = self.load_device_tree(&tree)?;
+
+        //The device tree is placed at the top of RAM and must not land on top of the initrd
+        if let Some((start, end)) = initrd {
+            if end > dtb {
+                return Err(BootError::DoesNotFit { path: boot.initrd.clone().unwrap(), addr: start, size: end - start });
+            }
+        }
+
+        Ok(())
+    }
+
+    // Runs debugger commands from a file, one per line with # marking comment lines, and returns how many
+    // expectations failed
+    pub fn run_script(&mut self, path: &Path, cycle_callback: fn(cycle: usize)) -> io::Result<usize> {
+        let script = fs::read_to_string(path)?;
+
+        for line in script.lines() {
+            let command = line.trim();
+
+            if command.is_empty() || command.starts_with('#') {
+                continue;
+            }
+
+            println!("debug> {}", command);
+            self.handle_debug_command(command, cycle_callback);
+        }
+
+        Ok(self.failed_expectations)
+    }
+
+    pub fn debug_loop(&mut self, cycle_callback: fn(cycle: usize)) {
+        println!("RISC-V Debugger. Type 'help' for commands.");
+
+        loop {
+            match self.editor.readline("debug> ") {
+                Ok(line) => {
+                    self.editor.add_history_entry(line.as_str()).expect("Should work");
+                    self.handle_debug_command(&line, cycle_callback);
+                }
+                Err(_) => break,
+            }
+        }
+    }
+
+    pub fn get_hart_count(&self) -> usize {
+        self.harts.len()
+    }
+
+    pub(crate) fn get_cpu_context(&self, hart_id: usize) -> &RV64CPUContext {
+        &self.harts[hart_id].cpu_context
+    }
+
+    pub(crate) fn get_cpu_context_mut(&mut self, hart_id: usize) -> &mut RV64CPUContext {
+        &mut self.harts[hart_id].cpu_context
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u64) -> usize {
+        self.breakpoints.insert(Location::Pc(addr), None)
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u64) -> bool {
+        self.breakpoints.remove_pc(addr)
+    }
+
+    pub fn get_breakpoints(&self) -> &Breakpoints {
+        &self.breakpoints
+    }
+
+    // Watchpoint on a physical range that stops the debugger, conditions are checked on the accessing hart
+    pub fn add_debugger_watchpoint(&mut self, watchpoint: Watchpoint, vaddr: Option<u64>, condition: Option<Condition>) -> usize {
+        let id = self.breakpoints.insert(Location::Watch { watchpoint, vaddr }, condition);
+        self.sync_watchpoint(&watchpoint);
+
+        id
+    }
+
+    pub fn delete_breakpoint(&mut self, id: usize) -> bool {
+        match self.breakpoints.remove(id) {
+            Some(breakpoint) => {
+                if let Location::Watch { watchpoint, .. } = breakpoint.location {
+                    self.sync_watchpoint(&watchpoint);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn set_breakpoint_enabled(&mut self, id: usize, enabled: bool) -> bool {
+        let Some(breakpoint) = self.breakpoints.get_mut(id) else {
+            return false;
+        };
+
+        breakpoint.enabled = enabled;
+
+        if let Location::Watch { watchpoint, .. } = breakpoint.location {
+            self.sync_watchpoint(&watchpoint);
+        }
+        true
+    }
+
+    pub fn set_breakpoint_condition(&mut self, id: usize, condition: Option<Condition>) -> bool {
+        match self.breakpoints.get_mut(id) {
+            Some(breakpoint) => {
+                breakpoint.condition = condition;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // The MMU watches a range once no matter how many enabled breakpoints share it
+    fn sync_watchpoint(&mut self, watchpoint: &Watchpoint) {
+        let mut mmu = self.mmu.write().unwrap();
+
+        mmu.remove_watchpoint(watchpoint);
+
+        if self.breakpoints.is_watched(watchpoint) {
+            mmu.add_watchpoint(*watchpoint);
+        }
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.mmu.write().unwrap().add_watchpoint(watchpoint);
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        self.mmu.write().unwrap().remove_watchpoint(watchpoint)
+    }
+
+    // First watchpoint hit since the last call and the hart whose access triggered it
+    pub fn take_watch_hit(&mut self) -> Option<(usize, WatchHit)> {
+        self.watch_hit.take()
+    }
+
+    // Stops once the register of the hart changes, checked after every round
+    pub fn add_register_watch(&mut self, hart_id: usize, register: Register, condition: Option<Condition>) -> Result<usize, Exception> {
+        let value = self.harts[hart_id].cpu_context.read_debug_register(register)?;
+
+        Ok(self.breakpoints.insert(Location::Register { hart_id, register, value }, condition))
+    }
+
+    // Watched register that changed during the last step and whose condition holds, with its hart, old and new value
+    pub(crate) fn register_watch_hit(&mut self) -> Option<(usize, usize, u64, u64)> {
+        let mut stop = None;
+
+        for hart_id in 0..self.harts.len() {
+            let cpu = &self.harts[hart_id].cpu_context;
+
+            if let Some((id, old)) = self.breakpoints.register_changed(hart_id, cpu) {
+                let new = self.breakpoints.get(id).map_or(old, |breakpoint| match breakpoint.location {
+                    Location::Register { value, .. } => value,
+                    _ => old,
+                });
+
+                stop.get_or_insert((hart_id, id, old, new));
+            }
+        }
+
+        stop
+    }
+
+    // Watched registers start over from their current values, used after the debugger changed them
+    fn refresh_register_watches(&mut self) {
+        for hart_id in 0..self.harts.len() {
+            self.breakpoints.refresh_registers(hart_id, &self.harts[hart_id].cpu_context);
+        }
+    }
+
+    // Keeps a snapshot of the machine every interval rounds and the old contents of every RAM write, so it
+    // can be put back to any of the last interval * limit rounds
+    pub fn start_recording(&mut self, interval: u64, limit: usize) {
+        self.mmu.write().unwrap().start_journal();
+
+        let first = self.take_snapshot(0);
+        self.history = Some(History::new(first, interval, limit));
+    }
+
+    pub fn stop_recording(&mut self) {
+        self.history = None;
+        self.mmu.write().unwrap().stop_journal();
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.history.is_some()
+    }
+
+    // Whether continue, run and until stop when a hart raises an exception. Off by default, a booting kernel
+    // takes page faults and system calls all the time and handles them itself
+    pub fn set_stop_on_trap(&mut self, enabled: bool) {
+        self.stop_on_trap = enabled;
+    }
+
+    // How many rounds the machine can go back
+    pub fn reversible_steps(&self) -> Option<u64> {
+        self.history.as_ref().map(|history| history.step() - history.oldest_step())
+    }
+
+    fn take_snapshot(&self, step: u64) -> Snapshot {
+        let mmu = self.mmu.read().unwrap();
+
+        Snapshot {
+            step,
+            journal_len: mmu.journal_len(),
+            harts: self.harts.clone(),
+            sbi: self.sbi.clone(),
+            clint: self.clint.snapshot(),
+            plic: self.plic.snapshot(),
+            uart: self.uart.as_ref().map(|uart| uart.snapshot()),
+            reservations: mmu.get_reservations(),
+        }
+    }
+
+    fn record_step(&mut self) {
+        let Some(history) = self.history.as_mut() else {
+            return;
+        };
+
+        if !history.advance() {
+            return;
+        }
+
+        let step = history.step();
+        let snapshot = self.take_snapshot(step);
+        let forgotten = self.history.as_mut().unwrap().push(snapshot);
+
+        self.mmu.write().unwrap().forget_journal(forgotten);
+    }
+
+    // Puts the machine back to where it was after the given round by restoring the closest snapshot before
+    // it and replaying from there. What devices output while replaying, like console text, is output again
+    fn rewind_to(&mut self, step: u64) {
+        let history = self.history.as_mut().unwrap();
+        let snapshot = history.rewind(step);
+
+        let mut mmu = self.mmu.write().unwrap();
+        mmu.rewind_journal(snapshot.journal_len);
+        mmu.set_reservations(snapshot.reservations.clone());
+        mmu.take_watch_hit();
+        drop(mmu);
+
+        self.harts = snapshot.harts.clone();
+        self.sbi = snapshot.sbi.clone();
+        self.clint.restore(&snapshot.clint);
+
+        // The PLIC goes first, the UART then drives its line into the restored gateway
+        self.plic.restore(&snapshot.plic);
+
+        if let (Some(uart), Some(state)) = (self.uart.as_ref(), snapshot.uart.as_ref()) {
+            uart.restore(state);
+        }
+
+        // Replayed instructions were traced when they first ran
+        let tracer = self.tracer.take();
+        self.sync_commit_logs();
+
+        while self.history.as_ref().unwrap().step() < step {
+            let _ = self.step();
+        }
+
+        self.tracer = tracer;
+        self.sync_commit_logs();
+
+        self.watch_hit = None;
+    }
+
+    // Goes back count rounds, returns false if the history doesn't reach that far and the machine was put at
+    // the oldest recorded round instead
+    pub fn reverse_step(&mut self, count: u64) -> bool {
+        let Some(history) = self.history.as_ref() else {
+            return false;
+        };
+
+        let reachable = history.step() - history.oldest_step() >= count;
+        let target = history.step().saturating_sub(count).max(history.oldest_step());
+
+        self.rewind_to(target);
+        self.refresh_register_watches();
+
+        reachable
+    }
+
+    // Goes back to the latest point before the current round at which stop reports something. stop is called
+    // after every replayed round with its result, the history is searched one snapshot interval at a time
+    // from the newest. Returns None with the machine at the oldest recorded round if nothing stopped it
+    pub(crate) fn reverse_continue<T>(&mut self, mut stop: impl FnMut(&mut Self, Result<(), (usize, Exception)>) -> Option<ReplayStop<T>>) -> Option<T> {
+        let end = self.history.as_ref()?.step();
+
+        // Replaying counts hits again, the breakpoints are put back the way they were
+        let breakpoints = self.breakpoints.clone();
+        let tracer = self.tracer.take();
+
+        let mut segment_end = end;
+        let mut found = None;
+
+        while found.is_none() {
+            let Some(start) = self.history.as_ref().unwrap().snapshot_before(segment_end) else {
+                break;
+            };
+
+            self.rewind_to(start);
+
+            for before in start..segment_end {
+                let result = self.step();
+
+                match stop(self, result) {
+                    Some(ReplayStop::Before(value)) => found = Some((before, value)),
+                    Some(ReplayStop::After(value)) if before + 1 < end => found = Some((before + 1, value)),
+                    _ => {}
+                }
+            }
+
+            segment_end = start;
+        }
+
+        let result = match found {
+            Some((step, value)) => {
+                self.rewind_to(step);
+                Some(value)
+            }
+            None => {
+                let oldest = self.history.as_ref().unwrap().oldest_step();
+                self.rewind_to(oldest);
+                None
+            }
+        };
+
+        self.breakpoints = breakpoints;
+        self.refresh_register_watches();
+
+        self.tracer = tracer;
+        self.sync_commit_logs();
+
+        result
+    }
+
+    // trace <file> [pc <start>:<end>] [cycles <start>:<end>] or trace stop
+    fn trace_command(&mut self, args: &[&str]) {
+        if args == ["stop"] {
+            return self.stop_trace();
+        }
+
+        let usage = "Usage: trace <file> [pc <start>:<end>] [cycles <start>:<end>] | trace stop";
+
+        let Some((path, filters)) = args.split_first() else {
+            return println!("{}", usage);
+        };
+
+        let (mut pc_range, mut cycle_range) = (None, None);
+
+        for filter in filters.chunks(2) {
+            let range = match filter.get(1).map(|range| range.parse::<TraceRange>()) {
+                Some(Ok(range)) => range,
+                Some(Err(e)) => return println!("{}", e),
+                None => return println!("{}", usage),
+            };
+
+            match filter[0] {
+                "pc" => pc_range = Some(range),
+                "cycles" => cycle_range = Some(range),
+                _ => return println!("{}", usage),
+            }
+        }
+
+        match Tracer::create(Path::new(path)) {
+            Ok(mut tracer) => {
+                tracer.set_pc_range(pc_range);
+                tracer.set_cycle_range(cycle_range);
+
+                // Whatever was traced before goes to its file first
+                self.stop_trace();
+                self.start_trace(tracer);
+            }
+            Err(e) => println!("Cannot create {}: {}", path, e),
+        }
+    }
+
+    // Writes what the harts execute from now on to tracer
+    pub fn start_trace(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+        self.sync_commit_logs();
+    }
+
+    pub fn stop_trace(&mut self) {
+        if let Some(mut tracer) = self.tracer.take() {
+            let _ = tracer.flush();
+        }
+
+        self.sync_commit_logs();
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.tracer.is_some()
+    }
+
+    // Harts only collect their writes while something traces them
+    fn sync_commit_logs(&mut self) {
+        let enabled = self.tracer.is_some();
+
+        for hart in self.harts.iter_mut() {
+            hart.cpu_context.commit.set_enabled(enabled);
+        }
+    }
+
+    // Harts stopped through HSM don't execute until another hart starts them again
+    pub(crate) fn is_running(&self, hart_id: usize) -> bool {
+        self.sbi.as_ref().is_none_or(|sbi| sbi.is_running(hart_id))
+    }
+
+    // Runs one instruction on every running hart in hart id order and advances the machine timer
+    // once, so runs are reproducible. Traps are taken right away, the first one is reported
+    pub fn step(&mut self) -> Result<(), (usize, Exception)> {
+        let mut result = Ok(());
+
+        for hart_id in 0..self.harts.len() {
+            if !self.is_running(hart_id) {
+                continue;
+            }
+
+            let mut trap = None;
+
+            match self.harts[hart_id].step() {
+                Ok(()) => {}
+                Err(Exception::EnvironmentCallFromSMode) if self.sbi.is_some() => self.handle_sbi_call(hart_id),
+                Err(e) => {
+                    trap = Some((e, self.harts[hart_id].cpu_context.trap_value));
+                    self.harts[hart_id].handle_exception(e);
+                    result = result.and(Err((hart_id, e)));
+                }
+            }
+
+            if let Some(tracer) = self.tracer.as_mut() {
+                if let Err(e) = tracer.trace(hart_id, &self.harts[hart_id].cpu_context.commit, trap, &self.symbols) {
+                    eprintln!("Failed to write trace, tracing stopped: {}", e);
+                    self.stop_trace();
+                }
+            }
+
+            if let Some(hit) = self.mmu.read().unwrap().take_watch_hit() {
+                self.watch_hit.get_or_insert((hart_id, hit));
+            }
+        }
+
+        let idle = (0..self.harts.len()).all(|hart_id| !self.is_running(hart_id) || self.harts[hart_id].is_idle());
+
+        if idle {
+            //Nothing can happen before the next timer deadline, skip ahead instead of spinning
+            self.clint.skip_to_next_deadline();
+        } else {
+            self.clint.tick();
+        }
+
+        self.record_step();
+
+        result
+    }
+
+    // First hart whose next instruction has a breakpoint on it whose condition holds, and that breakpoint
+    pub(crate) fn hart_at_breakpoint(&mut self) -> Option<(usize, usize)> {
+        let mut stop = None;
+
+        for hart_id in 0..self.harts.len() {
+            if !self.is_running(hart_id) {
+                continue;
+            }
+
+            let cpu = &self.harts[hart_id].cpu_context;
+
+            if let Some(id) = self.breakpoints.hit(|location| *location == Location::Pc(cpu.pc), cpu) {
+                stop.get_or_insert((hart_id, id));
+            }
+        }
+
+        stop
+    }
+
+    // Watchpoint of the debugger hit during the last step whose condition holds, with the hart that hit it
+    pub(crate) fn watchpoint_hit(&mut self) -> Option<(usize, usize, WatchHit)> {
+        let (hart_id, hit) = self.take_watch_hit()?;
+        let cpu = &self.harts[hart_id].cpu_context;
+
+        let id = self.breakpoints.hit(|location| matches!(location, Location::Watch { watchpoint, .. } if *watchpoint == hit.watchpoint), cpu)?;
+
+        Some((hart_id, id, hit))
+    }
+
+    // Serves an ECALL from S-mode in place of the M-mode firmware and resumes after it
+    fn handle_sbi_call(&mut self, hart_id: usize) {
+        let sbi = self.sbi.as_mut().unwrap();
+        let mut harts: Vec<&mut RV64CPUContext> = self.harts.iter_mut().map(|hart| &mut hart.cpu_context).collect();
+
+        sbi.handle_ecall(hart_id, &mut harts, &self.clint, self.uart.as_deref());
+
+        let cpu = &mut self.harts[hart_id].cpu_context;
+        cpu.pc += 4;
+
+        // For the guest the ecall completes like any other instruction
+        if cpu.commit.is_enabled() {
+            cpu.commit.retire(&cpu.csrs);
+        }
+    }
+
+    // The guest powered the machine off or asked for a reboot through SBI
+    pub fn is_halted(&self) -> bool {
+        self.get_reset().is_some()
+    }
+
+    pub fn get_reset(&self) -> Option<SystemReset> {
+        self.sbi.as_ref().and_then(|sbi| sbi.get_reset())
+    }
+
+    // watch [-p] <x> [len] [if <cond>], virtual addresses are translated through the selected hart once,
+    // when the watchpoint is set
+    fn watch_command(&mut self, kind: WatchKind, args: &[&str]) {
+        let (args, condition) = match split_condition(args) {
+            Ok(split) => split,
+            Err(e) => return println!("{}", e),
+        };
+
+        if let Some(name) = args.first().and_then(|arg| arg.strip_prefix('$')) {
+            let Some(register) = Register::from_name(name).filter(|_| kind == WatchKind::Write && args.len() == 1) else {
+                return println!("Usage: watch $<register> [if <condition>]");
+            };
+
+            return match self.add_register_watch(self.selected_hart, register, condition) {
+                Ok(id) => println!("Watchpoint {}: {} on hart {}", id, register, self.selected_hart),
+                Err(e) => println!("Cannot read {}: {:?}", name, e),
+            };
+        }
+
+        let physical = args.first() == Some(&"-p");
+        let args = if physical { &args[1..] } else { &args[..] };
+
+        let addr = args.first().and_then(|arg| self.parse_address(arg));
+        let len = match args.get(1) {
+            Some(arg) => arg.parse::<u64>().ok().filter(|len| *len > 0),
+            None => Some(4),
         };
 
-        platform.harts.push(Interpreter::new(0x1000, platform.mmu.clone()));
+        let (Some(addr), Some(len)) = (addr, len) else {
+            return println!("Usage: watch | rwatch | awatch [-p] <address|symbol> [len] [if <condition>]");
+        };
+
+        let (paddr, vaddr) = match physical {
+            true => (addr, None),
+            false => match self.harts[self.selected_hart].cpu_context.debug_translate(addr) {
+                Ok(paddr) => (paddr, Some(addr)),
+                Err(e) => return println!("Cannot translate {:#x}: {:?}", addr, e),
+            },
+        };
 
-        platform
+        let id = self.add_debugger_watchpoint(Watchpoint { addr: paddr, len, kind }, vaddr, condition);
+        println!("Watchpoint {}: {}", id, describe_watchpoint(&Watchpoint { addr: paddr, len, kind }, vaddr));
     }
 
-    pub fn load_disk_image(&mut self, disk_image: &str) {
-        let path = Path::new(disk_image);
+    fn print_watch_hit(&self, hart_id: usize, id: usize, hit: &WatchHit) {
+        let vaddr = match self.breakpoints.get(id).map(|breakpoint| breakpoint.location) {
+            Some(Location::Watch { vaddr: Some(vaddr), watchpoint }) => vaddr + hit.addr.saturating_sub(watchpoint.addr),
+            _ => hit.addr,
+        };
 
-        if(path.is_file()) {
-            let mut file = File::open(path).unwrap();
-            let len = file.metadata().unwrap().len() as usize;
-            let mut buf = vec![0; len];
+        println!("Watchpoint {} hit on hart {}: {} at {:#x} by {}", id, hart_id, if hit.write { "write" } else { "read" }, vaddr,
+                 self.format_address(self.harts[hart_id].cpu_context.pc));
+    }
 
-            file.read(buf.as_mut_slice()).expect("Failed to read disk_image");
+    fn print_register_hit(&self, hart_id: usize, id: usize, old: u64, new: u64) {
+        let register = match self.breakpoints.get(id).map(|breakpoint| breakpoint.location) {
+            Some(Location::Register { register, .. }) => register.to_string(),
+            _ => "register".to_string(),
+        };
+
+        println!("Watchpoint {} hit on hart {}: {} changed from {:#x} to {:#x} by {}", id, hart_id, register, old, new,
+                 self.format_address(self.harts[hart_id].cpu_context.pc));
+    }
+
+    fn print_breakpoints(&self) {
+        if self.breakpoints.iter().next().is_none() {
+            return println!("No breakpoints or watchpoints.");
+        }
+
+        println!("{:<4} {:<16} {:<4} What", "Num", "Type", "Enb");
+
+        for breakpoint in self.breakpoints.iter() {
+            let (kind, what) = match breakpoint.location {
+                Location::Pc(addr) => ("breakpoint", self.format_address(addr)),
+                Location::Watch { watchpoint, vaddr } => {
+                    let kind = match watchpoint.kind {
+                        WatchKind::Write => "watchpoint",
+                        WatchKind::Read => "read watchpoint",
+                        WatchKind::Access => "acc watchpoint",
+                    };
+
+                    (kind, describe_watchpoint(&watchpoint, vaddr))
+                }
+                Location::Register { hart_id, register, .. } => ("watchpoint", format!("{} on hart {}", register, hart_id)),
+            };
+
+            println!("{:<4} {:<16} {:<4} {}", breakpoint.id, kind, if breakpoint.enabled { "y" } else { "n" }, what);
+
+            if let Some(condition) = breakpoint.condition {
+                println!("        stop only if {}", condition);
+            }
 
-            self.mmu.write().unwrap().write(0x1000, len, buf.as_slice());
+            if breakpoint.hits > 0 {
+                println!("        already hit {} time{}", breakpoint.hits, if breakpoint.hits == 1 { "" } else { "s" });
+            }
         }
     }
 
-    pub fn debug_loop(&mut self, cycle_callback: fn(cycle: usize)) {
-        println!("RISC-V Debugger. Type 'help' for commands.");
+    // Runs until a breakpoint, watchpoint, exception or reset stops the machine, after rounds steps or once
+    // the selected hart reaches until
+    fn run(&mut self, rounds: Option<u64>, until: Option<u64>) {
+        let mut steps = 0;
 
-        loop {
-            match self.editor.readline("debug> ") {
-                Ok(line) => {
-                    self.editor.add_history_entry(line.as_str()).expect("Should work");
-                    self.handle_debug_command(&line, cycle_callback);
+        self.set_console_input(true);
+
+        let stop = loop {
+            let result = self.step();
+            steps += 1;
+
+            if let Some(stop) = self.check_stop(result) {
+                break Some(stop);
+            }
+
+            let pc = self.harts[self.selected_hart].cpu_context.pc;
+
+            if until == Some(pc) || rounds == Some(steps) {
+                break None;
+            }
+        };
+
+        self.set_console_input(false);
+
+        match stop {
+            Some(stop) => self.report_stop(stop),
+            None => {
+                let pc = self.harts[self.selected_hart].cpu_context.pc;
+                println!("Stopped after {} steps, hart {} at {}", steps, self.selected_hart, self.format_address(pc));
+            }
+        }
+    }
+
+    // Whatever stops the debugger after a step with the given result
+    fn check_stop(&mut self, result: Result<(), (usize, Exception)>) -> Option<Stop> {
+        let watch_hit = self.watchpoint_hit();
+        let register_hit = self.register_watch_hit();
+
+        if let Some((hart_id, e)) = result.err().filter(|_| self.stop_on_trap) {
+            return Some(Stop::Exception(hart_id, e));
+        }
+
+        if let Some(reset) = self.get_reset() {
+            return Some(Stop::Reset(reset));
+        }
+
+        if let Some((hart_id, id, hit)) = watch_hit {
+            return Some(Stop::Watchpoint(hart_id, id, hit));
+        }
+
+        if let Some((hart_id, id, old, new)) = register_hit {
+            return Some(Stop::Register { hart_id, id, old, new });
+        }
+
+        self.hart_at_breakpoint().map(|(hart_id, id)| Stop::Breakpoint(hart_id, id))
+    }
+
+    // Selects the hart that stopped the machine and tells why
+    fn report_stop(&mut self, stop: Stop) {
+        match stop {
+            Stop::Exception(hart_id, e) => println!("Hart {} raised {:?}", hart_id, e),
+            Stop::Reset(reset) => println!("System reset requested (type {}, reason {})", reset.reset_type, reset.reason),
+            Stop::Watchpoint(hart_id, id, hit) => {
+                self.selected_hart = hart_id;
+                self.print_watch_hit(hart_id, id, &hit);
+            }
+            Stop::Register { hart_id, id, old, new } => {
+                self.selected_hart = hart_id;
+                self.print_register_hit(hart_id, id, old, new);
+            }
+            Stop::Breakpoint(hart_id, id) => {
+                self.selected_hart = hart_id;
+                println!("Breakpoint {} hit on hart {} at {}", id, hart_id, self.format_address(self.harts[hart_id].cpu_context.pc));
+            }
+        }
+    }
+
+    // Watchpoints stop before the access that hit them, everything else after the round that caused it
+    fn reverse_continue_command(&mut self) {
+        let stop = self.reverse_continue(|platform, result| {
+            platform.check_stop(result).map(|stop| match stop {
+                Stop::Watchpoint(..) | Stop::Register { .. } => ReplayStop::Before(stop),
+                _ => ReplayStop::After(stop),
+            })
+        });
+
+        match stop {
+            Some(stop) => self.report_stop(stop),
+            None => {
+                let pc = self.harts[self.selected_hart].cpu_context.pc;
+                println!("No more reverse-execution history, hart {} at {}", self.selected_hart, self.format_address(pc));
+            }
+        }
+    }
+
+    // expect <register> <value>, returns whether the selected hart's register holds the value
+    fn expect_command(&self, args: &[&str]) -> bool {
+        let [name, expected] = args else {
+            println!("Usage: expect <register> <value>");
+            return false;
+        };
+
+        let (Some(register), Some(expected)) = (Register::from_name(name), parse_value(expected)) else {
+            println!("Usage: expect <register> <value>");
+            return false;
+        };
+
+        match self.harts[self.selected_hart].cpu_context.read_debug_register(register) {
+            Ok(value) if value == expected => true,
+            Ok(value) => {
+                println!("Expectation failed: {} is {:#x}, expected {:#x}", name, value, expected);
+                false
+            }
+            Err(e) => {
+                println!("Expectation failed: cannot read {}: {:?}", name, e);
+                false
+            }
+        }
+    }
+
+    // set reg <register> <value> | set mem[/<size>] [-p] <address> <value>
+    fn set_command(&mut self, args: &[&str]) {
+        let hart_id = self.selected_hart;
+
+        match args {
+            ["reg", name, value] => {
+                let (Some(register), Some(value)) = (Register::from_name(name), parse_value(value)) else {
+                    return println!("Usage: set reg <register> <value>");
+                };
+
+                if let Err(e) = self.harts[hart_id].cpu_context.write_debug_register(register, value) {
+                    println!("Cannot write {}: {:?}", name, e);
+                }
+
+                self.refresh_register_watches();
+            }
+            [command, rest @ ..] if command.starts_with("mem") => {
+                let size = match command.strip_prefix("mem/") {
+                    Some(size) => parse_examine_size(size),
+                    None if *command == "mem" => Some(4),
+                    None => None,
+                };
+
+                let physical = rest.first() == Some(&"-p");
+                let rest = if physical { &rest[1..] } else { rest };
+
+                let parsed = match rest {
+                    [addr, value] => self.parse_address(addr).zip(parse_value(value)),
+                    _ => None,
+                };
+
+                let (Some(size), Some((addr, value))) = (size, parsed) else {
+                    return println!("Usage: set mem[/b|h|w|g] [-p] <address|symbol> <value>");
+                };
+
+                if let Err(e) = self.write_memory(hart_id, addr, physical, &value.to_le_bytes()[..size]) {
+                    println!("Cannot write memory at {:#x}: {:?}", addr, e);
                 }
-                Err(_) => break,
             }
+            _ => println!("Usage: set reg <register> <value> | set mem[/b|h|w|g] [-p] <address|symbol> <value>"),
         }
     }
 
@@ -66,42 +1263,310 @@ impl RV64Platform {
 
         match args[0] {
             "s" | "step" => {
-                if let Err(e) = self.harts.first_mut().unwrap().step() {
-                    self.harts.first_mut().unwrap().handle_exception(e);
+                let result = self.step();
+
+                if let Some((hart_id, id, hit)) = self.watchpoint_hit() {
+                    self.print_watch_hit(hart_id, id, &hit);
+                }
+
+                if let Some((hart_id, id, old, new)) = self.register_watch_hit() {
+                    self.print_register_hit(hart_id, id, old, new);
+                }
+
+                if let Err((hart_id, e)) = result {
+                    println!("Hart {} raised {:?}", hart_id, e);
                 }
             }
-            "c" | "continue" => {
-                while !self.breakpoints.contains(&self.harts.first_mut().unwrap().cpu_context.pc) {
-                    if let Err(e) = self.harts.first_mut().unwrap().step()  {
-                        self.harts.first_mut().unwrap().handle_exception(e);
-                        break;
+            "c" | "continue" => self.run(None, None),
+            "rs" | "reverse-step" | "rc" | "reverse-continue" if !self.is_recording() => {
+                println!("Not recording, start with 'record' or --record");
+            },
+            "rs" | "reverse-step" => match args.get(1).map_or(Ok(1), |arg| arg.parse::<u64>()) {
+                Ok(count) => {
+                    if !self.reverse_step(count) {
+                        let pc = self.harts[self.selected_hart].cpu_context.pc;
+                        println!("No more reverse-execution history, hart {} at {}", self.selected_hart, self.format_address(pc));
                     }
                 }
-                println!("Breakpoint hit at {:#x}", self.harts.first().unwrap().cpu_context.pc);
-            }
+                Err(_) => println!("Usage: reverse-step [n]"),
+            },
+            "rc" | "reverse-continue" => self.reverse_continue_command(),
+            "record" => match args.get(1) {
+                None if self.is_recording() => println!("Already recording"),
+                None => self.start_recording(SNAPSHOT_INTERVAL, SNAPSHOT_LIMIT),
+                Some(&"stop") => self.stop_recording(),
+                Some(_) => println!("Usage: record [stop]"),
+            },
+            "trace" => self.trace_command(&args[1..]),
+            "catch" => match args.get(1) {
+                None => self.set_stop_on_trap(true),
+                Some(&"off") => self.set_stop_on_trap(false),
+                Some(_) => println!("Usage: catch [off]"),
+            },
+            "run" => match args.get(1).map(|arg| arg.parse::<u64>()) {
+                Some(Ok(rounds)) => self.run(Some(rounds), None),
+                _ => println!("Usage: run <n>"),
+            },
+            "until" => match args.get(1).and_then(|arg| self.parse_address(arg)) {
+                Some(addr) => self.run(None, Some(addr)),
+                None => println!("Usage: until <address|symbol>"),
+            },
+            "echo" => println!("{}", line.trim_start()[args[0].len()..].trim_start()),
+            "expect" => {
+                if !self.expect_command(&args[1..]) {
+                    self.failed_expectations += 1;
+                }
+            },
             "b" | "break" => {
-                if args.len() > 1 {
-                    if let Ok(addr) = u64::from_str_radix(args[1].trim_start_matches("0x"), 16) {
-                        self.breakpoints.insert(addr);
-                        println!("Breakpoint set at {:#x}", addr);
+                let (args, condition) = match split_condition(&args[1..]) {
+                    Ok(split) => split,
+                    Err(e) => return println!("{}", e),
+                };
+
+                match args.first().and_then(|arg| self.parse_address(arg)) {
+                    Some(addr) => {
+                        let id = self.breakpoints.insert(Location::Pc(addr), condition);
+                        println!("Breakpoint {} at {}", id, self.format_address(addr));
+                    }
+                    None => println!("Usage: break <address|symbol> [if <condition>]"),
+                }
+            },
+            "watch" | "rwatch" | "awatch" => {
+                let kind = match args[0] {
+                    "rwatch" => WatchKind::Read,
+                    "awatch" => WatchKind::Access,
+                    _ => WatchKind::Write,
+                };
+
+                self.watch_command(kind, &args[1..]);
+            },
+            "condition" => {
+                let id = args.get(1).and_then(|arg| arg.parse::<usize>().ok());
+
+                let condition = match args.len() {
+                    0..=2 => Ok(None),
+                    _ => args[2..].join(" ").parse::<Condition>().map(Some),
+                };
+
+                match (id, condition) {
+                    (Some(id), Ok(condition)) if self.set_breakpoint_condition(id, condition) => {}
+                    (Some(id), Ok(_)) => println!("No breakpoint number {}", id),
+                    (_, Err(e)) => println!("{}", e),
+                    (None, _) => println!("Usage: condition <n> [<register|hits> <op> <value>]"),
+                }
+            },
+            "delete" | "disable" | "enable" => {
+                let ids: Vec<usize> = match args.len() {
+                    1 => self.breakpoints.iter().map(|breakpoint| breakpoint.id).collect(),
+                    _ => match args[1..].iter().map(|arg| arg.parse::<usize>()).collect() {
+                        Ok(ids) => ids,
+                        Err(_) => return println!("Usage: {} [n...]", args[0]),
+                    },
+                };
+
+                for id in ids {
+                    let found = match args[0] {
+                        "delete" => self.delete_breakpoint(id),
+                        command => self.set_breakpoint_enabled(id, command == "enable"),
+                    };
+
+                    if !found {
+                        println!("No breakpoint number {}", id);
                     }
                 }
             },
+            "i" | "info" if args.get(1).is_some_and(|arg| "breakpoints".starts_with(arg) || *arg == "watchpoints") => {
+                self.print_breakpoints();
+            },
+            "i" | "info" if args.get(1) == Some(&"record") => match self.reversible_steps() {
+                Some(steps) => println!("Recording, the machine can go back {} rounds", steps),
+                None => println!("Not recording"),
+            },
             "p" | "print" => {
-                self.harts.first().unwrap().print_state();
+                let hart = &self.harts[self.selected_hart];
+
+                match args.get(1) {
+                    None => {
+                        hart.print_state();
+
+                        match self.disassemble(self.selected_hart, hart.cpu_context.pc, 1).first() {
+                            Some(line) => println!("at {}", line),
+                            None => println!("at {}", self.format_address(hart.cpu_context.pc)),
+                        }
+                    }
+                    Some(&"f") => hart.print_float_state(),
+                    Some(name) => match Register::from_name(name).map(|register| hart.cpu_context.read_debug_register(register)) {
+                        Some(Ok(value)) => println!("{} = {:#x} ({})", name, value, value as i64),
+                        Some(Err(e)) => println!("Cannot read {}: {:?}", name, e),
+                        None => println!("Unknown register '{}'", name),
+                    },
+                }
+            },
+            command if command == "x" || command.starts_with("x/") => {
+                let spec = command.strip_prefix("x/").unwrap_or("");
+                let physical = args.get(1) == Some(&"-p");
+                let addr = args.get(if physical { 2 } else { 1 }).and_then(|arg| self.parse_address(arg));
+
+                match addr.map(|addr| self.examine(self.selected_hart, spec, addr, physical)) {
+                    Some(Ok(lines)) => lines.iter().for_each(|line| println!("{}", line)),
+                    Some(Err(e)) => println!("{}", e),
+                    None => println!("Usage: x/<count><format><size> [-p] <address|symbol>"),
+                }
+            },
+            "set" => self.set_command(&args[1..]),
+            "csr" => {
+                let cpu = &self.harts[self.selected_hart].cpu_context;
+
+                let addresses: Vec<u16> = match args.get(1) {
+                    None => CSRAddress::ALL.iter().map(|csr| *csr as u16).collect(),
+                    Some(arg) => match CSRAddress::from_name(arg).map(|csr| csr as u16).or_else(|| u16::from_str_radix(arg.trim_start_matches("0x"), 16).ok()) {
+                        Some(address) => vec![address],
+                        None => return println!("Unknown CSR '{}'", arg),
+                    },
+                };
+
+                for address in addresses {
+                    match cpu.csrs.read_csr(address, true) {
+                        Ok(value) => {
+                            println!("{} ({:#05x}) = {:#018x}", disasm::csr(address as u32), address, value);
+
+                            let fields = CSRAddress::ALL.iter().find(|csr| **csr as u16 == address).and_then(|csr| csr.describe_fields(value));
+                            if let Some(fields) = fields {
+                                println!("  {}", fields);
+                            }
+                        }
+                        Err(e) => println!("{} ({:#05x}) cannot be read: {:?}", disasm::csr(address as u32), address, e),
+                    }
+                }
+            },
+            "disas" => {
+                let addr = match args.get(1) {
+                    Some(arg) => self.parse_address(arg),
+                    None => Some(self.harts[self.selected_hart].cpu_context.pc),
+                };
+                let count = match args.get(2) {
+                    Some(arg) => arg.parse::<usize>().ok(),
+                    None => Some(10),
+                };
+
+                match (addr, count) {
+                    (Some(addr), Some(count)) => {
+                        let lines = self.disassemble(self.selected_hart, addr, count);
+
+                        if lines.is_empty() {
+                            println!("Cannot access memory at {:#x}", addr);
+                        }
+
+                        for line in lines {
+                            println!("{}", line);
+                        }
+                    }
+                    _ => println!("Usage: disas [address|symbol] [count]"),
+                }
+            },
+            "tlb" => {
+                self.harts[self.selected_hart].print_tlb();
+            },
+            "hart" => {
+                match args.get(1).map(|arg| arg.parse::<usize>()) {
+                    Some(Ok(hart_id)) if hart_id < self.harts.len() => self.selected_hart = hart_id,
+                    Some(_) => println!("No such hart, the machine has {}", self.harts.len()),
+                    None => {
+                        for (hart_id, hart) in self.harts.iter().enumerate() {
+                            let marker = if hart_id == self.selected_hart { "*" } else { " " };
+                            let state = if !self.is_running(hart_id) { "stopped" } else if hart.is_idle() { "wfi" } else { "running" };
+
+                            println!("{} hart {} {} at {}", marker, hart_id, state, self.format_address(hart.cpu_context.pc));
+                        }
+                    }
+                }
             },
             "h" | "help" => {
                 println!("Command list:");
-                println!("s | step => Advance program counter by one instruction");
-                println!("c | continue => Advance program counter until breakpoint or exit");
-                println!("b | break <x> => Set breakpoint at x");
-                println!("p | print => Print register state");
+                println!("s | step => Execute one instruction on every running hart");
+                println!("c | continue => Run until any hart hits a breakpoint or watchpoint, or raises an exception with catch on");
+                println!("run <n> => Run n steps unless something stops the machine earlier");
+                println!("until <x> => Run until the selected hart reaches address or symbol x");
+                println!("catch [off] => Also stop continue, run and until when a hart raises an exception, the guest's trap handler is entered first");
+                println!("echo <text> => Print text");
+                println!("expect <r> <value> => Check a register of the selected hart, failures make --script exit with an error");
+                println!("b | break <x> [if <cond>] => Set breakpoint at address or symbol x");
+                println!("watch | rwatch | awatch [-p] <x> [len] [if <cond>] => Stop on writes, reads or any access to len bytes (default 4) at virtual or, with -p, physical address x");
+                println!("watch $<r> [if <cond>] => Stop when register r of the selected hart changes");
+                println!("condition <n> [cond] => Set or clear the condition of breakpoint n, cond is '<register|hits> <op> <value>'");
+                println!("i | info breakpoints => List breakpoints and watchpoints");
+                println!("record [stop] => Start or stop recording execution history for reverse execution");
+                println!("i | info record => Show how many rounds the machine can go back");
+                println!("rs | reverse-step [n] => Go back n rounds (default 1)");
+                println!("trace <file> [pc <start>:<end>] [cycles <start>:<end>] | trace stop => Write executed instructions to file like Spike's -l --log-commits, optionally only in a pc or per hart instruction number range");
+                println!("rc | reverse-continue => Go back to the last breakpoint, exception with catch on or watched access or register change");
+                println!("delete | disable | enable [n...] => Delete, disable or enable breakpoints, all of them without n");
+                println!("p | print [f|r] => Print integer registers, floating point registers or register r of the selected hart");
+                println!("x/<n><fmt><size> [-p] <x> => Examine n units of memory at virtual or physical address x, fmt is x, d, u, s or i, size is b, h, w or g");
+                println!("set reg <r> <value> => Write an integer, floating point or control and status register");
+                println!("set mem[/<size>] [-p] <x> <value> => Write a value of size b, h, w (default) or g to memory");
+                println!("csr [name|address] => Show one or all CSRs, with the fields of mstatus, sstatus, mip, sip, mie, sie and satp decoded");
+                println!("disas [x] [n] => Disassemble n instructions (default 10) at address or symbol x, default pc");
+                println!("tlb => Print cached address translations of the selected hart");
+                println!("hart [n] => List harts or select hart n for print and tlb");
             }
             _ => println!("Unknown command. Type 'help' for commands."),
         }
     }
 }
 
+fn parse_examine_size(size: &str) -> Option<usize> {
+    match size {
+        "b" => Some(1),
+        "h" => Some(2),
+        "w" => Some(4),
+        "g" => Some(8),
+        _ => None,
+    }
+}
+
+// <count><format><size> with every part optional, defaults to one hex word
+fn parse_examine_spec(spec: &str) -> Option<(usize, char, usize)> {
+    let digits = spec.find(|c: char| !c.is_ascii_digit()).unwrap_or(spec.len());
+    let count = match digits {
+        0 => 1,
+        _ => spec[..digits].parse().ok()?,
+    };
+
+    let mut format = 'x';
+    let mut size = 4;
+
+    for c in spec[digits..].chars() {
+        match c {
+            'x' | 'd' | 'u' | 's' | 'i' => format = c,
+            _ => size = parse_examine_size(&c.to_string())?,
+        }
+    }
+
+    Some((count, format, size))
+}
+
+// Splits debugger arguments at "if" into the arguments before it and the parsed condition after it
+fn split_condition<'a>(args: &[&'a str]) -> Result<(Vec<&'a str>, Option<Condition>), String> {
+    match args.iter().position(|arg| *arg == "if") {
+        Some(index) => Ok((args[..index].to_vec(), Some(args[index + 1..].join(" ").parse::<Condition>()?))),
+        None => Ok((args.to_vec(), None)),
+    }
+}
+
+fn describe_watchpoint(watchpoint: &Watchpoint, vaddr: Option<u64>) -> String {
+    match vaddr {
+        Some(vaddr) => format!("{:#x} len {} (pa {:#x})", vaddr, watchpoint.len, watchpoint.addr),
+        None => format!("pa {:#x} len {}", watchpoint.addr, watchpoint.len),
+    }
+}
+
+// reg property for a region with two address and two size cells
+fn reg_cells(base: u64, size: u64) -> [u32; 4] {
+    [(base >> 32) as u32, base as u32, (size >> 32) as u32, size as u32]
+}
+
+#[derive(Clone)]
 pub(crate) struct Interpreter {
     cpu_context: RV64CPUContext,
     cycles: usize,
@@ -113,149 +1578,147 @@ impl Interpreter {
         Interpreter { cpu_context: RV64CPUContext::new(entrypoint, memory_management_unit), cycles: 0 }
     }
 
-    fn handle_exception_machine(&mut self, exception: Exception) {
-        let mstatus = self.cpu_context.csrs.read_csr(CSRAddress::MStatus as u16, true).unwrap();
-
-        if(MStatusFlags::from_bits_retain(mstatus).contains(MStatusFlags::MIE)) {
-            let mtvec = self.cpu_context.csrs.read_csr(CSRAddress::MTVec as u16, true).unwrap();
-            self.cpu_context.csrs.write_csr(CSRAddress::MCause as u16, exception as u64, true).unwrap();
-            self.cpu_context.csrs.write_csr(CSRAddress::MEPC as u16, self.cpu_context.pc, true).unwrap();
-            self.cpu_context.csrs.write_csr(CSRAddress::MStatus as u16, mstatus & !MStatusFlags::MIE.bits(), true).unwrap();
+    // Enters the trap handler in machine or, if delegated, supervisor mode
+    fn take_trap(&mut self, cause: u64, interrupt: bool, tval: u64) {
+        let epc = self.cpu_context.pc;
 
-            let mode = (mtvec >> 2) & 0b11;
-
-            if mode == 0 {
-                self.cpu_context.pc = mtvec & !0b11;
-            } else if mode == 1 {
-                self.cpu_context.pc = (mtvec & !0b11) + 4 * exception as u64;
-            } else {
-                panic!("Unsupported mtvec mode");
-            }
-        } else {
-            panic!("Machine mode exception with MIE disabled");
-        }
+        self.cpu_context.pc = self.cpu_context.csrs.enter_trap(cause, interrupt, epc, tval);
+        self.cpu_context.waiting_for_interrupt = false;
     }
 
     fn handle_exception(&mut self, exception: Exception) {
-        match self.cpu_context.csrs.get_current_privilege() {
-            PrivilegeMode::Machine => {
-                //Machine mode exceptions are passed directly to the handler
-                self.handle_exception_machine(exception);
-            }
-            PrivilegeMode::Supervisor => {
-                //Supervisor mode exceptions are passed directly to the handler due supervisor not being able to cause traps
-                self.handle_exception_machine(exception);
-            }
-            PrivilegeMode::User => {
-                //User mode exceptions are passed to the supervisor if not delegated.
-                let medeleg = self.cpu_context.csrs.read_csr(CSRAddress::MEDeleg as u16, true).unwrap();
-
-                if (medeleg << exception as u64 & 1 == 0) {
-                    self.cpu_context.csrs.change_privilege(PrivilegeMode::Machine);
-
-                    self.handle_exception_machine(exception);
-                } else {
-                    let stvec = self.cpu_context.csrs.read_csr(CSRAddress::STVec as u16, true).unwrap();
-                    self.cpu_context.csrs.write_csr(CSRAddress::SCause as u16, exception as u64, true).unwrap();
-                    self.cpu_context.csrs.write_csr(CSRAddress::SEPC as u16, self.cpu_context.pc, true).unwrap();
-                    self.cpu_context.csrs.write_csr(CSRAddress::SStatus as u16, self.cpu_context.csrs.read_csr(CSRAddress::SStatus as u16, true).unwrap() & !MStatusFlags::SIE.bits(), true).unwrap();
-
-                    let mode = (stvec >> 2) & 0b11;
-
-                    self.cpu_context.csrs.change_privilege(PrivilegeMode::Supervisor);
-
-                    if mode == 0 {
-                        self.cpu_context.pc = stvec & !0b11;
-                    } else if mode == 1 {
-                        self.cpu_context.pc = (stvec & !0b11) + 4 * exception as u64;
-                    } else {
-                        panic!("Unsupported stvec mode");
-                    }
-                }
-            }
-        }
+        //pc still points at the faulting instruction, trap_value was filled in by whoever raised it
+        let tval = self.cpu_context.trap_value;
+
+        self.take_trap(exception as u64, false, tval);
     }
 
     fn handle_interrupt(&mut self, interrupt_no: u64) {
-        match interrupt_no {
-            _ => {
-                panic!("Interrupts?!");
-            }
+        if self.cpu_context.commit.is_enabled() {
+            self.cpu_context.commit.interrupt = Some(interrupt_no);
         }
+
+        //Interrupts resume at the instruction that was about to execute
+        self.take_trap(interrupt_no, true, 0);
     }
 
     fn print_state(&self) {
-        println!("CPU STATE AT {:x}", self.cpu_context.pc);
-        for i in 0..31 {
-            println!("x{}: {:x}", i, self.cpu_context.x[i]);
+        println!("HART {} STATE AT {:x}", self.cpu_context.hart_id, self.cpu_context.pc);
+        for (i, value) in self.cpu_context.x.iter().enumerate() {
+            println!("x{:<2} {:<4} {:#018x}", i, X_ABI_NAMES[i], value);
         }
 
         println!("pc: {:x}", self.cpu_context.pc);
     }
 
-    fn check_for_interrupt(&mut self) -> Result<(), Exception> {
-        let mstatus = self.cpu_context.csrs.read_csr(CSRAddress::MStatus as u16, true)?;
+    // NaN-boxed registers are shown as single precision values, everything else as double precision
+    fn print_float_state(&self) {
+        println!("HART {} FLOATING POINT STATE", self.cpu_context.hart_id);
+        for (i, &bits) in self.cpu_context.f.iter().enumerate() {
+            let value = match bits & NAN_BOX_MASK == NAN_BOX_MASK {
+                true => format!("{} (single)", f32::from_bits(bits as u32)),
+                false => format!("{}", f64::from_bits(bits)),
+            };
 
-        match self.cpu_context.csrs.get_current_privilege() {
-            PrivilegeMode::Machine => {
-                if (MStatusFlags::MIE.contains(MStatusFlags::from_bits_retain(mstatus))) {
-                    let mie = self.cpu_context.csrs.read_csr(CSRAddress::MIE as u16, true)?;
-                    //Check for pending interrupts in MIP
-                    let mip = self.cpu_context.csrs.read_csr(CSRAddress::MIP as u16, true)?;
+            println!("f{:<2} {:<4} {:#018x}  {}", i, F_ABI_NAMES[i], bits, value);
+        }
 
-                    for i in 0..64 {
-                        let shift = 1 << i;
+        let fcsr = self.cpu_context.csrs.read_csr(CSRAddress::FCSR as u16, true).unwrap_or(0);
+        let flags: Vec<&str> = FFlags::from_bits_truncate(fcsr).iter_names().map(|(name, _)| name).collect();
 
-                        if (mip & shift != 0) {
-                            if (mie & shift != 0) {
-                                //Handle interrupt
-                                self.handle_interrupt(i);
-                            }
-                        }
-                    }
-                }
+        println!("fcsr: {:#x} frm {} fflags {}", fcsr, (fcsr >> 5) & 0x7, flags.join(" "));
+    }
+
+    fn print_tlb(&self) {
+        for (name, tlb) in [("I-TLB", &self.cpu_context.itlb), ("D-TLB", &self.cpu_context.dtlb)] {
+            println!("{}:", name);
+
+            for entry in tlb.entries() {
+                println!("  va {:#018x} -> pa {:#018x} asid {:#06x} size {:#x} flags {:?}",
+                         entry.vpn << PAGE_SHIFT, entry.ppn << PAGE_SHIFT, entry.asid, entry.page_size(), entry.flags);
             }
-            _ => {
-                panic!("Unsupported privilege mode");
+        }
+    }
+
+    // Takes the highest priority pending interrupt, returns whether one was taken
+    fn check_for_interrupt(&mut self) -> bool {
+        match self.cpu_context.csrs.get_pending_interrupt() {
+            Some(interrupt_no) => {
+                self.handle_interrupt(interrupt_no);
+                true
             }
+            None => false,
         }
+    }
 
-        Ok(())
+    // Hart is stalled in WFI with nothing to wake it up
+    pub(crate) fn is_idle(&self) -> bool {
+        self.cpu_context.waiting_for_interrupt && !self.cpu_context.csrs.has_pending_interrupt()
     }
 
     pub fn step(&mut self) -> Result<(), Exception> {
+        if self.cpu_context.commit.is_enabled() {
+            self.cpu_context.commit.begin(self.cpu_context.pc, self.cycles, &self.cpu_context.csrs);
+        }
+
+        if self.is_idle() {
+            return Ok(());
+        }
+
+        self.cpu_context.waiting_for_interrupt = false;
+
+        //Pending interrupts are taken before the next instruction executes
+        if self.check_for_interrupt() {
+            return Ok(());
+        }
+
         let old_pc = self.cpu_context.pc;
-        
+
         self.cycles += 1;
 
-        let current_pc = self.cpu_context.pc;
-        let instr = self.cpu_context.memory.read().unwrap().read_word(current_pc as usize);
+        self.cpu_context.trap_value = 0;
+
+        let mut instr = self.cpu_context.fetch_instruction()?;
+        let mut length = 4;
+
+        if self.cpu_context.commit.is_enabled() {
+            self.cpu_context.commit.instruction = Some(match is_compressed(instr) {
+                true => (instr & 0xFFFF, 2),
+                false => (instr, 4),
+            });
+        }
+
+        //16-bit parcels are expanded into their 32-bit equivalent before decoding
+        if is_compressed(instr) {
+            if !self.cpu_context.csrs.is_compressed_enabled() {
+                return Err(Exception::IllegalInstruction);
+            }
+
+            instr = expand(instr as u16).ok_or(Exception::IllegalInstruction)?;
+            length = 2;
+        }
+
+        self.cpu_context.instruction_length = length;
+        self.cpu_context.branch_taken = false;
 
         let instr_fn = RV64InstructionParser::parse(instr);
 
         let execution_result = instr_fn(&mut self.cpu_context, instr);
 
-        if self.cpu_context.pc == old_pc {
-            self.cpu_context.pc += 4;
-        }
-        
-        //Check for instruction exception
+        //Check for instruction exception, the trap has to point at the faulting instruction
         if let Err(e) = execution_result {
+            self.cpu_context.pc = old_pc;
             return Err(e);
         }
 
-        self.check_for_interrupt()?;
-
-        Ok(())
-    }
-
-    pub fn main_loop(&mut self, cycle_callback: fn(cycle: usize)) {
-        loop {
-            if let Err(e) = self.step() {
-                self.handle_exception(e);
-            }
+        if !self.cpu_context.branch_taken {
+            self.cpu_context.pc = old_pc.wrapping_add(length);
+        }
 
-            cycle_callback(self.cycles);
+        if self.cpu_context.commit.is_enabled() {
+            self.cpu_context.commit.retire(&self.cpu_context.csrs);
         }
+
+        Ok(())
     }
 }
\ No newline at end of file
@@ -2,4 +2,13 @@ pub mod state;
 pub mod interpreter;
 pub mod instructions;
 pub mod constants;
-pub mod devices;
\ No newline at end of file
+pub mod devices;
+pub mod softfloat;
+pub mod elf;
+pub mod dtb;
+pub mod boot;
+pub mod sbi;
+pub mod gdb;
+pub mod breakpoints;
+pub mod history;
+pub mod trace;
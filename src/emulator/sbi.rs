@@ -0,0 +1,328 @@
+use crate::emulator::constants::PAGE_SIZE;
+use crate::emulator::devices::clint::ClintState;
+use crate::emulator::devices::uart::UartState;
+use crate::emulator::state::rv64_cpu_context::{CSRAddress, MIPFlags, MStatusFlags, PrivilegeMode, RV64CPUContext};
+
+// Extension ids, most of them are the extension name in ASCII
+pub const SBI_EXT_BASE: u64 = 0x10;
+pub const SBI_EXT_TIME: u64 = 0x5449_4D45;
+pub const SBI_EXT_IPI: u64 = 0x73_5049;
+pub const SBI_EXT_RFENCE: u64 = 0x5246_4E43;
+pub const SBI_EXT_HSM: u64 = 0x48_534D;
+pub const SBI_EXT_SRST: u64 = 0x5352_5354;
+pub const SBI_EXT_DBCN: u64 = 0x4442_434E;
+
+const SUPPORTED_EXTENSIONS: [u64; 7] = [SBI_EXT_BASE, SBI_EXT_TIME, SBI_EXT_IPI, SBI_EXT_RFENCE, SBI_EXT_HSM, SBI_EXT_SRST, SBI_EXT_DBCN];
+
+// Version 2.0 of the SBI specification
+const SBI_SPEC_VERSION: u64 = 2 << 24;
+
+// Not a registered implementation id, picked so it doesn't collide with any of the known firmwares
+const SBI_IMPL_ID: u64 = 0x5256;
+const SBI_IMPL_VERSION: u64 = 1;
+
+pub const SBI_SUCCESS: i64 = 0;
+pub const SBI_ERR_FAILED: i64 = -1;
+pub const SBI_ERR_NOT_SUPPORTED: i64 = -2;
+pub const SBI_ERR_INVALID_PARAM: i64 = -3;
+pub const SBI_ERR_INVALID_ADDRESS: i64 = -5;
+pub const SBI_ERR_ALREADY_STARTED: i64 = -7;
+pub const SBI_ERR_ALREADY_STOPPED: i64 = -8;
+
+// hart_mask_base value selecting every hart
+const HART_MASK_ALL: u64 = u64::MAX;
+
+const SUSPEND_RETENTIVE: u64 = 0;
+
+// Traps the kernel handles itself, everything but environment calls from S-mode which come to us
+const MEDELEG_SUPERVISOR: u64 = 0xB1FF;
+const MIDELEG_SUPERVISOR: u64 = MIPFlags::SSIP.bits() | MIPFlags::STIP.bits() | MIPFlags::SEIP.bits();
+
+// cycle, time and instret are readable from S-mode
+const MCOUNTEREN_ALL: u64 = 0b111;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HartState {
+    Started = 0,
+    Stopped = 1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SystemReset {
+    pub reset_type: u32,
+    pub reason: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SbiReturn {
+    pub error: i64,
+    pub value: u64,
+}
+
+impl SbiReturn {
+    fn success(value: u64) -> SbiReturn {
+        Self { error: SBI_SUCCESS, value }
+    }
+
+    fn error(error: i64) -> SbiReturn {
+        Self { error, value: 0 }
+    }
+}
+
+// Supervisor binary interface served by the emulator itself when no M-mode firmware is loaded
+#[derive(Clone)]
+pub struct Sbi {
+    hart_states: Vec<HartState>,
+    reset: Option<SystemReset>,
+}
+
+impl Sbi {
+    // Hart 0 boots, the others wait for HSM hart_start
+    pub fn new(harts: usize) -> Sbi {
+        Self {
+            hart_states: (0..harts).map(|hart_id| if hart_id == 0 { HartState::Started } else { HartState::Stopped }).collect(),
+            reset: None,
+        }
+    }
+
+    // Sets up a hart the way OpenSBI leaves it before jumping to the kernel in S-mode. A hart started again
+    // after hart_stop must not keep the paging and interrupt enable it had, the spec wants satp and SIE cleared
+    pub fn prepare_hart(cpu_context: &mut RV64CPUContext) {
+        cpu_context.csrs.write_csr(CSRAddress::MEDeleg as u16, MEDELEG_SUPERVISOR, true).unwrap();
+        cpu_context.csrs.write_csr(CSRAddress::MIDeleg as u16, MIDELEG_SUPERVISOR, true).unwrap();
+        cpu_context.csrs.write_csr(CSRAddress::MCounterEn as u16, MCOUNTEREN_ALL, true).unwrap();
+        cpu_context.csrs.write_csr(CSRAddress::SATP as u16, 0, true).unwrap();
+
+        let mut mstatus = MStatusFlags::from_bits_retain(cpu_context.csrs.read_csr(CSRAddress::MStatus as u16, true).unwrap());
+        mstatus.remove(MStatusFlags::SIE | MStatusFlags::SPP);
+        cpu_context.csrs.write_csr(CSRAddress::MStatus as u16, mstatus.bits(), true).unwrap();
+
+        cpu_context.flush_tlb(None, None);
+        cpu_context.csrs.change_privilege(PrivilegeMode::Supervisor);
+    }
+
+    pub fn get_hart_state(&self, hart_id: usize) -> HartState {
+        self.hart_states[hart_id]
+    }
+
+    pub fn is_running(&self, hart_id: usize) -> bool {
+        self.reset.is_none() && self.hart_states[hart_id] != HartState::Stopped
+    }
+
+    // Set once the guest asked for a shutdown or reboot
+    pub fn get_reset(&self) -> Option<SystemReset> {
+        self.reset
+    }
+
+    // Services the ECALL in a7/a6 of the calling hart and writes the result to a0/a1
+    pub fn handle_ecall(&mut self, hart_id: usize, harts: &mut [&mut RV64CPUContext], clint: &ClintState, uart: Option<&UartState>) {
+        let args: [u64; 6] = std::array::from_fn(|i| harts[hart_id].x[10 + i]);
+        let extension = harts[hart_id].x[17];
+        let function = harts[hart_id].x[16];
+
+        let result = match extension {
+            SBI_EXT_BASE => self.base(function, args[0], harts[hart_id]),
+            SBI_EXT_TIME if function == 0 => {
+                clint.write_mtimecmp(hart_id, args[0]);
+                SbiReturn::success(0)
+            }
+            SBI_EXT_IPI if function == 0 => self.send_ipi(args[0], args[1], harts),
+            SBI_EXT_RFENCE => self.remote_fence(function, &args, harts),
+            SBI_EXT_HSM => self.hsm(hart_id, function, &args, harts),
+            SBI_EXT_SRST if function == 0 => self.system_reset(args[0], args[1]),
+            SBI_EXT_DBCN => self.debug_console(function, &args, harts[hart_id], uart),
+            _ => SbiReturn::error(SBI_ERR_NOT_SUPPORTED),
+        };
+
+        let caller = &mut harts[hart_id];
+
+        // A stopped hart's registers belong to whoever starts it next
+        if self.hart_states[hart_id] != HartState::Stopped {
+            caller.set_register(10, result.error as u64);
+            caller.set_register(11, result.value);
+        }
+    }
+
+    fn base(&self, function: u64, extension: u64, cpu_context: &RV64CPUContext) -> SbiReturn {
+        let read_csr = |csr: CSRAddress| cpu_context.csrs.read_csr(csr as u16, true).unwrap();
+
+        match function {
+            0 => SbiReturn::success(SBI_SPEC_VERSION),
+            1 => SbiReturn::success(SBI_IMPL_ID),
+            2 => SbiReturn::success(SBI_IMPL_VERSION),
+            3 => SbiReturn::success(SUPPORTED_EXTENSIONS.contains(&extension) as u64),
+            4 => SbiReturn::success(read_csr(CSRAddress::MVendorID)),
+            5 => SbiReturn::success(read_csr(CSRAddress::MArchID)),
+            6 => SbiReturn::success(read_csr(CSRAddress::MImpID)),
+            _ => SbiReturn::error(SBI_ERR_NOT_SUPPORTED),
+        }
+    }
+
+    // Resolves hart_mask/hart_mask_base into hart ids, None if the mask names a hart that doesn't exist
+    fn select_harts(hart_mask: u64, hart_mask_base: u64, harts: usize) -> Option<Vec<usize>> {
+        if hart_mask_base == HART_MASK_ALL {
+            return Some((0..harts).collect());
+        }
+
+        // The base comes from the guest, one that overflows selects no hart at all
+        let selected: Vec<usize> = (0..64)
+            .filter(|bit| (hart_mask >> bit) & 1 != 0)
+            .map(|bit| (hart_mask_base as usize).checked_add(bit))
+            .collect::<Option<_>>()?;
+
+        if selected.iter().any(|&hart_id| hart_id >= harts) {
+            return None;
+        }
+
+        Some(selected)
+    }
+
+    fn send_ipi(&self, hart_mask: u64, hart_mask_base: u64, harts: &mut [&mut RV64CPUContext]) -> SbiReturn {
+        let selected = match Self::select_harts(hart_mask, hart_mask_base, harts.len()) {
+            Some(selected) => selected,
+            None => return SbiReturn::error(SBI_ERR_INVALID_PARAM),
+        };
+
+        // SSIP is set in mip itself so the kernel can acknowledge it by clearing sip
+        for hart_id in selected {
+            harts[hart_id].csrs.raise_interrupt(MIPFlags::SSIP);
+        }
+
+        SbiReturn::success(0)
+    }
+
+    fn remote_fence(&self, function: u64, args: &[u64; 6], harts: &mut [&mut RV64CPUContext]) -> SbiReturn {
+        let selected = match Self::select_harts(args[0], args[1], harts.len()) {
+            Some(selected) => selected,
+            None => return SbiReturn::error(SBI_ERR_INVALID_PARAM),
+        };
+
+        let (start, size) = (args[2], args[3]);
+
+        let asid = match function {
+            0 => return SbiReturn::success(0), //FENCE.I, instruction fetches are never cached
+            1 => None,
+            2 => Some(args[4] & 0xFFFF),
+            _ => return SbiReturn::error(SBI_ERR_NOT_SUPPORTED),
+        };
+
+        for hart_id in selected {
+            // Large ranges are cheaper to flush as a whole than page by page
+            if size == u64::MAX || (start == 0 && size == 0) || size / PAGE_SIZE > 64 {
+                harts[hart_id].flush_tlb(None, asid);
+                continue;
+            }
+
+            for page in (0..size).step_by(PAGE_SIZE as usize) {
+                harts[hart_id].flush_tlb(Some(start.wrapping_add(page)), asid);
+            }
+        }
+
+        SbiReturn::success(0)
+    }
+
+    fn hsm(&mut self, hart_id: usize, function: u64, args: &[u64; 6], harts: &mut [&mut RV64CPUContext]) -> SbiReturn {
+        match function {
+            0 => {
+                let target = args[0] as usize;
+
+                if target >= harts.len() {
+                    return SbiReturn::error(SBI_ERR_INVALID_PARAM);
+                }
+
+                if self.hart_states[target] != HartState::Stopped {
+                    return SbiReturn::error(SBI_ERR_ALREADY_STARTED);
+                }
+
+                if !harts[target].memory.read().unwrap().is_mapped(args[1] as usize, 4) {
+                    return SbiReturn::error(SBI_ERR_INVALID_ADDRESS);
+                }
+
+                let cpu_context = &mut harts[target];
+                Self::prepare_hart(cpu_context);
+                cpu_context.pc = args[1];
+                cpu_context.set_register(10, target as u64);
+                cpu_context.set_register(11, args[2]);
+                cpu_context.waiting_for_interrupt = false;
+
+                self.hart_states[target] = HartState::Started;
+
+                SbiReturn::success(0)
+            }
+            1 => {
+                if self.hart_states[hart_id] == HartState::Stopped {
+                    return SbiReturn::error(SBI_ERR_ALREADY_STOPPED);
+                }
+
+                self.hart_states[hart_id] = HartState::Stopped;
+
+                SbiReturn::success(0)
+            }
+            2 => match self.hart_states.get(args[0] as usize) {
+                Some(state) => SbiReturn::success(*state as u64),
+                None => SbiReturn::error(SBI_ERR_INVALID_PARAM),
+            },
+            3 => {
+                // Only retentive suspend, it behaves like WFI and returns once an interrupt is pending
+                if args[0] != SUSPEND_RETENTIVE {
+                    return SbiReturn::error(SBI_ERR_NOT_SUPPORTED);
+                }
+
+                harts[hart_id].waiting_for_interrupt = true;
+
+                SbiReturn::success(0)
+            }
+            _ => SbiReturn::error(SBI_ERR_NOT_SUPPORTED),
+        }
+    }
+
+    fn system_reset(&mut self, reset_type: u64, reason: u64) -> SbiReturn {
+        // Shutdown, cold reboot and warm reboot
+        if reset_type > 2 || reason > 1 {
+            return SbiReturn::error(SBI_ERR_INVALID_PARAM);
+        }
+
+        self.reset = Some(SystemReset { reset_type: reset_type as u32, reason: reason as u32 });
+
+        SbiReturn::success(0)
+    }
+
+    fn debug_console(&self, function: u64, args: &[u64; 6], cpu_context: &RV64CPUContext, uart: Option<&UartState>) -> SbiReturn {
+        let uart = match uart {
+            Some(uart) => uart,
+            None => return SbiReturn::error(SBI_ERR_FAILED),
+        };
+
+        // Buffers are given as a physical address split into a low and a high half, the high half is 0 on RV64
+        let (len, addr) = (args[0] as usize, args[1] as usize);
+
+        match function {
+            0 | 1 => {
+                let mut mmu = cpu_context.memory.write().unwrap();
+
+                // The length comes from the guest, a buffer that wraps around is never mapped
+                if args[2] != 0 || addr.checked_add(len).is_none() || !mmu.is_mapped(addr, len) {
+                    return SbiReturn::error(SBI_ERR_INVALID_PARAM);
+                }
+
+                if function == 0 {
+                    let mut buf = vec![0; len];
+                    mmu.read(addr, len, &mut buf);
+                    uart.transmit(&buf);
+
+                    SbiReturn::success(len as u64)
+                } else {
+                    let input = uart.take_input(len);
+                    mmu.write(addr, input.len(), &input);
+
+                    SbiReturn::success(input.len() as u64)
+                }
+            }
+            2 => {
+                uart.transmit(&[args[0] as u8]);
+                SbiReturn::success(0)
+            }
+            _ => SbiReturn::error(SBI_ERR_NOT_SUPPORTED),
+        }
+    }
+}
@@ -0,0 +1,657 @@
+use crate::emulator::state::rv64_cpu_context::FFlags;
+
+// Software IEEE-754 binary32/binary64 arithmetic operating on raw bit patterns.
+// Results are always rounded exactly once, with tininess detected after rounding as RISC-V requires.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    NearestEven,         // RNE
+    TowardZero,          // RTZ
+    Down,                // RDN, towards -infinity
+    Up,                  // RUP, towards +infinity
+    NearestMaxMagnitude, // RMM, ties away from zero
+}
+
+impl RoundingMode {
+    // Decodes the rm field / frm CSR, 5 to 7 are not rounding modes
+    pub fn from_bits(rm: u8) -> Option<Self> {
+        match rm {
+            0 => Some(RoundingMode::NearestEven),
+            1 => Some(RoundingMode::TowardZero),
+            2 => Some(RoundingMode::Down),
+            3 => Some(RoundingMode::Up),
+            4 => Some(RoundingMode::NearestMaxMagnitude),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FloatFormat {
+    pub exponent_bits: u32,
+    pub fraction_bits: u32,
+}
+
+pub const SINGLE: FloatFormat = FloatFormat { exponent_bits: 8, fraction_bits: 23 };
+pub const DOUBLE: FloatFormat = FloatFormat { exponent_bits: 11, fraction_bits: 52 };
+
+// Extra low bits kept while aligning addends, far more than the two needed for rounding
+const ADD_GUARD_BITS: i32 = 64;
+
+impl FloatFormat {
+    fn bias(&self) -> i32 {
+        (1 << (self.exponent_bits - 1)) - 1
+    }
+
+    fn max_exponent_field(&self) -> u64 {
+        (1 << self.exponent_bits) - 1
+    }
+
+    // Unbiased exponent of the smallest normal number
+    fn min_exponent(&self) -> i32 {
+        1 - self.bias()
+    }
+
+    fn fraction_mask(&self) -> u64 {
+        (1 << self.fraction_bits) - 1
+    }
+
+    fn exponent_field(&self, bits: u64) -> u64 {
+        (bits >> self.fraction_bits) & self.max_exponent_field()
+    }
+
+    pub fn sign_mask(&self) -> u64 {
+        1 << (self.exponent_bits + self.fraction_bits)
+    }
+
+    pub fn canonical_nan(&self) -> u64 {
+        (self.max_exponent_field() << self.fraction_bits) | (1 << (self.fraction_bits - 1))
+    }
+
+    fn sign_bit(&self, sign: bool) -> u64 {
+        if sign { self.sign_mask() } else { 0 }
+    }
+
+    fn zero(&self, sign: bool) -> u64 {
+        self.sign_bit(sign)
+    }
+
+    fn infinity(&self, sign: bool) -> u64 {
+        self.sign_bit(sign) | (self.max_exponent_field() << self.fraction_bits)
+    }
+
+    fn max_finite(&self, sign: bool) -> u64 {
+        self.sign_bit(sign) | ((self.max_exponent_field() - 1) << self.fraction_bits) | self.fraction_mask()
+    }
+
+    pub fn is_sign_negative(&self, bits: u64) -> bool {
+        bits & self.sign_mask() != 0
+    }
+
+    pub fn is_nan(&self, bits: u64) -> bool {
+        self.exponent_field(bits) == self.max_exponent_field() && bits & self.fraction_mask() != 0
+    }
+
+    pub fn is_signaling_nan(&self, bits: u64) -> bool {
+        self.is_nan(bits) && bits & (1 << (self.fraction_bits - 1)) == 0
+    }
+
+    pub fn is_infinite(&self, bits: u64) -> bool {
+        self.exponent_field(bits) == self.max_exponent_field() && bits & self.fraction_mask() == 0
+    }
+
+    pub fn is_zero(&self, bits: u64) -> bool {
+        bits & !self.sign_mask() == 0
+    }
+
+    // Splits a finite non-zero value into sign, exponent and integer significand, value = sig * 2^exp
+    fn unpack(&self, bits: u64) -> (bool, i32, u128) {
+        let sign = self.is_sign_negative(bits);
+        let field = self.exponent_field(bits);
+        let fraction = (bits & self.fraction_mask()) as u128;
+        let fraction_bits = self.fraction_bits as i32;
+
+        if field == 0 {
+            (sign, self.min_exponent() - fraction_bits, fraction)
+        } else {
+            (sign, field as i32 - self.bias() - fraction_bits, fraction | (1 << fraction_bits))
+        }
+    }
+}
+
+// Shifts right, ORing every bit shifted out into the lowest bit so inexactness is not lost
+fn shift_right_jam(sig: u128, shift: u32) -> u128 {
+    if shift == 0 {
+        sig
+    } else if shift >= 128 {
+        (sig != 0) as u128
+    } else {
+        (sig >> shift) | ((sig & ((1 << shift) - 1)) != 0) as u128
+    }
+}
+
+// Drops the low `shift` bits of sig and rounds the rest, returns the rounded value and whether it is inexact
+fn shift_right_round(sig: u128, shift: i32, sign: bool, rm: RoundingMode) -> (u128, bool) {
+    if shift <= 0 {
+        return (sig << -shift, false);
+    }
+
+    // Keep a round bit and a sticky bit below the result
+    let extended = if shift == 1 { sig << 1 } else { shift_right_jam(sig, (shift - 2) as u32) };
+    let kept = extended >> 2;
+    let round = extended & 0b10 != 0;
+    let sticky = extended & 0b01 != 0;
+    let inexact = round || sticky;
+
+    let increment = match rm {
+        RoundingMode::NearestEven => round && (sticky || kept & 1 != 0),
+        RoundingMode::TowardZero => false,
+        RoundingMode::Down => inexact && sign,
+        RoundingMode::Up => inexact && !sign,
+        RoundingMode::NearestMaxMagnitude => round,
+    };
+
+    (kept + increment as u128, inexact)
+}
+
+// Rounds sig * 2^exp to the format, handling subnormals, overflow and the UF/OF/NX flags
+fn round_pack(format: FloatFormat, sign: bool, exp: i32, sig: u128, rm: RoundingMode) -> (u64, FFlags) {
+    if sig == 0 {
+        return (format.zero(sign), FFlags::empty());
+    }
+
+    let fraction_bits = format.fraction_bits as i32;
+    let min_exponent = format.min_exponent();
+    let leading_bit = 127 - sig.leading_zeros() as i32;
+    let exponent = exp + leading_bit;
+
+    // A result is tiny if it would still be below the normal range after rounding with an unbounded exponent
+    let tiny = if exponent < min_exponent - 1 {
+        true
+    } else if exponent == min_exponent - 1 {
+        let (kept, _) = shift_right_round(sig, leading_bit - fraction_bits, sign, rm);
+        kept < (1 << (fraction_bits + 1))
+    } else {
+        false
+    };
+
+    // Exponent of the least significant bit of the result
+    let lsb_exponent = exponent.max(min_exponent) - fraction_bits;
+    let (mut kept, inexact) = shift_right_round(sig, lsb_exponent - exp, sign, rm);
+    let mut result_exponent = lsb_exponent + fraction_bits;
+
+    // Rounding carried into a new binade
+    if kept >> (fraction_bits + 1) != 0 {
+        kept >>= 1;
+        result_exponent += 1;
+    }
+
+    let mut flags = FFlags::empty();
+
+    if inexact {
+        flags |= FFlags::NX;
+
+        if tiny {
+            flags |= FFlags::UF;
+        }
+    }
+
+    if kept >> fraction_bits == 0 {
+        // Subnormal, or rounded down to zero
+        return (format.sign_bit(sign) | kept as u64, flags);
+    }
+
+    let field = (result_exponent + format.bias()) as u64;
+
+    if field >= format.max_exponent_field() {
+        let to_infinity = match rm {
+            RoundingMode::NearestEven | RoundingMode::NearestMaxMagnitude => true,
+            RoundingMode::TowardZero => false,
+            RoundingMode::Down => sign,
+            RoundingMode::Up => !sign,
+        };
+
+        let result = if to_infinity { format.infinity(sign) } else { format.max_finite(sign) };
+
+        return (result, flags | FFlags::OF | FFlags::NX);
+    }
+
+    (format.sign_bit(sign) | (field << fraction_bits) | (kept as u64 & format.fraction_mask()), flags)
+}
+
+// Any NaN operand produces the canonical NaN, signaling NaNs additionally raise NV
+fn propagate_nan(format: FloatFormat, operands: &[u64]) -> Option<(u64, FFlags)> {
+    if !operands.iter().any(|&operand| format.is_nan(operand)) {
+        return None;
+    }
+
+    let flags = if operands.iter().any(|&operand| format.is_signaling_nan(operand)) { FFlags::NV } else { FFlags::empty() };
+
+    Some((format.canonical_nan(), flags))
+}
+
+// Adds two normalized significands that share an exponent, signed zero results follow the rounding mode
+fn add_significands(format: FloatFormat, sign_a: bool, sig_a: u128, sign_b: bool, sig_b: u128, exp: i32, rm: RoundingMode) -> (u64, FFlags) {
+    let (sign, sig) = if sign_a == sign_b {
+        (sign_a, sig_a + sig_b)
+    } else if sig_a >= sig_b {
+        (sign_a, sig_a - sig_b)
+    } else {
+        (sign_b, sig_b - sig_a)
+    };
+
+    // Exact cancellation is +0 except when rounding down
+    if sig == 0 {
+        return (format.zero(rm == RoundingMode::Down), FFlags::empty());
+    }
+
+    round_pack(format, sign, exp, sig, rm)
+}
+
+pub fn add(format: FloatFormat, a: u64, b: u64, rm: RoundingMode) -> (u64, FFlags) {
+    if let Some(nan) = propagate_nan(format, &[a, b]) {
+        return nan;
+    }
+
+    let (sign_a, sign_b) = (format.is_sign_negative(a), format.is_sign_negative(b));
+
+    if format.is_infinite(a) || format.is_infinite(b) {
+        if format.is_infinite(a) && format.is_infinite(b) && sign_a != sign_b {
+            return (format.canonical_nan(), FFlags::NV);
+        }
+
+        return (if format.is_infinite(a) { a } else { b }, FFlags::empty());
+    }
+
+    if format.is_zero(a) && format.is_zero(b) {
+        let sign = if sign_a == sign_b { sign_a } else { rm == RoundingMode::Down };
+        return (format.zero(sign), FFlags::empty());
+    }
+
+    if format.is_zero(a) {
+        return (b, FFlags::empty());
+    }
+
+    if format.is_zero(b) {
+        return (a, FFlags::empty());
+    }
+
+    let (_, exp_a, sig_a) = format.unpack(a);
+    let (_, exp_b, sig_b) = format.unpack(b);
+
+    let (exp_a, sig_a) = (exp_a - ADD_GUARD_BITS, sig_a << ADD_GUARD_BITS);
+    let (exp_b, sig_b) = (exp_b - ADD_GUARD_BITS, sig_b << ADD_GUARD_BITS);
+
+    let exp = exp_a.max(exp_b);
+    let sig_a = shift_right_jam(sig_a, (exp - exp_a) as u32);
+    let sig_b = shift_right_jam(sig_b, (exp - exp_b) as u32);
+
+    add_significands(format, sign_a, sig_a, sign_b, sig_b, exp, rm)
+}
+
+pub fn sub(format: FloatFormat, a: u64, b: u64, rm: RoundingMode) -> (u64, FFlags) {
+    add(format, a, b ^ format.sign_mask(), rm)
+}
+
+pub fn mul(format: FloatFormat, a: u64, b: u64, rm: RoundingMode) -> (u64, FFlags) {
+    if let Some(nan) = propagate_nan(format, &[a, b]) {
+        return nan;
+    }
+
+    let sign = format.is_sign_negative(a) != format.is_sign_negative(b);
+
+    if format.is_infinite(a) || format.is_infinite(b) {
+        if format.is_zero(a) || format.is_zero(b) {
+            return (format.canonical_nan(), FFlags::NV);
+        }
+
+        return (format.infinity(sign), FFlags::empty());
+    }
+
+    if format.is_zero(a) || format.is_zero(b) {
+        return (format.zero(sign), FFlags::empty());
+    }
+
+    let (_, exp_a, sig_a) = format.unpack(a);
+    let (_, exp_b, sig_b) = format.unpack(b);
+
+    // The full product fits in 106 bits, so it is exact before rounding
+    round_pack(format, sign, exp_a + exp_b, sig_a * sig_b, rm)
+}
+
+pub fn div(format: FloatFormat, a: u64, b: u64, rm: RoundingMode) -> (u64, FFlags) {
+    if let Some(nan) = propagate_nan(format, &[a, b]) {
+        return nan;
+    }
+
+    let sign = format.is_sign_negative(a) != format.is_sign_negative(b);
+
+    if format.is_infinite(a) {
+        if format.is_infinite(b) {
+            return (format.canonical_nan(), FFlags::NV);
+        }
+
+        return (format.infinity(sign), FFlags::empty());
+    }
+
+    if format.is_infinite(b) {
+        return (format.zero(sign), FFlags::empty());
+    }
+
+    if format.is_zero(b) {
+        if format.is_zero(a) {
+            return (format.canonical_nan(), FFlags::NV);
+        }
+
+        return (format.infinity(sign), FFlags::DZ);
+    }
+
+    if format.is_zero(a) {
+        return (format.zero(sign), FFlags::empty());
+    }
+
+    let (_, exp_a, sig_a) = format.unpack(a);
+    let (_, exp_b, sig_b) = format.unpack(b);
+
+    // Move the dividend to the top so the quotient has at least 70 bits
+    let shift = sig_a.leading_zeros() as i32 - 2;
+    let dividend = sig_a << shift;
+    let quotient = dividend / sig_b;
+    let remainder = dividend % sig_b;
+
+    round_pack(format, sign, exp_a - shift - exp_b, quotient | (remainder != 0) as u128, rm)
+}
+
+// Bitwise integer square root, returns the root and whether it was exact
+fn integer_sqrt(value: u128) -> (u128, bool) {
+    let mut remainder = value;
+    let mut root: u128 = 0;
+    let mut bit: u128 = 1 << 126;
+
+    while bit > value {
+        bit >>= 2;
+    }
+
+    while bit != 0 {
+        if remainder >= root + bit {
+            remainder -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+
+        bit >>= 2;
+    }
+
+    (root, remainder == 0)
+}
+
+pub fn sqrt(format: FloatFormat, a: u64, rm: RoundingMode) -> (u64, FFlags) {
+    if let Some(nan) = propagate_nan(format, &[a]) {
+        return nan;
+    }
+
+    // sqrt(-0) is -0
+    if format.is_zero(a) {
+        return (a, FFlags::empty());
+    }
+
+    if format.is_sign_negative(a) {
+        return (format.canonical_nan(), FFlags::NV);
+    }
+
+    if format.is_infinite(a) {
+        return (a, FFlags::empty());
+    }
+
+    let (_, mut exp, mut sig) = format.unpack(a);
+
+    // Scale up so the root has enough bits to round, the exponent has to stay even to halve it
+    let shift = sig.leading_zeros() as i32 - 2;
+    sig <<= shift;
+    exp -= shift;
+
+    if exp & 1 != 0 {
+        sig <<= 1;
+        exp -= 1;
+    }
+
+    let (root, exact) = integer_sqrt(sig);
+
+    round_pack(format, false, exp / 2, root | (!exact) as u128, rm)
+}
+
+// Computes a * b + c with a single rounding
+pub fn fused_mul_add(format: FloatFormat, a: u64, b: u64, c: u64, rm: RoundingMode) -> (u64, FFlags) {
+    // infinity * 0 is invalid even if the addend is a quiet NaN
+    let product_invalid = (format.is_infinite(a) && format.is_zero(b)) || (format.is_zero(a) && format.is_infinite(b));
+
+    if let Some((nan, flags)) = propagate_nan(format, &[a, b, c]) {
+        return (nan, if product_invalid { flags | FFlags::NV } else { flags });
+    }
+
+    if product_invalid {
+        return (format.canonical_nan(), FFlags::NV);
+    }
+
+    let sign_product = format.is_sign_negative(a) != format.is_sign_negative(b);
+    let sign_c = format.is_sign_negative(c);
+
+    if format.is_infinite(a) || format.is_infinite(b) {
+        if format.is_infinite(c) && sign_c != sign_product {
+            return (format.canonical_nan(), FFlags::NV);
+        }
+
+        return (format.infinity(sign_product), FFlags::empty());
+    }
+
+    if format.is_infinite(c) {
+        return (c, FFlags::empty());
+    }
+
+    if format.is_zero(a) || format.is_zero(b) {
+        if format.is_zero(c) {
+            let sign = if sign_product == sign_c { sign_c } else { rm == RoundingMode::Down };
+            return (format.zero(sign), FFlags::empty());
+        }
+
+        return (c, FFlags::empty());
+    }
+
+    let (_, exp_a, sig_a) = format.unpack(a);
+    let (_, exp_b, sig_b) = format.unpack(b);
+    let (mut exp_product, mut sig_product) = (exp_a + exp_b, sig_a * sig_b);
+
+    if format.is_zero(c) {
+        return round_pack(format, sign_product, exp_product, sig_product, rm);
+    }
+
+    let (_, mut exp_c, mut sig_c) = format.unpack(c);
+
+    // Normalize both terms to the same bit position before aligning them
+    let shift = sig_product.leading_zeros() as i32 - 2;
+    sig_product <<= shift;
+    exp_product -= shift;
+
+    let shift = sig_c.leading_zeros() as i32 - 2;
+    sig_c <<= shift;
+    exp_c -= shift;
+
+    let exp = exp_product.max(exp_c);
+    let sig_product = shift_right_jam(sig_product, (exp - exp_product) as u32);
+    let sig_c = shift_right_jam(sig_c, (exp - exp_c) as u32);
+
+    add_significands(format, sign_product, sig_product, sign_c, sig_c, exp, rm)
+}
+
+// Ordered less-than for operands that are not NaN
+fn less_than(format: FloatFormat, a: u64, b: u64) -> bool {
+    if format.is_zero(a) && format.is_zero(b) {
+        return false;
+    }
+
+    match (format.is_sign_negative(a), format.is_sign_negative(b)) {
+        (false, false) => a < b,
+        (true, true) => a > b,
+        (sign_a, _) => sign_a,
+    }
+}
+
+fn equal(format: FloatFormat, a: u64, b: u64) -> bool {
+    a == b || (format.is_zero(a) && format.is_zero(b))
+}
+
+// Quiet comparison, only signaling NaNs raise NV
+pub fn eq(format: FloatFormat, a: u64, b: u64) -> (bool, FFlags) {
+    if format.is_nan(a) || format.is_nan(b) {
+        let signaling = format.is_signaling_nan(a) || format.is_signaling_nan(b);
+        return (false, if signaling { FFlags::NV } else { FFlags::empty() });
+    }
+
+    (equal(format, a, b), FFlags::empty())
+}
+
+// Signaling comparison, any NaN raises NV
+pub fn lt(format: FloatFormat, a: u64, b: u64) -> (bool, FFlags) {
+    if format.is_nan(a) || format.is_nan(b) {
+        return (false, FFlags::NV);
+    }
+
+    (less_than(format, a, b), FFlags::empty())
+}
+
+// Signaling comparison, any NaN raises NV
+pub fn le(format: FloatFormat, a: u64, b: u64) -> (bool, FFlags) {
+    if format.is_nan(a) || format.is_nan(b) {
+        return (false, FFlags::NV);
+    }
+
+    (less_than(format, a, b) || equal(format, a, b), FFlags::empty())
+}
+
+// IEEE 754-2019 minimumNumber/maximumNumber, -0 orders before +0 and a single NaN is ignored
+fn min_max(format: FloatFormat, a: u64, b: u64, is_max: bool) -> (u64, FFlags) {
+    let flags = if format.is_signaling_nan(a) || format.is_signaling_nan(b) { FFlags::NV } else { FFlags::empty() };
+
+    let result = match (format.is_nan(a), format.is_nan(b)) {
+        (true, true) => format.canonical_nan(),
+        (true, false) => b,
+        (false, true) => a,
+        _ => {
+            let a_less = less_than(format, a, b) || (format.is_zero(a) && format.is_zero(b) && format.is_sign_negative(a));
+            if a_less != is_max { a } else { b }
+        }
+    };
+
+    (result, flags)
+}
+
+pub fn min(format: FloatFormat, a: u64, b: u64) -> (u64, FFlags) {
+    min_max(format, a, b, false)
+}
+
+pub fn max(format: FloatFormat, a: u64, b: u64) -> (u64, FFlags) {
+    min_max(format, a, b, true)
+}
+
+// Returns the one-hot FCLASS mask
+pub fn classify(format: FloatFormat, a: u64) -> u64 {
+    let sign = format.is_sign_negative(a);
+    let subnormal = format.exponent_field(a) == 0 && !format.is_zero(a);
+
+    let bit = if format.is_nan(a) {
+        if format.is_signaling_nan(a) { 8 } else { 9 }
+    } else if format.is_infinite(a) {
+        if sign { 0 } else { 7 }
+    } else if format.is_zero(a) {
+        if sign { 3 } else { 4 }
+    } else if subnormal {
+        if sign { 2 } else { 5 }
+    } else {
+        if sign { 1 } else { 6 }
+    };
+
+    1 << bit
+}
+
+// Converts between formats, widening is always exact
+pub fn convert(from: FloatFormat, to: FloatFormat, a: u64, rm: RoundingMode) -> (u64, FFlags) {
+    if from.is_nan(a) {
+        return (to.canonical_nan(), if from.is_signaling_nan(a) { FFlags::NV } else { FFlags::empty() });
+    }
+
+    let sign = from.is_sign_negative(a);
+
+    if from.is_infinite(a) {
+        return (to.infinity(sign), FFlags::empty());
+    }
+
+    if from.is_zero(a) {
+        return (to.zero(sign), FFlags::empty());
+    }
+
+    let (_, exp, sig) = from.unpack(a);
+
+    round_pack(to, sign, exp, sig, rm)
+}
+
+// Converts to a width bit integer, saturating and raising NV on NaN and out of range values.
+// 32 bit results are returned sign extended, the way FCVT.W and FCVT.WU write them to rd.
+pub fn to_int(format: FloatFormat, a: u64, rm: RoundingMode, signed: bool, width: u32) -> (u64, FFlags) {
+    let (min, max): (i128, i128) = if signed {
+        (-(1 << (width - 1)), (1 << (width - 1)) - 1)
+    } else {
+        (0, (1 << width) - 1)
+    };
+
+    let extend = |value: i128| -> u64 {
+        if width == 32 { value as u32 as i32 as i64 as u64 } else { value as u64 }
+    };
+
+    let sign = format.is_sign_negative(a);
+    let saturated = (extend(if sign { min } else { max }), FFlags::NV);
+
+    if format.is_nan(a) {
+        return (extend(max), FFlags::NV);
+    }
+
+    if format.is_infinite(a) {
+        return saturated;
+    }
+
+    if format.is_zero(a) {
+        return (0, FFlags::empty());
+    }
+
+    let (_, exp, sig) = format.unpack(a);
+
+    let (magnitude, inexact) = if exp >= 0 {
+        if exp > 64 {
+            return saturated;
+        }
+
+        (sig << exp, false)
+    } else {
+        shift_right_round(sig, -exp, sign, rm)
+    };
+
+    let value = if sign { -(magnitude as i128) } else { magnitude as i128 };
+
+    if value < min || value > max {
+        return saturated;
+    }
+
+    (extend(value), if inexact { FFlags::NX } else { FFlags::empty() })
+}
+
+// Converts the low width bits of value, interpreted as signed or unsigned, to the format
+pub fn from_int(format: FloatFormat, value: u64, signed: bool, width: u32, rm: RoundingMode) -> (u64, FFlags) {
+    let value: i128 = match (signed, width) {
+        (true, 32) => value as i32 as i128,
+        (false, 32) => value as u32 as i128,
+        (true, _) => value as i64 as i128,
+        (false, _) => value as i128,
+    };
+
+    round_pack(format, value < 0, 0, value.unsigned_abs(), rm)
+}
@@ -0,0 +1,36 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::emulator::state::rv64_cpu_context::MIPFlags;
+
+// Interrupt pending bits driven by devices, shared between a hart and everything wired to it.
+// Devices may run on their own threads, so the lines are kept in an atomic instead of the CSR file.
+pub struct InterruptLines {
+    pending: AtomicU64,
+}
+
+impl InterruptLines {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self { pending: AtomicU64::new(0) })
+    }
+
+    pub fn raise(&self, lines: MIPFlags) {
+        self.pending.fetch_or(lines.bits(), Ordering::SeqCst);
+    }
+
+    pub fn lower(&self, lines: MIPFlags) {
+        self.pending.fetch_and(!lines.bits(), Ordering::SeqCst);
+    }
+
+    // Level triggered devices simply mirror their output onto the line
+    pub fn set(&self, lines: MIPFlags, level: bool) {
+        if level {
+            self.raise(lines);
+        } else {
+            self.lower(lines);
+        }
+    }
+
+    pub fn pending(&self) -> u64 {
+        self.pending.load(Ordering::SeqCst)
+    }
+}
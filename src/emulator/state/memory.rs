@@ -1,9 +1,45 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::sync::{Arc, Mutex, RwLock};
 
+// LR reserves the aligned doubleword around its address, any write into it breaks the reservation
+const RESERVATION_GRANULE: u64 = 8;
+
 pub struct MemoryManagementUnit {
     regions: BTreeMap<usize, MemoryRegion>,
     reservations: Mutex<HashMap<u64, u64>>,
+    watchpoints: Vec<Watchpoint>,
+    watch_hit: Mutex<Option<WatchHit>>,
+    journal: Option<VecDeque<JournalEntry>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+// Physical address range the debugger wants to stop on when it is accessed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addr: u64,
+    pub len: u64,
+    pub kind: WatchKind,
+}
+
+// RAM bytes overwritten by a write, kept while execution history is recorded so the write can be undone
+#[derive(Debug, Clone, Copy)]
+pub struct JournalEntry {
+    addr: usize,
+    len: usize,
+    old: [u8; 8],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub watchpoint: Watchpoint,
+    pub addr: u64,
+    pub write: bool,
 }
 
 struct MemoryRegion {
@@ -14,12 +50,20 @@ struct MemoryRegion {
 
 impl MemoryManagementUnit {
     pub(crate) fn new(memory_size: usize) -> Self {
+        Self::new_at(0, memory_size)
+    }
+
+    // Places RAM at ram_base, devices are mapped around it with add_region
+    pub(crate) fn new_at(ram_base: usize, memory_size: usize) -> Self {
         let mut mmu = Self {
             regions: BTreeMap::new(),
             reservations: Mutex::new(HashMap::new()),
+            watchpoints: Vec::new(),
+            watch_hit: Mutex::new(None),
+            journal: None,
         };
 
-        mmu.add_region(0, memory_size, Box::new(Memory::new(memory_size)));
+        mmu.add_region(ram_base, memory_size, Box::new(Memory::new(memory_size)));
 
         mmu
     }
@@ -28,7 +72,7 @@ impl MemoryManagementUnit {
         Arc::new(RwLock::new(Self::new(memory_size)))
     }
 
-    fn add_region(&mut self, start: usize, size: usize, device: Box<dyn Device>) {
+    pub(crate) fn add_region(&mut self, start: usize, size: usize, device: Box<dyn Device>) {
         // Check for overlaps
         if self.has_overlap(start, size) {
             panic!("Memory region overlap at {:#x}", start);
@@ -72,6 +116,14 @@ impl MemoryManagementUnit {
             .filter(|region| addr < region.start + region.size)
     }
 
+    // Checks that the whole range [addr, addr + size) is backed by a single region
+    pub fn is_mapped(&self, addr: usize, size: usize) -> bool {
+        match self.find_region(addr) {
+            Some(region) => addr.checked_add(size).is_some_and(|end| end <= region.start + region.size),
+            None => false,
+        }
+    }
+
     pub fn read(&self, addr: usize, size: usize, buf: &mut [u8]) {
         if let Some(region) = self.find_region(addr) {
             region.device.read(addr - region.start, size, buf);
@@ -81,6 +133,9 @@ impl MemoryManagementUnit {
     }
 
     pub fn write(&mut self, addr: usize, size: usize, buf: &[u8]) {
+        self.invalidate_reservations(addr, size);
+        self.record_write(addr, size);
+
         if let Some(region) = self.find_region_mut(addr) {
             region.device.write(addr - region.start, size, buf);
         } else {
@@ -89,6 +144,8 @@ impl MemoryManagementUnit {
     }
 
     pub fn read_byte(&self, addr: usize) -> u8 {
+        self.check_watchpoints(addr, 1, false);
+
         if let Some(region) = self.find_region(addr) {
             region.device.read_byte(addr - region.start)
         } else {
@@ -97,6 +154,10 @@ impl MemoryManagementUnit {
     }
 
     pub fn write_byte(&mut self, addr: usize, value: u8) {
+        self.invalidate_reservations(addr, 1);
+        self.record_write(addr, 1);
+        self.check_watchpoints(addr, 1, true);
+
         if let Some(region) = self.find_region_mut(addr) {
             region.device.write_byte(addr - region.start, value)
         } else {
@@ -105,6 +166,8 @@ impl MemoryManagementUnit {
     }
 
     pub fn read_half_word(&self, addr: usize) -> u16 {
+        self.check_watchpoints(addr, 2, false);
+
         if let Some(region) = self.find_region(addr) {
             region.device.read_half_word(addr - region.start)
         } else {
@@ -113,6 +176,10 @@ impl MemoryManagementUnit {
     }
 
     pub fn write_half_word(&mut self, addr: usize, value: u16) {
+        self.invalidate_reservations(addr, 2);
+        self.record_write(addr, 2);
+        self.check_watchpoints(addr, 2, true);
+
         if let Some(region) = self.find_region_mut(addr) {
             region.device.write_half_word(addr - region.start, value)
         } else {
@@ -121,6 +188,8 @@ impl MemoryManagementUnit {
     }
 
     pub fn read_word(&self, addr: usize) -> u32 {
+        self.check_watchpoints(addr, 4, false);
+
         if let Some(region) = self.find_region(addr) {
             region.device.read_word(addr - region.start)
         } else {
@@ -129,6 +198,10 @@ impl MemoryManagementUnit {
     }
 
     pub fn write_word(&mut self, addr: usize, value: u32) {
+        self.invalidate_reservations(addr, 4);
+        self.record_write(addr, 4);
+        self.check_watchpoints(addr, 4, true);
+
         if let Some(region) = self.find_region_mut(addr) {
             region.device.write_word(addr - region.start, value)
         } else {
@@ -137,6 +210,8 @@ impl MemoryManagementUnit {
     }
 
     pub fn read_double_word(&self, addr: usize) -> u64 {
+        self.check_watchpoints(addr, 8, false);
+
         if let Some(region) = self.find_region(addr) {
             region.device.read_double_word(addr - region.start)
         } else {
@@ -145,6 +220,10 @@ impl MemoryManagementUnit {
     }
 
     pub fn write_double_word(&mut self, addr: usize, value: u64) {
+        self.invalidate_reservations(addr, 8);
+        self.record_write(addr, 8);
+        self.check_watchpoints(addr, 8, true);
+
         if let Some(region) = self.find_region_mut(addr) {
             region.device.write_double_word(addr - region.start, value)
         } else {
@@ -167,6 +246,141 @@ impl MemoryManagementUnit {
         reservations.retain(|_, &mut v| v != addr);
     }
 
+    // SC gives up the reservation of its hart whether it succeeds or not
+    pub fn clear_reservation(&self, hart_id: u64) {
+        let mut reservations = self.reservations.lock().unwrap();
+        reservations.remove(&hart_id);
+    }
+
+    // Writers hold the lock exclusively, so no other hart can be halfway through an LR/SC or AMO here
+    fn invalidate_reservations(&mut self, addr: usize, size: usize) {
+        let reservations = self.reservations.get_mut().unwrap();
+
+        if reservations.is_empty() {
+            return;
+        }
+
+        let start = addr as u64;
+        let end = start + size as u64;
+
+        reservations.retain(|_, &mut reserved| {
+            let granule = reserved & !(RESERVATION_GRANULE - 1);
+            granule >= end || granule + RESERVATION_GRANULE <= start
+        });
+    }
+
+    // Reservations of every hart, saved and restored along with the harts by reverse execution
+    pub fn get_reservations(&self) -> HashMap<u64, u64> {
+        self.reservations.lock().unwrap().clone()
+    }
+
+    pub fn set_reservations(&mut self, reservations: HashMap<u64, u64>) {
+        *self.reservations.get_mut().unwrap() = reservations;
+    }
+
+    // Starts keeping the old contents of every RAM write, device registers can't be restored and aren't kept
+    pub fn start_journal(&mut self) {
+        self.journal.get_or_insert_with(VecDeque::new);
+    }
+
+    pub fn stop_journal(&mut self) {
+        self.journal = None;
+    }
+
+    pub fn journal_len(&self) -> usize {
+        self.journal.as_ref().map_or(0, |journal| journal.len())
+    }
+
+    // Undoes the writes recorded after the first len entries, newest first
+    pub fn rewind_journal(&mut self, len: usize) {
+        let Some(mut journal) = self.journal.take() else {
+            return;
+        };
+
+        while journal.len() > len {
+            let entry = journal.pop_back().unwrap();
+
+            if let Some(region) = self.find_region_mut(entry.addr) {
+                region.device.write(entry.addr - region.start, entry.len, &entry.old);
+            }
+        }
+
+        self.journal = Some(journal);
+    }
+
+    // Drops the oldest entries, the writes they recorded can't be undone anymore
+    pub fn forget_journal(&mut self, count: usize) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.drain(..count.min(journal.len()));
+        }
+    }
+
+    fn record_write(&mut self, addr: usize, size: usize) {
+        let Some(mut journal) = self.journal.take() else {
+            return;
+        };
+
+        if let Some(region) = self.find_region(addr).filter(|region| matches!(region.device.memory_type(), MemoryType::RAM)) {
+            let size = size.min(region.start + region.size - addr);
+
+            // Bulk writes are kept in doubleword sized pieces
+            for offset in (0..size).step_by(8) {
+                let mut entry = JournalEntry { addr: addr + offset, len: (size - offset).min(8), old: [0; 8] };
+
+                region.device.read(entry.addr - region.start, entry.len, &mut entry.old);
+                journal.push_back(entry);
+            }
+        }
+
+        self.journal = Some(journal);
+    }
+
+    // Only the sized accesses done by instructions are watched, bulk reads and writes from loaders,
+    // devices and the debugger itself don't trigger
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|other| other != watchpoint);
+
+        self.watchpoints.len() != len
+    }
+
+    pub fn get_watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    // The first watchpoint hit since the last call
+    pub fn take_watch_hit(&self) -> Option<WatchHit> {
+        self.watch_hit.lock().unwrap().take()
+    }
+
+    #[inline(always)]
+    fn check_watchpoints(&self, addr: usize, size: usize, write: bool) {
+        if self.watchpoints.is_empty() {
+            return;
+        }
+
+        let start = addr as u64;
+        let end = start + size as u64;
+
+        let hit = self.watchpoints.iter().find(|watchpoint| {
+            let kind_matches = match watchpoint.kind {
+                WatchKind::Read => !write,
+                WatchKind::Write => write,
+                WatchKind::Access => true,
+            };
+
+            kind_matches && start < watchpoint.addr + watchpoint.len && watchpoint.addr < end
+        });
+
+        if let Some(watchpoint) = hit {
+            self.watch_hit.lock().unwrap().get_or_insert(WatchHit { watchpoint: *watchpoint, addr: start, write });
+        }
+    }
+
     pub fn size(&self) -> u64 {
         let mut len: u64 = 0;
 
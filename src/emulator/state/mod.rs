@@ -1,2 +1,5 @@
 pub mod rv64_cpu_context;
-pub mod memory;
\ No newline at end of file
+pub mod memory;
+pub mod translation;
+pub mod tlb;
+pub mod interrupts;
\ No newline at end of file
@@ -1,5 +1,13 @@
+use std::fmt;
 use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::emulator::constants::{PAGE_SIZE, SATP_MODE_BARE, SATP_MODE_SV39, SATP_MODE_SV48, SATP_MODE_SV57};
 use crate::emulator::state::memory::{Memory, MemoryManagementUnit};
+use crate::emulator::state::tlb::Tlb;
+use crate::emulator::state::interrupts::InterruptLines;
+use crate::emulator::instructions::rv64::compressed::is_compressed;
+use crate::emulator::trace::CommitLog;
+use crate::emulator::state::translation::{check_leaf_permissions, levels_for_mode, needs_accessed_dirty_update, AccessDirtyPolicy, AccessType, TranslationContext, SATP_ASID_MASK, SATP_ASID_SHIFT, SATP_MODE_SHIFT, SATP_PPN_MASK};
 use bitflags::bitflags;
 
 // MSTATUS register flags
@@ -13,10 +21,23 @@ bitflags! {
         const SPP = 1 << 8;    // Supervisor Previous Privilege
         const SBE = 1 << 9;    // Supervisor Big-Endian
         const MBE = 1 << 10;   // Machine Big-Endian
+        const MPRV = 1 << 17;  // Modify Privilege
+        const SUM = 1 << 18;   // Permit Supervisor User Memory access
+        const MXR = 1 << 19;   // Make eXecutable Readable
+        const TVM = 1 << 20;   // Trap Virtual Memory
+        const TW = 1 << 21;    // Timeout Wait
+        const TSR = 1 << 22;   // Trap SRET
         const SD = 1 << 63;    // State Dirty - summary bit
     }
 }
 
+// MENVCFG register flags (Machine Environment Configuration)
+bitflags! {
+    pub struct MEnvCfgFlags: u64 {
+        const ADUE = 1 << 61;  // Hardware updating of PTE A/D bits Enable
+    }
+}
+
 // MIE register flags (Machine Interrupt Enable)
 bitflags! {
     pub struct MIEFlags: u64 {
@@ -34,6 +55,7 @@ bitflags! {
 
 // MIP register flags (Machine Interrupt Pending)
 bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct MIPFlags: u64 {
         const USIP = 1 << 0;   // User Software Interrupt Pending
         const SSIP = 1 << 1;   // Supervisor Software Interrupt Pending
@@ -48,10 +70,11 @@ bitflags! {
 }
 
 bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct FFlags: u64 {
         const NX = 1 << 0;   // Inexact
-        const UF = 1 << 0;   // Underflow
-        const OF = 1 << 1;   // Overflow
+        const UF = 1 << 1;   // Underflow
+        const OF = 1 << 2;   // Overflow
         const DZ = 1 << 3;   // Divide by Zero
         const NV = 1 << 4;   // Invalid operation
     }
@@ -70,10 +93,10 @@ pub enum Exception {
     StoreAccessFault,
     EnvironmentCallFromUMode,
     EnvironmentCallFromSMode,
-    EnvironmentCallFromMMode,
-    InstructionPageFault,
-    LoadPageFault,
-    StorePageFault,
+    EnvironmentCallFromMMode = 11,
+    InstructionPageFault = 12,
+    LoadPageFault = 13,
+    StorePageFault = 15,
 }
 
 // CSR addresses
@@ -95,6 +118,9 @@ pub enum CSRAddress {
     MTVec = 0x305,
     MCounterEn = 0x306,
 
+    // Machine Configuration
+    MEnvCfg = 0x30A,
+
     // Machine Trap Handling
     MScratch = 0x340,
     MEPC = 0x341,
@@ -131,12 +157,153 @@ pub enum CSRAddress {
     MCycle = 0xB00,
     MInstRet = 0xB02,
 
+    // Unprivileged Counters
+    Cycle = 0xC00,
+    Time = 0xC01,
+    InstRet = 0xC02,
+
     //User mode registers
     FCSR = 0x003,
     FRM = 0x002,
     FFlags = 0x001,
 }
 
+impl CSRAddress {
+    // Every CSR the hart implements, for debuggers listing or looking them up by name
+    pub const ALL: [CSRAddress; 40] = [
+        CSRAddress::MVendorID,
+        CSRAddress::MArchID,
+        CSRAddress::MImpID,
+        CSRAddress::MHartID,
+        CSRAddress::MStatus,
+        CSRAddress::MIsa,
+        CSRAddress::MEDeleg,
+        CSRAddress::MIDeleg,
+        CSRAddress::MIE,
+        CSRAddress::MTVec,
+        CSRAddress::MCounterEn,
+        CSRAddress::MEnvCfg,
+        CSRAddress::MScratch,
+        CSRAddress::MEPC,
+        CSRAddress::MCause,
+        CSRAddress::MTVal,
+        CSRAddress::MIP,
+        CSRAddress::SStatus,
+        CSRAddress::SIE,
+        CSRAddress::STVec,
+        CSRAddress::SCounterEn,
+        CSRAddress::SScratch,
+        CSRAddress::SEPC,
+        CSRAddress::SCause,
+        CSRAddress::STVal,
+        CSRAddress::SIP,
+        CSRAddress::SATP,
+        CSRAddress::SContext,
+        CSRAddress::SStateEn0,
+        CSRAddress::SStateEn1,
+        CSRAddress::SStateEn2,
+        CSRAddress::SStateEn3,
+        CSRAddress::MCycle,
+        CSRAddress::MInstRet,
+        CSRAddress::Cycle,
+        CSRAddress::Time,
+        CSRAddress::InstRet,
+        CSRAddress::FCSR,
+        CSRAddress::FRM,
+        CSRAddress::FFlags,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CSRAddress::MVendorID => "mvendorid",
+            CSRAddress::MArchID => "marchid",
+            CSRAddress::MImpID => "mimpid",
+            CSRAddress::MHartID => "mhartid",
+            CSRAddress::MStatus => "mstatus",
+            CSRAddress::MIsa => "misa",
+            CSRAddress::MEDeleg => "medeleg",
+            CSRAddress::MIDeleg => "mideleg",
+            CSRAddress::MIE => "mie",
+            CSRAddress::MTVec => "mtvec",
+            CSRAddress::MCounterEn => "mcounteren",
+            CSRAddress::MEnvCfg => "menvcfg",
+            CSRAddress::MScratch => "mscratch",
+            CSRAddress::MEPC => "mepc",
+            CSRAddress::MCause => "mcause",
+            CSRAddress::MTVal => "mtval",
+            CSRAddress::MIP => "mip",
+            CSRAddress::SStatus => "sstatus",
+            CSRAddress::SIE => "sie",
+            CSRAddress::STVec => "stvec",
+            CSRAddress::SCounterEn => "scounteren",
+            CSRAddress::SScratch => "sscratch",
+            CSRAddress::SEPC => "sepc",
+            CSRAddress::SCause => "scause",
+            CSRAddress::STVal => "stval",
+            CSRAddress::SIP => "sip",
+            CSRAddress::SATP => "satp",
+            CSRAddress::SContext => "scontext",
+            CSRAddress::SStateEn0 => "sstateen0",
+            CSRAddress::SStateEn1 => "sstateen1",
+            CSRAddress::SStateEn2 => "sstateen2",
+            CSRAddress::SStateEn3 => "sstateen3",
+            CSRAddress::MCycle => "mcycle",
+            CSRAddress::MInstRet => "minstret",
+            CSRAddress::Cycle => "cycle",
+            CSRAddress::Time => "time",
+            CSRAddress::InstRet => "instret",
+            CSRAddress::FCSR => "fcsr",
+            CSRAddress::FRM => "frm",
+            CSRAddress::FFlags => "fflags",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<CSRAddress> {
+        Self::ALL.iter().copied().find(|csr| csr.name() == name)
+    }
+
+    // Set flags and multi-bit fields of a value of this CSR for the debugger, None if it has no fields
+    pub fn describe_fields(self, value: u64) -> Option<String> {
+        let mut fields: Vec<String> = match self {
+            CSRAddress::MStatus | CSRAddress::SStatus => MStatusFlags::from_bits_truncate(value).iter_names().map(|(name, _)| name.to_string()).collect(),
+            CSRAddress::MIP | CSRAddress::SIP => MIPFlags::from_bits_truncate(value).iter_names().map(|(name, _)| name.to_string()).collect(),
+            CSRAddress::MIE | CSRAddress::SIE => MIEFlags::from_bits_truncate(value).iter_names().map(|(name, _)| name.to_string()).collect(),
+            CSRAddress::SATP => Vec::new(),
+            _ => return None,
+        };
+
+        if self == CSRAddress::MStatus {
+            let mpp = match (value & MPP_MASK) >> MPP_SHIFT {
+                0b00 => "U",
+                0b01 => "S",
+                _ => "M",
+            };
+            fields.push(format!("MPP={}", mpp));
+        }
+
+        if self == CSRAddress::MStatus || self == CSRAddress::SStatus {
+            let fs = ["Off", "Initial", "Clean", "Dirty"][((value & FS_MASK) >> FS_SHIFT) as usize];
+            fields.push(format!("FS={}", fs));
+        }
+
+        if self == CSRAddress::SATP {
+            let mode = match value >> SATP_MODE_SHIFT {
+                SATP_MODE_BARE => "Bare".to_string(),
+                SATP_MODE_SV39 => "Sv39".to_string(),
+                SATP_MODE_SV48 => "Sv48".to_string(),
+                SATP_MODE_SV57 => "Sv57".to_string(),
+                mode => mode.to_string(),
+            };
+
+            fields.push(format!("MODE={}", mode));
+            fields.push(format!("ASID={:#x}", (value >> SATP_ASID_SHIFT) & SATP_ASID_MASK));
+            fields.push(format!("PPN={:#x}", value & SATP_PPN_MASK));
+        }
+
+        Some(fields.join(" "))
+    }
+}
+
 const FS_SHIFT: u64 = 13;
 const FS_MASK: u64 = 0b11 << FS_SHIFT;
 const XS_SHIFT: u64 = 15;
@@ -144,6 +311,36 @@ const XS_MASK: u64 = 0b11 << XS_SHIFT;
 const MPP_SHIFT: u64 = 11;
 const MPP_MASK: u64 = 0b11 << MPP_SHIFT;
 
+// FS values
+const FS_OFF: u64 = 0b00 << FS_SHIFT;
+const FS_DIRTY: u64 = 0b11 << FS_SHIFT;
+
+// MISA fields
+const MISA_MXL_64: u64 = 2 << 62;
+const MISA_C: u64 = 1 << (b'C' - b'A');
+const MISA_EXTENSIONS: u64 = (1 << (b'A' - b'A')) | MISA_C | (1 << (b'D' - b'A')) | (1 << (b'F' - b'A'))
+    | (1 << (b'I' - b'A')) | (1 << (b'M' - b'A')) | (1 << (b'S' - b'A')) | (1 << (b'U' - b'A'));
+
+// Trap causes, mcause/scause have the top bit set for interrupts
+pub const INTERRUPT_BIT: u64 = 1 << 63;
+
+// Interrupt causes in the order they are taken when several are pending
+const INTERRUPT_PRIORITY: [u64; 6] = [11, 3, 7, 9, 1, 5];  // MEI, MSI, MTI, SEI, SSI, STI
+
+// Supervisor interrupts can be raised by machine mode software, machine interrupts only by devices
+const MIP_WRITABLE: u64 = MIPFlags::SSIP.bits() | MIPFlags::STIP.bits() | MIPFlags::SEIP.bits();
+const MIE_WRITABLE: u64 = MIEFlags::SSIE.bits() | MIEFlags::MSIE.bits() | MIEFlags::STIE.bits()
+    | MIEFlags::MTIE.bits() | MIEFlags::SEIE.bits() | MIEFlags::MEIE.bits();
+
+// Exceptions that can be delegated, everything but environment calls from machine mode
+const MEDELEG_WRITABLE: u64 = 0xB3FF;
+// Only supervisor level interrupts can be delegated
+const MIDELEG_WRITABLE: u64 = MIPFlags::SSIP.bits() | MIPFlags::STIP.bits() | MIPFlags::SEIP.bits();
+
+// mtvec/stvec modes, everything above vectored is reserved
+const TVEC_MODE_MASK: u64 = 0b11;
+const TVEC_MODE_VECTORED: u64 = 0b01;
+
 // MPP values
 const MPP_USER: u64 = 0b00 << MPP_SHIFT;
 const MPP_SUPERVISOR: u64 = 0b01 << MPP_SHIFT;
@@ -157,6 +354,7 @@ pub enum PrivilegeMode {
     Machine = 0b11,
 }
 
+#[derive(Clone)]
 pub struct CSRFile {
     // Machine Information Registers
     mvendorid: u64,
@@ -173,6 +371,9 @@ pub struct CSRFile {
     mtvec: u64,
     mcounteren: u64,
 
+    // Machine Configuration
+    menvcfg: u64,
+
     // Machine Trap Handling
     mscratch: u64,
     mepc: u64,
@@ -214,11 +415,17 @@ pub struct CSRFile {
 
     //User mode registers
     cycle: u64,
-    time: u64,
+    time: Arc<AtomicU64>, //Shared with the CLINT's mtime
     instret: u64,
 
     // Current privilege level
     current_privilege: PrivilegeMode,
+
+    // How the page walker handles clear A/D bits, decides if menvcfg.ADUE is writable
+    access_dirty_policy: AccessDirtyPolicy,
+
+    // Pending bits driven by the timer, software and external interrupt devices
+    interrupt_lines: Arc<InterruptLines>,
 }
 
 impl CSRFile {
@@ -229,12 +436,13 @@ impl CSRFile {
             mimpid: 0, //Implementation id
             mhartid: 0, //Processor id
             mstatus: 0, //Machine Status
-            misa: 0,  // Set this based on CPU features
+            misa: MISA_MXL_64 | MISA_EXTENSIONS,  // RV64IMAFDC with supervisor and user mode
             medeleg: 0, //Machine Exception Delegation Register
             mideleg: 0, //Machine Interrupt Delegation Register
             mie: 0, //Machine Interrupt Enable
             mtvec: 0, //Machine Trap Handler Base Address
             mcounteren: 0, //Machine Hardware Counter Enable
+            menvcfg: 0, //Machine Environment Configuration
             mscratch: 0, //Thread-local storage
             mepc: 0, //Machine Exception Return Address
             mcause: 0, //Machine Exception cause
@@ -259,12 +467,38 @@ impl CSRFile {
             sstateen3: 0,
             fcsr: 0,
             cycle: 0,
-            time: 0,
+            time: Arc::new(AtomicU64::new(0)),
             instret: 0,
             current_privilege: PrivilegeMode::Machine,
+            access_dirty_policy: AccessDirtyPolicy::FaultOnClear,
+            interrupt_lines: InterruptLines::new(),
         }
     }
 
+    pub fn get_access_dirty_policy(&self) -> AccessDirtyPolicy {
+        self.access_dirty_policy
+    }
+
+    // Machines with Svadu come out of reset with hardware A/D updates enabled
+    pub fn set_access_dirty_policy(&mut self, policy: AccessDirtyPolicy) {
+        self.access_dirty_policy = policy;
+
+        match policy {
+            AccessDirtyPolicy::FaultOnClear => self.menvcfg &= !MEnvCfgFlags::ADUE.bits(),
+            AccessDirtyPolicy::HardwareUpdate => self.menvcfg |= MEnvCfgFlags::ADUE.bits(),
+        }
+    }
+
+    // The time CSR reads the platform's mtime counter
+    pub fn set_time_source(&mut self, mtime: Arc<AtomicU64>) {
+        self.time = mtime;
+    }
+
+    // Handle for devices to drive this hart's interrupt pending bits
+    pub fn get_interrupt_lines(&self) -> Arc<InterruptLines> {
+        self.interrupt_lines.clone()
+    }
+
     pub fn get_current_privilege(&self) -> PrivilegeMode {
         return self.current_privilege;
     }
@@ -273,15 +507,172 @@ impl CSRFile {
         self.current_privilege = privilege;
     }
 
+    fn get_machine_previous_privilege(&self) -> PrivilegeMode {
+        match (self.mstatus & MPP_MASK) >> MPP_SHIFT {
+            0b00 => PrivilegeMode::User,
+            0b01 => PrivilegeMode::Supervisor,
+            _ => PrivilegeMode::Machine,
+        }
+    }
+
+    // MRET: unstacks MIE from MPIE, returns to the privilege in MPP and yields the address to resume at
+    pub fn return_from_machine_trap(&mut self) -> u64 {
+        let previous_privilege = self.get_machine_previous_privilege();
+        let mut mstatus = MStatusFlags::from_bits_retain(self.mstatus);
+
+        mstatus.set(MStatusFlags::MIE, mstatus.contains(MStatusFlags::MPIE));
+        mstatus.insert(MStatusFlags::MPIE);
+
+        // MPRV only stays set when returning to machine mode
+        if previous_privilege != PrivilegeMode::Machine {
+            mstatus.remove(MStatusFlags::MPRV);
+        }
+
+        self.mstatus = (mstatus.bits() & !MPP_MASK) | MPP_USER;
+        self.change_privilege(previous_privilege);
+
+        self.mepc
+    }
+
+    // SRET: unstacks SIE from SPIE, returns to the privilege in SPP and yields the address to resume at
+    pub fn return_from_supervisor_trap(&mut self) -> u64 {
+        let mut mstatus = MStatusFlags::from_bits_retain(self.mstatus);
+
+        let previous_privilege = if mstatus.contains(MStatusFlags::SPP) {
+            PrivilegeMode::Supervisor
+        } else {
+            PrivilegeMode::User
+        };
+
+        mstatus.set(MStatusFlags::SIE, mstatus.contains(MStatusFlags::SPIE));
+        mstatus.insert(MStatusFlags::SPIE);
+        mstatus.remove(MStatusFlags::SPP | MStatusFlags::MPRV);
+
+        self.mstatus = mstatus.bits();
+        self.change_privilege(previous_privilege);
+
+        self.sepc
+    }
+
+    // Reserved trap vector modes fall back to direct mode
+    fn legalize_tvec(value: u64) -> u64 {
+        if value & TVEC_MODE_MASK > TVEC_MODE_VECTORED {
+            value & !TVEC_MODE_MASK
+        } else {
+            value
+        }
+    }
+
+    // Traps taken in machine mode never go down, otherwise the delegation registers decide
+    fn get_trap_target(&self, cause: u64, interrupt: bool) -> PrivilegeMode {
+        let delegation = if interrupt { self.mideleg } else { self.medeleg };
+
+        if self.current_privilege != PrivilegeMode::Machine && (delegation >> cause) & 1 != 0 {
+            PrivilegeMode::Supervisor
+        } else {
+            PrivilegeMode::Machine
+        }
+    }
+
+    // Handler address for a trap, vectored mode only applies to interrupts
+    fn get_trap_vector(tvec: u64, cause: u64, interrupt: bool) -> u64 {
+        let base = tvec & !TVEC_MODE_MASK;
+
+        if interrupt && (tvec & TVEC_MODE_MASK) == TVEC_MODE_VECTORED {
+            base.wrapping_add(4 * cause)
+        } else {
+            base
+        }
+    }
+
+    // Trap entry: saves cause, epc and tval, stacks the interrupt enable and privilege, returns the handler address
+    pub fn enter_trap(&mut self, cause: u64, interrupt: bool, epc: u64, tval: u64) -> u64 {
+        let target = self.get_trap_target(cause, interrupt);
+        let previous_privilege = self.current_privilege;
+        let cause_value = if interrupt { cause | INTERRUPT_BIT } else { cause };
+        let mut mstatus = MStatusFlags::from_bits_retain(self.mstatus);
+
+        let tvec = match target {
+            PrivilegeMode::Supervisor => {
+                self.scause = cause_value;
+                self.sepc = epc;
+                self.stval = tval;
+
+                mstatus.set(MStatusFlags::SPIE, mstatus.contains(MStatusFlags::SIE));
+                mstatus.remove(MStatusFlags::SIE);
+                mstatus.set(MStatusFlags::SPP, previous_privilege == PrivilegeMode::Supervisor);
+
+                self.mstatus = mstatus.bits();
+                self.stvec
+            }
+            _ => {
+                self.mcause = cause_value;
+                self.mepc = epc;
+                self.mtval = tval;
+
+                mstatus.set(MStatusFlags::MPIE, mstatus.contains(MStatusFlags::MIE));
+                mstatus.remove(MStatusFlags::MIE);
+
+                self.mstatus = (mstatus.bits() & !MPP_MASK) | ((previous_privilege as u64) << MPP_SHIFT);
+                self.mtvec
+            }
+        };
+
+        self.change_privilege(target);
+
+        Self::get_trap_vector(tvec, cause, interrupt)
+    }
+
+    // Highest priority interrupt that is pending, enabled and allowed to preempt the current privilege
+    pub fn get_pending_interrupt(&self) -> Option<u64> {
+        let pending = self.read_mip() & self.mie;
+
+        if pending == 0 {
+            return None;
+        }
+
+        let mstatus = MStatusFlags::from_bits_retain(self.mstatus);
+
+        // Interrupts for a more privileged mode are always enabled, for the current one the global enable decides
+        let machine_enabled = match self.current_privilege {
+            PrivilegeMode::Machine => mstatus.contains(MStatusFlags::MIE),
+            _ => true,
+        };
+
+        let supervisor_enabled = match self.current_privilege {
+            PrivilegeMode::Machine => false,
+            PrivilegeMode::Supervisor => mstatus.contains(MStatusFlags::SIE),
+            PrivilegeMode::User => true,
+        };
+
+        let mut enabled = 0;
+
+        if machine_enabled {
+            enabled |= pending & !self.mideleg;
+        }
+
+        // Interrupts destined for machine mode are taken first
+        if enabled == 0 && supervisor_enabled {
+            enabled |= pending & self.mideleg;
+        }
+
+        INTERRUPT_PRIORITY.iter().copied().find(|cause| (enabled >> cause) & 1 != 0)
+    }
+
+    // WFI wakes up on any locally enabled pending interrupt, regardless of the global enable bits
+    pub fn has_pending_interrupt(&self) -> bool {
+        (self.read_mip() & self.mie) != 0
+    }
+
     // Helper method to determine required privilege level for a CSR
     fn get_required_privilege_for_csr(&self, csr_addr: u16) -> PrivilegeMode {
         // In RISC-V, CSR address space is divided based on privilege:
-        // The two most significant bits indicate the lowest privilege that can access the CSR
-        match (csr_addr >> 10) & 0b11 {
-            0b00 => PrivilegeMode::User,      // User/unprivileged CSRs (0x000-0x3FF)
-            0b01 => PrivilegeMode::Supervisor, // Supervisor CSRs (0x400-0x7FF)
-            0b10 => PrivilegeMode::Machine,    // Reserved for hypervisor CSRs (0x800-0xBFF) - treat as machine
-            0b11 => PrivilegeMode::Machine,    // Machine CSRs (0xC00-0xFFF)
+        // Bits 9:8 indicate the lowest privilege that can access the CSR, bits 11:10 the read/write accessibility
+        match (csr_addr >> 8) & 0b11 {
+            0b00 => PrivilegeMode::User,      // User/unprivileged CSRs (0x000-0x0FF, 0xC00-0xCFF, ...)
+            0b01 => PrivilegeMode::Supervisor, // Supervisor CSRs (0x100-0x1FF, 0x500-0x5FF, ...)
+            0b10 => PrivilegeMode::Machine,    // Hypervisor CSRs - not implemented, treat as machine
+            0b11 => PrivilegeMode::Machine,    // Machine CSRs (0x300-0x3FF, 0xB00-0xBFF, 0xF00-0xFFF, ...)
             _ => unreachable!(), // This should never happen as we're only using 2 bits
         }
     }
@@ -326,7 +717,7 @@ impl CSRFile {
         }
 
         // Special case for counter CSRs
-        if (csr_addr >= 0xC00 && csr_addr <= 0xC1F) {
+        if (0xC00..=0xC1F).contains(&csr_addr) {
             return self.is_counter_accessible(csr_addr);
         }
 
@@ -340,6 +731,14 @@ impl CSRFile {
             return Err(Exception::IllegalInstruction);
         }
 
+        if Self::is_fp_csr(csr_addr) && !self.is_fp_enabled() && !override_privs {
+            return Err(Exception::IllegalInstruction);
+        }
+
+        if (0xC00..=0xC1F).contains(&csr_addr) && !self.is_counter_accessible(csr_addr) && !override_privs {
+            return Err(Exception::IllegalInstruction);
+        }
+
         match csr_addr {
             // Machine Information Registers
             x if x == CSRAddress::MVendorID as u16 => Ok(self.mvendorid),
@@ -352,21 +751,29 @@ impl CSRFile {
             x if x == CSRAddress::MIsa as u16 => Ok(self.misa),
             x if x == CSRAddress::MEDeleg as u16 => Ok(self.medeleg),
             x if x == CSRAddress::MIDeleg as u16 => Ok(self.mideleg),
-            x if x == CSRAddress::MIE as u16 => Ok(/*self.read_mie()*/0),
+            x if x == CSRAddress::MIE as u16 => Ok(self.mie),
             x if x == CSRAddress::MTVec as u16 => Ok(self.mtvec),
             x if x == CSRAddress::MCounterEn as u16 => Ok(self.mcounteren),
 
+            // Machine Configuration
+            x if x == CSRAddress::MEnvCfg as u16 => Ok(self.menvcfg),
+
             // Machine Trap Handling
             x if x == CSRAddress::MScratch as u16 => Ok(self.mscratch),
             x if x == CSRAddress::MEPC as u16 => Ok(self.mepc),
             x if x == CSRAddress::MCause as u16 => Ok(self.mcause),
             x if x == CSRAddress::MTVal as u16 => Ok(self.mtval),
-            x if x == CSRAddress::MIP as u16 => Ok(/*self.read_mip()*/ 0),
+            x if x == CSRAddress::MIP as u16 => Ok(self.read_mip()),
 
             // Machine Counters
             x if x == CSRAddress::MCycle as u16 => Ok(self.mcycle),
             x if x == CSRAddress::MInstRet as u16 => Ok(self.minstret),
 
+            // Unprivileged Counters
+            x if x == CSRAddress::Cycle as u16 => Ok(self.mcycle),
+            x if x == CSRAddress::Time as u16 => Ok(self.time.load(Ordering::SeqCst)),
+            x if x == CSRAddress::InstRet as u16 => Ok(self.minstret),
+
             // Supervisor Trap Setup
             x if x == CSRAddress::SStatus as u16 => Ok(self.read_sstatus()),
             x if x == CSRAddress::SIE as u16 => Ok(self.read_sie()),
@@ -413,6 +820,14 @@ impl CSRFile {
             return Err(Exception::IllegalInstruction);
         }
 
+        if Self::is_fp_csr(csr_addr) {
+            if !self.is_fp_enabled() && !override_privs {
+                return Err(Exception::IllegalInstruction);
+            }
+
+            self.set_fp_dirty();
+        }
+
         match csr_addr {
             // Machine Information Registers - Read-Only
             x if x == CSRAddress::MVendorID as u16
@@ -431,11 +846,11 @@ impl CSRFile {
                 Err(Exception::IllegalInstruction)
             },
             x if x == CSRAddress::MEDeleg as u16 => {
-                self.medeleg = value;
+                self.medeleg = value & MEDELEG_WRITABLE;
                 Ok(())
             },
             x if x == CSRAddress::MIDeleg as u16 => {
-                self.mideleg = value;
+                self.mideleg = value & MIDELEG_WRITABLE;
                 Ok(())
             },
             x if x == CSRAddress::MIE as u16 => {
@@ -443,7 +858,7 @@ impl CSRFile {
                 Ok(())
             },
             x if x == CSRAddress::MTVec as u16 => {
-                self.mtvec = value;
+                self.mtvec = Self::legalize_tvec(value);
                 Ok(())
             },
             x if x == CSRAddress::MCounterEn as u16 => {
@@ -451,6 +866,12 @@ impl CSRFile {
                 Ok(())
             },
 
+            // Machine Configuration
+            x if x == CSRAddress::MEnvCfg as u16 => {
+                self.write_menvcfg(value);
+                Ok(())
+            },
+
             // Machine Trap Handling
             x if x == CSRAddress::MScratch as u16 => {
                 self.mscratch = value;
@@ -470,7 +891,7 @@ impl CSRFile {
                 Ok(())
             },
             x if x == CSRAddress::MIP as u16 => {
-                //self.write_mip(value);
+                self.write_mip(value);
                 Ok(())
             },
 
@@ -494,7 +915,7 @@ impl CSRFile {
                 Ok(())
             },
             x if x == CSRAddress::STVec as u16 => {
-                self.stvec = value;
+                self.stvec = Self::legalize_tvec(value);
                 Ok(())
             },
             x if x == CSRAddress::SCounterEn as u16 => {
@@ -534,7 +955,11 @@ impl CSRFile {
                         return Err(Exception::IllegalInstruction);
                     }
                 }
-                self.satp = value;
+                // Writes selecting an unsupported translation mode have no effect
+                let mode = value >> SATP_MODE_SHIFT;
+                if mode == SATP_MODE_BARE || levels_for_mode(mode).is_some() {
+                    self.satp = value;
+                }
                 Ok(())
             },
 
@@ -581,8 +1006,56 @@ impl CSRFile {
         }
     }
 
+    // Builds the state the page walker needs, applying MPRV to loads and stores
+    pub fn translation_context(&self, access: AccessType) -> TranslationContext {
+        let mstatus = MStatusFlags::from_bits_retain(self.mstatus);
+
+        let privilege = if access != AccessType::Fetch && mstatus.contains(MStatusFlags::MPRV) {
+            self.get_machine_previous_privilege()
+        } else {
+            self.current_privilege
+        };
+
+        TranslationContext {
+            satp: self.satp,
+            privilege,
+            sum: mstatus.contains(MStatusFlags::SUM),
+            mxr: mstatus.contains(MStatusFlags::MXR),
+            hardware_ad: MEnvCfgFlags::from_bits_retain(self.menvcfg).contains(MEnvCfgFlags::ADUE),
+        }
+    }
+
     pub fn set_fcsr_bit(&mut self, bit: u64) {
         self.fcsr |= bit;
+        self.set_fp_dirty();
+    }
+
+    fn is_fp_csr(csr_addr: u16) -> bool {
+        csr_addr == CSRAddress::FFlags as u16 || csr_addr == CSRAddress::FRM as u16 || csr_addr == CSRAddress::FCSR as u16
+    }
+
+    // With C enabled IALIGN is 16 bits, otherwise instructions must be 32 bit aligned
+    pub fn is_compressed_enabled(&self) -> bool {
+        (self.misa & MISA_C) != 0
+    }
+
+    // ISA string for the device tree, single letter extensions in canonical order
+    pub fn get_isa_string(&self) -> String {
+        let extensions: String = "IEMAFDQLCBKJTPVH".chars()
+            .filter(|extension| (self.misa >> (*extension as u8 - b'A')) & 1 != 0)
+            .collect();
+
+        format!("rv64{}", extensions.to_lowercase())
+    }
+
+    // Floating point instructions and CSRs are illegal while mstatus.FS is Off
+    pub fn is_fp_enabled(&self) -> bool {
+        (self.mstatus & FS_MASK) != FS_OFF
+    }
+
+    // Any write to the floating point state marks it dirty for context switching
+    pub fn set_fp_dirty(&mut self) {
+        self.mstatus |= FS_DIRTY;
     }
 
     // SSTATUS is a subset of MSTATUS
@@ -651,13 +1124,28 @@ impl CSRFile {
     // SIP is a subset of MIP
     fn read_sip(&self) -> u64 {
         // SIP is MIP masked by mideleg
-        self.mip & self.mideleg
+        self.read_mip() & self.mideleg
+    }
+
+    // Software writable bits or'ed with the lines driven by devices
+    fn read_mip(&self) -> u64 {
+        self.mip | self.interrupt_lines.pending()
+    }
+
+    fn write_mip(&mut self, value: u64) {
+        // MEIP, MTIP and MSIP are read-only, they are controlled by the PLIC and CLINT
+        self.mip = (self.mip & !MIP_WRITABLE) | (value & MIP_WRITABLE);
+    }
+
+    // Sets software writable pending bits directly, without latching the bits driven by devices
+    pub fn raise_interrupt(&mut self, interrupt: MIPFlags) {
+        self.mip |= interrupt.bits() & MIP_WRITABLE;
     }
 
     fn write_sip(&mut self, value: u64) {
         // Only certain bits of SIP are writable by software
         // And only those that are delegated
-        let writable_mask = MIPFlags::USIP.bits() | MIPFlags::SSIP.bits();
+        let writable_mask = MIPFlags::SSIP.bits();
         let delegated_mask = self.mideleg;
 
         let effective_mask = writable_mask & delegated_mask;
@@ -722,12 +1210,19 @@ impl CSRFile {
             (xs << XS_SHIFT);
     }
 
-    fn write_mie(&mut self, value: u64) {
-        // Only delegated interrupts can be controlled via SIE
-        let mie = MIEFlags::from_bits_truncate(value);
+    fn write_menvcfg(&mut self, value: u64) {
+        // ADUE only exists on machines implementing Svadu, otherwise it is read-only zero
+        let mask = match self.access_dirty_policy {
+            AccessDirtyPolicy::FaultOnClear => 0,
+            AccessDirtyPolicy::HardwareUpdate => MEnvCfgFlags::ADUE.bits(),
+        };
 
-        // Set the new values for delegated bits
-        self.mie = mie.bits();
+        self.menvcfg = (self.menvcfg & !mask) | (value & mask);
+    }
+
+    fn write_mie(&mut self, value: u64) {
+        // User level interrupts don't exist without the N extension
+        self.mie = value & MIE_WRITABLE;
     }
 
     // Write FFLAGS (bits 4:0 of FCSR)
@@ -747,19 +1242,102 @@ impl CSRFile {
     }
 }
 
+// Register names from the psABI, indexed by register number
+pub const X_ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
+// Accepts x0-x31 as well as ABI names, fp is the frame pointer alias of s0
+pub fn x_register_from_name(name: &str) -> Option<usize> {
+    match name.strip_prefix('x').and_then(|index| index.parse::<usize>().ok()) {
+        Some(index) if index < 32 => Some(index),
+        _ if name == "fp" => Some(8),
+        _ => X_ABI_NAMES.iter().position(|abi_name| *abi_name == name),
+    }
+}
+
+pub const F_ABI_NAMES: [&str; 32] = [
+    "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7", "fs0", "fs1", "fa0", "fa1", "fa2", "fa3", "fa4", "fa5",
+    "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6", "fs7", "fs8", "fs9", "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+];
+
+pub fn f_register_from_name(name: &str) -> Option<usize> {
+    match name.strip_prefix('f').and_then(|index| index.parse::<usize>().ok()) {
+        Some(index) if index < 32 => Some(index),
+        _ => F_ABI_NAMES.iter().position(|abi_name| *abi_name == name),
+    }
+}
+
+// Any register the debugger can name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    X(usize),
+    F(usize),
+    Pc,
+    Csr(u16),
+}
+
+impl Register {
+    // Register names, ABI names, pc and CSR names
+    pub fn from_name(name: &str) -> Option<Register> {
+        if name == "pc" {
+            return Some(Register::Pc);
+        }
+
+        x_register_from_name(name).map(Register::X)
+            .or_else(|| f_register_from_name(name).map(Register::F))
+            .or_else(|| CSRAddress::from_name(name).map(|csr| Register::Csr(csr as u16)))
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Register::X(index) => write!(f, "{}", X_ABI_NAMES[index]),
+            Register::F(index) => write!(f, "{}", F_ABI_NAMES[index]),
+            Register::Pc => write!(f, "pc"),
+            Register::Csr(address) => match CSRAddress::ALL.iter().find(|csr| **csr as u16 == address) {
+                Some(csr) => write!(f, "{}", csr.name()),
+                None => write!(f, "{:#x}", address),
+            },
+        }
+    }
+}
+
+// Upper half of an f register holding a single precision value
+pub const NAN_BOX_MASK: u64 = 0xFFFF_FFFF_0000_0000;
+pub const CANONICAL_NAN_SINGLE: u32 = 0x7FC0_0000;
+
+#[derive(Clone)]
 pub struct RV64CPUContext {
     pub(crate) x: [u64; 32], //General purpose registers
-    pub(crate) f: [f64; 32], //Floating point registers
+    pub(crate) f: [u64; 32], //Floating point registers, raw bit patterns with singles NaN-boxed
     pub(crate) pc: u64, //Program counter
     pub(crate) csrs: CSRFile,
     pub(crate) hart_id: u64,
+    pub(crate) trap_value: u64, //Value for mtval/stval of the last raised exception
+    pub(crate) instruction_length: u64, //Size in bytes of the executing instruction, 2 for compressed ones
+    pub(crate) branch_taken: bool, //Set when the executing instruction redirected pc
+    pub(crate) waiting_for_interrupt: bool, //Hart is stalled in WFI
+
+    pub(crate) itlb: Tlb, //Instruction fetch translations
+    pub(crate) dtlb: Tlb, //Load and store translations
+
+    pub(crate) commit: CommitLog, //Writes of the executing instruction while tracing
 
     pub(crate) memory: Arc<RwLock<MemoryManagementUnit>>,
 }
 
 impl RV64CPUContext {
     pub fn new(pc: u64, memory: Arc<RwLock<MemoryManagementUnit>>) -> Self {
-        Self { x: [0; 32], f: [0f64; 32], pc, memory: memory, csrs: CSRFile::new(), hart_id: 0 }
+        Self { x: [0; 32], f: [0; 32], pc, memory: memory, csrs: CSRFile::new(), hart_id: 0, trap_value: 0, instruction_length: 4, branch_taken: false, waiting_for_interrupt: false, itlb: Tlb::new(), dtlb: Tlb::new(), commit: CommitLog::default() }
+    }
+
+    // The id LR/SC reservations are tracked under is also what the guest reads from mhartid
+    pub fn set_hart_id(&mut self, hart_id: u64) {
+        self.hart_id = hart_id;
+        self.csrs.mhartid = hart_id;
     }
 
     #[inline(always)]
@@ -773,18 +1351,286 @@ impl RV64CPUContext {
         }
 
         self.x[register] = value;
+        self.commit.register(Register::X(register), value);
     }
 
     #[inline(always)]
-    pub(crate) fn set_register_float(&mut self, register: usize, value: f64) {
-        if(register == 0) {
-            return
-        }
-
+    pub(crate) fn set_register_float(&mut self, register: usize, value: u64) {
+        // Unlike x0, f0 is a regular register
         if(register > 31) {
             return
         }
 
         self.f[register] = value;
+        self.commit.register(Register::F(register), value);
+        self.csrs.set_fp_dirty();
+    }
+
+    // Single precision values live in the low half of an f register with the upper 32 bits set
+    #[inline(always)]
+    pub(crate) fn set_register_single(&mut self, register: usize, value: u32) {
+        self.set_register_float(register, NAN_BOX_MASK | value as u64);
+    }
+
+    // Reads a single precision operand, a value that is not properly NaN-boxed reads as the canonical NaN
+    #[inline(always)]
+    pub(crate) fn get_register_single(&self, register: usize) -> u32 {
+        let value = self.f[register];
+
+        if value & NAN_BOX_MASK != NAN_BOX_MASK {
+            return CANONICAL_NAN_SINGLE;
+        }
+
+        value as u32
+    }
+
+    // Translates a virtual address for the current hart, recording the faulting address on failure
+    pub(crate) fn translate_address(&mut self, vaddr: u64, access: AccessType) -> Result<u64, Exception> {
+        let ctx = self.csrs.translation_context(access);
+
+        if ctx.is_bare() {
+            return Ok(vaddr);
+        }
+
+        let asid = ctx.asid();
+        let tlb = if access == AccessType::Fetch { &mut self.itlb } else { &mut self.dtlb };
+
+        if let Some(entry) = tlb.lookup(vaddr, asid) {
+            // Permissions depend on privilege, SUM and MXR, so they are rechecked on every hit.
+            // Anything that doesn't pass falls back to a full walk which raises the proper fault.
+            if check_leaf_permissions(entry.flags, access, &ctx) && !needs_accessed_dirty_update(entry.flags, access) {
+                return Ok(entry.translate(vaddr));
+            }
+        }
+
+        let result = loop {
+            let walk = self.memory.read().unwrap().translate_page(vaddr, access, &ctx);
+
+            match walk {
+                Ok(walk) if needs_accessed_dirty_update(walk.flags(), access) => {
+                    // Svadu, retry the walk if the PTE changed before the bits could be set
+                    if let Some(walk) = self.memory.write().unwrap().commit_accessed_dirty(&walk, access) {
+                        break Ok(walk);
+                    }
+                }
+                other => break other,
+            }
+        };
+
+        match result {
+            Ok(walk) => {
+                tlb.insert(vaddr, &walk, asid);
+                Ok(walk.physical_address)
+            }
+            Err(e) => {
+                self.trap_value = vaddr;
+                Err(e)
+            }
+        }
+    }
+
+    // Translation for the debugger, leaves the TLB, A/D bits and trap state alone. Permissions are not
+    // enforced beyond the page being valid, so read-only text can be patched as well
+    pub(crate) fn debug_translate(&self, vaddr: u64) -> Result<u64, Exception> {
+        let mut ctx = self.csrs.translation_context(AccessType::Load);
+
+        if ctx.is_bare() {
+            return Ok(vaddr);
+        }
+
+        ctx.privilege = PrivilegeMode::Supervisor;
+        ctx.sum = true;
+        ctx.mxr = true;
+
+        Ok(self.memory.read().unwrap().walk_page_table(vaddr, AccessType::Load, &ctx)?.physical_address)
+    }
+
+    // Splits a debugger access at page boundaries since each page can map somewhere else
+    fn debug_pages(&self, vaddr: u64, len: usize) -> Result<Vec<(usize, usize)>, Exception> {
+        let mut pages = Vec::new();
+        let mut offset = 0;
+
+        while offset < len {
+            let addr = vaddr.wrapping_add(offset as u64);
+            let size = ((PAGE_SIZE - addr % PAGE_SIZE) as usize).min(len - offset);
+            let paddr = self.debug_translate(addr)? as usize;
+
+            if !self.memory.read().unwrap().is_mapped(paddr, size) {
+                return Err(Exception::LoadAccessFault);
+            }
+
+            pages.push((paddr, size));
+            offset += size;
+        }
+
+        Ok(pages)
+    }
+
+    pub(crate) fn debug_read(&self, vaddr: u64, buf: &mut [u8]) -> Result<(), Exception> {
+        let mut offset = 0;
+
+        for (paddr, size) in self.debug_pages(vaddr, buf.len())? {
+            self.memory.read().unwrap().read(paddr, size, &mut buf[offset..offset + size]);
+            offset += size;
+        }
+
+        Ok(())
+    }
+
+    // CSRs are accessed regardless of the current privilege
+    pub(crate) fn read_debug_register(&self, register: Register) -> Result<u64, Exception> {
+        match register {
+            Register::X(index) => Ok(self.x[index]),
+            Register::F(index) => Ok(self.f[index]),
+            Register::Pc => Ok(self.pc),
+            Register::Csr(address) => self.csrs.read_csr(address, true),
+        }
+    }
+
+    pub(crate) fn write_debug_register(&mut self, register: Register, value: u64) -> Result<(), Exception> {
+        match register {
+            Register::X(index) => self.set_register(index, value),
+            Register::F(index) => self.set_register_float(index, value),
+            Register::Pc => self.pc = value,
+            Register::Csr(address) => self.csrs.write_csr(address, value, true)?,
+        }
+
+        Ok(())
+    }
+
+    // Nothing is written unless the whole range is mapped
+    pub(crate) fn debug_write(&self, vaddr: u64, data: &[u8]) -> Result<(), Exception> {
+        let mut offset = 0;
+
+        for (paddr, size) in self.debug_pages(vaddr, data.len())? {
+            self.memory.write().unwrap().write(paddr, size, &data[offset..offset + size]);
+            offset += size;
+        }
+
+        Ok(())
+    }
+
+    // Drops cached translations as requested by SFENCE.VMA
+    pub(crate) fn flush_tlb(&mut self, vaddr: Option<u64>, asid: Option<u64>) {
+        self.itlb.flush(vaddr, asid);
+        self.dtlb.flush(vaddr, asid);
+    }
+
+    // Translates an access of size bytes and makes sure the physical range is backed by a device
+    pub(crate) fn translate_access(&mut self, vaddr: u64, size: usize, access: AccessType) -> Result<usize, Exception> {
+        let paddr = self.translate_address(vaddr, access)?;
+
+        if !self.memory.read().unwrap().is_mapped(paddr as usize, size) {
+            self.trap_value = vaddr;
+            return Err(access.access_fault());
+        }
+
+        Ok(paddr as usize)
+    }
+
+    #[inline(always)]
+    fn crosses_page(vaddr: u64, size: usize) -> bool {
+        (vaddr % PAGE_SIZE) + size as u64 > PAGE_SIZE
+    }
+
+    // Reads size bytes from virtual memory, zero extended
+    pub(crate) fn load(&mut self, vaddr: u64, size: usize) -> Result<u64, Exception> {
+        let value = self.load_split(vaddr, size)?;
+        self.commit.load(vaddr);
+
+        Ok(value)
+    }
+
+    fn load_split(&mut self, vaddr: u64, size: usize) -> Result<u64, Exception> {
+        if Self::crosses_page(vaddr, size) {
+            // Each page might map somewhere else, so the access is split into bytes
+            let mut value = 0u64;
+            for i in 0..size {
+                value |= self.load_split(vaddr.wrapping_add(i as u64), 1)? << (8 * i);
+            }
+            return Ok(value);
+        }
+
+        let paddr = self.translate_access(vaddr, size, AccessType::Load)?;
+        let memory = self.memory.read().unwrap();
+
+        Ok(match size {
+            1 => memory.read_byte(paddr) as u64,
+            2 => memory.read_half_word(paddr) as u64,
+            4 => memory.read_word(paddr) as u64,
+            _ => memory.read_double_word(paddr),
+        })
+    }
+
+    // Writes the lower size bytes of value to virtual memory
+    pub(crate) fn store(&mut self, vaddr: u64, size: usize, value: u64) -> Result<(), Exception> {
+        self.store_split(vaddr, size, value)?;
+        self.commit.store(vaddr, size, value);
+
+        Ok(())
+    }
+
+    fn store_split(&mut self, vaddr: u64, size: usize, value: u64) -> Result<(), Exception> {
+        if Self::crosses_page(vaddr, size) {
+            // Translate both pages first so a fault on the second one doesn't leave a partial write
+            self.translate_access(vaddr, 1, AccessType::Store)?;
+            self.translate_access(vaddr.wrapping_add(size as u64 - 1), 1, AccessType::Store)?;
+
+            for i in 0..size {
+                self.store_split(vaddr.wrapping_add(i as u64), 1, value >> (8 * i))?;
+            }
+            return Ok(());
+        }
+
+        let paddr = self.translate_access(vaddr, size, AccessType::Store)?;
+        let mut memory = self.memory.write().unwrap();
+
+        match size {
+            1 => memory.write_byte(paddr, value as u8),
+            2 => memory.write_half_word(paddr, value as u16),
+            4 => memory.write_word(paddr, value as u32),
+            _ => memory.write_double_word(paddr, value),
+        }
+
+        Ok(())
+    }
+
+    // Fetches the instruction at pc, compressed instructions are returned as their 16-bit parcel
+    pub(crate) fn fetch_instruction(&mut self) -> Result<u32, Exception> {
+        let pc = self.pc;
+
+        if Self::crosses_page(pc, 4) {
+            let low = self.fetch_half_word(pc)? as u32;
+
+            // The second half is only touched when the instruction actually extends into the next page
+            if is_compressed(low) {
+                return Ok(low);
+            }
+
+            let high = self.fetch_half_word(pc.wrapping_add(2))? as u32;
+            return Ok(low | (high << 16));
+        }
+
+        let paddr = self.translate_access(pc, 4, AccessType::Fetch)?;
+        Ok(self.memory.read().unwrap().read_word(paddr))
+    }
+
+    // Redirects control flow, raising a misaligned fetch when the target violates IALIGN
+    pub(crate) fn jump(&mut self, target: u64) -> Result<(), Exception> {
+        let alignment_mask = if self.csrs.is_compressed_enabled() { 0b01 } else { 0b11 };
+
+        if target & alignment_mask != 0 {
+            self.trap_value = target;
+            return Err(Exception::InstructionAddressMisaligned);
+        }
+
+        self.pc = target;
+        self.branch_taken = true;
+        Ok(())
+    }
+
+    fn fetch_half_word(&mut self, vaddr: u64) -> Result<u16, Exception> {
+        let paddr = self.translate_access(vaddr, 2, AccessType::Fetch)?;
+        Ok(self.memory.read().unwrap().read_half_word(paddr))
     }
 }
\ No newline at end of file
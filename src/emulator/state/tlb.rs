@@ -0,0 +1,103 @@
+use crate::emulator::constants::PAGE_SHIFT;
+use crate::emulator::state::translation::{PTEFlags, PageWalkResult};
+
+pub const TLB_ENTRIES: usize = 256;
+
+const VPN_BITS: u64 = 9;
+
+// A cached translation for one 4 KiB slice of a (possibly larger) page
+#[derive(Debug, Clone, Copy)]
+pub struct TlbEntry {
+    pub vpn: u64,      // Virtual page number of the cached slice
+    pub ppn: u64,      // Physical page number of the cached slice
+    pub asid: u64,
+    pub flags: PTEFlags,
+    pub level: u64,    // Level of the leaf PTE, superpages are > 0
+}
+
+impl TlbEntry {
+    pub fn is_global(&self) -> bool {
+        self.flags.contains(PTEFlags::G)
+    }
+
+    pub fn matches_asid(&self, asid: u64) -> bool {
+        self.is_global() || self.asid == asid
+    }
+
+    // Checks if vaddr lies within the page (or superpage) this entry was filled from
+    pub fn covers(&self, vaddr: u64) -> bool {
+        let shift = self.level * VPN_BITS;
+        (self.vpn >> shift) == ((vaddr >> PAGE_SHIFT) >> shift)
+    }
+
+    pub fn page_size(&self) -> u64 {
+        1 << (PAGE_SHIFT + self.level * VPN_BITS)
+    }
+
+    pub fn translate(&self, vaddr: u64) -> u64 {
+        (self.ppn << PAGE_SHIFT) | (vaddr & ((1 << PAGE_SHIFT) - 1))
+    }
+}
+
+// Direct mapped translation cache, superpages are cached one 4 KiB slice at a time
+#[derive(Clone)]
+pub struct Tlb {
+    entries: Vec<Option<TlbEntry>>,
+}
+
+impl Default for Tlb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tlb {
+    pub fn new() -> Self {
+        Self { entries: vec![None; TLB_ENTRIES] }
+    }
+
+    #[inline(always)]
+    fn index(vpn: u64) -> usize {
+        (vpn as usize) % TLB_ENTRIES
+    }
+
+    pub fn lookup(&self, vaddr: u64, asid: u64) -> Option<&TlbEntry> {
+        let vpn = vaddr >> PAGE_SHIFT;
+
+        match &self.entries[Self::index(vpn)] {
+            Some(entry) if entry.vpn == vpn && entry.matches_asid(asid) => Some(entry),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&mut self, vaddr: u64, walk: &PageWalkResult, asid: u64) {
+        let vpn = vaddr >> PAGE_SHIFT;
+
+        self.entries[Self::index(vpn)] = Some(TlbEntry {
+            vpn,
+            ppn: walk.physical_address >> PAGE_SHIFT,
+            asid,
+            flags: walk.flags(),
+            level: walk.level,
+        });
+    }
+
+    // SFENCE.VMA semantics: None means "all addresses" / "all address spaces".
+    // Global mappings are only removed when no ASID is given.
+    pub fn flush(&mut self, vaddr: Option<u64>, asid: Option<u64>) {
+        for slot in self.entries.iter_mut() {
+            if let Some(entry) = slot {
+                let address_match = vaddr.is_none_or(|vaddr| entry.covers(vaddr));
+                let asid_match = asid.is_none_or(|asid| !entry.is_global() && entry.asid == asid);
+
+                if address_match && asid_match {
+                    *slot = None;
+                }
+            }
+        }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &TlbEntry> {
+        self.entries.iter().filter_map(|entry| entry.as_ref())
+    }
+}
@@ -0,0 +1,258 @@
+use bitflags::bitflags;
+use crate::emulator::constants::{PAGE_SHIFT, PAGE_SIZE, SATP_MODE_BARE, SATP_MODE_SV39, SATP_MODE_SV48, SATP_MODE_SV57};
+use crate::emulator::state::memory::MemoryManagementUnit;
+use crate::emulator::state::rv64_cpu_context::{Exception, PrivilegeMode};
+
+// Page table entry flags
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PTEFlags: u64 {
+        const V = 1 << 0;   // Valid
+        const R = 1 << 1;   // Readable
+        const W = 1 << 2;   // Writable
+        const X = 1 << 3;   // Executable
+        const U = 1 << 4;   // User accessible
+        const G = 1 << 5;   // Global mapping
+        const A = 1 << 6;   // Accessed
+        const D = 1 << 7;   // Dirty
+    }
+}
+
+pub const PTE_SIZE: u64 = 8;
+pub const PTE_PPN_SHIFT: u64 = 10;
+pub const PTE_PPN_MASK: u64 = (1 << 44) - 1;
+// Bits 63:54 hold Svpbmt/Svnapot attributes and reserved bits, none of which are implemented
+pub const PTE_RESERVED_MASK: u64 = 0x3FF << 54;
+
+pub const SATP_MODE_SHIFT: u64 = 60;
+pub const SATP_ASID_SHIFT: u64 = 44;
+pub const SATP_ASID_MASK: u64 = 0xFFFF;
+pub const SATP_PPN_MASK: u64 = (1 << 44) - 1;
+
+const VPN_BITS: u64 = 9;
+const VPN_MASK: u64 = (1 << VPN_BITS) - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessType {
+    Fetch,
+    Load,
+    Store,
+}
+
+impl AccessType {
+    pub fn page_fault(self) -> Exception {
+        match self {
+            AccessType::Fetch => Exception::InstructionPageFault,
+            AccessType::Load => Exception::LoadPageFault,
+            AccessType::Store => Exception::StorePageFault,
+        }
+    }
+
+    pub fn access_fault(self) -> Exception {
+        match self {
+            AccessType::Fetch => Exception::InstructionAccessFault,
+            AccessType::Load => Exception::LoadAccessFault,
+            AccessType::Store => Exception::StoreAccessFault,
+        }
+    }
+}
+
+// Everything the page walker needs to know about the hart performing the access
+#[derive(Debug, Clone, Copy)]
+pub struct TranslationContext {
+    pub satp: u64,
+    pub privilege: PrivilegeMode, // Effective privilege, MPRV already applied
+    pub sum: bool,                // Permit supervisor access to user pages
+    pub mxr: bool,                // Make executable pages readable
+    pub hardware_ad: bool,        // menvcfg.ADUE, update A/D bits instead of faulting
+}
+
+impl TranslationContext {
+    pub fn mode(&self) -> u64 {
+        self.satp >> SATP_MODE_SHIFT
+    }
+
+    pub fn asid(&self) -> u64 {
+        (self.satp >> SATP_ASID_SHIFT) & SATP_ASID_MASK
+    }
+
+    pub fn root_table(&self) -> u64 {
+        (self.satp & SATP_PPN_MASK) << PAGE_SHIFT
+    }
+
+    // Translation is skipped in M-mode and when satp selects Bare
+    pub fn is_bare(&self) -> bool {
+        self.privilege == PrivilegeMode::Machine || self.mode() == SATP_MODE_BARE
+    }
+}
+
+// Number of page table levels for a satp mode, None if the mode is not supported
+pub fn levels_for_mode(mode: u64) -> Option<u64> {
+    match mode {
+        SATP_MODE_SV39 => Some(3),
+        SATP_MODE_SV48 => Some(4),
+        SATP_MODE_SV57 => Some(5),
+        _ => None,
+    }
+}
+
+// How the page walker deals with leaf PTEs that still have A=0, or D=0 on a store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessDirtyPolicy {
+    FaultOnClear,   // Svade, software manages the bits from the page fault handler
+    HardwareUpdate, // Svadu, the walker sets the bits when menvcfg.ADUE is set
+}
+
+// Checks if a leaf PTE needs its accessed or dirty bit set before the access can proceed
+pub fn needs_accessed_dirty_update(flags: PTEFlags, access: AccessType) -> bool {
+    !flags.contains(PTEFlags::A) || (access == AccessType::Store && !flags.contains(PTEFlags::D))
+}
+
+// Result of a successful page walk
+#[derive(Debug, Clone, Copy)]
+pub struct PageWalkResult {
+    pub physical_address: u64,
+    pub pte: u64,
+    pub pte_address: u64,
+    pub level: u64, // 0 for a 4 KiB page, 1 for a 2 MiB megapage, ...
+}
+
+impl PageWalkResult {
+    pub fn flags(&self) -> PTEFlags {
+        PTEFlags::from_bits_truncate(self.pte)
+    }
+}
+
+// Checks the R/W/X/U permission bits of a leaf PTE against the requested access
+pub fn check_leaf_permissions(flags: PTEFlags, access: AccessType, ctx: &TranslationContext) -> bool {
+    match ctx.privilege {
+        PrivilegeMode::User => {
+            if !flags.contains(PTEFlags::U) {
+                return false;
+            }
+        }
+        PrivilegeMode::Supervisor => {
+            if flags.contains(PTEFlags::U) {
+                // Supervisor may never execute user pages, and only touches user data with SUM set
+                if access == AccessType::Fetch || !ctx.sum {
+                    return false;
+                }
+            }
+        }
+        PrivilegeMode::Machine => {}
+    }
+
+    match access {
+        AccessType::Fetch => flags.contains(PTEFlags::X),
+        AccessType::Load => flags.contains(PTEFlags::R) || (ctx.mxr && flags.contains(PTEFlags::X)),
+        AccessType::Store => flags.contains(PTEFlags::W),
+    }
+}
+
+impl MemoryManagementUnit {
+    // Translates a virtual address into a physical address using the page tables pointed to by satp
+    pub fn translate(&self, vaddr: u64, access: AccessType, ctx: &TranslationContext) -> Result<u64, Exception> {
+        if ctx.is_bare() {
+            return Ok(vaddr);
+        }
+
+        Ok(self.translate_page(vaddr, access, ctx)?.physical_address)
+    }
+
+    // Walks the page table and enforces the accessed/dirty policy, returning the leaf for TLB refills.
+    // With hardware A/D updates enabled the returned leaf may still need commit_accessed_dirty.
+    pub fn translate_page(&self, vaddr: u64, access: AccessType, ctx: &TranslationContext) -> Result<PageWalkResult, Exception> {
+        let walk = self.walk_page_table(vaddr, access, ctx)?;
+
+        // Svade: the OS is responsible for A/D bits, so report a fault if they still need to be set
+        if needs_accessed_dirty_update(walk.flags(), access) && !ctx.hardware_ad {
+            return Err(access.page_fault());
+        }
+
+        Ok(walk)
+    }
+
+    // Svadu: sets A (and D for stores) in the leaf PTE. This is a compare-and-swap against the PTE seen
+    // by the walk, so None is returned if another hart changed it in the meantime and the walk must be redone.
+    pub fn commit_accessed_dirty(&mut self, walk: &PageWalkResult, access: AccessType) -> Option<PageWalkResult> {
+        let mut flags = PTEFlags::A;
+        if access == AccessType::Store {
+            flags |= PTEFlags::D;
+        }
+
+        let address = walk.pte_address as usize;
+
+        if self.read_double_word(address) != walk.pte {
+            return None;
+        }
+
+        let pte = walk.pte | flags.bits();
+        self.write_double_word(address, pte);
+
+        Some(PageWalkResult { pte, ..*walk })
+    }
+
+    // Walks the page table for vaddr and returns the leaf PTE, checking everything except A/D
+    pub fn walk_page_table(&self, vaddr: u64, access: AccessType, ctx: &TranslationContext) -> Result<PageWalkResult, Exception> {
+        let levels = match levels_for_mode(ctx.mode()) {
+            Some(levels) => levels,
+            None => return Err(access.page_fault()),
+        };
+
+        // Virtual addresses must be sign extended from the highest translated bit
+        let va_bits = PAGE_SHIFT + levels * VPN_BITS;
+        let upper = (vaddr as i64) >> (va_bits - 1);
+        if upper != 0 && upper != -1 {
+            return Err(access.page_fault());
+        }
+
+        let mut table = ctx.root_table();
+        let mut level = levels - 1;
+
+        loop {
+            let vpn = (vaddr >> (PAGE_SHIFT + level * VPN_BITS)) & VPN_MASK;
+            let pte_address = table + vpn * PTE_SIZE;
+
+            if !self.is_mapped(pte_address as usize, PTE_SIZE as usize) {
+                return Err(access.access_fault());
+            }
+
+            let pte = self.read_double_word(pte_address as usize);
+            let flags = PTEFlags::from_bits_truncate(pte);
+
+            if !flags.contains(PTEFlags::V)
+                || (!flags.contains(PTEFlags::R) && flags.contains(PTEFlags::W))
+                || pte & PTE_RESERVED_MASK != 0 {
+                return Err(access.page_fault());
+            }
+
+            let ppn = (pte >> PTE_PPN_SHIFT) & PTE_PPN_MASK;
+
+            if !flags.intersects(PTEFlags::R | PTEFlags::X) {
+                // Pointer to the next level of the page table
+                if level == 0 || flags.intersects(PTEFlags::A | PTEFlags::D | PTEFlags::U) {
+                    return Err(access.page_fault());
+                }
+
+                level -= 1;
+                table = ppn << PAGE_SHIFT;
+                continue;
+            }
+
+            if !check_leaf_permissions(flags, access, ctx) {
+                return Err(access.page_fault());
+            }
+
+            // Superpages must be aligned to their size
+            let superpage_mask = (1u64 << (level * VPN_BITS)) - 1;
+            if ppn & superpage_mask != 0 {
+                return Err(access.page_fault());
+            }
+
+            let offset_mask = (PAGE_SIZE << (level * VPN_BITS)) - 1;
+            let physical_address = ((ppn << PAGE_SHIFT) & !offset_mask) | (vaddr & offset_mask);
+
+            return Ok(PageWalkResult { physical_address, pte, pte_address, level });
+        }
+    }
+}
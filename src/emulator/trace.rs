@@ -0,0 +1,331 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use crate::emulator::breakpoints::parse_value;
+use crate::emulator::elf::SymbolTable;
+use crate::emulator::instructions::rv64::disasm::disassemble;
+use crate::emulator::state::rv64_cpu_context::{CSRAddress, CSRFile, Exception, PrivilegeMode, Register};
+
+// CSRs compared before and after every traced instruction to catch writes it makes as a side effect, like
+// mstatus.FS or fflags. Counters change on their own, interrupt pending bits follow the devices, and the
+// sstatus, sie and fcsr views are logged through the registers behind them
+const SIDE_EFFECT_CSRS: [CSRAddress; 30] = [
+    CSRAddress::MVendorID,
+    CSRAddress::MArchID,
+    CSRAddress::MImpID,
+    CSRAddress::MHartID,
+    CSRAddress::MStatus,
+    CSRAddress::MIsa,
+    CSRAddress::MEDeleg,
+    CSRAddress::MIDeleg,
+    CSRAddress::MIE,
+    CSRAddress::MTVec,
+    CSRAddress::MCounterEn,
+    CSRAddress::MEnvCfg,
+    CSRAddress::MScratch,
+    CSRAddress::MEPC,
+    CSRAddress::MCause,
+    CSRAddress::MTVal,
+    CSRAddress::STVec,
+    CSRAddress::SCounterEn,
+    CSRAddress::SScratch,
+    CSRAddress::SEPC,
+    CSRAddress::SCause,
+    CSRAddress::STVal,
+    CSRAddress::SATP,
+    CSRAddress::SContext,
+    CSRAddress::SStateEn0,
+    CSRAddress::SStateEn1,
+    CSRAddress::SStateEn2,
+    CSRAddress::SStateEn3,
+    CSRAddress::FRM,
+    CSRAddress::FFlags,
+];
+
+// What the instruction a hart executed last wrote and accessed, only collected while tracing
+#[derive(Clone)]
+pub struct CommitLog {
+    enabled: bool,
+    pub pc: u64,
+    pub privilege: PrivilegeMode,
+    pub cycle: usize,
+    // Fetched parcel and its length in bytes, None when the hart took an interrupt or couldn't fetch
+    pub instruction: Option<(u32, u64)>,
+    pub interrupt: Option<u64>,
+    pub retired: bool,
+    // Registers in the order they were first written, with their final value
+    pub registers: Vec<(Register, u64)>,
+    pub loads: Vec<u64>,
+    // Virtual address, value and size in bytes
+    pub stores: Vec<(u64, u64, usize)>,
+    csr_writes: Vec<u16>,
+    csr_values: Vec<u64>,
+}
+
+impl Default for CommitLog {
+    fn default() -> Self {
+        CommitLog {
+            enabled: false,
+            pc: 0,
+            privilege: PrivilegeMode::Machine,
+            cycle: 0,
+            instruction: None,
+            interrupt: None,
+            retired: false,
+            registers: Vec::new(),
+            loads: Vec::new(),
+            stores: Vec::new(),
+            csr_writes: Vec::new(),
+            csr_values: Vec::new(),
+        }
+    }
+}
+
+impl CommitLog {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    // Starts over for the next instruction of the hart
+    pub fn begin(&mut self, pc: u64, cycle: usize, csrs: &CSRFile) {
+        self.pc = pc;
+        self.privilege = csrs.get_current_privilege();
+        self.cycle = cycle;
+        self.instruction = None;
+        self.interrupt = None;
+        self.retired = false;
+        self.registers.clear();
+        self.loads.clear();
+        self.stores.clear();
+        self.csr_writes.clear();
+
+        self.csr_values.clear();
+        self.csr_values.extend(SIDE_EFFECT_CSRS.iter().map(|csr| csrs.read_csr(*csr as u16, true).unwrap_or(0)));
+    }
+
+    // The instruction completed, CSRs it wrote are logged with the value they hold now
+    pub fn retire(&mut self, csrs: &CSRFile) {
+        self.retired = true;
+
+        let changed = SIDE_EFFECT_CSRS.iter().zip(self.csr_values.iter())
+            .map(|(csr, old)| (*csr as u16, *old))
+            .filter(|(address, old)| csrs.read_csr(*address, true).unwrap_or(0) != *old)
+            .map(|(address, _)| address);
+
+        let written: Vec<u16> = self.csr_writes.iter().copied().chain(changed).collect();
+
+        for address in written {
+            self.register(Register::Csr(address), csrs.read_csr(address, true).unwrap_or(0));
+        }
+    }
+
+    #[inline(always)]
+    pub fn register(&mut self, register: Register, value: u64) {
+        if !self.enabled {
+            return;
+        }
+
+        match self.registers.iter_mut().find(|(written, _)| *written == register) {
+            Some(entry) => entry.1 = value,
+            None => self.registers.push((register, value)),
+        }
+    }
+
+    // Explicit write by a CSR instruction, logged even if the value didn't change
+    pub fn csr(&mut self, address: u16) {
+        if self.enabled {
+            self.csr_writes.push(address);
+        }
+    }
+
+    #[inline(always)]
+    pub fn load(&mut self, vaddr: u64) {
+        if self.enabled {
+            self.loads.push(vaddr);
+        }
+    }
+
+    #[inline(always)]
+    pub fn store(&mut self, vaddr: u64, size: usize, value: u64) {
+        if self.enabled {
+            self.stores.push((vaddr, value, size));
+        }
+    }
+}
+
+// start:end with end excluded, either side can be left out, e.g. 0x80000000:0x80001000 or 1000:
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl TraceRange {
+    pub fn contains(&self, value: u64) -> bool {
+        self.start <= value && value < self.end
+    }
+}
+
+impl FromStr for TraceRange {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid range '{}', expected <start>:<end>", value);
+
+        let (start, end) = value.split_once(':').ok_or_else(invalid)?;
+
+        let start = match start {
+            "" => 0,
+            start => parse_value(start).ok_or_else(invalid)?,
+        };
+        let end = match end {
+            "" => u64::MAX,
+            end => parse_value(end).ok_or_else(invalid)?,
+        };
+
+        Ok(TraceRange { start, end })
+    }
+}
+
+// Writes what every hart executes in the format of Spike's -l --log-commits: a disassembly line for every
+// instruction, followed by a commit line with its register, CSR and memory writes once it retired, or by
+// the trap it raised
+pub struct Tracer {
+    output: Box<dyn Write + Send>,
+    pc_range: Option<TraceRange>,
+    cycle_range: Option<TraceRange>,
+}
+
+impl Tracer {
+    pub fn new(output: Box<dyn Write + Send>) -> Self {
+        Tracer { output, pc_range: None, cycle_range: None }
+    }
+
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self::new(Box::new(BufWriter::new(File::create(path)?))))
+    }
+
+    // Only instructions at a pc in range are traced
+    pub fn set_pc_range(&mut self, range: Option<TraceRange>) {
+        self.pc_range = range;
+    }
+
+    // Only instructions whose number is in range are traced, they are counted per hart from 0
+    pub fn set_cycle_range(&mut self, range: Option<TraceRange>) {
+        self.cycle_range = range;
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+
+    // Logs the last step of a hart, trap is the exception it raised if any and the trap value it came with
+    pub(crate) fn trace(&mut self, hart_id: usize, commit: &CommitLog, trap: Option<(Exception, u64)>, symbols: &SymbolTable) -> io::Result<()> {
+        let in_range = self.pc_range.is_none_or(|range| range.contains(commit.pc))
+            && self.cycle_range.is_none_or(|range| range.contains(commit.cycle as u64));
+
+        if !in_range {
+            return Ok(());
+        }
+
+        if let Some(interrupt) = commit.interrupt {
+            return writeln!(self.output, "core {:3}: exception interrupt #{}, epc 0x{:016x}", hart_id, interrupt, commit.pc);
+        }
+
+        let Some((instr, length)) = commit.instruction else {
+            return match trap {
+                Some((exception, tval)) => self.write_trap(hart_id, commit.pc, exception, tval),
+                None => Ok(()),
+            };
+        };
+
+        if let Some((symbol, 0)) = symbols.lookup(commit.pc) {
+            writeln!(self.output, "core {:3}: >>>>  {}", hart_id, symbol.name)?;
+        }
+
+        writeln!(self.output, "core {:3}: 0x{:016x} (0x{:08x}) {}", hart_id, commit.pc, instr, disassemble(instr, commit.pc))?;
+
+        if let Some((exception, tval)) = trap {
+            return self.write_trap(hart_id, commit.pc, exception, tval);
+        }
+
+        if commit.retired {
+            self.write_commit(hart_id, commit, instr, length)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_commit(&mut self, hart_id: usize, commit: &CommitLog, instr: u32, length: u64) -> io::Result<()> {
+        let mut line = format!("core{:4}: {} 0x{:016x} ({})", hart_id, commit.privilege as u8, commit.pc, hex(instr as u64, length as usize));
+
+        for (register, value) in commit.registers.iter() {
+            match register {
+                Register::X(index) => line += &format!(" x{:<2} 0x{:016x}", index, value),
+                Register::F(index) => line += &format!(" f{:<2} 0x{:016x}", index, value),
+                Register::Csr(address) => line += &format!(" c{}_{} 0x{:016x}", address, csr_name(*address), value),
+                Register::Pc => {}
+            }
+        }
+
+        for vaddr in commit.loads.iter() {
+            line += &format!(" mem 0x{:016x}", vaddr);
+        }
+
+        for (vaddr, value, size) in commit.stores.iter() {
+            line += &format!(" mem 0x{:016x} {}", vaddr, hex(*value, *size));
+        }
+
+        writeln!(self.output, "{}", line)
+    }
+
+    fn write_trap(&mut self, hart_id: usize, epc: u64, exception: Exception, tval: u64) -> io::Result<()> {
+        writeln!(self.output, "core {:3}: exception {}, epc 0x{:016x}", hart_id, trap_name(exception), epc)?;
+
+        // Environment calls are the only traps without a trap value
+        if !matches!(exception, Exception::EnvironmentCallFromUMode | Exception::EnvironmentCallFromSMode | Exception::EnvironmentCallFromMMode) {
+            writeln!(self.output, "core {:3}:           tval 0x{:016x}", hart_id, tval)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Zero padded to size bytes
+fn hex(value: u64, size: usize) -> String {
+    let value = match size {
+        8 => value,
+        _ => value & ((1u64 << (size * 8)) - 1),
+    };
+
+    format!("0x{:0width$x}", value, width = size * 2)
+}
+
+fn csr_name(address: u16) -> &'static str {
+    CSRAddress::ALL.iter().find(|csr| **csr as u16 == address).map_or("unknown", |csr| csr.name())
+}
+
+// Names Spike gives the exceptions
+fn trap_name(exception: Exception) -> &'static str {
+    match exception {
+        Exception::InstructionAddressMisaligned => "trap_instruction_address_misaligned",
+        Exception::InstructionAccessFault => "trap_instruction_access_fault",
+        Exception::IllegalInstruction => "trap_illegal_instruction",
+        Exception::Breakpoint => "trap_breakpoint",
+        Exception::LoadAddressMisaligned => "trap_load_address_misaligned",
+        Exception::LoadAccessFault => "trap_load_access_fault",
+        Exception::StoreAddressMisaligned => "trap_store_address_misaligned",
+        Exception::StoreAccessFault => "trap_store_access_fault",
+        Exception::EnvironmentCallFromUMode => "trap_user_ecall",
+        Exception::EnvironmentCallFromSMode => "trap_supervisor_ecall",
+        Exception::EnvironmentCallFromMMode => "trap_machine_ecall",
+        Exception::InstructionPageFault => "trap_instruction_page_fault",
+        Exception::LoadPageFault => "trap_load_page_fault",
+        Exception::StorePageFault => "trap_store_page_fault",
+    }
+}
@@ -1,27 +1,219 @@
 pub mod emulator;
 pub mod tests;
 
-use clap::Parser;
+use std::fs;
+use std::path::Path;
+use clap::{Parser, Subcommand};
+use crate::emulator::devices::uart::UartBackend;
+use crate::emulator::boot::{BootError, LinuxBoot};
+use crate::emulator::dtb::{parse_blob, DtbError, DtbNode};
+use crate::emulator::elf::{is_elf_file, ElfError, ElfImage};
+use crate::emulator::gdb::{self, GdbTarget};
+use crate::emulator::history::{SNAPSHOT_INTERVAL, SNAPSHOT_LIMIT};
+use crate::emulator::instructions::rv64::disasm::listing;
+use crate::emulator::devices::clint::CLINT_MAX_HARTS;
 use crate::emulator::interpreter::{Interpreter, RV64Platform};
+use crate::emulator::state::translation::AccessDirtyPolicy;
+use crate::emulator::trace::{TraceRange, Tracer};
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     //Memory size in MB
-    #[arg(short, long, help = "Memory size in MB")]
-    memory_size: usize,
+    #[arg(short, long, required = true, help = "Memory size in MB")]
+    memory_size: Option<usize>,
+
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..=CLINT_MAX_HARTS), help = "Number of harts, they are run round-robin")]
+    harts: u64,
+
+    #[arg(short, long, required_unless_present_any = ["kernel", "dump_dtb"], help = "Raw or ELF image to run on bare metal")]
+    image_path: Option<String>,
+
+    #[arg(long, requires = "kernel", help = "Firmware started in M-mode at the beginning of RAM, e.g. OpenSBI fw_jump")]
+    firmware: Option<String>,
+
+    #[arg(long, conflicts_with = "image_path", help = "Linux kernel Image loaded 2 MiB into RAM, SBI calls are handled by the emulator unless --firmware is given")]
+    kernel: Option<String>,
+
+    #[arg(long, requires = "kernel", help = "Initial ramdisk for the kernel")]
+    initrd: Option<String>,
+
+    #[arg(long, requires = "kernel", help = "Kernel command line")]
+    cmdline: Option<String>,
+
+    #[arg(long, help = "Set PTE accessed/dirty bits in hardware (Svadu) instead of raising page faults")]
+    svadu: bool,
+
+    #[arg(long, default_value = "stdio", help = "UART console backend: stdio, file:<path> or unix:<path>")]
+    uart: UartBackend,
+
+    #[arg(long, help = "Pass this device tree blob to the guest instead of the generated one")]
+    dtb: Option<String>,
+
+    #[arg(long, help = "Write the device tree blob passed to the guest to this file and exit")]
+    dump_dtb: Option<String>,
 
-    #[arg(short, long)]
-    image_path: String,
+    #[arg(long, help = "Wait for gdb on a TCP port, <host>:<port> or unix:<path> instead of starting the interactive debugger")]
+    gdb: Option<GdbTarget>,
+
+    #[arg(long, conflicts_with = "gdb", help = "Run debugger commands from this file instead of starting the interactive debugger, exits with 1 if an expect command failed")]
+    script: Option<String>,
+
+    #[arg(long, help = "Stop the debugger's continue, run and until when a hart raises an exception, like its catch command")]
+    stop_on_trap: bool,
+
+    #[arg(long, help = "Record execution history from the start so the debugger and gdb can step backwards, costs memory and speed")]
+    record: bool,
+
+    #[arg(long, help = "Write a trace of every executed instruction to this file, in the format of Spike's -l --log-commits")]
+    trace: Option<String>,
+
+    #[arg(long, requires = "trace", help = "Only trace instructions at a pc in <start>:<end>, end excluded")]
+    trace_pc: Option<TraceRange>,
+
+    #[arg(long, requires = "trace", help = "Only trace instructions whose number, counted per hart from 0, is in <start>:<end>, end excluded")]
+    trace_cycles: Option<TraceRange>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[command(about = "Disassemble the executable segments of an ELF file and exit")]
+    Disasm {
+        elf: String,
+    },
 }
 
 fn main() {
     let args = Args::parse();
 
-    let mut interpreter = RV64Platform::new(0x1000, (args.memory_size * 1024 * 1024) as u64);
-    interpreter.load_disk_image(&args.image_path);
+    if let Some(Command::Disasm { elf }) = &args.command {
+        if let Err(e) = disassemble_elf(elf) {
+            eprintln!("Failed to disassemble {}: {}", elf, e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let memory_size = args.memory_size.expect("clap requires a memory size");
+
+    let mut interpreter = RV64Platform::new(args.harts, (memory_size * 1024 * 1024) as u64);
+    interpreter.attach_uart(&args.uart).expect("Failed to open UART backend");
+
+    let device_tree = match &args.dtb {
+        Some(path) => parse_blob(&fs::read(path).expect("Failed to read device tree")),
+        None => Ok(interpreter.generate_device_tree()),
+    };
+
+    let result = match (&args.kernel, &args.image_path) {
+        (Some(kernel), _) => {
+            let boot = LinuxBoot {
+                firmware: args.firmware.clone(),
+                kernel: kernel.clone(),
+                initrd: args.initrd.clone(),
+                cmdline: args.cmdline.clone(),
+            };
+
+            device_tree.map_err(BootError::from).and_then(|tree| interpreter.boot_linux(&boot, tree))
+        }
+        (None, Some(image_path)) => load_bare_metal(&mut interpreter, image_path, device_tree),
+        (None, None) if args.dump_dtb.is_some() => device_tree.and_then(|tree| interpreter.load_device_tree(&tree)).map(|_| ()).map_err(BootError::from),
+        (None, None) => unreachable!("clap requires an image or a kernel"),
+    };
+
+    if let Err(e) = result {
+        eprintln!("Failed to boot: {}", e);
+        std::process::exit(1);
+    }
+
+    //Dumped after booting so it has the /chosen properties the guest gets
+    if let Some(path) = &args.dump_dtb {
+        fs::write(path, interpreter.device_tree_blob().expect("booting loads a device tree")).expect("Failed to write device tree");
+        return;
+    }
+
+    if args.svadu {
+        interpreter.set_access_dirty_policy(AccessDirtyPolicy::HardwareUpdate);
+    }
+
+    if args.stop_on_trap {
+        interpreter.set_stop_on_trap(true);
+    }
+
+    if args.record {
+        interpreter.start_recording(SNAPSHOT_INTERVAL, SNAPSHOT_LIMIT);
+    }
+
+    if let Some(path) = &args.trace {
+        let mut tracer = Tracer::create(Path::new(path)).expect("Failed to create trace file");
+        tracer.set_pc_range(args.trace_pc);
+        tracer.set_cycle_range(args.trace_cycles);
+
+        interpreter.start_trace(tracer);
+    }
+
+    let success = match (&args.gdb, &args.script) {
+        (Some(target), _) => match gdb::serve(&mut interpreter, target) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("GDB stub failed: {}", e);
+                false
+            }
+        },
+        (None, Some(script)) => match interpreter.run_script(Path::new(script), |_cycle| {}) {
+            Ok(0) => true,
+            Ok(failures) => {
+                eprintln!("{} expectation(s) failed", failures);
+                false
+            }
+            Err(e) => {
+                eprintln!("Failed to read script {}: {}", script, e);
+                false
+            }
+        },
+        (None, None) => {
+            interpreter.debug_loop(|_cycle| {
+
+            });
+            true
+        }
+    };
+
+    //Flushes the trace file, exiting doesn't
+    interpreter.stop_trace();
+
+    if !success {
+        std::process::exit(1);
+    }
+}
+
+// Runs an image without firmware, the device tree is still passed in a1
+fn load_bare_metal(interpreter: &mut RV64Platform, image_path: &str, device_tree: Result<DtbNode, DtbError>) -> Result<(), BootError> {
+    if is_elf_file(Path::new(image_path)) {
+        interpreter.load_elf(image_path)?;
+    } else {
+        interpreter.load_disk_image(image_path);
+    }
+
+    interpreter.load_device_tree(&device_tree?)?;
+
+    Ok(())
+}
+
+fn disassemble_elf(path: &str) -> Result<(), ElfError> {
+    let image = ElfImage::from_file(Path::new(path))?;
+
+    for segment in image.segments.iter().filter(|segment| segment.is_executable()) {
+        println!("Segment at {:#x}:", segment.vaddr);
+
+        for line in listing(image.segment_data(segment)?, segment.vaddr, &image.symbols) {
+            println!("{}", line);
+        }
 
-    interpreter.debug_loop(|_cycle| {
+        println!();
+    }
 
-    });
+    Ok(())
 }
@@ -1 +1,20 @@
-pub mod test_instructions;
\ No newline at end of file
+pub mod test_instructions;
+pub mod test_mmu;
+pub mod test_softfloat;
+pub mod test_traps;
+pub mod test_clint;
+pub mod test_plic;
+pub mod test_uart;
+pub mod test_elf;
+pub mod test_dtb;
+pub mod test_boot;
+pub mod test_sbi;
+pub mod test_smp;
+pub mod test_gdb;
+pub mod test_disasm;
+pub mod test_breakpoints;
+pub mod test_inspect;
+pub mod test_script;
+pub mod test_reverse;
+pub mod test_trace;
+pub mod helpers;
@@ -0,0 +1,36 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::emulator::constants::DRAM_BASE;
+use crate::emulator::interpreter::RV64Platform;
+use crate::emulator::state::rv64_cpu_context::{CSRAddress, RV64CPUContext};
+
+// Cases run in parallel, every temporary file gets a name of its own
+static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+// Puts the instructions at DRAM_BASE, where the harts start
+pub fn write_program(platform: &RV64Platform, program: &[u32]) {
+    let bytes: Vec<u8> = program.iter().flat_map(|instr| instr.to_le_bytes()).collect();
+    platform.write_memory(0, DRAM_BASE, true, &bytes).unwrap();
+}
+
+pub fn mip(hart: &RV64CPUContext) -> u64 {
+    hart.csrs.read_csr(CSRAddress::MIP as u16, true).unwrap()
+}
+
+pub fn temp_path(name: &str, extension: &str) -> PathBuf {
+    let id = TEMP_FILES.fetch_add(1, Ordering::SeqCst);
+
+    std::env::temp_dir().join(format!("rocket-v-{}-{}-{}.{}", name, std::process::id(), id, extension))
+}
+
+// Runs the debugger commands in script, returns how many expectations failed
+pub fn run_script(platform: &mut RV64Platform, script: &str) -> usize {
+    let path = temp_path("script", "txt");
+    fs::write(&path, script).unwrap();
+
+    let failures = platform.run_script(&path, |_cycle| {}).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    failures
+}
@@ -0,0 +1,70 @@
+use std::path::PathBuf;
+use rstest::rstest;
+use crate::emulator::boot::{initrd_address, set_chosen, BootError, LinuxBoot, KERNEL_BASE};
+use crate::emulator::constants::DRAM_BASE;
+use crate::emulator::dtb::{parse_blob, DtbNode};
+use crate::emulator::interpreter::RV64Platform;
+use crate::tests::helpers::temp_path;
+
+fn write_temp(name: &str, size: usize) -> PathBuf {
+    let path = temp_path(&format!("boot-{}", name), "bin");
+    std::fs::write(&path, vec![0x13; size]).unwrap();
+    path
+}
+
+#[rstest]
+#[case::small_machine(64 << 20, KERNEL_BASE + (32 << 20))]
+#[case::large_machine(1 << 30, KERNEL_BASE + (128 << 20))]
+pub fn test_initrd_address(#[case] memory_size: u64, #[case] address: u64) {
+    assert_eq!(initrd_address(KERNEL_BASE, memory_size), address);
+}
+
+#[rstest]
+pub fn test_set_chosen() {
+    let mut tree = DtbNode::new("");
+    tree.add_child(DtbNode::new("chosen")).set_string("stdout-path", "/soc/serial@10000000");
+
+    set_chosen(&mut tree, Some("console=ttyS0"), Some((0x8400_0000, 0x8410_0000)));
+
+    // The existing node is amended instead of adding a second one
+    assert_eq!(tree.children.len(), 1);
+
+    let chosen = tree.find("/chosen").unwrap();
+    assert_eq!(chosen.get_property("bootargs"), Some(&b"console=ttyS0\0"[..]));
+    assert_eq!(chosen.get_property("linux,initrd-start"), Some(&[0, 0, 0, 0, 0x84, 0, 0, 0][..]));
+    assert_eq!(chosen.get_property("linux,initrd-end"), Some(&[0, 0, 0, 0, 0x84, 0x10, 0, 0][..]));
+    assert!(chosen.get_property("stdout-path").is_some());
+}
+
+#[rstest]
+#[case::fits(0x1000, Ok(()))]
+#[case::initrd_too_large(0x200_0000, Err(()))]
+pub fn test_boot_linux(#[case] initrd_size: usize, #[case] result: Result<(), ()>) {
+    let firmware = write_temp("fw", 0x100);
+    let kernel = write_temp("kernel", 0x1000);
+    let initrd = write_temp(&format!("initrd-{:x}", initrd_size), initrd_size);
+
+    let mut platform = RV64Platform::new(1, 0x400_0000);
+    let tree = platform.generate_device_tree();
+
+    let boot = LinuxBoot {
+        firmware: Some(firmware.to_string_lossy().into_owned()),
+        kernel: kernel.to_string_lossy().into_owned(),
+        initrd: Some(initrd.to_string_lossy().into_owned()),
+        cmdline: Some("console=ttyS0".to_string()),
+    };
+
+    match (platform.boot_linux(&boot, tree), result) {
+        (Ok(()), Ok(())) => {
+            // What the guest gets has the command line and initrd
+            let tree = parse_blob(platform.device_tree_blob().unwrap()).unwrap();
+            assert_eq!(tree.find("/chosen").unwrap().get_property("bootargs"), Some(&b"console=ttyS0\0"[..]));
+        }
+        (Err(BootError::DoesNotFit { addr, .. }), Err(())) => assert!(addr > DRAM_BASE),
+        (other, _) => panic!("unexpected boot result {:?}", other),
+    }
+
+    for path in [firmware, kernel, initrd] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
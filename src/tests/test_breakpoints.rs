@@ -0,0 +1,145 @@
+use rstest::rstest;
+use crate::emulator::breakpoints::{Comparison, Condition, Operand};
+use crate::emulator::constants::DRAM_BASE;
+use crate::emulator::interpreter::RV64Platform;
+use crate::tests::helpers::write_program;
+use crate::emulator::state::memory::{WatchKind, Watchpoint};
+use crate::emulator::state::rv64_cpu_context::Register;
+
+// Counting loop: a0 is incremented every time pc reaches DRAM_BASE
+fn counting_loop() -> RV64Platform {
+    let platform = RV64Platform::new(1, 0x10_0000);
+    write_program(&platform, &[
+        0x00150513, // addi a0, a0, 1
+        0xffdff06f, // j -4
+    ]);
+
+    platform
+}
+
+// Steps until a breakpoint stops the machine, returns the breakpoint and the number of steps
+fn run_to_breakpoint(platform: &mut RV64Platform) -> Option<(usize, usize)> {
+    for steps in 1..=100 {
+        platform.step().unwrap();
+
+        if let Some((_, id)) = platform.hart_at_breakpoint() {
+            return Some((id, steps));
+        }
+    }
+
+    None
+}
+
+#[rstest]
+#[case::register("a0 == 0x10", Ok(Condition { operand: Operand::Register(10), comparison: Comparison::Eq, value: 0x10 }))]
+#[case::numbered_register("x31 >= 7", Ok(Condition { operand: Operand::Register(31), comparison: Comparison::Ge, value: 7 }))]
+#[case::frame_pointer("fp != -1", Ok(Condition { operand: Operand::Register(8), comparison: Comparison::Ne, value: u64::MAX }))]
+#[case::hits("hits < 3", Ok(Condition { operand: Operand::Hits, comparison: Comparison::Lt, value: 3 }))]
+#[case::unknown_register("x32 == 1", Err(()))]
+#[case::unknown_comparison("a0 =~ 1", Err(()))]
+#[case::missing_value("a0 ==", Err(()))]
+pub fn test_parse_condition(#[case] condition: &str, #[case] expected: Result<Condition, ()>) {
+    assert_eq!(condition.parse::<Condition>().map_err(|_| ()), expected);
+}
+
+#[rstest]
+#[case::unconditional(None, 2, 1)]
+#[case::hit_count(Some("hits == 3"), 6, 3)]
+#[case::register(Some("a0 >= 5"), 10, 5)]
+pub fn test_conditional_breakpoint(#[case] condition: Option<&str>, #[case] expected_steps: usize, #[case] expected_a0: u64) {
+    let platform = &mut counting_loop();
+
+    let id = platform.add_breakpoint(DRAM_BASE);
+    platform.set_breakpoint_condition(id, condition.map(|condition| condition.parse().unwrap()));
+
+    assert_eq!(run_to_breakpoint(platform), Some((id, expected_steps)));
+    assert_eq!(platform.get_cpu_context(0).x[10], expected_a0);
+    assert_eq!(platform.get_breakpoints().get(id).unwrap().hits, expected_a0);
+}
+
+#[rstest]
+pub fn test_disable_and_delete_breakpoint() {
+    let platform = &mut counting_loop();
+
+    let first = platform.add_breakpoint(DRAM_BASE);
+    let second = platform.add_breakpoint(DRAM_BASE + 4);
+
+    assert!(platform.set_breakpoint_enabled(first, false));
+    assert_eq!(run_to_breakpoint(platform), Some((second, 1)));
+
+    assert!(platform.delete_breakpoint(second));
+    assert!(!platform.delete_breakpoint(second));
+    assert_eq!(run_to_breakpoint(platform), None);
+
+    // Disabled breakpoints don't count hits either
+    assert_eq!(platform.get_breakpoints().get(first).unwrap().hits, 0);
+
+    assert!(platform.set_breakpoint_enabled(first, true));
+    assert_eq!(run_to_breakpoint(platform).map(|(id, _)| id), Some(first));
+}
+
+#[rstest]
+pub fn test_conditional_watchpoint() {
+    let platform = &mut RV64Platform::new(1, 0x10_0000);
+    write_program(platform, &[
+        0x00001517, // auipc a0, 1
+        0x00b52023, // sw a1, 0(a0)
+        0xffdff06f, // j -4
+    ]);
+
+    let watchpoint = Watchpoint { addr: DRAM_BASE + 0x1000, len: 4, kind: WatchKind::Write };
+    let id = platform.add_debugger_watchpoint(watchpoint, None, Some("hits == 2".parse().unwrap()));
+
+    let mut stops = Vec::new();
+    for steps in 1..=10 {
+        platform.step().unwrap();
+
+        if let Some((hart_id, hit_id, hit)) = platform.watchpoint_hit() {
+            stops.push((steps, hart_id, hit_id, hit.addr, hit.write));
+        }
+    }
+
+    // The store retires on steps 2, 4, 6 and so on, only the second one satisfies the condition
+    assert_eq!(stops, [(4, 0, id, DRAM_BASE + 0x1000, true)]);
+}
+
+#[rstest]
+pub fn test_shared_watchpoint_range() {
+    let platform = &mut RV64Platform::new(1, 0x10_0000);
+    let watchpoint = Watchpoint { addr: DRAM_BASE, len: 8, kind: WatchKind::Access };
+    let watched = |platform: &RV64Platform| platform.get_cpu_context(0).memory.read().unwrap().get_watchpoints().to_vec();
+
+    let first = platform.add_debugger_watchpoint(watchpoint, None, None);
+    let second = platform.add_debugger_watchpoint(watchpoint, Some(0x1000), None);
+    assert_eq!(watched(platform), [watchpoint]);
+
+    // The range stays watched while any enabled breakpoint still needs it
+    platform.set_breakpoint_enabled(first, false);
+    assert_eq!(watched(platform), [watchpoint]);
+
+    platform.delete_breakpoint(second);
+    assert!(watched(platform).is_empty());
+
+    platform.set_breakpoint_enabled(first, true);
+    assert_eq!(watched(platform), [watchpoint]);
+}
+
+#[rstest]
+pub fn test_register_watch() {
+    let platform = &mut counting_loop();
+
+    let id = platform.add_register_watch(0, Register::X(10), Some("a0 >= 2".parse().unwrap())).unwrap();
+
+    let mut stops = Vec::new();
+    for steps in 1..=6 {
+        platform.step().unwrap();
+
+        if let Some(hit) = platform.register_watch_hit() {
+            stops.push((steps, hit));
+        }
+    }
+
+    // a0 changes on steps 1, 3 and 5, the first change doesn't satisfy the condition
+    assert_eq!(stops, [(3, (0, id, 1, 2)), (5, (0, id, 2, 3))]);
+    assert_eq!(platform.get_breakpoints().get(id).unwrap().hits, 3);
+}
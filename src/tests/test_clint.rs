@@ -0,0 +1,130 @@
+use std::sync::Arc;
+use rstest::rstest;
+use crate::emulator::devices::clint::{Clint, ClintState, CLINT_BASE, CLINT_SIZE};
+use crate::emulator::state::memory::MemoryManagementUnit;
+use crate::emulator::state::rv64_cpu_context::{CSRAddress, MIPFlags, PrivilegeMode, RV64CPUContext};
+use crate::tests::helpers::mip;
+
+// Two harts sharing one CLINT, mapped like on the real platform
+fn setup_clint() -> (Vec<RV64CPUContext>, Arc<ClintState>) {
+    let mmu = MemoryManagementUnit::new_guard(0x1000);
+    let mut harts = vec![RV64CPUContext::new(0, mmu.clone()), RV64CPUContext::new(0, mmu.clone())];
+
+    let clint = ClintState::new(harts.iter().map(|hart| hart.csrs.get_interrupt_lines()).collect());
+    mmu.write().unwrap().add_region(CLINT_BASE, CLINT_SIZE, Box::new(Clint::new(clint.clone())));
+
+    for hart in harts.iter_mut() {
+        hart.csrs.set_time_source(clint.get_mtime());
+    }
+
+    (harts, clint)
+}
+
+#[rstest]
+#[case::hart0(0)]
+#[case::hart1(1)]
+pub fn test_clint_msip(#[case] hart_id: usize) {
+    let (harts, clint) = setup_clint();
+
+    harts[0].memory.write().unwrap().write_word(CLINT_BASE + 4 * hart_id, 1);
+
+    assert!(clint.read_msip(hart_id));
+    assert_eq!(mip(&harts[hart_id]), MIPFlags::MSIP.bits());
+    assert_eq!(mip(&harts[1 - hart_id]), 0);
+
+    harts[0].memory.write().unwrap().write_word(CLINT_BASE + 4 * hart_id, 0);
+
+    assert_eq!(mip(&harts[hart_id]), 0);
+}
+
+#[rstest]
+#[case::not_expired(100, 50, false)]
+#[case::expired(100, 100, true)]
+#[case::reset_value(u64::MAX, 1 << 40, false)]
+pub fn test_clint_mtimecmp(#[case] mtimecmp: u64, #[case] mtime: u64, #[case] pending: bool) {
+    let (harts, _clint) = setup_clint();
+
+    {
+        let mut memory = harts[0].memory.write().unwrap();
+
+        if mtimecmp != u64::MAX {
+            memory.write_double_word(CLINT_BASE + 0x4000 + 8, mtimecmp);
+        }
+
+        memory.write_double_word(CLINT_BASE + 0xBFF8, mtime);
+    }
+
+    assert_eq!(mip(&harts[1]) & MIPFlags::MTIP.bits() != 0, pending);
+    assert_eq!(mip(&harts[0]), 0);
+}
+
+#[test]
+pub fn test_clint_split_access() {
+    let (harts, clint) = setup_clint();
+
+    {
+        let mut memory = harts[0].memory.write().unwrap();
+        // 32-bit firmware writes the halves separately
+        memory.write_word(CLINT_BASE + 0xBFF8, 0x89abcdef);
+        memory.write_word(CLINT_BASE + 0xBFFC, 0x01234567);
+    }
+
+    assert_eq!(clint.read_mtime(), 0x0123456789abcdef);
+    assert_eq!(harts[0].memory.read().unwrap().read_word(CLINT_BASE + 0xBFFC), 0x01234567);
+    assert_eq!(harts[0].memory.read().unwrap().read_double_word(CLINT_BASE + 0x4000), u64::MAX);
+}
+
+#[test]
+pub fn test_clint_time_csr() {
+    let (mut harts, clint) = setup_clint();
+
+    clint.write_mtime(41);
+    clint.tick();
+
+    assert_eq!(harts[1].csrs.read_csr(CSRAddress::Time as u16, false).unwrap(), 42);
+
+    // Lower privileges need the TM bit in the counter enables
+    harts[1].csrs.change_privilege(PrivilegeMode::Supervisor);
+    assert!(harts[1].csrs.read_csr(CSRAddress::Time as u16, false).is_err());
+
+    harts[1].csrs.write_csr(CSRAddress::MCounterEn as u16, 0b010, true).unwrap();
+    assert_eq!(harts[1].csrs.read_csr(CSRAddress::Time as u16, false).unwrap(), 42);
+}
+
+#[test]
+pub fn test_clint_skip_to_deadline() {
+    let (harts, clint) = setup_clint();
+
+    clint.write_mtimecmp(0, 5000);
+    clint.write_mtimecmp(1, 3000);
+    clint.skip_to_next_deadline();
+
+    assert_eq!(clint.read_mtime(), 3000);
+    assert_eq!(mip(&harts[1]), MIPFlags::MTIP.bits());
+    assert_eq!(mip(&harts[0]), 0);
+
+    clint.skip_to_next_deadline();
+
+    assert_eq!(clint.read_mtime(), 5000);
+    assert_eq!(mip(&harts[0]), MIPFlags::MTIP.bits());
+}
+
+#[test]
+pub fn test_clint_bulk_access() {
+    let (harts, clint) = setup_clint();
+    let bytes: Vec<u8> = [0x1122334455667788u64, 0x99aabbccddeeff00].iter().flat_map(|value| value.to_le_bytes()).collect();
+
+    // The debugger accesses several registers at once, even at unaligned addresses
+    harts[0].memory.write().unwrap().write(CLINT_BASE + 0x4000, bytes.len(), &bytes);
+    clint.write_msip(1, true);
+
+    assert_eq!(clint.read_mtimecmp(0), 0x1122334455667788);
+    assert_eq!(clint.read_mtimecmp(1), 0x99aabbccddeeff00);
+
+    let mut buf = [0; 8];
+    harts[0].memory.read().unwrap().read(CLINT_BASE, 8, &mut buf);
+    assert_eq!(buf, [0, 0, 0, 0, 1, 0, 0, 0]);
+
+    harts[0].memory.read().unwrap().read(CLINT_BASE + 0x4006, 4, &mut buf[..4]);
+    assert_eq!(buf[..4], [0x22, 0x11, 0x00, 0xff]);
+}
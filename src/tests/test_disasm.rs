@@ -0,0 +1,121 @@
+use rstest::rstest;
+use crate::emulator::constants::DRAM_BASE;
+use crate::emulator::elf::{Symbol, SymbolKind, SymbolTable};
+use crate::emulator::instructions::rv64::disasm::{disassemble, format_instruction, listing, read_instruction};
+use crate::emulator::interpreter::RV64Platform;
+
+#[rstest]
+#[case::add(0x00c58533, "add     a0, a1, a2")]
+#[case::neg(0x40c00533, "neg     a0, a2")]
+#[case::snez(0x00b03533, "snez    a0, a1")]
+#[case::negw(0x40b0053b, "negw    a0, a1")]
+#[case::nop(0x00000013, "nop")]
+#[case::li(0xffb00513, "li      a0, -5")]
+#[case::mv(0x00058513, "mv      a0, a1")]
+#[case::not(0xfff5c513, "not     a0, a1")]
+#[case::seqz(0x0015b513, "seqz    a0, a1")]
+#[case::srai(0x4215d513, "srai    a0, a1, 33")]
+#[case::sext_w(0x0005851b, "sext.w  a0, a1")]
+#[case::lui(0x12345537, "lui     a0, 0x12345")]
+#[case::ret(0x00008067, "ret")]
+#[case::jalr(0x00878567, "jalr    a0, 8(a5)")]
+#[case::ld(0x00813083, "ld      ra, 8(sp)")]
+#[case::sd(0xfe113823, "sd      ra, -16(sp)")]
+#[case::fence(0x0ff0000f, "fence")]
+#[case::fence_sets(0x0210000f, "fence   r, w")]
+#[case::fence_tso(0x8330000f, "fence.tso")]
+#[case::sfence_vma(0x12b50073, "sfence.vma a0, a1")]
+#[case::csrr(0x30002573, "csrr    a0, mstatus")]
+#[case::csrw(0x18051073, "csrw    satp, a0")]
+#[case::csrrsi(0x30046573, "csrrsi  a0, mstatus, 8")]
+#[case::unknown_csr(0x7c002573, "csrr    a0, 0x7c0")]
+#[case::lr(0x1405b52f, "lr.d.aq a0, (a1)")]
+#[case::amoadd(0x06c5b52f, "amoadd.d.aqrl a0, a2, (a1)")]
+#[case::fadd_dynamic(0x00c5f553, "fadd.s  fa0, fa1, fa2")]
+#[case::fadd_static(0x02c59553, "fadd.d  fa0, fa1, fa2, rtz")]
+#[case::fneg(0x20b59553, "fneg.s  fa0, fa1")]
+#[case::fcvt_d_s(0x42058553, "fcvt.d.s fa0, fa1")]
+#[case::fcvt_w_s(0xc0051553, "fcvt.w.s a0, fa0, rtz")]
+#[case::fmv_x_w(0xe0050553, "fmv.x.w a0, fa0")]
+#[case::fnmsub(0x68c5854b, "fnmsub.s fa0, fa1, fa2, fa3, rne")]
+#[case::fsd(0xfe843c27, "fsd     fs0, -8(s0)")]
+#[case::compressed_mv(0x852e, "mv      a0, a1")]
+#[case::compressed_addi16sp(0x7139, "addi    sp, sp, -64")]
+#[case::compressed_ebreak(0x9002, "ebreak")]
+#[case::unimp(0x0000, "unimp")]
+#[case::reserved_compressed(0x8002, ".2byte  0x8002")]
+#[case::illegal(0xffffffff, ".4byte  0xffffffff")]
+pub fn test_disassemble(#[case] instr: u32, #[case] expected: &str) {
+    assert_eq!(disassemble(instr, DRAM_BASE).to_string(), expected);
+}
+
+#[rstest]
+#[case::jump(0x0080006f, "j       0x80000008", 0x8000_0008)]
+#[case::call_backwards(0xff9ff0ef, "jal     0x7ffffff8", 0x7fff_fff8)]
+#[case::branch_zero(0x00050863, "beqz    a0, 0x80000010", 0x8000_0010)]
+#[case::branch_backwards(0xfeb51ee3, "bne     a0, a1, 0x7ffffffc", 0x7fff_fffc)]
+#[case::blez(0x00a05463, "blez    a0, 0x80000008", 0x8000_0008)]
+#[case::compressed_jump(0xa801, "j       0x80000010", 0x8000_0010)]
+pub fn test_branch_targets(#[case] instr: u32, #[case] expected: &str, #[case] target: u64) {
+    let disassembly = disassemble(instr, DRAM_BASE);
+
+    assert_eq!(disassembly.to_string(), expected);
+    assert_eq!(disassembly.target, Some(target));
+}
+
+fn symbols() -> SymbolTable {
+    SymbolTable::new(vec![
+        Symbol { name: "_start".to_string(), address: DRAM_BASE, size: 8, kind: SymbolKind::Function },
+        Symbol { name: "loop".to_string(), address: DRAM_BASE + 8, size: 0, kind: SymbolKind::Other },
+    ])
+}
+
+#[rstest]
+pub fn test_format_instruction() {
+    let symbols = symbols();
+
+    assert_eq!(format_instruction(0x0080006f, DRAM_BASE, &symbols), "j       0x80000008 <loop>");
+    assert_eq!(format_instruction(0x00000013, DRAM_BASE, &symbols), "nop");
+}
+
+#[rstest]
+#[case::full(&[0x13, 0x05, 0x10, 0x00], Some((0x00100513, 4)))]
+#[case::compressed(&[0x2e, 0x85, 0x13], Some((0x852e, 2)))]
+#[case::truncated(&[0x13, 0x05, 0x10], None)]
+#[case::empty(&[], None)]
+pub fn test_read_instruction(#[case] bytes: &[u8], #[case] expected: Option<(u32, usize)>) {
+    assert_eq!(read_instruction(bytes), expected);
+}
+
+#[rstest]
+pub fn test_listing() {
+    let code = [
+        0x13, 0x05, 0x10, 0x00, // li a0, 1
+        0x2e, 0x85,             // mv a0, a1
+        0x01, 0x00,             // nop
+        0x6f, 0x00, 0x00, 0x00, // j .
+    ];
+
+    assert_eq!(listing(&code, DRAM_BASE, &symbols()), [
+        "0000000080000000 <_start>:",
+        "        80000000:  00100513  li      a0, 1",
+        "        80000004:  852e      mv      a0, a1",
+        "        80000006:  0001      nop",
+        "0000000080000008 <loop>:",
+        "        80000008:  0000006f  j       0x80000008 <loop>",
+    ]);
+}
+
+#[rstest]
+pub fn test_platform_disassemble() {
+    let platform = RV64Platform::new(1, 0x10_0000);
+    let code = [0x13, 0x05, 0x10, 0x00, 0x2e, 0x85];
+    platform.get_cpu_context(0).memory.write().unwrap().write(DRAM_BASE as usize, code.len(), &code);
+
+    let lines = platform.disassemble(0, DRAM_BASE, 2);
+    assert_eq!(lines, ["0x80000000:  00100513  li      a0, 1", "0x80000004:  852e      mv      a0, a1"]);
+
+    // Decoding stops where guest memory ends
+    assert_eq!(platform.disassemble(0, DRAM_BASE + 0x10_0000 - 2, 4).len(), 1);
+    assert!(platform.disassemble(0, 0x10, 1).is_empty());
+}
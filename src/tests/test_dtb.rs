@@ -0,0 +1,100 @@
+use rstest::rstest;
+use crate::emulator::constants::DRAM_BASE;
+use crate::emulator::devices::uart::UartBackend;
+use crate::emulator::dtb::{parse_blob, to_blob, DtbError, DtbNode};
+use crate::emulator::interpreter::RV64Platform;
+use crate::tests::helpers::temp_path;
+
+fn be32(value: &[u8]) -> u32 {
+    u32::from_be_bytes(value.try_into().unwrap())
+}
+
+fn setup_platform(memory_size: u64) -> RV64Platform {
+    let mut platform = RV64Platform::new(1, memory_size);
+    let path = temp_path("dtb", "log");
+    platform.attach_uart(&UartBackend::File(path)).unwrap();
+    platform
+}
+
+#[rstest]
+pub fn test_dtb_round_trip() {
+    let mut root = DtbNode::new("");
+    root.set_u32("#address-cells", 2);
+    root.set_strings("compatible", &["a", "b"]);
+
+    let child = root.add_child(DtbNode::new("node@1000"));
+    child.set_u64("reg", 0x1_0000_1000);
+    child.set_empty("flag");
+    // Overwriting keeps a single property
+    child.set_u64("reg", 0x1000);
+
+    let blob = to_blob(&root, 0);
+
+    assert_eq!(be32(&blob[0..4]), 0xD00D_FEED);
+    assert_eq!(be32(&blob[4..8]) as usize, blob.len());
+    assert_eq!(be32(&blob[20..24]), 17);
+
+    let parsed = parse_blob(&blob).unwrap();
+    assert_eq!(parsed.get_property("compatible"), Some(&b"a\0b\0"[..]));
+
+    let child = parsed.find("/node@1000").unwrap();
+    assert_eq!(child.properties.len(), 2);
+    assert_eq!(child.get_property("reg"), Some(&[0, 0, 0, 0, 0, 0, 0x10, 0][..]));
+    assert_eq!(child.get_property("flag"), Some(&[][..]));
+}
+
+#[rstest]
+pub fn test_dtb_rejects_garbage() {
+    assert!(matches!(parse_blob(&[0x12; 64]), Err(DtbError::Parse(_))));
+}
+
+#[rstest]
+pub fn test_dtb_platform_description() {
+    let platform = setup_platform(0x100_0000);
+    let tree = parse_blob(&to_blob(&platform.generate_device_tree(), 0)).unwrap();
+
+    let cpu = tree.find("/cpus/cpu@0").unwrap();
+    assert_eq!(cpu.get_property("riscv,isa"), Some(&b"rv64imafdc\0"[..]));
+    assert!(tree.find("/cpus/cpu@0/interrupt-controller").is_some());
+
+    let memory = tree.find(&format!("/memory@{:x}", DRAM_BASE)).unwrap();
+    assert_eq!(memory.get_property("reg"), Some(&[0, 0, 0, 0, 0x80, 0, 0, 0, 0, 0, 0, 0, 0x01, 0, 0, 0][..]));
+
+    assert!(tree.find("/soc/clint@2000000").is_some());
+    assert!(tree.find("/soc/plic@c000000").is_some());
+
+    // The UART interrupt goes to the PLIC, which is the phandle after the single hart's controller
+    let serial = tree.find("/soc/serial@10000000").unwrap();
+    assert_eq!(serial.get_property("interrupts"), Some(&[0, 0, 0, 10][..]));
+    assert_eq!(serial.get_property("interrupt-parent"), Some(&[0, 0, 0, 2][..]));
+    assert_eq!(tree.find("/chosen").unwrap().get_property("stdout-path"), Some(&b"/soc/serial@10000000\0"[..]));
+}
+
+#[rstest]
+#[case::fits(0x10_0000, true)]
+#[case::too_small(0x100, false)]
+pub fn test_dtb_placement(#[case] memory_size: u64, #[case] fits: bool) {
+    let mut platform = setup_platform(memory_size);
+    let tree = platform.generate_device_tree();
+
+    match platform.load_device_tree(&tree) {
+        Ok(addr) => {
+            assert!(fits);
+            assert_eq!(addr % 0x1000, 0);
+            assert!(addr >= DRAM_BASE && addr < DRAM_BASE + memory_size);
+            assert_eq!(platform.device_tree_blob(), Some(&to_blob(&tree, 0)[..]));
+        }
+        Err(e) => assert!(!fits && matches!(e, DtbError::TooLarge { .. }) && platform.device_tree_blob().is_none()),
+    }
+}
+
+#[rstest]
+pub fn test_dtb_chosen_without_uart() {
+    let platform = RV64Platform::new(1, 0x100_0000);
+    let tree = platform.generate_device_tree();
+
+    // The boot code fills in /chosen, it is there even without a console
+    let chosen = tree.find("/chosen").unwrap();
+    assert!(chosen.get_property("stdout-path").is_none());
+    assert!(tree.find("/soc/serial@10000000").is_none());
+}
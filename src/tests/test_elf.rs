@@ -0,0 +1,141 @@
+use rstest::rstest;
+use crate::emulator::elf::{ElfError, ElfImage, SymbolKind};
+use crate::emulator::state::memory::MemoryManagementUnit;
+
+const LOAD_ADDR: u64 = 0x1000;
+const TEXT: [u8; 8] = [0x13, 0x05, 0x10, 0x00, 0x6F, 0x00, 0x00, 0x00]; //li a0, 1; j .
+
+// Minimal RV64 executable with one PT_LOAD segment and a symbol table
+fn build_elf(class: u8, machine: u16, memory_size: u64) -> Vec<u8> {
+    let strtab = b"\0_start\0buffer\0";
+    let phoff = 64u64;
+    let text_offset = phoff + 56;
+    let symtab_offset = text_offset + TEXT.len() as u64;
+    let strtab_offset = symtab_offset + 3 * 24;
+    let shoff = strtab_offset + strtab.len() as u64;
+
+    let mut elf = Vec::new();
+    elf.extend_from_slice(&[0x7F, b'E', b'L', b'F', class, 1, 1, 0]);
+    elf.extend_from_slice(&[0; 8]);
+    elf.extend_from_slice(&2u16.to_le_bytes()); //ET_EXEC
+    elf.extend_from_slice(&machine.to_le_bytes());
+    elf.extend_from_slice(&1u32.to_le_bytes());
+    elf.extend_from_slice(&(LOAD_ADDR + 4).to_le_bytes()); //e_entry
+    elf.extend_from_slice(&phoff.to_le_bytes());
+    elf.extend_from_slice(&shoff.to_le_bytes());
+    elf.extend_from_slice(&0u32.to_le_bytes());
+    for half in [64u16, 56, 1, 64, 3, 0] {
+        elf.extend_from_slice(&half.to_le_bytes());
+    }
+
+    // PT_LOAD, rwx
+    elf.extend_from_slice(&1u32.to_le_bytes());
+    elf.extend_from_slice(&7u32.to_le_bytes());
+    for word in [text_offset, LOAD_ADDR, LOAD_ADDR, TEXT.len() as u64, memory_size, 0x1000] {
+        elf.extend_from_slice(&word.to_le_bytes());
+    }
+
+    elf.extend_from_slice(&TEXT);
+
+    // Null symbol, _start (function) and buffer (object, in .bss)
+    for (name, info, value, size) in [(0u32, 0u8, 0u64, 0u64), (1, 0x12, LOAD_ADDR, 8), (8, 0x11, LOAD_ADDR + 8, 8)] {
+        elf.extend_from_slice(&name.to_le_bytes());
+        elf.push(info);
+        elf.push(0);
+        elf.extend_from_slice(&1u16.to_le_bytes());
+        elf.extend_from_slice(&value.to_le_bytes());
+        elf.extend_from_slice(&size.to_le_bytes());
+    }
+
+    elf.extend_from_slice(strtab);
+
+    // Null section, .symtab linked to .strtab
+    for (kind, offset, size, link) in [(0u32, 0u64, 0u64, 0u32), (2, symtab_offset, 3 * 24, 2), (3, strtab_offset, strtab.len() as u64, 0)] {
+        elf.extend_from_slice(&0u32.to_le_bytes());
+        elf.extend_from_slice(&kind.to_le_bytes());
+        for word in [0u64, 0, offset, size] {
+            elf.extend_from_slice(&word.to_le_bytes());
+        }
+        elf.extend_from_slice(&link.to_le_bytes());
+        elf.extend_from_slice(&0u32.to_le_bytes());
+        elf.extend_from_slice(&8u64.to_le_bytes());
+        elf.extend_from_slice(&24u64.to_le_bytes());
+    }
+
+    elf
+}
+
+#[rstest]
+pub fn test_elf_load() {
+    let image = ElfImage::parse(build_elf(2, 243, 16)).unwrap();
+    let mmu = MemoryManagementUnit::new_guard(0x2000);
+
+    // Stale data where the .bss ends up
+    mmu.write().unwrap().write_double_word(LOAD_ADDR as usize + 8, u64::MAX);
+
+    image.load(&mut mmu.write().unwrap()).unwrap();
+
+    assert_eq!(image.physical_entry(), LOAD_ADDR + 4);
+    assert_eq!(mmu.read().unwrap().read_word(LOAD_ADDR as usize), 0x00100513);
+    assert_eq!(mmu.read().unwrap().read_double_word(LOAD_ADDR as usize + 8), 0);
+}
+
+#[rstest]
+pub fn test_elf_symbols() {
+    let image = ElfImage::parse(build_elf(2, 243, 16)).unwrap();
+
+    assert_eq!(image.symbols.symbols().len(), 2);
+    assert_eq!(image.symbols.find("_start").unwrap().kind, SymbolKind::Function);
+    assert_eq!(image.symbols.find("buffer").unwrap().address, LOAD_ADDR + 8);
+
+    assert_eq!(image.symbols.describe(LOAD_ADDR).as_deref(), Some("_start"));
+    assert_eq!(image.symbols.describe(LOAD_ADDR + 4).as_deref(), Some("_start+0x4"));
+    assert_eq!(image.symbols.describe(LOAD_ADDR + 0xC).as_deref(), Some("buffer+0x4"));
+    assert_eq!(image.symbols.describe(LOAD_ADDR + 0x10), None);
+    assert_eq!(image.symbols.describe(0), None);
+}
+
+#[rstest]
+#[case::elf32(1, 243)]
+#[case::x86_64(2, 62)]
+pub fn test_elf_rejected(#[case] class: u8, #[case] machine: u16) {
+    let result = ElfImage::parse(build_elf(class, machine, 16));
+
+    assert!(matches!(result, Err(ElfError::UnsupportedClass(1)) | Err(ElfError::UnsupportedMachine(62))));
+}
+
+#[rstest]
+pub fn test_elf_malformed() {
+    assert!(matches!(ElfImage::parse(b"#!/bin/sh\n".to_vec()), Err(ElfError::Truncated)));
+    assert!(matches!(ElfImage::parse(vec![0; 64]), Err(ElfError::BadMagic)));
+
+    // Segment contents cut off
+    let mut elf = build_elf(2, 243, 16);
+    elf.truncate(64 + 56 + 4);
+    assert!(matches!(ElfImage::parse(elf), Err(ElfError::Truncated)));
+
+    // More file contents than the segment holds
+    assert!(matches!(ElfImage::parse(build_elf(2, 243, 4)), Err(ElfError::Truncated)));
+}
+
+#[rstest]
+#[case::program_headers(32)]
+#[case::section_headers(40)]
+pub fn test_elf_table_offset_overflow(#[case] field: usize) {
+    let mut elf = build_elf(2, 243, 16);
+    elf[field..field + 8].copy_from_slice(&(u64::MAX - 8).to_le_bytes());
+
+    assert!(matches!(ElfImage::parse(elf), Err(ElfError::Truncated)));
+}
+
+#[rstest]
+pub fn test_elf_segment_outside_memory() {
+    let image = ElfImage::parse(build_elf(2, 243, 0x2000)).unwrap();
+    let mmu = MemoryManagementUnit::new_guard(0x2000);
+
+    assert!(matches!(image.load(&mut mmu.write().unwrap()), Err(ElfError::SegmentNotMapped { .. })));
+
+    // The end of the segment wraps around the address space
+    let image = ElfImage::parse(build_elf(2, 243, u64::MAX)).unwrap();
+    assert!(matches!(image.load(&mut mmu.write().unwrap()), Err(ElfError::SegmentNotMapped { .. })));
+}
@@ -0,0 +1,210 @@
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use rstest::rstest;
+use crate::emulator::gdb::{Connection, GdbSession, GdbTarget, SessionEnd};
+use crate::emulator::interpreter::RV64Platform;
+use crate::tests::helpers::write_program;
+
+// Plays back what gdb sent and records everything the stub answered
+struct ScriptedConnection {
+    input: io::Cursor<Vec<u8>>,
+    output: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Read for ScriptedConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for ScriptedConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Connection for ScriptedConnection {
+    fn set_nonblocking(&self, _nonblocking: bool) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn packet(data: &str) -> String {
+    let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    format!("${}#{:02x}", data, checksum)
+}
+
+// Runs the commands after switching to no-ack mode and returns the replies to them
+fn run_session(platform: &mut RV64Platform, commands: &[&str]) -> (SessionEnd, Vec<String>) {
+    let mut input = packet("QStartNoAckMode") + "+";
+    for command in commands {
+        input.push_str(&packet(command));
+    }
+
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let connection = ScriptedConnection { input: io::Cursor::new(input.into_bytes()), output: output.clone() };
+
+    let end = GdbSession::new(platform, Box::new(connection)).run().unwrap();
+
+    let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+    let replies = output.split('$').skip(1)
+        .map(|reply| reply.rsplit_once('#').unwrap().0.to_string())
+        .collect::<Vec<_>>();
+
+    // The first reply is the OK to QStartNoAckMode
+    (end, replies[1..].to_vec())
+}
+
+#[rstest]
+#[case::port("1234", Ok(GdbTarget::Tcp("127.0.0.1:1234".to_string())))]
+#[case::host_port("0.0.0.0:1234", Ok(GdbTarget::Tcp("0.0.0.0:1234".to_string())))]
+#[case::tcp("tcp:localhost:1234", Ok(GdbTarget::Tcp("localhost:1234".to_string())))]
+#[case::unix("unix:/tmp/gdb.sock", Ok(GdbTarget::UnixSocket(PathBuf::from("/tmp/gdb.sock"))))]
+#[case::invalid("gdb", Err(()))]
+pub fn test_parse_target(#[case] value: &str, #[case] expected: Result<GdbTarget, ()>) {
+    assert_eq!(value.parse::<GdbTarget>().map_err(|_| ()), expected);
+}
+
+#[rstest]
+pub fn test_registers() {
+    let platform = &mut RV64Platform::new(2, 0x10_0000);
+    platform.get_cpu_context_mut(1).set_register(5, 0x1122_3344_5566_7788);
+
+    let (end, replies) = run_session(platform, &[
+        "qfThreadInfo",
+        "Hg2",
+        "p5",
+        "p20",   // pc
+        "pf55",  // mhartid
+        "P6=efbeadde00000000",
+        "g",
+        "D",
+    ]);
+
+    assert_eq!(end, SessionEnd::Detached);
+    assert_eq!(replies[0], "m1,2");
+    assert_eq!(replies[1], "OK");
+    assert_eq!(replies[2], "8877665544332211");
+    assert_eq!(replies[3], "0000008000000000");
+    assert_eq!(replies[4], "0100000000000000");
+    assert_eq!(replies[5], "OK");
+
+    // x0-x31 and pc, 16 hex digits each
+    assert_eq!(replies[6].len(), 33 * 16);
+    assert_eq!(&replies[6][5 * 16..7 * 16], "8877665544332211efbeadde00000000");
+
+    assert_eq!(platform.get_cpu_context(1).x[6], 0xDEAD_BEEF);
+    assert_eq!(platform.get_cpu_context(0).x[6], 0);
+}
+
+#[rstest]
+pub fn test_memory() {
+    let platform = &mut RV64Platform::new(1, 0x10_0000);
+
+    let (_, replies) = run_session(platform, &[
+        "M80000010,4:13050000",
+        "m80000010,4",
+        "m10,4", // Nothing is mapped there
+    ]);
+
+    assert_eq!(replies, ["OK", "13050000", "E14"]);
+}
+
+#[rstest]
+pub fn test_target_xml() {
+    let platform = &mut RV64Platform::new(1, 0x10_0000);
+
+    let (_, replies) = run_session(platform, &[
+        "qSupported:multiprocess+;swbreak+",
+        "qXfer:features:read:target.xml:0,ffff",
+    ]);
+
+    assert!(replies[0].contains("qXfer:features:read+"));
+    assert!(replies[1].starts_with('m') || replies[1].starts_with('l'));
+    assert!(replies[1].contains("org.gnu.gdb.riscv.cpu"));
+    assert!(replies[1].contains("<reg name=\"mstatus\" bitsize=\"64\" type=\"int\" regnum=\"833\"/>"));
+}
+
+#[rstest]
+pub fn test_breakpoint_and_step() {
+    let platform = &mut RV64Platform::new(1, 0x10_0000);
+    write_program(platform, &[0x00000013; 8]); // nop
+
+    let (_, replies) = run_session(platform, &[
+        "Z0,80000008,4",
+        "c",
+        "s",
+        "z0,80000008,4",
+        "p20",
+    ]);
+
+    assert_eq!(replies, ["OK", "T05thread:1;swbreak:;", "T05thread:1;", "OK", "0c00008000000000"]);
+}
+
+#[rstest]
+pub fn test_watchpoint() {
+    let platform = &mut RV64Platform::new(1, 0x10_0000);
+    write_program(platform, &[
+        0x00001517, // auipc a0, 1
+        0x00052583, // lw a1, 0(a0)
+        0x00b52223, // sw a1, 4(a0)
+        0x0000006F, // j .
+    ]);
+
+    let (_, replies) = run_session(platform, &[
+        "Z2,80001004,4",
+        "vCont;c",
+        "p20",
+    ]);
+
+    // The stop comes after the store retired
+    assert_eq!(replies, ["OK", "T05thread:1;watch:80001004;", "0c00008000000000"]);
+}
+
+#[rstest]
+pub fn test_reverse_execution() {
+    let platform = &mut RV64Platform::new(1, 0x10_0000);
+    write_program(platform, &[
+        0x00001517, // auipc a0, 1
+        0x00158593, // addi a1, a1, 1
+        0x00b53023, // sd a1, 0(a0)
+        0xff9ff06f, // j -8
+    ]);
+    platform.start_recording(4, 100);
+
+    let (_, replies) = run_session(platform, &[
+        "qSupported",
+        "Z2,80001000,8",
+        "c",
+        "c",
+        "bc",
+        "p20",
+        "bs",
+        "p20",
+        "bc",
+        "bc",
+        "p20",
+    ]);
+
+    assert!(replies[0].ends_with(";ReverseStep+;ReverseContinue+"));
+
+    // Going back stops right before the store that hit the watchpoint, then at the start of the history
+    assert_eq!(replies[1..], [
+        "OK",
+        "T05thread:1;watch:80001000;",
+        "T05thread:1;watch:80001000;",
+        "T05thread:1;watch:80001000;",
+        "0800008000000000",
+        "T05thread:1;",
+        "0400008000000000",
+        "T05thread:1;watch:80001000;",
+        "T05thread:1;replaylog:begin;",
+        "0000008000000000",
+    ]);
+}
@@ -0,0 +1,110 @@
+use rstest::rstest;
+use crate::emulator::constants::DRAM_BASE;
+use crate::emulator::interpreter::RV64Platform;
+use crate::emulator::state::rv64_cpu_context::{CSRAddress, Register};
+
+fn platform_with_data() -> RV64Platform {
+    let platform = RV64Platform::new(1, 0x10_0000);
+    let data = [
+        0x13, 0x05, 0x10, 0x00, // li a0, 1
+        0x2e, 0x85,             // mv a0, a1
+        0xff, 0x80,
+        b'h', b'i', 0, b'!', 0, 0, 0, 0,
+    ];
+    platform.write_memory(0, DRAM_BASE, true, &data).unwrap();
+
+    platform
+}
+
+#[rstest]
+#[case::abi("a0", Some(Register::X(10)))]
+#[case::numbered("x31", Some(Register::X(31)))]
+#[case::frame_pointer("fp", Some(Register::X(8)))]
+#[case::float_abi("fa1", Some(Register::F(11)))]
+#[case::float_numbered("f0", Some(Register::F(0)))]
+#[case::pc("pc", Some(Register::Pc))]
+#[case::csr("mstatus", Some(Register::Csr(0x300)))]
+#[case::out_of_range("x32", None)]
+#[case::unknown("foo", None)]
+pub fn test_register_from_name(#[case] name: &str, #[case] expected: Option<Register>) {
+    assert_eq!(Register::from_name(name), expected);
+}
+
+#[rstest]
+#[case::mstatus(CSRAddress::MStatus, 0x8000_0000_0000_6088, Some("MIE MPIE SD MPP=U FS=Dirty"))]
+#[case::mstatus_mpp(CSRAddress::MStatus, 0x1800, Some("MPP=M FS=Off"))]
+#[case::sstatus(CSRAddress::SStatus, 0x4102, Some("SIE SPP FS=Clean"))]
+#[case::mip(CSRAddress::MIP, 0xA0, Some("STIP MTIP"))]
+#[case::sie(CSRAddress::SIE, 0x222, Some("SSIE STIE SEIE"))]
+#[case::satp(CSRAddress::SATP, 0x8000_1000_0008_0123, Some("MODE=Sv39 ASID=0x1 PPN=0x80123"))]
+#[case::no_fields(CSRAddress::MEPC, 0x1234, None)]
+pub fn test_describe_csr_fields(#[case] csr: CSRAddress, #[case] value: u64, #[case] expected: Option<&str>) {
+    assert_eq!(csr.describe_fields(value).as_deref(), expected);
+}
+
+#[rstest]
+#[case::default_word("", &["0x80000000:  0x00100513"])]
+#[case::words("3xw", &["0x80000000:  0x00100513  0x80ff852e  0x21006968"])]
+#[case::doublewords("3xg", &["0x80000000:  0x80ff852e00100513  0x0000000021006968", "0x80000010:  0x0000000000000000"])]
+#[case::signed_bytes("2db", &["0x80000006:  -1  -128"])]
+#[case::unsigned_byte("ub", &["0x80000006:  255"])]
+#[case::strings("2s", &["0x80000008:  \"hi\"", "0x8000000b:  \"!\""])]
+#[case::instructions("2i", &["0x80000000:  00100513  li      a0, 1", "0x80000004:  852e      mv      a0, a1"])]
+pub fn test_examine(#[case] spec: &str, #[case] expected: &[&str]) {
+    let platform = platform_with_data();
+
+    let addr = match spec {
+        "2db" | "ub" => DRAM_BASE + 6,
+        "2s" => DRAM_BASE + 8,
+        _ => DRAM_BASE,
+    };
+
+    assert_eq!(platform.examine(0, spec, addr, false).unwrap(), expected);
+}
+
+#[rstest]
+#[case::bad_format("4q")]
+#[case::bad_count("99999999999999999999x")]
+pub fn test_examine_invalid_spec(#[case] spec: &str) {
+    let platform = platform_with_data();
+
+    assert!(platform.examine(0, spec, DRAM_BASE, false).is_err());
+}
+
+#[rstest]
+pub fn test_examine_unmapped() {
+    let platform = platform_with_data();
+
+    assert_eq!(platform.examine(0, "xw", 0x10, true), Err("Cannot access memory at 0x10".to_string()));
+    assert!(platform.examine(0, "i", 0x10, false).is_err());
+}
+
+#[rstest]
+pub fn test_memory_access() {
+    let platform = platform_with_data();
+
+    platform.write_memory(0, DRAM_BASE + 0x100, false, &0xdead_beefu32.to_le_bytes()).unwrap();
+
+    let mut buf = [0u8; 4];
+    platform.read_memory(0, DRAM_BASE + 0x100, true, &mut buf).unwrap();
+    assert_eq!(u32::from_le_bytes(buf), 0xdead_beef);
+
+    // Partially mapped ranges are rejected as a whole
+    assert!(platform.write_memory(0, DRAM_BASE + 0x10_0000 - 2, true, &[0; 4]).is_err());
+    assert!(platform.read_memory(0, DRAM_BASE + 0x10_0000 - 2, false, &mut buf).is_err());
+}
+
+#[rstest]
+#[case::x_register(Register::X(10), 0x1234, 0x1234)]
+#[case::zero_register(Register::X(0), 0x1234, 0)]
+#[case::f_register(Register::F(3), 0x4000_0000_0000_0000, 0x4000_0000_0000_0000)]
+#[case::pc(Register::Pc, DRAM_BASE + 8, DRAM_BASE + 8)]
+#[case::csr(Register::Csr(CSRAddress::MScratch as u16), 0xabcd, 0xabcd)]
+pub fn test_debug_registers(#[case] register: Register, #[case] value: u64, #[case] expected: u64) {
+    let platform = &mut RV64Platform::new(1, 0x10_0000);
+    let cpu = platform.get_cpu_context_mut(0);
+
+    cpu.write_debug_register(register, value).unwrap();
+
+    assert_eq!(cpu.read_debug_register(register).unwrap(), expected);
+}
@@ -1,7 +1,8 @@
 use rstest::rstest;
 use crate::emulator::instructions::rv64::RV64InstructionParser;
+use crate::emulator::instructions::rv64::compressed::expand;
 use crate::emulator::state::memory::{Device, MemoryManagementUnit};
-use crate::emulator::state::rv64_cpu_context::{Exception, RV64CPUContext};
+use crate::emulator::state::rv64_cpu_context::{CSRAddress, Exception, PrivilegeMode, RV64CPUContext};
 
 #[rstest]
 #[case::add(0x004182b3, 2, 2, 4)]
@@ -9,7 +10,9 @@ use crate::emulator::state::rv64_cpu_context::{Exception, RV64CPUContext};
 #[case::xor(0x0041c2b3, 1, 1, 0)]
 #[case::or(0x0041e2b3, 1, 2, 3)]
 #[case::and(0x0041f2b3, 2, 1, 0)]
+#[case::add_wrap(0x004182b3, 0xffffffffffffffff, 1, 0)]
 #[case::sll(0x004192b3, 2, 1, 4)]
+#[case::sll_masked(0x004192b3, 1, 65, 2)] //Only the low 6 bits of rs2 are used
 #[case::srl(0x0041d2b3, 4, 1, 2)]
 #[case::sra(0x4041d2b3, 0x8000000000000000, 1, 0xc000000000000000)]
 #[case::slt(0x0041a2b3, 0x8000000000000000, 2, 1)] //-1 < 2 = 1
@@ -81,6 +84,7 @@ pub fn test_integer_ops_32(#[case] instr: u32, #[case] x3: u32, #[case] x4: u32,
 #[case::slli(0x00119293, 2, 4)] //SLLI x5, x3, 1
 #[case::srli(0x0011d293, 4, 2)] //SRLI x5, x3, 1
 #[case::srai(0x4011d293, 0x8000000000000000, 0xc000000000000000)] //SRAI x5, x3, 1
+#[case::srai_33(0x4211d293, 0x8000000000000000, 0xffffffffc0000000)] //SRAI x5, x3, 33
 #[case::slti(0x0021a293, 0x8000000000000000, 1)] //-1 < 2 = 1
 #[case::sltui(0x0021b293, 0x8000000000000000, 0)] // SLTUI x5, x3, 2
 pub fn test_integer_ops_imm(#[case] instr: u32, #[case] x3: u64, #[case] result: u64) {
@@ -116,6 +120,7 @@ pub fn test_integer_ops_imm_32(#[case] instr: u32, #[case] x3: u32, #[case] resu
 
 #[rstest]
 #[case::lui(0x000c82b7, 0xc8000)]
+#[case::lui_negative(0x800002b7, 0xffffffff80000000)]
 pub fn test_lui(#[case] instr: u32, #[case] result: u64) {
     let mut cpu = RV64CPUContext::new(0x1000, MemoryManagementUnit::new_guard(1024));
 
@@ -145,6 +150,7 @@ pub fn test_auipc(#[case] instr: u32, #[case] result: u64) {
 #[case::jalr(0x002182e7, 0x1020, 0, 0x1022)]
 #[case::beq(0x0c418463, 1, 1, 0x10C8)]
 #[case::beq_2(0x0c418463, 1, 2, 0x1000)]
+#[case::beq_back(0xfe418ce3, 1, 1, 0xff8)]
 #[case::bne(0x0c419463, 0x1020, 0, 0x10C8)]
 #[case::bge(0x0c41d463, 0x1020, 0, 0x10C8)]
 #[case::bge_2(0x0c41d463, 0, 0x1020, 0x1000)]
@@ -335,4 +341,262 @@ pub fn test_amo_misaligned(
 
     assert!(result.is_err(), "expected misaligned exception but got success");
     assert!(matches!(result.unwrap_err(), Exception::LoadAddressMisaligned));
-}
\ No newline at end of file
+}
+
+// Hart with mstatus.FS set to Initial so floating point instructions are legal
+fn setup_fp(memory_size: usize) -> RV64CPUContext {
+    let mut cpu = RV64CPUContext::new(0x1000, MemoryManagementUnit::new_guard(memory_size));
+
+    cpu.csrs.write_csr(CSRAddress::MStatus as u16, 1 << 13, true).unwrap();
+
+    cpu
+}
+
+#[rstest]
+#[case::fadd_s(0x0041f2d3, 0xffffffff3fc00000, 0xffffffff40100000, 0xffffffff40700000)]
+#[case::fadd_d(0x0241f2d3, 0x3fb999999999999a, 0x3fc999999999999a, 0x3fd3333333333334)]
+#[case::fsub_d(0x0a41f2d3, 0x3ff0000000000000, 0x4008000000000000, 0xc000000000000000)]
+#[case::fmul_s(0x1041f2d3, 0xffffffffbfc00000, 0xffffffff40800000, 0xffffffffc0c00000)]
+#[case::fdiv_d(0x1a41f2d3, 0x3ff0000000000000, 0x4010000000000000, 0x3fd0000000000000)]
+#[case::fdiv_by_zero(0x1a41f2d3, 0x3ff0000000000000, 0x0000000000000000, 0x7ff0000000000000)]
+#[case::fsqrt_d(0x5a01f2d3, 0x4000000000000000, 0x0000000000000000, 0x3ff6a09e667f3bcd)]
+#[case::fsgnj_d(0x224182d3, 0x4000000000000000, 0xbff0000000000000, 0xc000000000000000)]
+#[case::fsgnjn_s(0x204192d3, 0xffffffff40000000, 0xffffffffbf800000, 0xffffffff40000000)]
+#[case::fsgnjx_d(0x2241a2d3, 0xc000000000000000, 0xbff0000000000000, 0x4000000000000000)]
+#[case::fsgnj_d_nan_payload(0x224182d3, 0x7ff0000000000001, 0xbff0000000000000, 0xfff0000000000001)] // Sign injection keeps NaN payloads
+#[case::fsgnj_s_unboxed(0x204182d3, 0x000000003f800000, 0xffffffffbf800000, 0xffffffffffc00000)] // Improperly boxed singles read as canonical NaN
+#[case::fmin_d(0x2a4182d3, 0x0000000000000000, 0x8000000000000000, 0x8000000000000000)]
+#[case::fmin_nan(0x2a4182d3, 0x7ff8000000000000, 0x4008000000000000, 0x4008000000000000)]
+#[case::fmax_s(0x284192d3, 0xffffffffbf800000, 0xffffffff40a00000, 0xffffffff40a00000)]
+#[case::fadd_s_nan(0x0041f2d3, 0xffffffff7fc00001, 0xffffffff3f800000, 0xffffffff7fc00000)] // NaN results are always canonical
+#[case::fcvt_s_d(0x4011f2d3, 0x3fe0000000000000, 0x0000000000000000, 0xffffffff3f000000)]
+#[case::fcvt_d_s(0x4201f2d3, 0xffffffff3e800000, 0x0000000000000000, 0x3fd0000000000000)]
+#[case::fmadd_d(0x1241f2c3, 0x4000000000000000, 0x4008000000000000, 0x401c000000000000)] // f2 = 1.0
+#[case::fmsub_d(0x1241f2c7, 0x4000000000000000, 0x4008000000000000, 0x4014000000000000)]
+#[case::fnmsub_d(0x1241f2cb, 0x4000000000000000, 0x4008000000000000, 0xc014000000000000)]
+#[case::fnmadd_d(0x1241f2cf, 0x4000000000000000, 0x4008000000000000, 0xc01c000000000000)]
+pub fn test_fp_ops(#[case] instr: u32, #[case] f3: u64, #[case] f4: u64, #[case] result: u64) {
+    let mut cpu = setup_fp(1024);
+
+    cpu.set_register_float(2, 0x3ff0000000000000);
+    cpu.set_register_float(3, f3);
+    cpu.set_register_float(4, f4);
+
+    let instr_fn = RV64InstructionParser::parse(instr);
+
+    let instr_result = instr_fn(&mut cpu, instr);
+
+    assert!(instr_result.is_ok(), "exception {:?}", instr_result.expect_err("This shouldn't happen at all"));
+    assert_eq!(cpu.f[5], result);
+}
+
+#[rstest]
+#[case::feq_d(0xa241a2d3, 0x3ff0000000000000, 0x3ff0000000000000, 1)]
+#[case::feq_nan(0xa241a2d3, 0x7ff8000000000000, 0x7ff8000000000000, 0)]
+#[case::flt_s(0xa04192d3, 0xffffffffbf800000, 0xffffffff3f800000, 1)]
+#[case::fle_d(0xa24182d3, 0x4000000000000000, 0x3ff0000000000000, 0)]
+#[case::fcvt_w_d(0xc20192d3, 0xc00f333333333333, 0x0000000000000000, 0xfffffffffffffffd)]
+#[case::fcvt_w_d_saturate(0xc20192d3, 0x4415af1d78b58c40, 0x0000000000000000, 0x7fffffff)]
+#[case::fcvt_wu_s(0xc01192d3, 0xffffffff40900000, 0x0000000000000000, 4)]
+#[case::fcvt_wu_s_negative(0xc01192d3, 0xffffffffc0900000, 0x0000000000000000, 0)]
+#[case::fcvt_l_d_ties_even(0xc22182d3, 0x4004000000000000, 0x0000000000000000, 2)]
+#[case::fcvt_lu_d_nan(0xc23192d3, 0x7ff8000000000000, 0x0000000000000000, 0xffffffffffffffff)]
+#[case::fmv_x_w(0xe00182d3, 0xffffffffbf800000, 0x0000000000000000, 0xffffffffbf800000)]
+#[case::fmv_x_w_unboxed(0xe00182d3, 0x123456787fc00001, 0x0000000000000000, 0x000000007fc00001)]
+#[case::fmv_x_d(0xe20182d3, 0x3ff0000000000000, 0x0000000000000000, 0x3ff0000000000000)]
+#[case::fclass_negative_infinity(0xe20192d3, 0xfff0000000000000, 0x0000000000000000, 1 << 0)]
+#[case::fclass_positive_zero(0xe20192d3, 0x0000000000000000, 0x0000000000000000, 1 << 4)]
+#[case::fclass_signaling_nan(0xe20192d3, 0x7ff0000000000001, 0x0000000000000000, 1 << 8)]
+#[case::fclass_quiet_nan(0xe20192d3, 0x7ff8000000000000, 0x0000000000000000, 1 << 9)]
+#[case::fclass_unboxed(0xe00192d3, 0x000000003f800000, 0x0000000000000000, 1 << 9)]
+pub fn test_fp_to_int(#[case] instr: u32, #[case] f3: u64, #[case] f4: u64, #[case] result: u64) {
+    let mut cpu = setup_fp(1024);
+
+    cpu.set_register_float(3, f3);
+    cpu.set_register_float(4, f4);
+
+    let instr_fn = RV64InstructionParser::parse(instr);
+
+    let instr_result = instr_fn(&mut cpu, instr);
+
+    assert!(instr_result.is_ok(), "exception {:?}", instr_result.expect_err("This shouldn't happen at all"));
+    assert_eq!(cpu.x[5], result);
+}
+
+#[rstest]
+#[case::fcvt_d_w(0xd201f2d3, 0xfffffffffffffffe, 0xc000000000000000)]
+#[case::fcvt_s_lu(0xd031f2d3, 16777216, 0xffffffff4b800000)]
+#[case::fmv_w_x(0xf00182d3, 0x123456783f800000, 0xffffffff3f800000)]
+#[case::fmv_d_x(0xf20182d3, 0xc000000000000000, 0xc000000000000000)]
+pub fn test_int_to_fp(#[case] instr: u32, #[case] x3: u64, #[case] result: u64) {
+    let mut cpu = setup_fp(1024);
+
+    cpu.set_register(3, x3);
+
+    let instr_fn = RV64InstructionParser::parse(instr);
+
+    let instr_result = instr_fn(&mut cpu, instr);
+
+    assert!(instr_result.is_ok(), "exception {:?}", instr_result.expect_err("This shouldn't happen at all"));
+    assert_eq!(cpu.f[5], result);
+}
+
+#[rstest]
+#[case::flw_fsw(0x0081a287, 0x0041a427, 0x7f800001, 0xffffffff7f800001)] // Signaling NaN payload survives the round trip
+#[case::fld_fsd(0x0081b287, 0x0041b427, 0x3ff8000000000000, 0x3ff8000000000000)]
+pub fn test_fp_load_store(#[case] load: u32, #[case] store: u32, #[case] bits: u64, #[case] register: u64) {
+    let mut cpu = setup_fp(16384);
+
+    cpu.set_register(3, 0x1000);
+    cpu.memory.write().unwrap().write_double_word(0x1008, bits);
+
+    let load_fn = RV64InstructionParser::parse(load);
+    let load_result = load_fn(&mut cpu, load);
+
+    assert!(load_result.is_ok(), "exception {:?}", load_result.expect_err("This shouldn't happen at all"));
+    assert_eq!(cpu.f[5], register);
+
+    cpu.memory.write().unwrap().write_double_word(0x1008, 0);
+    cpu.set_register_float(4, register);
+
+    let store_fn = RV64InstructionParser::parse(store);
+    let store_result = store_fn(&mut cpu, store);
+
+    assert!(store_result.is_ok(), "exception {:?}", store_result.expect_err("This shouldn't happen at all"));
+    assert_eq!(cpu.memory.read().unwrap().read_double_word(0x1008), bits);
+}
+
+#[rstest]
+#[case::fadd_d(0x0241f2d3)]
+#[case::fld(0x0081b287)]
+#[case::fsd(0x0041b427)]
+#[case::fmadd_d(0x1241f2c3)]
+#[case::fmv_x_d(0xe20182d3)]
+pub fn test_fp_disabled(#[case] instr: u32) {
+    let mut cpu = RV64CPUContext::new(0x1000, MemoryManagementUnit::new_guard(16384));
+
+    cpu.set_register(3, 0x1000);
+
+    let instr_fn = RV64InstructionParser::parse(instr);
+
+    assert!(matches!(instr_fn(&mut cpu, instr), Err(Exception::IllegalInstruction)));
+}
+
+#[rstest]
+#[case::addi4spn(0x0808, Some(0x01010513))] //C.ADDI4SPN a0, sp, 16
+#[case::fld(0x2588, Some(0x0085b507))] //C.FLD fa0, 8(a1)
+#[case::sw(0xc1c8, Some(0x00a5a223))] //C.SW a0, 4(a1)
+#[case::nop(0x0001, Some(0x00000013))]
+#[case::addi(0x0505, Some(0x00150513))] //C.ADDI a0, 1
+#[case::li(0x557d, Some(0xfff00513))] //C.LI a0, -1
+#[case::lui(0x757d, Some(0xfffff537))] //C.LUI a0, 0xfffff
+#[case::addi16sp(0x1141, Some(0xff010113))] //C.ADDI16SP -16
+#[case::srai(0x957d, Some(0x43f55513))] //C.SRAI a0, 63
+#[case::subw(0x9d0d, Some(0x40b5053b))] //C.SUBW a0, a1
+#[case::j(0xbff5, Some(0xffdff06f))] //C.J -4
+#[case::beqz(0xc401, Some(0x00040463))] //C.BEQZ s0, 8
+#[case::ldsp(0x60a2, Some(0x00813083))] //C.LDSP ra, 8(sp)
+#[case::sdsp(0xe406, Some(0x00113423))] //C.SDSP ra, 8(sp)
+#[case::jr(0x8082, Some(0x00008067))] //C.JR ra
+#[case::mv(0x852e, Some(0x00b00533))] //C.MV a0, a1
+#[case::add(0x952e, Some(0x00b50533))] //C.ADD a0, a1
+#[case::jalr(0x9502, Some(0x000500e7))] //C.JALR a0
+#[case::ebreak(0x9002, Some(0x00100073))]
+#[case::illegal(0x0000, None)]
+#[case::addiw_x0(0x2001, None)]
+#[case::jr_x0(0x8002, None)]
+#[case::lwsp_x0(0x4002, None)]
+#[case::reserved_alu(0x9d41, None)]
+pub fn test_compressed_expand(#[case] parcel: u16, #[case] expanded: Option<u32>) {
+    assert_eq!(expand(parcel), expanded);
+}
+
+#[rstest]
+#[case::jalr(0x9502, 0x2000, 0x2000, 0x1002)] //C.JALR a0 links the address of the next parcel
+#[case::jalr_halfword(0x9502, 0x2002, 0x2002, 0x1002)] //Targets only need 16-bit alignment with C enabled
+#[case::j(0xbff5, 0, 0xffc, 0)]
+pub fn test_compressed_jump(#[case] parcel: u16, #[case] a0: u64, #[case] pc: u64, #[case] ra: u64) {
+    let mut cpu = RV64CPUContext::new(0x1000, MemoryManagementUnit::new_guard(1024));
+
+    cpu.set_register(10, a0);
+    cpu.instruction_length = 2;
+
+    let instr = expand(parcel).unwrap();
+    let instr_fn = RV64InstructionParser::parse(instr);
+
+    let instr_result = instr_fn(&mut cpu, instr);
+
+    assert!(instr_result.is_ok(), "exception {:?}", instr_result.expect_err("This shouldn't happen at all"));
+    assert_eq!(cpu.pc, pc);
+    assert_eq!(cpu.x[1], ra);
+}
+
+#[rstest]
+#[case::mret_to_s(0x30200073, PrivilegeMode::Machine, 0x20880, 0x2000, PrivilegeMode::Supervisor, 0x88)] //MPRV is cleared when leaving machine mode
+#[case::mret_to_m(0x30200073, PrivilegeMode::Machine, 0x21800, 0x2000, PrivilegeMode::Machine, 0x20080)]
+#[case::sret_to_u(0x10200073, PrivilegeMode::Supervisor, 0x20, 0x3000, PrivilegeMode::User, 0x22)]
+#[case::sret_to_s(0x10200073, PrivilegeMode::Machine, 0x100, 0x3000, PrivilegeMode::Supervisor, 0x20)]
+pub fn test_trap_return(#[case] instr: u32, #[case] privilege: PrivilegeMode, #[case] mstatus: u64, #[case] pc: u64, #[case] result_privilege: PrivilegeMode, #[case] result_mstatus: u64) {
+    let mut cpu = RV64CPUContext::new(0x1000, MemoryManagementUnit::new_guard(1024));
+
+    cpu.csrs.write_csr(CSRAddress::MStatus as u16, mstatus, true).unwrap();
+    cpu.csrs.write_csr(CSRAddress::MEPC as u16, 0x2000, true).unwrap();
+    cpu.csrs.write_csr(CSRAddress::SEPC as u16, 0x3000, true).unwrap();
+    cpu.csrs.change_privilege(privilege);
+
+    let instr_fn = RV64InstructionParser::parse(instr);
+
+    let instr_result = instr_fn(&mut cpu, instr);
+
+    assert!(instr_result.is_ok(), "exception {:?}", instr_result.expect_err("This shouldn't happen at all"));
+    assert_eq!(cpu.pc, pc);
+    assert_eq!(cpu.csrs.get_current_privilege(), result_privilege);
+    assert_eq!(cpu.csrs.read_csr(CSRAddress::MStatus as u16, true).unwrap(), result_mstatus);
+}
+
+#[rstest]
+#[case::mret_from_s(0x30200073, PrivilegeMode::Supervisor, 0)]
+#[case::sret_from_u(0x10200073, PrivilegeMode::User, 0)]
+#[case::sret_tsr(0x10200073, PrivilegeMode::Supervisor, 1 << 22)]
+#[case::wfi_from_u(0x10500073, PrivilegeMode::User, 0)]
+#[case::wfi_tw(0x10500073, PrivilegeMode::Supervisor, 1 << 21)]
+pub fn test_privileged_illegal(#[case] instr: u32, #[case] privilege: PrivilegeMode, #[case] mstatus: u64) {
+    let mut cpu = RV64CPUContext::new(0x1000, MemoryManagementUnit::new_guard(1024));
+
+    cpu.csrs.write_csr(CSRAddress::MStatus as u16, mstatus, true).unwrap();
+    cpu.csrs.change_privilege(privilege);
+
+    let instr_fn = RV64InstructionParser::parse(instr);
+
+    assert!(matches!(instr_fn(&mut cpu, instr), Err(Exception::IllegalInstruction)));
+    assert_eq!(cpu.csrs.get_current_privilege(), privilege);
+}
+
+#[rstest]
+#[case::machine(PrivilegeMode::Machine)]
+#[case::supervisor(PrivilegeMode::Supervisor)]
+pub fn test_wfi(#[case] privilege: PrivilegeMode) {
+    let mut cpu = RV64CPUContext::new(0x1000, MemoryManagementUnit::new_guard(1024));
+
+    cpu.csrs.change_privilege(privilege);
+
+    let instr_fn = RV64InstructionParser::parse(0x10500073);
+
+    assert!(instr_fn(&mut cpu, 0x10500073).is_ok());
+    assert!(cpu.waiting_for_interrupt);
+    assert!(!cpu.csrs.has_pending_interrupt());
+}
+
+#[rstest]
+#[case::fence(0x0ff0000f, true)]
+#[case::fence_tso(0x8330000f, true)]
+#[case::fence_i(0x0000100f, true)]
+#[case::reserved_funct3(0x0000200f, false)]
+pub fn test_fence(#[case] instr: u32, #[case] legal: bool) {
+    let mut cpu = RV64CPUContext::new(0x1000, MemoryManagementUnit::new_guard(1024));
+
+    let instr_fn = RV64InstructionParser::parse(instr);
+
+    assert_eq!(instr_fn(&mut cpu, instr).is_ok(), legal);
+    assert_eq!(cpu.pc, 0x1000);
+}
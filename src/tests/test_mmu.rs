@@ -0,0 +1,180 @@
+use rstest::rstest;
+use crate::emulator::constants::SATP_MODE_SV39;
+use crate::emulator::instructions::rv64::RV64InstructionParser;
+use crate::emulator::state::memory::MemoryManagementUnit;
+use crate::emulator::state::rv64_cpu_context::{CSRAddress, Exception, MEnvCfgFlags, MStatusFlags, PrivilegeMode, RV64CPUContext};
+use crate::emulator::state::translation::{AccessDirtyPolicy, PTEFlags, SATP_MODE_SHIFT};
+
+const ROOT_TABLE: u64 = 0x1000;
+const LEVEL1_TABLE: u64 = 0x2000;
+const LEVEL0_TABLE: u64 = 0x3000;
+const DATA_PAGE: u64 = 0x5000;
+
+fn pte(physical_address: u64, flags: PTEFlags) -> u64 {
+    ((physical_address >> 12) << 10) | flags.bits()
+}
+
+// Builds an Sv39 address space with a single 4 KiB page at 0x4000_0000 and a 1 GiB page at 0x8000_0000
+fn setup_sv39(leaf_flags: PTEFlags) -> RV64CPUContext {
+    let mut cpu = RV64CPUContext::new(0x1000, MemoryManagementUnit::new_guard(0x10000));
+
+    {
+        let mut memory = cpu.memory.write().unwrap();
+        memory.write_double_word((ROOT_TABLE + 8) as usize, pte(LEVEL1_TABLE, PTEFlags::V));
+        memory.write_double_word((ROOT_TABLE + 2 * 8) as usize, pte(0, PTEFlags::V | PTEFlags::R | PTEFlags::W | PTEFlags::X | PTEFlags::A | PTEFlags::D));
+        memory.write_double_word(LEVEL1_TABLE as usize, pte(LEVEL0_TABLE, PTEFlags::V));
+        memory.write_double_word(LEVEL0_TABLE as usize, pte(DATA_PAGE, PTEFlags::V | leaf_flags));
+        memory.write_double_word(DATA_PAGE as usize, 0x1122334455667788);
+    }
+
+    cpu.csrs.write_csr(CSRAddress::SATP as u16, (SATP_MODE_SV39 << SATP_MODE_SHIFT) | (ROOT_TABLE >> 12), true).unwrap();
+    cpu.csrs.change_privilege(PrivilegeMode::Supervisor);
+
+    cpu
+}
+
+#[rstest]
+#[case::page(0x4000_0000, 0x1122334455667788)]
+#[case::page_offset(0x4000_0004, 0x11223344)]
+#[case::gigapage(0x8000_5000, 0x1122334455667788)]
+pub fn test_translated_load(#[case] vaddr: u64, #[case] result: u64) {
+    let mut cpu = setup_sv39(PTEFlags::R | PTEFlags::W | PTEFlags::A | PTEFlags::D);
+
+    let value = cpu.load(vaddr, if vaddr & 4 != 0 { 4 } else { 8 });
+
+    assert!(value.is_ok(), "exception {:?}", value.expect_err("This shouldn't happen at all"));
+    assert_eq!(value.unwrap(), result);
+}
+
+#[rstest]
+#[case::unmapped(0x4000_1000, PTEFlags::R | PTEFlags::A, PrivilegeMode::Supervisor, false)]
+#[case::non_canonical(0x0000_0080_0000_0000, PTEFlags::R | PTEFlags::A, PrivilegeMode::Supervisor, false)]
+#[case::no_read(0x4000_0000, PTEFlags::X | PTEFlags::A, PrivilegeMode::Supervisor, false)]
+#[case::not_accessed(0x4000_0000, PTEFlags::R, PrivilegeMode::Supervisor, false)]
+#[case::user_page_from_supervisor(0x4000_0000, PTEFlags::R | PTEFlags::U | PTEFlags::A, PrivilegeMode::Supervisor, false)]
+#[case::user_page_with_sum(0x4000_0000, PTEFlags::R | PTEFlags::U | PTEFlags::A, PrivilegeMode::Supervisor, true)]
+#[case::supervisor_page_from_user(0x4000_0000, PTEFlags::R | PTEFlags::A, PrivilegeMode::User, true)]
+pub fn test_load_page_fault(#[case] vaddr: u64, #[case] flags: PTEFlags, #[case] privilege: PrivilegeMode, #[case] sum: bool) {
+    let mut cpu = setup_sv39(flags);
+
+    cpu.csrs.change_privilege(privilege);
+
+    if sum {
+        cpu.csrs.write_csr(CSRAddress::MStatus as u16, MStatusFlags::SUM.bits(), true).unwrap();
+    }
+
+    let value = cpu.load(vaddr, 8);
+
+    if sum && privilege == PrivilegeMode::Supervisor {
+        assert!(value.is_ok(), "exception {:?}", value.expect_err("This shouldn't happen at all"));
+        return;
+    }
+
+    assert!(matches!(value, Err(Exception::LoadPageFault)), "expected page fault, got {:?}", value);
+    assert_eq!(cpu.trap_value, vaddr);
+}
+
+#[rstest]
+#[case::writable(PTEFlags::R | PTEFlags::W | PTEFlags::A | PTEFlags::D, true)]
+#[case::read_only(PTEFlags::R | PTEFlags::A | PTEFlags::D, false)]
+#[case::not_dirty(PTEFlags::R | PTEFlags::W | PTEFlags::A, false)]
+pub fn test_translated_store(#[case] flags: PTEFlags, #[case] allowed: bool) {
+    let mut cpu = setup_sv39(flags);
+
+    let result = cpu.store(0x4000_0008, 8, 0xdeadbeef);
+
+    if allowed {
+        assert!(result.is_ok(), "exception {:?}", result.expect_err("This shouldn't happen at all"));
+        assert_eq!(cpu.memory.read().unwrap().read_double_word((DATA_PAGE + 8) as usize), 0xdeadbeef);
+    } else {
+        assert!(matches!(result, Err(Exception::StorePageFault)), "expected page fault, got {:?}", result);
+        assert_eq!(cpu.trap_value, 0x4000_0008);
+    }
+}
+
+#[rstest]
+pub fn test_mprv_uses_mpp() {
+    let mut cpu = setup_sv39(PTEFlags::R | PTEFlags::A);
+
+    // M-mode loads are untranslated unless MPRV points them at a lower privilege
+    cpu.csrs.change_privilege(PrivilegeMode::Machine);
+    assert_eq!(cpu.load(DATA_PAGE, 8).unwrap(), 0x1122334455667788);
+
+    cpu.csrs.write_csr(CSRAddress::MStatus as u16, MStatusFlags::MPRV.bits() | (0b01 << 11), true).unwrap();
+    assert_eq!(cpu.load(0x4000_0000, 8).unwrap(), 0x1122334455667788);
+}
+
+#[rstest]
+#[case::flush_all(0x12000073, 0, 0, true)] // SFENCE.VMA zero, zero
+#[case::flush_address(0x12018073, 0x4000_0000, 0, true)] // SFENCE.VMA x3, zero
+#[case::flush_other_address(0x12018073, 0x4000_1000, 0, false)] // SFENCE.VMA x3, zero
+#[case::flush_asid(0x12400073, 0, 0, true)] // SFENCE.VMA zero, x4
+#[case::flush_other_asid(0x12400073, 0, 1, false)] // SFENCE.VMA zero, x4
+pub fn test_sfence_vma(#[case] instr: u32, #[case] rs1: u64, #[case] rs2: u64, #[case] flushed: bool) {
+    let mut cpu = setup_sv39(PTEFlags::R | PTEFlags::W | PTEFlags::A | PTEFlags::D);
+
+    assert_eq!(cpu.load(0x4000_0000, 8).unwrap(), 0x1122334455667788);
+
+    // Remap the page without telling the hart, the stale translation must survive until the fence
+    cpu.memory.write().unwrap().write_double_word(LEVEL0_TABLE as usize, pte(0x6000, PTEFlags::V | PTEFlags::R | PTEFlags::A));
+    assert_eq!(cpu.load(0x4000_0000, 8).unwrap(), 0x1122334455667788);
+
+    cpu.set_register(3, rs1);
+    cpu.set_register(4, rs2);
+
+    let instr_fn = RV64InstructionParser::parse(instr);
+    let result = instr_fn(&mut cpu, instr);
+
+    assert!(result.is_ok(), "exception {:?}", result.expect_err("This shouldn't happen at all"));
+    assert_eq!(cpu.load(0x4000_0000, 8).unwrap(), if flushed { 0 } else { 0x1122334455667788 });
+}
+
+#[rstest]
+pub fn test_sfence_vma_from_user() {
+    let mut cpu = setup_sv39(PTEFlags::R | PTEFlags::A);
+
+    cpu.csrs.change_privilege(PrivilegeMode::User);
+
+    let instr_fn = RV64InstructionParser::parse(0x12000073);
+
+    assert!(matches!(instr_fn(&mut cpu, 0x12000073), Err(Exception::IllegalInstruction)));
+}
+
+#[rstest]
+#[case::fault_on_clear_load(AccessDirtyPolicy::FaultOnClear, false, PTEFlags::empty())]
+#[case::fault_on_clear_store(AccessDirtyPolicy::FaultOnClear, true, PTEFlags::empty())]
+#[case::hardware_load(AccessDirtyPolicy::HardwareUpdate, false, PTEFlags::A)]
+#[case::hardware_store(AccessDirtyPolicy::HardwareUpdate, true, PTEFlags::A | PTEFlags::D)]
+pub fn test_access_dirty_policy(#[case] policy: AccessDirtyPolicy, #[case] is_store: bool, #[case] expected_flags: PTEFlags) {
+    let mut cpu = setup_sv39(PTEFlags::R | PTEFlags::W);
+
+    cpu.csrs.set_access_dirty_policy(policy);
+
+    let result = if is_store { cpu.store(0x4000_0000, 8, 0) } else { cpu.load(0x4000_0000, 8).map(|_| ()) };
+
+    let pte = cpu.memory.read().unwrap().read_double_word(LEVEL0_TABLE as usize);
+    let flags = PTEFlags::from_bits_truncate(pte) & (PTEFlags::A | PTEFlags::D);
+
+    assert_eq!(flags, expected_flags);
+
+    match policy {
+        AccessDirtyPolicy::FaultOnClear => assert!(matches!(result, Err(Exception::LoadPageFault | Exception::StorePageFault))),
+        AccessDirtyPolicy::HardwareUpdate => assert!(result.is_ok(), "exception {:?}", result.expect_err("This shouldn't happen at all")),
+    }
+}
+
+#[rstest]
+pub fn test_menvcfg_adue() {
+    let mut cpu = setup_sv39(PTEFlags::R | PTEFlags::W);
+
+    // Without Svadu the bit is hardwired to zero
+    cpu.csrs.write_csr(CSRAddress::MEnvCfg as u16, MEnvCfgFlags::ADUE.bits(), true).unwrap();
+    assert_eq!(cpu.csrs.read_csr(CSRAddress::MEnvCfg as u16, true).unwrap(), 0);
+
+    cpu.csrs.set_access_dirty_policy(AccessDirtyPolicy::HardwareUpdate);
+    assert_eq!(cpu.csrs.read_csr(CSRAddress::MEnvCfg as u16, true).unwrap(), MEnvCfgFlags::ADUE.bits());
+
+    // Clearing ADUE falls back to Svade behaviour
+    cpu.csrs.write_csr(CSRAddress::MEnvCfg as u16, 0, true).unwrap();
+    assert!(matches!(cpu.load(0x4000_0000, 8), Err(Exception::LoadPageFault)));
+}
@@ -0,0 +1,117 @@
+use std::sync::Arc;
+use rstest::rstest;
+use crate::emulator::devices::plic::{IrqLine, Plic, PlicState, PLIC_BASE, PLIC_SIZE};
+use crate::emulator::state::memory::MemoryManagementUnit;
+use crate::emulator::state::rv64_cpu_context::{MIPFlags, RV64CPUContext};
+use crate::tests::helpers::mip;
+
+const ENABLE: usize = PLIC_BASE + 0x2000;
+const THRESHOLD: usize = PLIC_BASE + 0x20_0000;
+const CLAIM: usize = PLIC_BASE + 0x20_0004;
+
+// Two harts, contexts 0/1 are hart 0 M/S-mode and 2/3 hart 1 M/S-mode
+fn setup_plic() -> (Vec<RV64CPUContext>, Arc<PlicState>) {
+    let mmu = MemoryManagementUnit::new_guard(0x1000);
+    let harts = vec![RV64CPUContext::new(0, mmu.clone()), RV64CPUContext::new(0, mmu.clone())];
+
+    let plic = PlicState::new(harts.iter().map(|hart| hart.csrs.get_interrupt_lines()).collect());
+    mmu.write().unwrap().add_region(PLIC_BASE, PLIC_SIZE, Box::new(Plic::new(plic.clone())));
+
+    (harts, plic)
+}
+
+fn write(hart: &RV64CPUContext, addr: usize, value: u32) {
+    hart.memory.write().unwrap().write_word(addr, value);
+}
+
+fn read(hart: &RV64CPUContext, addr: usize) -> u32 {
+    hart.memory.read().unwrap().read_word(addr)
+}
+
+#[rstest]
+#[case::hart0_machine(0, 0, MIPFlags::MEIP)]
+#[case::hart0_supervisor(1, 0, MIPFlags::SEIP)]
+#[case::hart1_machine(2, 1, MIPFlags::MEIP)]
+#[case::hart1_supervisor(3, 1, MIPFlags::SEIP)]
+pub fn test_plic_routing(#[case] context: usize, #[case] hart_id: usize, #[case] line: MIPFlags) {
+    let (harts, plic) = setup_plic();
+
+    write(&harts[0], PLIC_BASE + 4 * 10, 1);
+    write(&harts[0], ENABLE + 0x80 * context, 1 << 10);
+
+    plic.set_irq(10, true);
+
+    assert_eq!(mip(&harts[hart_id]), line.bits());
+    assert_eq!(mip(&harts[1 - hart_id]), 0);
+    assert_eq!(read(&harts[0], PLIC_BASE + 0x1000), 1 << 10);
+
+    // Claiming clears the pending bit and drops the line
+    assert_eq!(read(&harts[0], CLAIM + 0x1000 * context), 10);
+    assert_eq!(mip(&harts[hart_id]), 0);
+    assert_eq!(read(&harts[0], PLIC_BASE + 0x1000), 0);
+
+    // A level that is still high is pending again after completion
+    write(&harts[0], CLAIM + 0x1000 * context, 10);
+    assert_eq!(mip(&harts[hart_id]), line.bits());
+
+    plic.set_irq(10, false);
+    assert_eq!(mip(&harts[hart_id]), 0);
+}
+
+#[rstest]
+#[case::highest_priority(&[(3, 2), (5, 6), (7, 4)], 0, 5)]
+#[case::tie_lowest_id(&[(9, 4), (4, 4)], 0, 4)]
+#[case::threshold(&[(3, 2), (5, 3)], 3, 0)] //Only priorities above the threshold interrupt
+#[case::priority_zero_never(&[(3, 0)], 0, 0)]
+pub fn test_plic_claim(#[case] sources: &[(usize, u32)], #[case] threshold: u32, #[case] claimed: u32) {
+    let (harts, plic) = setup_plic();
+
+    write(&harts[0], THRESHOLD, threshold);
+
+    for &(source, priority) in sources {
+        write(&harts[0], PLIC_BASE + 4 * source, priority);
+        write(&harts[0], ENABLE, read(&harts[0], ENABLE) | (1 << source));
+        plic.set_irq(source, true);
+    }
+
+    assert_eq!(mip(&harts[0]) != 0, claimed != 0);
+    assert_eq!(read(&harts[0], CLAIM), claimed);
+}
+
+#[test]
+pub fn test_plic_irq_line() {
+    let (harts, plic) = setup_plic();
+    let line = IrqLine::new(plic.clone(), 33);
+
+    write(&harts[0], PLIC_BASE + 4 * 33, 7);
+    write(&harts[0], ENABLE + 0x80 + 4, 1 << 1);
+
+    line.set(true);
+
+    assert_eq!(mip(&harts[0]), MIPFlags::SEIP.bits());
+    // Priorities are only 3 bits wide, source 0 doesn't exist
+    write(&harts[0], PLIC_BASE, 5);
+    write(&harts[0], PLIC_BASE + 4 * 33, 0xFF);
+    assert_eq!(read(&harts[0], PLIC_BASE), 0);
+    assert_eq!(read(&harts[0], PLIC_BASE + 4 * 33), 7);
+}
+
+#[test]
+pub fn test_plic_bulk_access() {
+    let (harts, plic) = setup_plic();
+
+    write(&harts[0], PLIC_BASE + 4 * 5, 1);
+    write(&harts[0], ENABLE, 1 << 5);
+    plic.set_irq(5, true);
+
+    // Threshold and claim register in one read, the claim register is only read once
+    let mut buf = [0; 8];
+    harts[0].memory.read().unwrap().read(THRESHOLD, 8, &mut buf);
+
+    assert_eq!(buf, [0, 0, 0, 0, 5, 0, 0, 0]);
+    assert_eq!(read(&harts[0], CLAIM), 0);
+
+    harts[0].memory.write().unwrap().write(PLIC_BASE + 4 * 5 + 2, 4, &[0xFF, 0xFF, 3, 0]);
+    assert_eq!(read(&harts[0], PLIC_BASE + 4 * 5), 1);
+    assert_eq!(read(&harts[0], PLIC_BASE + 4 * 6), 3);
+}
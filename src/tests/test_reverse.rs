@@ -0,0 +1,132 @@
+use rstest::rstest;
+use crate::emulator::constants::DRAM_BASE;
+use crate::emulator::devices::clint::CLINT_BASE;
+use crate::emulator::devices::plic::PLIC_BASE;
+use crate::emulator::devices::uart::{UartBackend, UART_BASE, UART_IRQ};
+use crate::emulator::interpreter::RV64Platform;
+use crate::emulator::state::rv64_cpu_context::MIPFlags;
+use crate::tests::helpers::{mip, run_script, temp_path, write_program};
+
+// a1 counts up and is stored to DRAM_BASE + 0x1000 every time around the loop
+fn store_loop() -> RV64Platform {
+    let platform = RV64Platform::new(1, 0x10_0000);
+    write_program(&platform, &[
+        0x00001517, // auipc a0, 1
+        0x00158593, // addi a1, a1, 1
+        0x00b53023, // sd a1, 0(a0)
+        0xff9ff06f, // j -8
+    ]);
+
+    platform
+}
+
+// pc, a1, the stored value and mtime
+fn machine_state(platform: &RV64Platform) -> (u64, u64, u64, u64) {
+    let mut stored = [0u8; 8];
+    let mut mtime = [0u8; 8];
+    platform.read_memory(0, DRAM_BASE + 0x1000, true, &mut stored).unwrap();
+    platform.read_memory(0, CLINT_BASE as u64 + 0xBFF8, true, &mut mtime).unwrap();
+
+    let cpu = platform.get_cpu_context(0);
+
+    (cpu.pc, cpu.x[11], u64::from_le_bytes(stored), u64::from_le_bytes(mtime))
+}
+
+// Runs the loop while recording and returns the state after every round, the first one is the start
+fn record_rounds(platform: &mut RV64Platform, interval: u64, limit: usize, rounds: usize) -> Vec<(u64, u64, u64, u64)> {
+    platform.start_recording(interval, limit);
+
+    let mut states = vec![machine_state(platform)];
+    for _ in 0..rounds {
+        platform.step().unwrap();
+        states.push(machine_state(platform));
+    }
+
+    states
+}
+
+#[rstest]
+#[case::one(1)]
+#[case::to_snapshot(10)]
+#[case::across_snapshots(23)]
+#[case::to_start(50)]
+pub fn test_reverse_step(#[case] count: u64) {
+    let platform = &mut store_loop();
+    let states = record_rounds(platform, 4, 100, 50);
+
+    assert!(platform.reverse_step(count));
+    assert_eq!(machine_state(platform), states[50 - count as usize]);
+    assert_eq!(platform.reversible_steps(), Some(50 - count));
+
+    // Running forward again ends up where the recording left off
+    for _ in 0..count {
+        platform.step().unwrap();
+    }
+    assert_eq!(machine_state(platform), states[50]);
+}
+
+#[rstest]
+pub fn test_history_limit() {
+    let platform = &mut store_loop();
+    let states = record_rounds(platform, 2, 3, 20);
+
+    // Only the snapshots of rounds 16, 18 and 20 are kept
+    assert_eq!(platform.reversible_steps(), Some(4));
+    assert!(!platform.reverse_step(10));
+    assert_eq!(machine_state(platform), states[16]);
+}
+
+#[rstest]
+pub fn test_stop_recording() {
+    let platform = &mut store_loop();
+    record_rounds(platform, 4, 100, 10);
+
+    platform.stop_recording();
+
+    assert!(!platform.is_recording());
+    assert!(!platform.reverse_step(1));
+    assert_eq!(platform.reversible_steps(), None);
+}
+
+#[rstest]
+pub fn test_reverse_devices() {
+    let platform = &mut store_loop();
+    let console = temp_path("reverse", "log");
+    platform.attach_uart(&UartBackend::File(console.clone())).unwrap();
+
+    // The UART's interrupt is routed to M-mode of the hart, interrupts stay disabled so it is only pending
+    platform.write_memory(0, PLIC_BASE as u64 + 4 * UART_IRQ as u64, true, &1u32.to_le_bytes()).unwrap();
+    platform.write_memory(0, PLIC_BASE as u64 + 0x2000, true, &(1u32 << UART_IRQ).to_le_bytes()).unwrap();
+
+    record_rounds(platform, 4, 100, 5);
+
+    // Enabling the transmitter interrupt raises it right away
+    platform.write_memory(0, UART_BASE as u64 + 1, true, &[0x02]).unwrap();
+    platform.step().unwrap();
+    assert_eq!(mip(platform.get_cpu_context(0)), MIPFlags::MEIP.bits());
+
+    assert!(platform.reverse_step(6));
+
+    let mut ier = [0u8];
+    let mut pending = [0u8; 4];
+    platform.read_memory(0, UART_BASE as u64 + 1, true, &mut ier).unwrap();
+    platform.read_memory(0, PLIC_BASE as u64 + 0x1000, true, &mut pending).unwrap();
+
+    assert_eq!(ier, [0]);
+    assert_eq!(u32::from_le_bytes(pending), 0);
+    assert_eq!(mip(platform.get_cpu_context(0)), 0);
+
+    std::fs::remove_file(console).unwrap();
+}
+
+#[rstest]
+#[case::breakpoints("record\nrun 10\nb 0x80000008\nrc\nexpect pc 0x80000008\nexpect a1 3\nrc\nexpect a1 2\nrs\nexpect pc 0x80000004\nexpect a1 1")]
+#[case::watchpoint("record\nrun 10\nwatch 0x80001000 8\nrc\nexpect pc 0x80000008\nexpect a1 3")]
+#[case::register("record\nrun 10\nwatch $a1\nrc\nexpect pc 0x80000004\nexpect a1 2\nrc\nexpect a1 1")]
+#[case::history_start("record\nrun 3\nrc\nexpect pc 0x80000000\nexpect a1 0")]
+#[case::reverse_step_count("record\nrun 10\nrs 6\nexpect pc 0x80000004\nexpect a1 1\nrs 10\nexpect pc 0x80000000")]
+#[case::not_recording("run 3\nrs\nrc\nexpect pc 0x8000000c")]
+#[case::forward_again("record\nrun 10\nb 0x80000008\nrc\nc\nexpect a1 4")]
+pub fn test_reverse_commands(#[case] script: &str) {
+    assert_eq!(run_script(&mut store_loop(), script), 0);
+}
@@ -0,0 +1,210 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use rstest::rstest;
+use crate::emulator::devices::clint::ClintState;
+use crate::emulator::devices::plic::{IrqLine, PlicState};
+use crate::emulator::devices::uart::{UartBackend, UartState};
+use crate::emulator::sbi::{HartState, Sbi, SystemReset, SBI_ERR_ALREADY_STARTED, SBI_ERR_INVALID_PARAM, SBI_ERR_NOT_SUPPORTED,
+                           SBI_EXT_BASE, SBI_EXT_DBCN, SBI_EXT_HSM, SBI_EXT_IPI, SBI_EXT_SRST, SBI_EXT_TIME, SBI_SUCCESS};
+use crate::emulator::state::memory::MemoryManagementUnit;
+use crate::emulator::state::rv64_cpu_context::{CSRAddress, MIPFlags, MStatusFlags, PrivilegeMode, RV64CPUContext};
+use crate::tests::helpers::{mip, temp_path};
+
+struct Machine {
+    harts: Vec<RV64CPUContext>,
+    sbi: Sbi,
+    clint: Arc<ClintState>,
+    uart: Arc<UartState>,
+    console: PathBuf,
+}
+
+// Two harts in S-mode sharing 64 KiB of RAM at 0, the console goes to a file
+fn setup_sbi(name: &str) -> Machine {
+    let mmu = MemoryManagementUnit::new_guard(0x1_0000);
+    let mut harts = vec![RV64CPUContext::new(0, mmu.clone()), RV64CPUContext::new(0, mmu.clone())];
+    let lines: Vec<_> = harts.iter().map(|hart| hart.csrs.get_interrupt_lines()).collect();
+
+    let clint = ClintState::new(lines.clone());
+    clint.route_timer_interrupt(MIPFlags::STIP);
+
+    for hart in harts.iter_mut() {
+        hart.csrs.set_time_source(clint.get_mtime());
+    }
+
+    Sbi::prepare_hart(&mut harts[0]);
+
+    let console = temp_path(&format!("sbi-{}", name), "log");
+    let uart = UartState::new(IrqLine::new(PlicState::new(lines), 10), &UartBackend::File(console.clone())).unwrap();
+
+    Machine { harts, sbi: Sbi::new(2), clint, uart, console }
+}
+
+// Issues an SBI call from hart_id and returns a0/a1
+fn call(machine: &mut Machine, hart_id: usize, extension: u64, function: u64, args: &[u64]) -> (i64, u64) {
+    let caller = &mut machine.harts[hart_id];
+    caller.set_register(17, extension);
+    caller.set_register(16, function);
+
+    for (i, arg) in args.iter().enumerate() {
+        caller.set_register(10 + i, *arg);
+    }
+
+    let mut harts: Vec<&mut RV64CPUContext> = machine.harts.iter_mut().collect();
+    machine.sbi.handle_ecall(hart_id, &mut harts, &machine.clint, Some(&machine.uart));
+
+    (machine.harts[hart_id].x[10] as i64, machine.harts[hart_id].x[11])
+}
+
+#[rstest]
+#[case::spec_version(0, 0, (SBI_SUCCESS, 2 << 24))]
+#[case::probe_hsm(3, SBI_EXT_HSM, (SBI_SUCCESS, 1))]
+#[case::probe_dbcn(3, SBI_EXT_DBCN, (SBI_SUCCESS, 1))]
+#[case::probe_unknown(3, 0x0A00_0000, (SBI_SUCCESS, 0))]
+#[case::unknown_function(42, 0, (SBI_ERR_NOT_SUPPORTED, 0))]
+pub fn test_sbi_base(#[case] function: u64, #[case] arg: u64, #[case] result: (i64, u64)) {
+    let mut machine = setup_sbi(&format!("base-{}-{:x}", function, arg));
+
+    assert_eq!(call(&mut machine, 0, SBI_EXT_BASE, function, &[arg]), result);
+
+    std::fs::remove_file(machine.console).unwrap();
+}
+
+#[rstest]
+pub fn test_sbi_prepare_hart() {
+    let mut machine = setup_sbi("prepare");
+
+    assert_eq!(machine.harts[0].csrs.get_current_privilege(), PrivilegeMode::Supervisor);
+
+    // rdtime works from S-mode once mcounteren.TM is set
+    machine.clint.write_mtime(7);
+    assert_eq!(machine.harts[0].csrs.read_csr(CSRAddress::Time as u16, false).unwrap(), 7);
+
+    assert_eq!(call(&mut machine, 0, 0x0A00_0000, 0, &[]), (SBI_ERR_NOT_SUPPORTED, 0));
+
+    std::fs::remove_file(machine.console).unwrap();
+}
+
+#[rstest]
+pub fn test_sbi_set_timer() {
+    let mut machine = setup_sbi("timer");
+
+    assert_eq!(call(&mut machine, 0, SBI_EXT_TIME, 0, &[100]), (SBI_SUCCESS, 0));
+    assert_eq!(machine.clint.read_mtimecmp(0), 100);
+
+    // The comparator drives STIP instead of MTIP, a new deadline clears it again
+    machine.clint.write_mtime(100);
+    assert_eq!(mip(&machine.harts[0]), MIPFlags::STIP.bits());
+
+    call(&mut machine, 0, SBI_EXT_TIME, 0, &[200]);
+    assert_eq!(mip(&machine.harts[0]), 0);
+
+    std::fs::remove_file(machine.console).unwrap();
+}
+
+#[rstest]
+#[case::hart1(0b10, 0, Some(1))]
+#[case::base_offset(0b1, 1, Some(1))]
+#[case::all(0, u64::MAX, None)]
+#[case::out_of_range(0b100, 0, Some(2))]
+#[case::overflowing_base(0b100, u64::MAX - 1, Some(2))]
+pub fn test_sbi_send_ipi(#[case] hart_mask: u64, #[case] hart_mask_base: u64, #[case] target: Option<usize>) {
+    let mut machine = setup_sbi(&format!("ipi-{}-{:x}", hart_mask, hart_mask_base));
+
+    let (error, _) = call(&mut machine, 0, SBI_EXT_IPI, 0, &[hart_mask, hart_mask_base]);
+    let pending: Vec<bool> = machine.harts.iter()
+        .map(|hart| hart.csrs.read_csr(CSRAddress::MIP as u16, true).unwrap() & MIPFlags::SSIP.bits() != 0)
+        .collect();
+
+    match target {
+        Some(2) => {
+            assert_eq!(error, SBI_ERR_INVALID_PARAM);
+            assert_eq!(pending, [false, false]);
+        }
+        Some(hart_id) => {
+            assert_eq!(error, SBI_SUCCESS);
+            assert_eq!(pending, [hart_id == 0, hart_id == 1]);
+        }
+        None => assert_eq!(pending, [true, true]),
+    }
+
+    std::fs::remove_file(machine.console).unwrap();
+}
+
+#[rstest]
+pub fn test_sbi_hsm() {
+    let mut machine = setup_sbi("hsm");
+
+    assert_eq!(call(&mut machine, 0, SBI_EXT_HSM, 2, &[1]), (SBI_SUCCESS, HartState::Stopped as u64));
+    assert_eq!(call(&mut machine, 0, SBI_EXT_HSM, 0, &[1, 0x2000, 0xCAFE]), (SBI_SUCCESS, 0));
+
+    let started = &machine.harts[1];
+    assert_eq!(started.pc, 0x2000);
+    assert_eq!((started.x[10], started.x[11]), (1, 0xCAFE));
+    assert_eq!(started.csrs.get_current_privilege(), PrivilegeMode::Supervisor);
+    assert!(machine.sbi.is_running(1));
+
+    assert_eq!(call(&mut machine, 0, SBI_EXT_HSM, 0, &[1, 0x2000, 0]).0, SBI_ERR_ALREADY_STARTED);
+    assert_eq!(call(&mut machine, 0, SBI_EXT_HSM, 0, &[2, 0x2000, 0]).0, SBI_ERR_INVALID_PARAM);
+
+    // A stopped hart does not get a return value
+    machine.harts[1].set_register(10, 0x55);
+    call(&mut machine, 1, SBI_EXT_HSM, 1, &[]);
+    assert_eq!(machine.sbi.get_hart_state(1), HartState::Stopped);
+    assert!(!machine.sbi.is_running(1));
+
+    // Restarted without the paging and interrupt enable the kernel left behind
+    let stale = &mut machine.harts[1].csrs;
+    stale.write_csr(CSRAddress::SATP as u16, 8 << 60 | 0x1234, true).unwrap();
+    stale.write_csr(CSRAddress::MStatus as u16, (MStatusFlags::SIE | MStatusFlags::SPP).bits(), true).unwrap();
+
+    assert_eq!(call(&mut machine, 0, SBI_EXT_HSM, 0, &[1, 0x3000, 0]), (SBI_SUCCESS, 0));
+
+    let restarted = &machine.harts[1].csrs;
+    assert_eq!(restarted.read_csr(CSRAddress::SATP as u16, true).unwrap(), 0);
+    assert_eq!(restarted.read_csr(CSRAddress::MStatus as u16, true).unwrap() & (MStatusFlags::SIE | MStatusFlags::SPP).bits(), 0);
+
+    std::fs::remove_file(machine.console).unwrap();
+}
+
+#[rstest]
+#[case::shutdown(0, 0, true)]
+#[case::warm_reboot(2, 1, true)]
+#[case::invalid_type(3, 0, false)]
+pub fn test_sbi_system_reset(#[case] reset_type: u64, #[case] reason: u64, #[case] valid: bool) {
+    let mut machine = setup_sbi(&format!("srst-{}-{}", reset_type, reason));
+
+    let (error, _) = call(&mut machine, 0, SBI_EXT_SRST, 0, &[reset_type, reason]);
+
+    if valid {
+        assert_eq!(machine.sbi.get_reset(), Some(SystemReset { reset_type: reset_type as u32, reason: reason as u32 }));
+        assert!(!machine.sbi.is_running(0));
+    } else {
+        assert_eq!(error, SBI_ERR_INVALID_PARAM);
+        assert_eq!(machine.sbi.get_reset(), None);
+    }
+
+    std::fs::remove_file(machine.console).unwrap();
+}
+
+#[rstest]
+pub fn test_sbi_debug_console() {
+    let mut machine = setup_sbi("dbcn");
+
+    machine.harts[0].memory.write().unwrap().write(0x100, 6, b"hello ");
+
+    assert_eq!(call(&mut machine, 0, SBI_EXT_DBCN, 0, &[6, 0x100, 0]), (SBI_SUCCESS, 6));
+    assert_eq!(call(&mut machine, 0, SBI_EXT_DBCN, 2, &[b'!' as u64]), (SBI_SUCCESS, 0));
+    assert_eq!(call(&mut machine, 0, SBI_EXT_DBCN, 0, &[6, 0x10_0000, 0]).0, SBI_ERR_INVALID_PARAM);
+    assert_eq!(call(&mut machine, 0, SBI_EXT_DBCN, 0, &[u64::MAX, 0x100, 0]).0, SBI_ERR_INVALID_PARAM);
+    assert_eq!(call(&mut machine, 0, SBI_EXT_DBCN, 1, &[u64::MAX, 0x100, 0]).0, SBI_ERR_INVALID_PARAM);
+
+    assert_eq!(std::fs::read(&machine.console).unwrap(), b"hello !");
+
+    // Reads return whatever input is queued, possibly nothing
+    machine.uart.receive(b"ab");
+    assert_eq!(call(&mut machine, 0, SBI_EXT_DBCN, 1, &[8, 0x200, 0]), (SBI_SUCCESS, 2));
+    assert_eq!(machine.harts[0].memory.read().unwrap().read_half_word(0x200), u16::from_le_bytes(*b"ab"));
+    assert_eq!(call(&mut machine, 0, SBI_EXT_DBCN, 1, &[8, 0x200, 0]), (SBI_SUCCESS, 0));
+
+    std::fs::remove_file(machine.console).unwrap();
+}
@@ -0,0 +1,58 @@
+use rstest::rstest;
+use crate::emulator::constants::DRAM_BASE;
+use crate::emulator::interpreter::RV64Platform;
+use crate::tests::helpers::{run_script, write_program};
+
+fn run(script: &str) -> (RV64Platform, usize) {
+    let mut platform = RV64Platform::new(1, 0x10_0000);
+    write_program(&platform, &[
+        0x00500513, // li a0, 5
+        0x00150513, // addi a0, a0, 1
+        0x00150513, // addi a0, a0, 1
+        0x0000006f, // j .
+    ]);
+
+    let failures = run_script(&mut platform, script);
+
+    (platform, failures)
+}
+
+#[rstest]
+#[case::run("run 2\nexpect a0 6\nexpect pc 0x80000008", 0, DRAM_BASE + 8)]
+#[case::until("# comment\n\nuntil 0x8000000c\nexpect a0 7", 0, DRAM_BASE + 12)]
+#[case::breakpoint_stops_run("b 0x80000004\nrun 10\nexpect a0 5", 0, DRAM_BASE + 4)]
+#[case::failed_expectation("run 1\nexpect a0 6\nexpect a0 5", 1, DRAM_BASE + 4)]
+#[case::invalid_expectation("expect q0 1\nexpect a0\necho done", 2, DRAM_BASE)]
+#[case::set_then_expect("set reg t0 -1\nexpect x5 0xffffffffffffffff", 0, DRAM_BASE)]
+pub fn test_script(#[case] script: &str, #[case] expected_failures: usize, #[case] expected_pc: u64) {
+    let (platform, failures) = run(script);
+
+    assert_eq!(failures, expected_failures);
+    assert_eq!(platform.get_cpu_context(0).pc, expected_pc);
+}
+
+#[rstest]
+pub fn test_missing_script() {
+    let mut platform = RV64Platform::new(1, 0x10_0000);
+
+    assert!(platform.run_script(&std::env::temp_dir().join("rocket-v-no-such-script"), |_cycle| {}).is_err());
+}
+
+#[rstest]
+#[case::handled_by_guest("run 10\nexpect a0 7", 0x80000014)]
+#[case::catch("catch\nrun 10\nexpect a0 0", 0x80000010)]
+#[case::catch_off("catch\ncatch off\nrun 10\nexpect a0 7", 0x80000014)]
+pub fn test_script_traps(#[case] script: &str, #[case] expected_pc: u64) {
+    let mut platform = RV64Platform::new(1, 0x10_0000);
+    write_program(&platform, &[
+        0x00000297, // auipc t0, 0
+        0x01028293, // addi t0, t0, 16
+        0x30529073, // csrw mtvec, t0
+        0x00000073, // ecall
+        0x00700513, // li a0, 7
+        0x0000006f, // j .
+    ]);
+
+    assert_eq!(run_script(&mut platform, script), 0);
+    assert_eq!(platform.get_cpu_context(0).pc, expected_pc);
+}
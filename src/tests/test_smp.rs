@@ -0,0 +1,130 @@
+use rstest::rstest;
+use crate::emulator::constants::DRAM_BASE;
+use crate::emulator::instructions::rv64::RV64InstructionParser;
+use crate::emulator::interpreter::RV64Platform;
+use crate::tests::helpers::write_program;
+use crate::emulator::state::memory::MemoryManagementUnit;
+use crate::emulator::state::rv64_cpu_context::{CSRAddress, RV64CPUContext};
+
+fn execute(cpu: &mut RV64CPUContext, instr: u32) {
+    let instr_fn = RV64InstructionParser::parse(instr);
+    instr_fn(cpu, instr).unwrap();
+}
+
+#[rstest]
+#[case::single(1)]
+#[case::quad(4)]
+pub fn test_hart_ids(#[case] hart_count: u64) {
+    let platform = RV64Platform::new(hart_count, 0x10_0000);
+
+    assert_eq!(platform.get_hart_count(), hart_count as usize);
+
+    for hart_id in 0..platform.get_hart_count() {
+        let cpu = platform.get_cpu_context(hart_id);
+
+        assert_eq!(cpu.hart_id, hart_id as u64);
+        assert_eq!(cpu.csrs.read_csr(CSRAddress::MHartID as u16, false).unwrap(), hart_id as u64);
+    }
+
+    // Every hart shows up in the device tree
+    let tree = platform.generate_device_tree();
+    assert_eq!(tree.find("/cpus").unwrap().children.len(), hart_count as usize);
+}
+
+#[rstest]
+pub fn test_round_robin() {
+    let platform = &mut RV64Platform::new(2, 0x10_0000);
+
+    write_program(platform, &[
+        0x00001517, // auipc a0, 1
+        0x00100593, // addi a1, zero, 1
+        0x00B5202F, // amoadd.w zero, a1, (a0)
+        0xF1402673, // csrr a2, mhartid
+        0x0000006F, // j .
+    ]);
+
+    for _ in 0..5 {
+        platform.step().unwrap();
+    }
+
+    // Both harts ran the whole program in the same number of steps
+    for hart_id in 0..2 {
+        let cpu = platform.get_cpu_context(hart_id);
+
+        assert_eq!(cpu.pc, DRAM_BASE + 0x10);
+        assert_eq!(cpu.x[12], hart_id as u64);
+    }
+
+    let counter = platform.get_cpu_context(0).memory.read().unwrap().read_word(DRAM_BASE as usize + 0x1000);
+    assert_eq!(counter, 2);
+}
+
+#[rstest]
+pub fn test_stopped_harts_do_not_run() {
+    let platform = &mut RV64Platform::new(2, 0x10_0000);
+
+    write_program(platform, &[0x00000013; 4]); // nop
+
+    // The built-in SBI leaves every hart but the boot hart stopped
+    platform.enable_builtin_sbi();
+
+    for _ in 0..3 {
+        platform.step().unwrap();
+    }
+
+    assert_eq!(platform.get_cpu_context(0).pc, DRAM_BASE + 12);
+    assert_eq!(platform.get_cpu_context(1).pc, DRAM_BASE);
+}
+
+#[rstest]
+#[case::same_word(0x1000, 1, 0x1111)]
+#[case::same_doubleword(0x1004, 1, 0x1111)] //The reservation covers the whole aligned doubleword
+#[case::other_doubleword(0x1008, 0, 0x2222)]
+pub fn test_reservation_invalidated_by_other_hart(
+    #[case] store_addr: u64,
+    #[case] expected_rd: u64,
+    #[case] expected_mem: u32
+) {
+    let mmu = MemoryManagementUnit::new_guard(0x4000);
+    let mut hart0 = RV64CPUContext::new(0, mmu.clone());
+    let mut hart1 = RV64CPUContext::new(0, mmu.clone());
+    hart1.set_hart_id(1);
+
+    mmu.write().unwrap().write_word(0x1000, 0x1111);
+
+    hart0.set_register(3, 0x1000);
+    hart0.set_register(4, 0x2222);
+    execute(&mut hart0, 0x1001a2af); // lr.w x5, (x3)
+
+    hart1.store(store_addr, 4, 0x1111).unwrap();
+
+    execute(&mut hart0, 0x1841a2af); // sc.w x5, x4, (x3)
+
+    assert_eq!(hart0.x[5], expected_rd);
+    assert_eq!(mmu.read().unwrap().read_word(0x1000), expected_mem);
+}
+
+#[rstest]
+pub fn test_sc_releases_reservation() {
+    let mmu = MemoryManagementUnit::new_guard(0x4000);
+    let mut hart0 = RV64CPUContext::new(0, mmu.clone());
+    let mut hart1 = RV64CPUContext::new(0, mmu.clone());
+    hart1.set_hart_id(1);
+
+    for hart in [&mut hart0, &mut hart1] {
+        hart.set_register(3, 0x1000);
+        hart.set_register(4, 0x2222);
+        execute(hart, 0x1001a2af); // lr.w x5, (x3)
+    }
+
+    // The first SC wins and breaks the reservation of the other hart
+    execute(&mut hart1, 0x1841a2af); // sc.w x5, x4, (x3)
+    execute(&mut hart0, 0x1841a2af);
+
+    assert_eq!(hart1.x[5], 0);
+    assert_eq!(hart0.x[5], 1);
+
+    // A second SC without a new LR fails even though nothing else wrote the address
+    execute(&mut hart1, 0x1841a2af);
+    assert_eq!(hart1.x[5], 1);
+}
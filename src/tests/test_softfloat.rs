@@ -0,0 +1,126 @@
+use rstest::rstest;
+use crate::emulator::softfloat;
+use crate::emulator::softfloat::{FloatFormat, RoundingMode, DOUBLE, SINGLE};
+use crate::emulator::state::rv64_cpu_context::FFlags;
+
+const RNE: RoundingMode = RoundingMode::NearestEven;
+const RTZ: RoundingMode = RoundingMode::TowardZero;
+const RDN: RoundingMode = RoundingMode::Down;
+const RUP: RoundingMode = RoundingMode::Up;
+const RMM: RoundingMode = RoundingMode::NearestMaxMagnitude;
+
+const NONE: FFlags = FFlags::empty();
+
+#[rstest]
+#[case::third_rne(RNE, 0x3f800000, 0x3eaaaaab)]
+#[case::third_rtz(RTZ, 0x3f800000, 0x3eaaaaaa)]
+#[case::third_rdn(RDN, 0x3f800000, 0x3eaaaaaa)]
+#[case::third_rup(RUP, 0x3f800000, 0x3eaaaaab)]
+#[case::third_rmm(RMM, 0x3f800000, 0x3eaaaaab)]
+#[case::negative_third_rdn(RDN, 0xbf800000, 0xbeaaaaab)]
+#[case::negative_third_rup(RUP, 0xbf800000, 0xbeaaaaaa)]
+pub fn test_div_rounding(#[case] rm: RoundingMode, #[case] dividend: u64, #[case] result: u64) {
+    // x / 3.0
+    assert_eq!(softfloat::div(SINGLE, dividend, 0x40400000, rm), (result, FFlags::NX));
+}
+
+#[rstest]
+#[case::inexact(DOUBLE, RNE, 0x3fb999999999999a, 0x3fc999999999999a, 0x3fd3333333333334, FFlags::NX)]
+#[case::tie_to_even(SINGLE, RNE, 0x3f800000, 0x33800000, 0x3f800000, FFlags::NX)]
+#[case::tie_away(SINGLE, RMM, 0x3f800000, 0x33800000, 0x3f800001, FFlags::NX)]
+#[case::tie_up(SINGLE, RUP, 0x3f800000, 0x33800000, 0x3f800001, FFlags::NX)]
+#[case::exact_cancel(SINGLE, RNE, 0x3f800000, 0xbf800000, 0x00000000, NONE)]
+#[case::exact_cancel_rdn(SINGLE, RDN, 0x3f800000, 0xbf800000, 0x80000000, NONE)]
+#[case::infinity_minus_infinity(SINGLE, RNE, 0x7f800000, 0xff800000, 0x7fc00000, FFlags::NV)]
+#[case::quiet_nan(DOUBLE, RNE, 0x7ff8000000000001, 0x3ff0000000000000, 0x7ff8000000000000, NONE)]
+#[case::signaling_nan(DOUBLE, RNE, 0x7ff0000000000001, 0x3ff0000000000000, 0x7ff8000000000000, FFlags::NV)]
+pub fn test_add(#[case] format: FloatFormat, #[case] rm: RoundingMode, #[case] a: u64, #[case] b: u64, #[case] result: u64, #[case] flags: FFlags) {
+    assert_eq!(softfloat::add(format, a, b, rm), (result, flags));
+}
+
+#[rstest]
+#[case::overflow_rne(RNE, 0x7f7fffff, 0x40000000, 0x7f800000, FFlags::OF | FFlags::NX)]
+#[case::overflow_rtz(RTZ, 0x7f7fffff, 0x40000000, 0x7f7fffff, FFlags::OF | FFlags::NX)]
+#[case::overflow_rdn(RDN, 0xff7fffff, 0x40000000, 0xff800000, FFlags::OF | FFlags::NX)]
+#[case::overflow_rup(RUP, 0xff7fffff, 0x40000000, 0xff7fffff, FFlags::OF | FFlags::NX)]
+#[case::exact_subnormal(RNE, 0x00800000, 0x3f000000, 0x00400000, NONE)]
+#[case::inexact_subnormal(RNE, 0x00800001, 0x3f000000, 0x00400000, FFlags::UF | FFlags::NX)]
+#[case::tiny_rounds_to_normal(RNE, 0x3f7fffff, 0x00800000, 0x00800000, FFlags::UF | FFlags::NX)]
+#[case::underflow_to_zero(RNE, 0x00000001, 0x00000001, 0x00000000, FFlags::UF | FFlags::NX)]
+#[case::infinity_times_zero(RNE, 0x7f800000, 0x00000000, 0x7fc00000, FFlags::NV)]
+pub fn test_mul(#[case] rm: RoundingMode, #[case] a: u64, #[case] b: u64, #[case] result: u64, #[case] flags: FFlags) {
+    assert_eq!(softfloat::mul(SINGLE, a, b, rm), (result, flags));
+}
+
+#[rstest]
+#[case::divide_by_zero(0x3f800000, 0x00000000, 0x7f800000, FFlags::DZ)]
+#[case::negative_divide_by_zero(0x3f800000, 0x80000000, 0xff800000, FFlags::DZ)]
+#[case::zero_by_zero(0x00000000, 0x00000000, 0x7fc00000, FFlags::NV)]
+#[case::exact(0x40c00000, 0x40400000, 0x40000000, NONE)]
+pub fn test_div_special(#[case] a: u64, #[case] b: u64, #[case] result: u64, #[case] flags: FFlags) {
+    assert_eq!(softfloat::div(SINGLE, a, b, RNE), (result, flags));
+}
+
+#[rstest]
+#[case::root_two(0x4000000000000000, 0x3ff6a09e667f3bcd, FFlags::NX)]
+#[case::exact(0x4010000000000000, 0x4000000000000000, NONE)]
+#[case::subnormal(0x0000000000000004, 0x1e70000000000000, NONE)]
+#[case::negative_zero(0x8000000000000000, 0x8000000000000000, NONE)]
+#[case::negative(0xbff0000000000000, 0x7ff8000000000000, FFlags::NV)]
+pub fn test_sqrt(#[case] a: u64, #[case] result: u64, #[case] flags: FFlags) {
+    assert_eq!(softfloat::sqrt(DOUBLE, a, RNE), (result, flags));
+}
+
+#[rstest]
+// (1 + 2^-52) * (1 - 2^-53) - 1 is only exact without an intermediate rounding
+#[case::single_rounding(0x3ff0000000000001, 0x3fefffffffffffff, 0xbff0000000000000, 0x3c9ffffffffffffe, NONE)]
+#[case::infinity_times_zero_plus_quiet_nan(0x7ff0000000000000, 0x0000000000000000, 0x7ff8000000000000, 0x7ff8000000000000, FFlags::NV)]
+#[case::zero_product(0x0000000000000000, 0x3ff0000000000000, 0x8000000000000000, 0x0000000000000000, NONE)]
+pub fn test_fused_mul_add(#[case] a: u64, #[case] b: u64, #[case] c: u64, #[case] result: u64, #[case] flags: FFlags) {
+    assert_eq!(softfloat::fused_mul_add(DOUBLE, a, b, c, RNE), (result, flags));
+}
+
+#[rstest]
+#[case::tiny_after_rounding_rne(RNE, 0x380ffffff0000000, 0x00800000, FFlags::NX)]
+#[case::tiny_after_rounding_rtz(RTZ, 0x380ffffff0000000, 0x007fffff, FFlags::UF | FFlags::NX)]
+#[case::overflow(RNE, 0x47f0000000000000, 0x7f800000, FFlags::OF | FFlags::NX)]
+#[case::signaling_nan(RNE, 0x7ff0000000000001, 0x7fc00000, FFlags::NV)]
+pub fn test_narrowing_convert(#[case] rm: RoundingMode, #[case] a: u64, #[case] result: u64, #[case] flags: FFlags) {
+    assert_eq!(softfloat::convert(DOUBLE, SINGLE, a, rm), (result, flags));
+}
+
+#[rstest]
+#[case::ties_even(RNE, 0x4004000000000000, true, 64, 2, FFlags::NX)]
+#[case::ties_away(RMM, 0x4004000000000000, true, 64, 3, FFlags::NX)]
+#[case::down(RDN, 0xc004000000000000, true, 64, 0xfffffffffffffffd, FFlags::NX)]
+#[case::word_overflow(RTZ, 0x41e0000000000000, true, 32, 0x7fffffff, FFlags::NV)]
+#[case::word_minimum(RTZ, 0xc1e0000000000000, true, 32, 0xffffffff80000000, NONE)]
+#[case::unsigned_small_negative(RTZ, 0xbfe0000000000000, false, 32, 0, FFlags::NX)]
+#[case::unsigned_negative(RTZ, 0xbff0000000000000, false, 64, 0, FFlags::NV)]
+#[case::unsigned_word_max(RTZ, 0x41efffffffe00000, false, 32, 0xffffffffffffffff, NONE)]
+#[case::nan(RTZ, 0xfff8000000000000, true, 64, 0x7fffffffffffffff, FFlags::NV)]
+pub fn test_to_int(#[case] rm: RoundingMode, #[case] a: u64, #[case] signed: bool, #[case] width: u32, #[case] result: u64, #[case] flags: FFlags) {
+    assert_eq!(softfloat::to_int(DOUBLE, a, rm, signed, width), (result, flags));
+}
+
+#[rstest]
+#[case::rne(RNE, 0x43f0000000000000, FFlags::NX)]
+#[case::rtz(RTZ, 0x43efffffffffffff, FFlags::NX)]
+pub fn test_from_int(#[case] rm: RoundingMode, #[case] result: u64, #[case] flags: FFlags) {
+    assert_eq!(softfloat::from_int(DOUBLE, u64::MAX, false, 64, rm), (result, flags));
+}
+
+#[rstest]
+pub fn test_compare() {
+    let signaling_nan = 0x7fa00000;
+    let quiet_nan = 0x7fc00000;
+
+    assert_eq!(softfloat::eq(SINGLE, 0x00000000, 0x80000000), (true, NONE));
+    assert_eq!(softfloat::eq(SINGLE, quiet_nan, 0x3f800000), (false, NONE));
+    assert_eq!(softfloat::eq(SINGLE, signaling_nan, 0x3f800000), (false, FFlags::NV));
+    assert_eq!(softfloat::lt(SINGLE, quiet_nan, 0x3f800000), (false, FFlags::NV));
+    assert_eq!(softfloat::lt(SINGLE, 0xbf800000, 0x3f800000), (true, NONE));
+    assert_eq!(softfloat::le(SINGLE, 0x80000000, 0x00000000), (true, NONE));
+    assert_eq!(softfloat::min(SINGLE, quiet_nan, signaling_nan), (quiet_nan, FFlags::NV));
+    assert_eq!(softfloat::max(SINGLE, 0x80000000, 0x00000000), (0x00000000, NONE));
+}
@@ -0,0 +1,109 @@
+use std::fs;
+use rstest::rstest;
+use crate::emulator::constants::DRAM_BASE;
+use crate::emulator::interpreter::RV64Platform;
+use crate::emulator::trace::{TraceRange, Tracer};
+use crate::tests::helpers::temp_path;
+
+fn platform_with_program() -> RV64Platform {
+    let platform = RV64Platform::new(1, 0x10_0000);
+    let program: Vec<u8> = [
+        &0x00001517u32.to_le_bytes()[..], // auipc a0, 1
+        &0x00158593u32.to_le_bytes(),     // addi a1, a1, 1
+        &0x00b53023u32.to_le_bytes(),     // sd a1, 0(a0)
+        &0x00053603u32.to_le_bytes(),     // ld a2, 0(a0)
+        &0x86b2u16.to_le_bytes(),         // c.mv a3, a2
+        &0x34059073u32.to_le_bytes(),     // csrw mscratch, a1
+        &0x00000073u32.to_le_bytes(),     // ecall
+    ].concat();
+    platform.write_memory(0, DRAM_BASE, true, &program).unwrap();
+
+    platform
+}
+
+// Runs rounds steps with a trace using the given filters and returns its lines
+fn trace(platform: &mut RV64Platform, pc_range: Option<TraceRange>, cycle_range: Option<TraceRange>, rounds: usize) -> Vec<String> {
+    let path = temp_path("trace", "log");
+
+    let mut tracer = Tracer::create(&path).unwrap();
+    tracer.set_pc_range(pc_range);
+    tracer.set_cycle_range(cycle_range);
+    platform.start_trace(tracer);
+
+    for _ in 0..rounds {
+        let _ = platform.step();
+    }
+
+    platform.stop_trace();
+
+    let lines = fs::read_to_string(&path).unwrap().lines().map(str::to_string).collect();
+    fs::remove_file(&path).unwrap();
+
+    lines
+}
+
+#[rstest]
+pub fn test_commit_log() {
+    let platform = &mut platform_with_program();
+
+    let lines = trace(platform, None, None, 7);
+
+    assert_eq!(lines, [
+        "core   0: 0x0000000080000000 (0x00001517) auipc   a0, 0x1",
+        "core   0: 3 0x0000000080000000 (0x00001517) x10 0x0000000080001000",
+        "core   0: 0x0000000080000004 (0x00158593) addi    a1, a1, 1",
+        "core   0: 3 0x0000000080000004 (0x00158593) x11 0x0000000000000001",
+        "core   0: 0x0000000080000008 (0x00b53023) sd      a1, 0(a0)",
+        "core   0: 3 0x0000000080000008 (0x00b53023) mem 0x0000000080001000 0x0000000000000001",
+        "core   0: 0x000000008000000c (0x00053603) ld      a2, 0(a0)",
+        "core   0: 3 0x000000008000000c (0x00053603) x12 0x0000000000000001 mem 0x0000000080001000",
+        "core   0: 0x0000000080000010 (0x000086b2) mv      a3, a2",
+        "core   0: 3 0x0000000080000010 (0x86b2) x13 0x0000000000000001",
+        "core   0: 0x0000000080000012 (0x34059073) csrw    mscratch, a1",
+        "core   0: 3 0x0000000080000012 (0x34059073) c832_mscratch 0x0000000000000001",
+        "core   0: 0x0000000080000016 (0x00000073) ecall",
+        "core   0: exception trap_machine_ecall, epc 0x0000000080000016",
+    ]);
+}
+
+#[rstest]
+#[case::pc(Some(TraceRange { start: DRAM_BASE + 4, end: DRAM_BASE + 0xc }), None)]
+#[case::cycles(None, Some(TraceRange { start: 1, end: 3 }))]
+pub fn test_trace_filters(#[case] pc_range: Option<TraceRange>, #[case] cycle_range: Option<TraceRange>) {
+    let platform = &mut platform_with_program();
+
+    let lines = trace(platform, pc_range, cycle_range, 7);
+
+    // Only addi and sd
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].contains("addi"));
+    assert!(lines[2].contains("sd"));
+}
+
+#[rstest]
+pub fn test_replay_is_not_traced() {
+    let platform = &mut platform_with_program();
+    platform.start_recording(2, 10);
+
+    for _ in 0..3 {
+        platform.step().unwrap();
+    }
+
+    platform.reverse_step(1);
+
+    // Going back replays from the snapshot at round 2, only the instruction run again afterwards shows up
+    let lines = trace(platform, None, None, 1);
+
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains("sd"));
+}
+
+#[rstest]
+#[case::both("0x80000000:0x80001000", Ok(TraceRange { start: 0x8000_0000, end: 0x8000_1000 }))]
+#[case::open_end("1000:", Ok(TraceRange { start: 1000, end: u64::MAX }))]
+#[case::open_start(":16", Ok(TraceRange { start: 0, end: 16 }))]
+#[case::no_separator("0x80000000", Err(()))]
+#[case::bad_value("0x8000000g:", Err(()))]
+pub fn test_parse_trace_range(#[case] range: &str, #[case] expected: Result<TraceRange, ()>) {
+    assert_eq!(range.parse::<TraceRange>().map_err(|_| ()), expected);
+}
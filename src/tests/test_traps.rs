@@ -0,0 +1,135 @@
+use rstest::rstest;
+use crate::emulator::state::memory::MemoryManagementUnit;
+use crate::emulator::state::rv64_cpu_context::{CSRAddress, MIPFlags, MStatusFlags, PrivilegeMode, RV64CPUContext, INTERRUPT_BIT};
+
+const MTVEC: u64 = 0x8000;
+const STVEC: u64 = 0x9000;
+const EPC: u64 = 0x1234;
+const TVAL: u64 = 0xdead;
+
+fn setup_traps(privilege: PrivilegeMode, medeleg: u64, mideleg: u64, vectored: bool) -> RV64CPUContext {
+    let mut cpu = RV64CPUContext::new(0x1000, MemoryManagementUnit::new_guard(1024));
+    let mode = if vectored { 1 } else { 0 };
+
+    cpu.csrs.write_csr(CSRAddress::MTVec as u16, MTVEC | mode, true).unwrap();
+    cpu.csrs.write_csr(CSRAddress::STVec as u16, STVEC | mode, true).unwrap();
+    cpu.csrs.write_csr(CSRAddress::MEDeleg as u16, medeleg, true).unwrap();
+    cpu.csrs.write_csr(CSRAddress::MIDeleg as u16, mideleg, true).unwrap();
+    cpu.csrs.change_privilege(privilege);
+
+    cpu
+}
+
+#[rstest]
+#[case::user_ecall_to_m(PrivilegeMode::User, 0, 8, false, MTVEC, PrivilegeMode::Machine)]
+#[case::user_ecall_delegated(PrivilegeMode::User, 1 << 8, 8, false, STVEC, PrivilegeMode::Supervisor)]
+#[case::supervisor_page_fault_delegated(PrivilegeMode::Supervisor, 1 << 13, 13, false, STVEC, PrivilegeMode::Supervisor)]
+#[case::machine_ignores_delegation(PrivilegeMode::Machine, 1 << 2, 2, false, MTVEC, PrivilegeMode::Machine)]
+#[case::vectored_exception(PrivilegeMode::Machine, 0, 2, false, MTVEC, PrivilegeMode::Machine)] //Exceptions always use the base address
+#[case::vectored_interrupt(PrivilegeMode::Machine, 0, 7, true, MTVEC + 4 * 7, PrivilegeMode::Machine)]
+pub fn test_trap_target(#[case] privilege: PrivilegeMode, #[case] deleg: u64, #[case] cause: u64, #[case] interrupt: bool, #[case] handler: u64, #[case] target: PrivilegeMode) {
+    let mut cpu = setup_traps(privilege, if interrupt { 0 } else { deleg }, if interrupt { deleg } else { 0 }, true);
+
+    assert_eq!(cpu.csrs.enter_trap(cause, interrupt, EPC, TVAL), handler);
+    assert_eq!(cpu.csrs.get_current_privilege(), target);
+
+    let (cause_csr, epc_csr, tval_csr) = match target {
+        PrivilegeMode::Supervisor => (CSRAddress::SCause, CSRAddress::SEPC, CSRAddress::STVal),
+        _ => (CSRAddress::MCause, CSRAddress::MEPC, CSRAddress::MTVal),
+    };
+
+    let cause_value = if interrupt { cause | INTERRUPT_BIT } else { cause };
+
+    assert_eq!(cpu.csrs.read_csr(cause_csr as u16, true).unwrap(), cause_value);
+    assert_eq!(cpu.csrs.read_csr(epc_csr as u16, true).unwrap(), EPC);
+    assert_eq!(cpu.csrs.read_csr(tval_csr as u16, true).unwrap(), TVAL);
+}
+
+#[rstest]
+#[case::machine_from_user(PrivilegeMode::User, 0, MStatusFlags::MIE, MStatusFlags::MPIE)]
+#[case::machine_from_supervisor(PrivilegeMode::Supervisor, 0, MStatusFlags::empty(), MStatusFlags::empty())]
+#[case::machine_from_machine(PrivilegeMode::Machine, 0, MStatusFlags::MIE, MStatusFlags::MPIE)]
+#[case::supervisor_from_user(PrivilegeMode::User, 1 << 2, MStatusFlags::SIE, MStatusFlags::SPIE)]
+#[case::supervisor_from_supervisor(PrivilegeMode::Supervisor, 1 << 2, MStatusFlags::SIE, MStatusFlags::SPIE | MStatusFlags::SPP)]
+pub fn test_trap_status_stacking(#[case] privilege: PrivilegeMode, #[case] medeleg: u64, #[case] mstatus: MStatusFlags, #[case] result: MStatusFlags) {
+    let mut cpu = setup_traps(privilege, medeleg, 0, false);
+
+    cpu.csrs.write_csr(CSRAddress::MStatus as u16, mstatus.bits(), true).unwrap();
+    cpu.csrs.enter_trap(2, false, EPC, 0);
+
+    let mstatus = cpu.csrs.read_csr(CSRAddress::MStatus as u16, true).unwrap();
+
+    // MPP records the privilege the trap was taken from, unless the trap went to supervisor mode
+    let mpp = if medeleg == 0 { (privilege as u64) << 11 } else { 0 };
+
+    assert_eq!(mstatus, result.bits() | mpp);
+}
+
+#[rstest]
+#[case::medeleg(CSRAddress::MEDeleg, u64::MAX, 0xB3FF)] //Environment calls from machine mode can't be delegated
+#[case::mideleg(CSRAddress::MIDeleg, u64::MAX, 0x222)] //Only supervisor interrupts can be delegated
+#[case::mtvec_reserved_mode(CSRAddress::MTVec, 0x8002, 0x8000)]
+#[case::stvec_vectored(CSRAddress::STVec, 0x8001, 0x8001)]
+pub fn test_trap_csr_legalization(#[case] csr: CSRAddress, #[case] value: u64, #[case] result: u64) {
+    let mut cpu = RV64CPUContext::new(0x1000, MemoryManagementUnit::new_guard(1024));
+
+    cpu.csrs.write_csr(csr as u16, value, true).unwrap();
+
+    assert_eq!(cpu.csrs.read_csr(csr as u16, true).unwrap(), result);
+}
+
+#[rstest]
+#[case::timer_line(MIPFlags::MTIP, 0, MIPFlags::MTIP.bits())]
+#[case::lines_are_read_only(MIPFlags::MEIP, 0, MIPFlags::MEIP.bits())] //Software writes can't clear a device line
+#[case::software_bits(MIPFlags::empty(), u64::MAX, 0x222)] //Only SSIP, STIP and SEIP are software writable
+#[case::seip_or(MIPFlags::SEIP, 0, MIPFlags::SEIP.bits())] //SEIP is the software bit or'ed with the external line
+pub fn test_mip(#[case] lines: MIPFlags, #[case] write: u64, #[case] result: u64) {
+    let mut cpu = RV64CPUContext::new(0x1000, MemoryManagementUnit::new_guard(1024));
+
+    cpu.csrs.get_interrupt_lines().raise(lines);
+    cpu.csrs.write_csr(CSRAddress::MIP as u16, write, true).unwrap();
+
+    assert_eq!(cpu.csrs.read_csr(CSRAddress::MIP as u16, true).unwrap(), result);
+}
+
+#[rstest]
+#[case::delegated(0x222, 0x222, 0x2)] //Only SSIP can be written through SIP
+#[case::not_delegated(0, 0, 0)]
+pub fn test_sip_sie_aliasing(#[case] mideleg: u64, #[case] sie: u64, #[case] sip: u64) {
+    let mut cpu = setup_traps(PrivilegeMode::Supervisor, 0, mideleg, false);
+
+    cpu.csrs.write_csr(CSRAddress::MIE as u16, MIPFlags::MTIP.bits(), true).unwrap();
+    cpu.csrs.write_csr(CSRAddress::SIE as u16, u64::MAX, true).unwrap();
+    cpu.csrs.write_csr(CSRAddress::SIP as u16, u64::MAX, true).unwrap();
+
+    assert_eq!(cpu.csrs.read_csr(CSRAddress::SIE as u16, true).unwrap(), sie);
+    assert_eq!(cpu.csrs.read_csr(CSRAddress::MIE as u16, true).unwrap(), sie | MIPFlags::MTIP.bits());
+    assert_eq!(cpu.csrs.read_csr(CSRAddress::SIP as u16, true).unwrap(), sip);
+    assert_eq!(cpu.csrs.read_csr(CSRAddress::MIP as u16, true).unwrap(), sip);
+}
+
+#[rstest]
+#[case::machine_disabled(PrivilegeMode::Machine, 0, MStatusFlags::empty(), MIPFlags::MTIP, None)]
+#[case::machine_enabled(PrivilegeMode::Machine, 0, MStatusFlags::MIE, MIPFlags::MTIP, Some(7))]
+#[case::lower_privilege_always_enabled(PrivilegeMode::Supervisor, 0, MStatusFlags::empty(), MIPFlags::MTIP, Some(7))]
+#[case::priority(PrivilegeMode::Machine, 0, MStatusFlags::MIE, MIPFlags::MTIP | MIPFlags::MSIP | MIPFlags::MEIP, Some(11))]
+#[case::software_before_timer(PrivilegeMode::Machine, 0, MStatusFlags::MIE, MIPFlags::MTIP | MIPFlags::MSIP, Some(3))]
+#[case::delegated_masked_in_machine(PrivilegeMode::Machine, 0x222, MStatusFlags::MIE, MIPFlags::SEIP, None)]
+#[case::delegated_needs_sie(PrivilegeMode::Supervisor, 0x222, MStatusFlags::empty(), MIPFlags::STIP, None)]
+#[case::delegated_from_user(PrivilegeMode::User, 0x222, MStatusFlags::empty(), MIPFlags::STIP, Some(5))]
+#[case::machine_before_supervisor(PrivilegeMode::User, 0x222, MStatusFlags::empty(), MIPFlags::SEIP | MIPFlags::MTIP, Some(7))]
+pub fn test_pending_interrupt(#[case] privilege: PrivilegeMode, #[case] mideleg: u64, #[case] mstatus: MStatusFlags, #[case] lines: MIPFlags, #[case] result: Option<u64>) {
+    let mut cpu = setup_traps(privilege, 0, mideleg, false);
+
+    cpu.csrs.write_csr(CSRAddress::MStatus as u16, mstatus.bits(), true).unwrap();
+    cpu.csrs.write_csr(CSRAddress::MIE as u16, u64::MAX, true).unwrap();
+    cpu.csrs.get_interrupt_lines().raise(lines);
+
+    assert_eq!(cpu.csrs.get_pending_interrupt(), result);
+    // WFI wakes up regardless of the global enables
+    assert!(cpu.csrs.has_pending_interrupt());
+
+    cpu.csrs.get_interrupt_lines().lower(lines);
+
+    assert_eq!(cpu.csrs.get_pending_interrupt(), None);
+}
@@ -0,0 +1,189 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::OwnedFd;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use rstest::rstest;
+use crate::emulator::devices::plic::{IrqLine, Plic, PlicState, PLIC_BASE, PLIC_SIZE};
+use crate::emulator::devices::uart::{Uart, UartBackend, UartState, UART_BASE, UART_IRQ, UART_SIZE};
+use crate::emulator::state::memory::MemoryManagementUnit;
+use crate::emulator::state::rv64_cpu_context::{MIPFlags, RV64CPUContext};
+use crate::tests::helpers::{mip, temp_path};
+
+const RBR: usize = UART_BASE;
+const IER: usize = UART_BASE + 1;
+const IIR: usize = UART_BASE + 2;
+const LCR: usize = UART_BASE + 3;
+const LSR: usize = UART_BASE + 5;
+const SCR: usize = UART_BASE + 7;
+
+// UART wired to source 10 of a PLIC that routes it to M-mode of the hart
+fn setup_uart(name: &str) -> (RV64CPUContext, Arc<UartState>, std::path::PathBuf) {
+    let mmu = MemoryManagementUnit::new_guard(0x1000);
+    let hart = RV64CPUContext::new(0, mmu.clone());
+
+    let plic = PlicState::new(vec![hart.csrs.get_interrupt_lines()]);
+    mmu.write().unwrap().add_region(PLIC_BASE, PLIC_SIZE, Box::new(Plic::new(plic.clone())));
+    mmu.write().unwrap().write_word(PLIC_BASE + 4 * UART_IRQ, 1);
+    mmu.write().unwrap().write_word(PLIC_BASE + 0x2000, 1 << UART_IRQ);
+
+    let path = temp_path(name, "log");
+    let uart = UartState::new(IrqLine::new(plic, UART_IRQ), &UartBackend::File(path.clone())).unwrap();
+    mmu.write().unwrap().add_region(UART_BASE, UART_SIZE, Box::new(Uart::new(uart.clone())));
+
+    (hart, uart, path)
+}
+
+fn meip(hart: &RV64CPUContext) -> bool {
+    mip(hart) & MIPFlags::MEIP.bits() != 0
+}
+
+#[rstest]
+#[case::stdio("stdio", true)]
+#[case::file("file:/tmp/console.log", true)]
+#[case::unix("unix:/tmp/console.sock", true)]
+#[case::unknown("tcp:1234", false)]
+#[case::missing_path("file", false)]
+pub fn test_uart_backend_parse(#[case] spec: &str, #[case] valid: bool) {
+    assert_eq!(spec.parse::<UartBackend>().is_ok(), valid);
+}
+
+#[rstest]
+pub fn test_uart_transmit() {
+    let (hart, _uart, path) = setup_uart("transmit");
+
+    for byte in b"Hi!\n" {
+        hart.memory.write().unwrap().write_byte(RBR, *byte);
+    }
+
+    // Transmitter is always ready
+    assert_eq!(hart.memory.read().unwrap().read_byte(LSR) & 0x60, 0x60);
+    assert_eq!(std::fs::read(&path).unwrap(), b"Hi!\n");
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[rstest]
+pub fn test_uart_receive_interrupt() {
+    let (hart, uart, path) = setup_uart("receive");
+
+    // No interrupt until the guest enables it
+    uart.receive(b"ab");
+    assert!(!meip(&hart));
+    assert_eq!(hart.memory.read().unwrap().read_byte(LSR) & 1, 1);
+    assert_eq!(hart.memory.read().unwrap().read_byte(IIR) & 0x0F, 0x01);
+
+    hart.memory.write().unwrap().write_byte(IER, 0x01);
+    assert!(meip(&hart));
+    assert_eq!(hart.memory.read().unwrap().read_byte(IIR) & 0x0F, 0x04);
+
+    let claim = hart.memory.read().unwrap().read_word(PLIC_BASE + 0x20_0004);
+    assert_eq!(claim, UART_IRQ as u32);
+
+    // Draining the FIFO drops the line and clears data ready, so completion does not re-raise it
+    assert_eq!(hart.memory.read().unwrap().read_byte(RBR), b'a');
+    assert_eq!(hart.memory.read().unwrap().read_byte(RBR), b'b');
+    assert_eq!(hart.memory.read().unwrap().read_byte(LSR) & 1, 0);
+
+    hart.memory.write().unwrap().write_word(PLIC_BASE + 0x20_0004, claim);
+    assert!(!meip(&hart));
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[rstest]
+pub fn test_uart_transmit_interrupt() {
+    let (hart, _uart, path) = setup_uart("thre");
+
+    // Enabling the THR empty interrupt fires immediately, reading the IIR acknowledges it
+    hart.memory.write().unwrap().write_byte(IER, 0x02);
+    assert!(meip(&hart));
+    assert_eq!(hart.memory.read().unwrap().read_byte(IIR) & 0x0F, 0x02);
+    assert_eq!(hart.memory.read().unwrap().read_byte(IIR) & 0x0F, 0x01);
+
+    // Every transmitted byte empties the holding register again
+    hart.memory.write().unwrap().write_byte(RBR, b'x');
+    assert_eq!(hart.memory.read().unwrap().read_byte(IIR) & 0x0F, 0x02);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[rstest]
+pub fn test_uart_divisor_latch() {
+    let (hart, uart, path) = setup_uart("dlab");
+
+    hart.memory.write().unwrap().write_byte(LCR, 0x83);
+    hart.memory.write().unwrap().write_byte(RBR, 0x01);
+    hart.memory.write().unwrap().write_byte(IER, 0x02);
+
+    // Divisor writes neither transmit nor touch the IER
+    assert_eq!(hart.memory.read().unwrap().read_byte(RBR), 0x01);
+    assert_eq!(hart.memory.read().unwrap().read_byte(IER), 0x02);
+
+    hart.memory.write().unwrap().write_byte(LCR, 0x03);
+    assert_eq!(hart.memory.read().unwrap().read_byte(IER), 0x00);
+    assert!(!meip(&hart));
+
+    hart.memory.write().unwrap().write_byte(SCR, 0x5A);
+    assert_eq!(hart.memory.read().unwrap().read_byte(SCR), 0x5A);
+
+    uart.receive(b"z");
+    assert_eq!(hart.memory.read().unwrap().read_byte(RBR), b'z');
+    assert!(std::fs::read(&path).unwrap().is_empty());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+// Reads what the console passed on to the guest until len bytes arrived or it gives up
+fn console_input(uart: &UartState, len: usize) -> Vec<u8> {
+    let mut received = Vec::new();
+
+    for _ in 0..200 {
+        received.extend(uart.take_input(len));
+
+        if received.len() >= len {
+            break;
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    received
+}
+
+#[rstest]
+pub fn test_uart_console_input() {
+    let (_hart, uart, path) = setup_uart("console");
+
+    // The pipe stands in for the terminal, its reader clone for the debugger prompt
+    let (terminal, mut keyboard) = std::io::pipe().unwrap();
+    let mut prompt = terminal.try_clone().unwrap();
+    uart.attach_console(File::from(OwnedFd::from(terminal)));
+
+    // Typed at the prompt, nothing reaches the UART and the prompt still gets all of it
+    keyboard.write_all(b"help\n").unwrap();
+    thread::sleep(Duration::from_millis(100));
+    assert!(uart.take_input(16).is_empty());
+
+    let mut line = [0u8; 5];
+    prompt.read_exact(&mut line).unwrap();
+    assert_eq!(&line, b"help\n");
+
+    // While the machine runs the guest gets it
+    uart.set_console_input(true);
+    keyboard.write_all(b"ls\n").unwrap();
+    assert_eq!(console_input(&uart, 3), b"ls\n");
+
+    // And the prompt takes over again once it stops
+    uart.set_console_input(false);
+    keyboard.write_all(b"c\n").unwrap();
+    thread::sleep(Duration::from_millis(100));
+    assert!(uart.take_input(16).is_empty());
+
+    let mut line = [0u8; 2];
+    prompt.read_exact(&mut line).unwrap();
+    assert_eq!(&line, b"c\n");
+
+    std::fs::remove_file(path).unwrap();
+}